@@ -13,7 +13,7 @@ use curve25519_dalek::edwards::CompressedEdwardsY;
 use data_encoding::Encoding;
 use data_encoding_macro::new_encoding;
 use ed25519_dalek::{SigningKey, VerifyingKey};
-use n0_error::{e, ensure, stack_error};
+use n0_error::{AnyError, e, ensure, stack_error};
 use serde::{Deserialize, Serialize, de, ser};
 
 /// z-base-32 encoding as used by [pkarr](https://pkarr.org) for endpoint id domain names.
@@ -321,7 +321,7 @@ impl SecretKey {
 
     /// Sign the given message and return a digital signature
     pub fn sign(&self, msg: &[u8]) -> Signature {
-        use ed25519_dalek::Signer;
+        use ed25519_dalek::Signer as _;
 
         let sig = self.0.sign(msg);
         Signature(sig)
@@ -371,6 +371,61 @@ impl TryFrom<&[u8]> for SecretKey {
     }
 }
 
+/// Signs messages with the secret key of a [`PublicKey`].
+///
+/// This allows using a key without having the [`SecretKey`] in memory, e.g. a key held by
+/// a separate process or a hardware security module. [`SecretKey`] implements this trait
+/// for keys kept in memory.
+///
+/// Signing is synchronous because it is called from within TLS handshakes. It is also
+/// called from async contexts, so implementations should not block for long.
+pub trait Signer: Debug + Send + Sync + 'static {
+    /// Returns the public key of the signing key.
+    ///
+    /// Users of the signer call this often, so this should be cheap.
+    fn public_key(&self) -> PublicKey;
+
+    /// Signs `message` with Ed25519.
+    fn sign(&self, message: &[u8]) -> Result<Signature, SignError>;
+}
+
+impl Signer for SecretKey {
+    fn public_key(&self) -> PublicKey {
+        self.public()
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Signature, SignError> {
+        Ok(SecretKey::sign(self, message))
+    }
+}
+
+impl<T: Signer + ?Sized> Signer for std::sync::Arc<T> {
+    fn public_key(&self) -> PublicKey {
+        (**self).public_key()
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Signature, SignError> {
+        (**self).sign(message)
+    }
+}
+
+impl<T: Signer + ?Sized> Signer for Box<T> {
+    fn public_key(&self) -> PublicKey {
+        (**self).public_key()
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Signature, SignError> {
+        (**self).sign(message)
+    }
+}
+
+/// A [`Signer`] failed to produce a signature.
+#[stack_error(derive, add_meta)]
+#[error("Failed to sign")]
+pub struct SignError {
+    source: AnyError,
+}
+
 /// Ed25519 signature.
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub struct Signature(ed25519_dalek::Signature);
@@ -472,6 +527,73 @@ impl Signature {
 #[error("Invalid signature")]
 pub struct SignatureError {}
 
+/// A signed statement that a key was replaced by another key.
+///
+/// The predecessor key signs the successor key, so anyone knowing the predecessor can verify
+/// that the successor is legitimate. Endpoints which rotate their key publish this record
+/// under their old [`EndpointId`], so that peers can follow the chain of successions to the
+/// current [`EndpointId`].
+///
+/// A [`KeySuccession`] can only be created with a valid signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeySuccession {
+    predecessor: PublicKey,
+    successor: PublicKey,
+    signature: Signature,
+}
+
+impl KeySuccession {
+    /// Domain separation tag for the signed message.
+    const DOMAIN: &[u8] = b"iroh-key-succession-v1";
+
+    /// Creates a succession from the key of `predecessor` to `successor`.
+    pub fn new(
+        predecessor: &(impl Signer + ?Sized),
+        successor: PublicKey,
+    ) -> Result<Self, SignError> {
+        let predecessor_key = predecessor.public_key();
+        let signature = predecessor.sign(&Self::signed_message(&predecessor_key, &successor))?;
+        Ok(Self {
+            predecessor: predecessor_key,
+            successor,
+            signature,
+        })
+    }
+
+    /// Creates a succession from its parts, verifying the signature.
+    pub fn from_parts(
+        predecessor: PublicKey,
+        successor: PublicKey,
+        signature: Signature,
+    ) -> Result<Self, SignatureError> {
+        predecessor.verify(&Self::signed_message(&predecessor, &successor), &signature)?;
+        Ok(Self {
+            predecessor,
+            successor,
+            signature,
+        })
+    }
+
+    /// Returns the key which was replaced.
+    pub fn predecessor(&self) -> PublicKey {
+        self.predecessor
+    }
+
+    /// Returns the key which replaces the predecessor.
+    pub fn successor(&self) -> PublicKey {
+        self.successor
+    }
+
+    /// Returns the signature of the predecessor over the succession.
+    pub fn signature(&self) -> Signature {
+        self.signature
+    }
+
+    fn signed_message(predecessor: &PublicKey, successor: &PublicKey) -> Vec<u8> {
+        [Self::DOMAIN, predecessor.as_bytes(), successor.as_bytes()].concat()
+    }
+}
+
 fn decode_base32_hex(s: &str) -> Result<[u8; 32], KeyParsingError> {
     let mut bytes = [0u8; 32];
 
@@ -561,4 +683,37 @@ mod tests {
         let signature2: Signature = postcard::from_bytes(&bytes).unwrap();
         assert_eq!(signature, signature2);
     }
+
+    #[test]
+    fn test_key_succession() {
+        let old = SecretKey::generate();
+        let new = SecretKey::generate();
+        let succession = KeySuccession::new(&old, new.public()).unwrap();
+        assert_eq!(succession.predecessor(), old.public());
+        assert_eq!(succession.successor(), new.public());
+
+        let parsed =
+            KeySuccession::from_parts(old.public(), new.public(), succession.signature()).unwrap();
+        assert_eq!(parsed, succession);
+
+        // The signature does not vouch for other keys.
+        let other = SecretKey::generate().public();
+        assert!(KeySuccession::from_parts(old.public(), other, succession.signature()).is_err());
+        assert!(
+            KeySuccession::from_parts(new.public(), old.public(), succession.signature()).is_err()
+        );
+        // The successor can not claim to be the successor on its own.
+        let forged = KeySuccession::new(&new, new.public()).unwrap();
+        assert!(KeySuccession::from_parts(old.public(), new.public(), forged.signature()).is_err());
+    }
+
+    #[test]
+    fn test_signer() {
+        let key = SecretKey::generate();
+        let signer: std::sync::Arc<dyn Signer> = std::sync::Arc::new(key.clone());
+        assert_eq!(signer.public_key(), key.public());
+        let signature = signer.sign(b"hello world").unwrap();
+        assert_eq!(signature, key.sign(b"hello world"));
+        key.public().verify(b"hello world", &signature).unwrap();
+    }
 }
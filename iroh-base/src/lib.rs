@@ -14,8 +14,8 @@ mod relay_url;
 pub use self::endpoint_addr::{CustomAddr, EndpointAddr, TransportAddr};
 #[cfg(feature = "key")]
 pub use self::key::{
-    EndpointId, KeyParsingError, PublicKey, SecretKey, Signature, SignatureError,
-    SignatureParsingError,
+    EndpointId, KeyParsingError, KeySuccession, PublicKey, SecretKey, SignError, Signature,
+    SignatureError, SignatureParsingError, Signer,
 };
 #[cfg(feature = "relay")]
 pub use self::relay_url::{RelayUrl, RelayUrlParseError};
@@ -17,8 +17,13 @@ use tracing::info;
 
 use crate::store::Options;
 pub use crate::{
-    dns::DnsConfig,
-    http::{CertMode, HttpConfig, HttpsConfig, RateLimitConfig},
+    dns::{
+        DnsConfig, DnsListenerConfig, DnssecAlgorithm, DnssecConfig, TsigKeyAlgorithm,
+        TsigKeyConfig, ZoneTransferConfig,
+    },
+    http::{AdminConfig, CertMode, HttpConfig, HttpsConfig, RateLimitConfig},
+    policy::PublishPolicyConfig,
+    replication::ReplicationConfig,
 };
 
 const DEFAULT_METRICS_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 9117);
@@ -46,10 +51,10 @@ pub struct Config {
     /// the metrics server entirely, use [`MetricsConfig::disabled`].
     pub metrics: Option<MetricsConfig>,
 
-    /// Configuration for the mainline DHT fallback.
+    /// Configuration for the mainline DHT fallback and publishing.
     ///
     /// When `None` or disabled, packets that are not present in the local store
-    /// are not looked up on the mainline DHT.
+    /// are not looked up on the mainline DHT, and stored packets are not published to it.
     pub mainline: Option<MainlineConfig>,
 
     /// Configuration for the signed-packet zone store.
@@ -57,6 +62,22 @@ pub struct Config {
     /// When `None`, the defaults from [`StoreConfig::default`] are used.
     pub zone_store: Option<StoreConfig>,
 
+    /// Configuration for replicating signed packets between servers.
+    ///
+    /// When `None`, the server neither pushes packets to nor accepts packets from
+    /// other servers.
+    pub replication: Option<ReplicationConfig>,
+
+    /// Configuration for the admin API.
+    ///
+    /// When `None`, the admin API is disabled.
+    pub admin: Option<AdminConfig>,
+
+    /// Policy for packets published via `PUT /pkarr`, replicated, or imported.
+    ///
+    /// When `None`, every validly signed packet is accepted.
+    pub publish_policy: Option<PublishPolicyConfig>,
+
     /// Rate limit applied to `PUT /pkarr` requests.
     #[serde(default)]
     pub pkarr_put_rate_limit: RateLimitConfig,
@@ -72,12 +93,21 @@ pub struct Config {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[non_exhaustive]
 pub struct StoreConfig {
+    /// Database used to persist signed packets.
+    ///
+    /// Defaults to [`StoreBackend::Redb`].
+    #[serde(default)]
+    pub backend: StoreBackend,
+
     /// Maximum number of packets processed in a single write transaction.
+    ///
+    /// Only used by the [`StoreBackend::Redb`] backend.
     pub max_batch_size: usize,
 
     /// Maximum time a write transaction stays open before it is committed.
     ///
-    /// Bounds how much data can be lost on a crash.
+    /// Bounds how much data can be lost on a crash. Only used by the
+    /// [`StoreBackend::Redb`] backend.
     #[serde(with = "humantime_serde")]
     pub max_batch_time: Duration,
 
@@ -90,6 +120,23 @@ pub struct StoreConfig {
     pub eviction_interval: Duration,
 }
 
+/// Database backend for the signed-packet store.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum StoreBackend {
+    /// An embedded [redb](https://www.redb.org/) database.
+    ///
+    /// Writes are batched into transactions according to
+    /// [`StoreConfig::max_batch_size`] and [`StoreConfig::max_batch_time`].
+    #[default]
+    Redb,
+    /// An embedded [SQLite](https://sqlite.org/) database in WAL mode.
+    ///
+    /// Every write is committed in its own transaction.
+    Sqlite,
+}
+
 impl Default for StoreConfig {
     fn default() -> Self {
         Options::default().into()
@@ -99,6 +146,7 @@ impl Default for StoreConfig {
 impl From<Options> for StoreConfig {
     fn from(value: Options) -> Self {
         Self {
+            backend: StoreBackend::default(),
             max_batch_size: value.max_batch_size,
             max_batch_time: value.max_batch_time,
             eviction: value.eviction,
@@ -147,10 +195,11 @@ impl MetricsConfig {
     }
 }
 
-/// Configuration for the mainline DHT fallback.
+/// Configuration for the mainline DHT fallback and publishing.
 ///
 /// When enabled, the server looks up signed packets on the BitTorrent mainline
-/// DHT for keys that are not present in the local store.
+/// DHT for keys that are not present in the local store.  With [`Self::publish`]
+/// set, it also publishes the packets it stores to the DHT.
 #[derive(Debug, Serialize, Deserialize)]
 #[non_exhaustive]
 pub struct MainlineConfig {
@@ -162,6 +211,22 @@ pub struct MainlineConfig {
     /// or empty, the default BitTorrent mainline bootstrap nodes defined by
     /// pkarr are used.
     pub bootstrap: Option<Vec<String>>,
+    /// Publishes packets accepted via `PUT /pkarr` to the mainline DHT when set to `true`.
+    ///
+    /// Requires [`Self::enabled`].  All stored packets are republished in
+    /// [`Self::republish_interval`], so that they do not expire from the DHT while they are
+    /// stored on this server.
+    #[serde(default)]
+    pub publish: bool,
+    /// Interval in which stored packets are republished to the mainline DHT.
+    ///
+    /// DHT nodes drop items after about two hours. Defaults to one hour.
+    #[serde(default = "default_republish_interval", with = "humantime_serde")]
+    pub republish_interval: Duration,
+}
+
+fn default_republish_interval() -> Duration {
+    Duration::from_secs(60 * 60)
 }
 
 /// Bootstrap nodes for mainline DHT resolution.
@@ -174,12 +239,13 @@ pub(crate) enum BootstrapOption {
     Custom(Vec<String>),
 }
 
-#[allow(clippy::derivable_impls)]
 impl Default for MainlineConfig {
     fn default() -> Self {
         Self {
             enabled: false,
             bootstrap: None,
+            publish: false,
+            republish_interval: default_republish_interval(),
         }
     }
 }
@@ -221,10 +287,20 @@ impl Config {
 
     /// Returns the path to the signed-packet store database file.
     ///
-    /// The path is `<data_dir>/signed-packets-1.db`, where `<data_dir>` is
-    /// resolved by [`Self::data_dir`].
+    /// The path is `<data_dir>/signed-packets-1.db` for the [`StoreBackend::Redb`]
+    /// backend and `<data_dir>/signed-packets-1.sqlite` for the [`StoreBackend::Sqlite`]
+    /// backend, where `<data_dir>` is resolved by [`Self::data_dir`].
     pub fn signed_packet_store_path(&self) -> Result<PathBuf> {
-        Ok(self.data_dir()?.join("signed-packets-1.db"))
+        let backend = self
+            .zone_store
+            .as_ref()
+            .map(|config| config.backend)
+            .unwrap_or_default();
+        let file_name = match backend {
+            StoreBackend::Redb => "signed-packets-1.db",
+            StoreBackend::Sqlite => "signed-packets-1.sqlite",
+        };
+        Ok(self.data_dir()?.join(file_name))
     }
 
     /// Get the address where the metrics server should be bound, if set.
@@ -251,6 +327,19 @@ impl Config {
             }) => Some(BootstrapOption::Default),
         }
     }
+
+    /// Returns the republish interval if publishing to the mainline DHT is enabled.
+    pub(crate) fn mainline_publish(&self) -> Option<Duration> {
+        match self.mainline.as_ref() {
+            Some(MainlineConfig {
+                enabled: true,
+                publish: true,
+                republish_interval,
+                ..
+            }) => Some(*republish_interval),
+            _ => None,
+        }
+    }
 }
 
 impl Default for Config {
@@ -280,8 +369,15 @@ impl Default for Config {
                 rr_a: Some(Ipv4Addr::LOCALHOST),
                 rr_aaaa: None,
                 rr_ns: Some("ns1.irohdns.example.".to_string()),
+                tls: None,
+                quic: None,
+                dnssec: None,
+                transfer: None,
             },
             zone_store: None,
+            replication: None,
+            admin: None,
+            publish_policy: None,
             metrics: None,
             mainline: None,
             pkarr_put_rate_limit: RateLimitConfig::default(),
@@ -0,0 +1,283 @@
+//! Publishing of signed packets to the BitTorrent mainline DHT.
+//!
+//! When enabled with [`MainlineConfig::publish`], every packet accepted via `PUT /pkarr` is
+//! published to the DHT, so that clients which resolve straight from the DHT find endpoints
+//! that only publish to this server. DHT nodes drop items after about two hours, so all
+//! stored packets are republished in [`MainlineConfig::republish_interval`].
+//!
+//! [`MainlineConfig::publish`]: crate::config::MainlineConfig::publish
+//! [`MainlineConfig::republish_interval`]: crate::config::MainlineConfig::republish_interval
+
+use std::{sync::Arc, time::Duration};
+
+use iroh_dns::pkarr::SignedPacket;
+use mainline::{Dht, async_dht::AsyncDht};
+use n0_error::{Result, StdResultExt};
+use n0_future::{BufferedStreamExt, StreamExt, stream, task::AbortOnDropHandle};
+use tokio::sync::mpsc;
+use tracing::{Instrument, debug, info, info_span, warn};
+
+use crate::{
+    metrics::Metrics,
+    replication::MAX_PAGE_SIZE,
+    store::{ZoneStore, signed_packet_to_mutable_item},
+    util::PublicKeyBytes,
+};
+
+/// Number of packets queued for publishing before new packets are dropped.
+const QUEUE_SIZE: usize = 1024;
+
+/// Number of packets published to the DHT concurrently.
+const CONCURRENCY: usize = 16;
+
+/// Handle to the DHT publishing tasks.
+///
+/// The tasks are aborted once the last clone of the handle is dropped.
+#[derive(Debug, Clone)]
+pub(crate) struct DhtPublisher(Arc<Inner>);
+
+#[derive(Debug)]
+struct Inner {
+    queue: mpsc::Sender<SignedPacket>,
+    metrics: Arc<Metrics>,
+    _tasks: Vec<AbortOnDropHandle<()>>,
+}
+
+impl DhtPublisher {
+    /// Spawns the tasks that publish new packets and republish stored packets.
+    pub(crate) fn spawn(
+        dht: &Dht,
+        store: ZoneStore,
+        republish_interval: Duration,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        let publisher = Publisher {
+            dht: dht.clone().as_async(),
+            store,
+            metrics: metrics.clone(),
+        };
+        let (send, recv) = mpsc::channel(QUEUE_SIZE);
+        let span = info_span!("dht_publish");
+        let tasks = vec![
+            AbortOnDropHandle::new(tokio::spawn(
+                publisher
+                    .clone()
+                    .publish_loop(recv)
+                    .instrument(span.clone()),
+            )),
+            AbortOnDropHandle::new(tokio::spawn(
+                publisher
+                    .republish_loop(republish_interval)
+                    .instrument(span),
+            )),
+        ];
+        Self(Arc::new(Inner {
+            queue: send,
+            metrics,
+            _tasks: tasks,
+        }))
+    }
+
+    /// Queues a packet that was published to this server to be published to the DHT.
+    pub(crate) fn publish(&self, packet: &SignedPacket) {
+        if self.0.queue.try_send(packet.clone()).is_err() {
+            self.0.metrics.dht_publish_dropped.inc();
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Publisher {
+    dht: AsyncDht,
+    store: ZoneStore,
+    metrics: Arc<Metrics>,
+}
+
+impl Publisher {
+    /// Publishes queued packets.
+    ///
+    /// Packets that fail to be published are not retried, they are picked up by the next
+    /// republish.
+    async fn publish_loop(self, recv: mpsc::Receiver<SignedPacket>) {
+        let packets = tokio_stream::wrappers::ReceiverStream::new(recv);
+        packets
+            .map(|packet| {
+                let this = self.clone();
+                async move { this.publish(&packet).await }
+            })
+            .buffered_unordered(CONCURRENCY)
+            .for_each(|_| {})
+            .await;
+    }
+
+    /// Republishes all stored packets in the configured interval.
+    async fn republish_loop(self, interval: Duration) {
+        loop {
+            tokio::time::sleep(interval).await;
+            match self.republish().await {
+                Ok(count) => info!("republished {count} packets"),
+                Err(err) => warn!("failed to republish: {err:#}"),
+            }
+        }
+    }
+
+    /// Pages through all stored packets and publishes them.
+    ///
+    /// Returns the number of packets that were published successfully.
+    async fn republish(&self) -> Result<usize> {
+        let mut after: Option<PublicKeyBytes> = None;
+        let mut published = 0;
+        loop {
+            let packets = self.store.packets(after, MAX_PAGE_SIZE).await?;
+            let Some(last) = packets.last() else {
+                break;
+            };
+            after = Some(PublicKeyBytes::from_signed_packet(last));
+            published += stream::iter(packets)
+                .map(|packet| {
+                    let this = self.clone();
+                    async move { this.publish(&packet).await }
+                })
+                .buffered_unordered(CONCURRENCY)
+                .filter(|ok| *ok)
+                .count()
+                .await;
+        }
+        Ok(published)
+    }
+
+    /// Publishes a packet to the DHT, returning whether it succeeded.
+    async fn publish(&self, packet: &SignedPacket) -> bool {
+        let key = PublicKeyBytes::from_signed_packet(packet);
+        if self.store.is_blocked(&key) {
+            return false;
+        }
+        let item = signed_packet_to_mutable_item(packet);
+        match self.dht.put_mutable(item, None).await.anyerr() {
+            Ok(_) => {
+                debug!(%key, "published packet");
+                self.metrics.dht_publish_success.inc();
+                true
+            }
+            Err(err) => {
+                debug!(%key, "failed to publish packet: {err:#}");
+                self.metrics.dht_publish_error.inc();
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh_base::SecretKey;
+    use mainline::{DhtBuilder, Testnet};
+    use n0_error::ensure_any;
+    use n0_tracing_test::traced_test;
+
+    use super::*;
+    use crate::{
+        Server,
+        config::MainlineConfig,
+        replication::http_client,
+        server::test_config,
+        store::{PacketSource, mutable_item_to_signed_packet},
+    };
+
+    fn signed_packet(secret_key: &SecretKey) -> Result<SignedPacket> {
+        SignedPacket::from_txt_strings(secret_key, "_iroh", ["relay=https://relay.example"], 30)
+            .anyerr()
+    }
+
+    /// Resolves the packet for `key` from the DHT, retrying until it is found.
+    async fn resolve(dht: &AsyncDht, key: &PublicKeyBytes) -> Result<SignedPacket> {
+        tokio::time::timeout(Duration::from_secs(30), async {
+            loop {
+                if let Some(item) = dht.get_mutable_most_recent(key.as_bytes(), None).await {
+                    return mutable_item_to_signed_packet(&item).anyerr();
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .anyerr()?
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn publish_to_dht() -> Result {
+        let dir = tempfile::tempdir()?;
+        let testnet = Testnet::new_async(5).await.anyerr()?;
+
+        let mut config = test_config(dir.path());
+        config.mainline = Some(MainlineConfig {
+            enabled: true,
+            bootstrap: Some(testnet.bootstrap.clone()),
+            publish: true,
+            ..Default::default()
+        });
+        let server = Server::bind(config).await?;
+
+        let packet = signed_packet(&SecretKey::generate())?;
+        let url = format!(
+            "{}pkarr/{}",
+            server.http_url().expect("http is bound"),
+            packet.public_key().to_z32()
+        );
+        let res = http_client()?
+            .put(url)
+            .body(packet.to_relay_payload())
+            .send()
+            .await
+            .anyerr()?;
+        ensure_any!(
+            res.status().is_success(),
+            "publish failed: {}",
+            res.status()
+        );
+
+        let mut builder = DhtBuilder::default();
+        builder.bootstrap(&testnet.bootstrap);
+        let dht = builder.build().anyerr()?.as_async();
+        let key = PublicKeyBytes::from_signed_packet(&packet);
+        let resolved = resolve(&dht, &key).await?;
+        assert_eq!(resolved.as_bytes(), packet.as_bytes());
+        assert_eq!(server.metrics().dht_publish_success.get(), 1);
+
+        server.shutdown().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn republish_stored_packets() -> Result {
+        let testnet = Testnet::new_async(5).await.anyerr()?;
+        let mut builder = DhtBuilder::default();
+        builder.bootstrap(&testnet.bootstrap);
+        let dht = builder.build().anyerr()?;
+
+        let metrics = Arc::new(Metrics::default());
+        let store = ZoneStore::in_memory(Default::default(), metrics.clone())?;
+        let packets = [
+            signed_packet(&SecretKey::generate())?,
+            signed_packet(&SecretKey::generate())?,
+        ];
+        for packet in &packets {
+            store.insert(packet.clone(), PacketSource::Import).await?;
+        }
+
+        let publisher = Publisher {
+            dht: dht.clone().as_async(),
+            store,
+            metrics: metrics.clone(),
+        };
+        assert_eq!(publisher.republish().await?, 2);
+        assert_eq!(metrics.dht_publish_success.get(), 2);
+        for packet in &packets {
+            let key = PublicKeyBytes::from_signed_packet(packet);
+            let resolved = resolve(&publisher.dht, &key).await?;
+            assert_eq!(resolved.as_bytes(), packet.as_bytes());
+        }
+        Ok(())
+    }
+}
@@ -3,6 +3,7 @@
 use std::{
     collections::BTreeMap,
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::Path,
     sync::Arc,
     time::Duration,
 };
@@ -15,7 +16,7 @@ use hickory_server::{
         self,
         op::ResponseCode,
         rr::{
-            LowerName, Name, RData, Record, RecordSet, RecordType, RrKey,
+            Name, RData, Record, RecordSet, RecordType, RrKey,
             rdata::{self},
         },
     },
@@ -23,7 +24,9 @@ use hickory_server::{
     store::in_memory::InMemoryZoneHandler,
     zone_handler::{AxfrPolicy, Catalog, MessageResponse, ZoneType},
 };
-use n0_error::{Result, StdResultExt, anyerr};
+use n0_error::{Result, StackResultExt, StdResultExt, anyerr, ensure_any};
+use n0_future::task::AbortOnDropHandle;
+use rustls::server::ResolvesServerCert;
 use serde::{Deserialize, Serialize};
 use tokio::{
     net::{TcpListener, UdpSocket},
@@ -31,10 +34,16 @@ use tokio::{
 };
 use tracing::{debug, info};
 
-use self::node_zone_handler::NodeZoneHandler;
+use self::{dnssec::ZoneSigner, node_zone_handler::NodeZoneHandler, transfer::ZoneTransfers};
+pub use self::{
+    dnssec::{DnssecAlgorithm, DnssecConfig},
+    transfer::{TsigKeyAlgorithm, TsigKeyConfig, ZoneTransferConfig},
+};
 use crate::{metrics::Metrics, store::ZoneStore};
 
+mod dnssec;
 mod node_zone_handler;
+mod transfer;
 
 const DEFAULT_NS_TTL: u32 = 60 * 60 * 12; // 12h
 const DEFAULT_SOA_TTL: u32 = 60 * 60 * 24 * 14; // 14d
@@ -64,6 +73,28 @@ pub struct DnsConfig {
     pub rr_aaaa: Option<Ipv6Addr>,
     /// Optional `NS` record to serve at each origin apex.
     pub rr_ns: Option<String>,
+
+    /// Optional DNS-over-TLS (RFC 7858) listener.
+    ///
+    /// Serves the certificates of the HTTPS listener, which must be configured.
+    pub tls: Option<DnsListenerConfig>,
+    /// Optional DNS-over-QUIC (RFC 9250) listener.
+    ///
+    /// Serves the certificates of the HTTPS listener, which must be configured.
+    pub quic: Option<DnsListenerConfig>,
+
+    /// Optional DNSSEC signing of the served zones.
+    ///
+    /// When set, the server publishes a `DNSKEY` record at each origin apex and signs all
+    /// answers for queries with the DNSSEC OK bit. The `DS` records to add to the parent
+    /// zones are logged at startup.
+    pub dnssec: Option<DnssecConfig>,
+
+    /// Optional zone transfers (AXFR, IXFR) to secondary name servers.
+    ///
+    /// When set, secondaries can mirror the zone of each origin with transfers that are
+    /// authenticated with TSIG, and are sent a NOTIFY when the zones change.
+    pub transfer: Option<ZoneTransferConfig>,
 }
 
 impl DnsConfig {
@@ -81,21 +112,63 @@ impl DnsConfig {
             rr_a: None,
             rr_aaaa: None,
             rr_ns: None,
+            tls: None,
+            quic: None,
+            dnssec: None,
+            transfer: None,
+        }
+    }
+}
+
+/// Configuration for an encrypted DNS listener.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct DnsListenerConfig {
+    /// Port to bind the listener to.
+    ///
+    /// The standard port for both DNS-over-TLS and DNS-over-QUIC is 853.
+    pub port: u16,
+    /// Address to bind the listener to (defaults to `0.0.0.0`).
+    pub bind_addr: Option<IpAddr>,
+}
+
+impl DnsListenerConfig {
+    /// Creates a new [`DnsListenerConfig`] that binds to `port` on all interfaces.
+    pub fn new(port: u16) -> Self {
+        Self {
+            port,
+            bind_addr: None,
         }
     }
+
+    fn bind_addr(&self) -> SocketAddr {
+        SocketAddr::new(
+            self.bind_addr.unwrap_or(Ipv4Addr::UNSPECIFIED.into()),
+            self.port,
+        )
+    }
 }
 
 /// A DNS server that serves pkarr signed packets.
 pub(crate) struct DnsServer {
     local_addr: SocketAddr,
+    tls_addr: Option<SocketAddr>,
+    quic_addr: Option<SocketAddr>,
     server: hickory_server::Server<DnsHandler>,
+    _notify: Option<AbortOnDropHandle<()>>,
 }
 
 impl DnsServer {
     /// Spawn the server.
-    pub(crate) async fn spawn(config: DnsConfig, dns_handler: DnsHandler) -> Result<Self> {
+    ///
+    /// The DNS-over-TLS and DNS-over-QUIC listeners serve the certificates from `cert_resolver`.
+    pub(crate) async fn spawn(
+        config: DnsConfig,
+        dns_handler: DnsHandler,
+        cert_resolver: Option<Arc<dyn ResolvesServerCert>>,
+    ) -> Result<Self> {
         const TCP_TIMEOUT: Duration = Duration::from_millis(1000);
-        let mut server = hickory_server::Server::new(dns_handler);
+        let mut server = hickory_server::Server::new(dns_handler.clone());
 
         let bind_addr = SocketAddr::new(
             config.bind_addr.unwrap_or(Ipv4Addr::UNSPECIFIED.into()),
@@ -108,16 +181,51 @@ impl DnsServer {
 
         const TCP_RESPONSE_BUFFER: usize = 64 * 1024;
         server.register_socket(socket);
+        // Bind to the address of the UDP socket, so that TCP uses the same port if port 0 is configured.
         server.register_listener(
-            TcpListener::bind(bind_addr).await.anyerr()?,
+            TcpListener::bind(socket_addr).await.anyerr()?,
             TCP_TIMEOUT,
             TCP_RESPONSE_BUFFER,
         );
         info!("DNS server listening on {}", bind_addr);
 
+        let tls_addr = if let Some(tls) = &config.tls {
+            let cert_resolver = cert_resolver
+                .clone()
+                .context("DNS-over-TLS requires the HTTPS listener for certificates")?;
+            let listener = TcpListener::bind(tls.bind_addr()).await.anyerr()?;
+            let addr = listener.local_addr().anyerr()?;
+            server
+                .register_tls_listener(listener, TCP_TIMEOUT, cert_resolver)
+                .anyerr()?;
+            info!("DNS-over-TLS server listening on {addr}");
+            Some(addr)
+        } else {
+            None
+        };
+
+        let quic_addr = if let Some(quic) = &config.quic {
+            let cert_resolver = cert_resolver
+                .context("DNS-over-QUIC requires the HTTPS listener for certificates")?;
+            let socket = UdpSocket::bind(quic.bind_addr()).await.anyerr()?;
+            let addr = socket.local_addr().anyerr()?;
+            server
+                .register_quic_listener(socket, TCP_TIMEOUT, cert_resolver)
+                .anyerr()?;
+            info!("DNS-over-QUIC server listening on {addr}");
+            Some(addr)
+        } else {
+            None
+        };
+
+        let notify = dns_handler.spawn_notify();
+
         Ok(Self {
             server,
             local_addr: socket_addr,
+            tls_addr,
+            quic_addr,
+            _notify: notify,
         })
     }
 
@@ -126,6 +234,16 @@ impl DnsServer {
         self.local_addr
     }
 
+    /// Get the local address of the DNS-over-TLS listener.
+    pub(crate) fn tls_addr(&self) -> Option<SocketAddr> {
+        self.tls_addr
+    }
+
+    /// Get the local address of the DNS-over-QUIC listener.
+    pub(crate) fn quic_addr(&self) -> Option<SocketAddr> {
+        self.quic_addr
+    }
+
     /// Shutdown the server an wait for all tasks to complete.
     pub(crate) async fn shutdown(mut self) -> Result<()> {
         self.server.shutdown_gracefully().await.anyerr()?;
@@ -146,15 +264,19 @@ impl DnsServer {
 pub(crate) struct DnsHandler {
     #[debug("Catalog")]
     catalog: Arc<Catalog>,
+    transfers: Option<Arc<ZoneTransfers>>,
     metrics: Arc<Metrics>,
 }
 
 impl DnsHandler {
     /// Create a DNS server given some settings, a connection to the DB for DID-by-username lookups
     /// and the server DID to serve under `_did.<origin>`.
+    ///
+    /// The DNSSEC zone signing key is stored in `data_dir` unless configured otherwise.
     pub(crate) fn new(
         zone_store: ZoneStore,
         config: &DnsConfig,
+        data_dir: &Path,
         metrics: Arc<Metrics>,
     ) -> Result<Self> {
         let origins = config
@@ -164,25 +286,59 @@ impl DnsHandler {
             .collect::<Result<Vec<_>, _>>()
             .anyerr()?;
 
-        let (static_authority, serial) = create_static_authority(&origins, config)?;
-        let authority = Arc::new(NodeZoneHandler::new(
-            zone_store,
-            static_authority,
-            origins,
-            serial,
-        )?);
+        ensure_any!(!origins.is_empty(), "at least one origin is required");
+
+        let (static_authority, soa) = create_static_authority(&origins, config)?;
+        let static_authority = Arc::new(static_authority);
+        zone_store.raise_serial(soa.serial);
+        let signer = config
+            .dnssec
+            .as_ref()
+            .map(|dnssec| {
+                let key_file = dnssec
+                    .key_file
+                    .clone()
+                    .unwrap_or_else(|| data_dir.join("dnssec-zone.key"));
+                ZoneSigner::load_or_generate(dnssec, &key_file, &origins, soa.minimum)
+            })
+            .transpose()?
+            .map(Arc::new);
+        let zones: Vec<_> = origins
+            .into_iter()
+            .map(|origin| {
+                Arc::new(NodeZoneHandler::new(
+                    zone_store.clone(),
+                    static_authority.clone(),
+                    origin,
+                    soa.clone(),
+                    signer.clone(),
+                ))
+            })
+            .collect();
 
         let mut catalog = Catalog::new();
-        for origin in authority.origins() {
-            catalog.upsert(LowerName::from(origin), vec![authority.clone()]);
+        for zone in &zones {
+            catalog.upsert(zone.origin_name().into(), vec![zone.clone()]);
         }
+        let transfers = config
+            .transfer
+            .as_ref()
+            .map(|transfer| ZoneTransfers::new(transfer, zones, &zone_store, metrics.clone()))
+            .transpose()?
+            .map(Arc::new);
 
         Ok(Self {
             catalog: Arc::new(catalog),
+            transfers,
             metrics,
         })
     }
 
+    /// Spawns the task that notifies secondaries of zone changes, if configured.
+    fn spawn_notify(&self) -> Option<AbortOnDropHandle<()>> {
+        self.transfers.as_ref()?.spawn_notify()
+    }
+
     /// Handle a DNS request
     pub(crate) async fn answer_request(&self, request: Request) -> Result<Bytes> {
         let (tx, mut rx) = broadcast::channel(1);
@@ -208,10 +364,24 @@ impl RequestHandler for DnsHandler {
             Protocol::Https => {
                 self.metrics.dns_requests_https.inc();
             }
+            Protocol::Tls => {
+                self.metrics.dns_requests_tls.inc();
+            }
+            Protocol::Quic => {
+                self.metrics.dns_requests_quic.inc();
+            }
             _ => {}
         }
         debug!(protocol=%request.protocol(), queries=?request.queries, "incoming DNS request");
 
+        if let Some(transfers) = &self.transfers
+            && ZoneTransfers::is_transfer(request)
+        {
+            return transfers
+                .handle(request, response_handle, T::current_time())
+                .await;
+        }
+
         let res = self
             .catalog
             .handle_request::<_, T>(request, response_handle)
@@ -258,7 +428,7 @@ impl ResponseHandler for Handle {
 fn create_static_authority(
     origins: &[Name],
     config: &DnsConfig,
-) -> Result<(InMemoryZoneHandler, u32)> {
+) -> Result<(InMemoryZoneHandler, rdata::SOA)> {
     let soa = match RData::try_from_str(RecordType::SOA, &config.default_soa).anyerr()? {
         RData::SOA(soa) => soa,
         _ => return Err(anyerr!("Couldn't parse SOA: {}", config.default_soa)),
@@ -266,6 +436,7 @@ fn create_static_authority(
     let serial = soa.serial;
     let mut records = BTreeMap::new();
     for name in origins {
+        // The `NodeZoneHandler` serves the SOA records with the serial of the zone store.
         push_record(
             &mut records,
             serial,
@@ -295,11 +466,16 @@ fn create_static_authority(
         }
     }
 
-    let static_authority =
-        InMemoryZoneHandler::new(Name::root(), records, ZoneType::Primary, AxfrPolicy::Deny)
-            .map_err(|e| anyerr!("new authority: {e}"))?;
+    let static_authority = InMemoryZoneHandler::new(
+        Name::root(),
+        records,
+        ZoneType::Primary,
+        AxfrPolicy::Deny,
+        None,
+    )
+    .map_err(|e| anyerr!("new authority: {e}"))?;
 
-    Ok((static_authority, serial))
+    Ok((static_authority, soa))
 }
 
 fn push_record(records: &mut BTreeMap<RrKey, RecordSet>, serial: u32, record: Record) {
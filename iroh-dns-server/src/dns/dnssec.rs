@@ -0,0 +1,335 @@
+//! Online DNSSEC signing of the served zones.
+//!
+//! All origins are signed with a single combined signing key, which is published as a
+//! `DNSKEY` record at each origin apex. Records are signed when a query is answered for
+//! a client that sets the DNSSEC OK bit, because pkarr zones change at any time and cannot
+//! be signed ahead of time.
+//!
+//! Missing names and record types are proven with compact denial of existence
+//! ([RFC 9824]): instead of an NSEC chain, which would require enumerating all published
+//! zones, the server answers NODATA with an `NSEC` record at the query name whose next name
+//! is the immediate successor `\000.<qname>`.
+//!
+//! [RFC 9824]: https://www.rfc-editor.org/rfc/rfc9824.html
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use hickory_server::proto::{
+    ProtoError,
+    dnssec::{
+        Algorithm, DigestType, DnssecSigner,
+        crypto::{EcdsaSigningKey, Ed25519SigningKey, signing_key_from_der},
+        rdata::{DNSKEY, DNSSECRData, DS, NSEC, RRSIG},
+    },
+    rr::{DNSClass, Name, RData, Record, RecordSet, RecordType},
+};
+use n0_error::{Result, StdResultExt};
+use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+/// TTL of the `DNSKEY` records at the origin apex.
+const DNSKEY_TTL: u32 = 60 * 60; // 1h
+
+/// Offset of the signature inception into the past, to allow for clock skew of validators.
+const INCEPTION_OFFSET: time::Duration = time::Duration::hours(1);
+
+/// The NXNAME meta type from RFC 9824, marking a name that does not exist.
+const NXNAME: RecordType = RecordType::Unknown(128);
+
+/// Configuration for DNSSEC signing of the served zones.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct DnssecConfig {
+    /// Path to the zone signing key, a PKCS#8 DER encoded private key.
+    ///
+    /// A new key is generated if the file does not exist. Defaults to `dnssec-zone.key`
+    /// in the data directory.
+    pub key_file: Option<PathBuf>,
+    /// Signature algorithm of the zone signing key.
+    #[serde(default)]
+    pub algorithm: DnssecAlgorithm,
+    /// Validity period of created signatures.
+    ///
+    /// Defaults to seven days.
+    #[serde(default = "default_signature_validity", with = "humantime_serde")]
+    pub signature_validity: Duration,
+}
+
+impl Default for DnssecConfig {
+    fn default() -> Self {
+        Self {
+            key_file: None,
+            algorithm: DnssecAlgorithm::default(),
+            signature_validity: default_signature_validity(),
+        }
+    }
+}
+
+fn default_signature_validity() -> Duration {
+    Duration::from_secs(60 * 60 * 24 * 7)
+}
+
+/// Signature algorithm of the DNSSEC zone signing key.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum DnssecAlgorithm {
+    /// Ed25519 (algorithm 15).
+    #[default]
+    Ed25519,
+    /// ECDSA with curve P-256 and SHA-256 (algorithm 13).
+    ///
+    /// Use this if validators of your zones do not yet support Ed25519.
+    EcdsaP256Sha256,
+}
+
+impl DnssecAlgorithm {
+    fn to_hickory(self) -> Algorithm {
+        match self {
+            DnssecAlgorithm::Ed25519 => Algorithm::ED25519,
+            DnssecAlgorithm::EcdsaP256Sha256 => Algorithm::ECDSAP256SHA256,
+        }
+    }
+
+    fn generate_key(self) -> Result<PrivatePkcs8KeyDer<'static>> {
+        let key = match self {
+            DnssecAlgorithm::Ed25519 => Ed25519SigningKey::generate_pkcs8(),
+            DnssecAlgorithm::EcdsaP256Sha256 => {
+                EcdsaSigningKey::generate_pkcs8(Algorithm::ECDSAP256SHA256)
+            }
+        };
+        key.std_context("failed to generate DNSSEC zone signing key")
+    }
+}
+
+/// Signs the records served for the configured origins.
+#[derive(derive_more::Debug)]
+pub(super) struct ZoneSigner {
+    /// One signer per origin, ordered from the most specific origin.
+    #[debug(skip)]
+    signers: Vec<DnssecSigner>,
+    /// TTL of the records that prove the absence of a name or type.
+    negative_ttl: u32,
+}
+
+impl ZoneSigner {
+    /// Loads the zone signing key from `key_file`, or generates it if the file does not exist.
+    ///
+    /// Logs the `DS` record that has to be added to the parent zone of each origin.
+    pub(super) fn load_or_generate(
+        config: &DnssecConfig,
+        key_file: &Path,
+        origins: &[Name],
+        negative_ttl: u32,
+    ) -> Result<Self> {
+        let algorithm = config.algorithm.to_hickory();
+        let key_der = if key_file.exists() {
+            std::fs::read(key_file)
+                .std_context("failed to read DNSSEC zone signing key")?
+                .into()
+        } else {
+            let key = config.algorithm.generate_key()?;
+            if let Some(parent) = key_file.parent() {
+                std::fs::create_dir_all(parent)
+                    .std_context("failed to create DNSSEC key directory")?;
+            }
+            write_key_file(key_file, key.secret_pkcs8_der())
+                .std_context("failed to write DNSSEC zone signing key")?;
+            info!(
+                "generated DNSSEC zone signing key at {}",
+                key_file.display()
+            );
+            key
+        };
+        let key_der = PrivateKeyDer::Pkcs8(key_der);
+
+        let mut origins = origins.to_vec();
+        origins.sort_by_key(|origin| std::cmp::Reverse(origin.num_labels()));
+        let mut signers = Vec::with_capacity(origins.len());
+        for origin in origins {
+            let key = signing_key_from_der(&key_der, algorithm)
+                .std_context("invalid DNSSEC zone signing key")?;
+            let public_key = key
+                .to_public_key()
+                .std_context("invalid DNSSEC zone signing key")?;
+            let ds = DS::from_key(&public_key, &origin, DigestType::SHA256)
+                .std_context("failed to create DS record")?;
+            info!("DNSSEC enabled for {origin}, DS record: {origin} IN DS {ds}");
+            let dnskey = DNSKEY::from_key(&public_key);
+            signers.push(DnssecSigner::new(
+                dnskey,
+                key,
+                origin,
+                config.signature_validity,
+            ));
+        }
+        Ok(Self {
+            signers,
+            negative_ttl,
+        })
+    }
+
+    fn signer(&self, name: &Name) -> Option<&DnssecSigner> {
+        self.signers
+            .iter()
+            .find(|signer| signer.signer_name().zone_of(name))
+    }
+
+    /// Replaces the signatures of `rrset` with a fresh signature.
+    ///
+    /// Records outside of the configured origins are left unsigned.
+    pub(super) fn sign(&self, rrset: &mut RecordSet) -> Result<(), ProtoError> {
+        let Some(signer) = self.signer(rrset.name()) else {
+            return Ok(());
+        };
+        let inception = time::OffsetDateTime::now_utc() - INCEPTION_OFFSET;
+        let rrsig = RRSIG::from_rrset(rrset, DNSClass::IN, inception, signer)?;
+        rrset.clear_rrsigs();
+        rrset.insert_rrsig(Record::from_rdata(
+            rrset.name().clone(),
+            rrset.ttl(),
+            RData::DNSSEC(DNSSECRData::RRSIG(rrsig)),
+        ));
+        Ok(())
+    }
+
+    /// Returns the `DNSKEY` record set if `name` is the apex of an origin.
+    pub(super) fn dnskey(&self, name: &Name) -> Option<RecordSet> {
+        let signer = self
+            .signers
+            .iter()
+            .find(|signer| signer.signer_name() == name)?;
+        let record = Record::from_rdata(
+            name.clone(),
+            DNSKEY_TTL,
+            RData::DNSSEC(DNSSECRData::DNSKEY(signer.dnskey().clone())),
+        );
+        Some(record.into())
+    }
+
+    /// Returns the signed `NSEC` record set that denies all types at `name` except `types`.
+    ///
+    /// If `types` is empty, the record denies the existence of `name`. The type bitmap always
+    /// contains `NSEC` and `RRSIG`, which exist at `name` with the denial itself.
+    pub(super) fn nsec(&self, name: &Name, types: &[RecordType]) -> Result<RecordSet, ProtoError> {
+        let next = name.prepend_label(&[0u8][..])?;
+        let types = match types {
+            [] => vec![NXNAME],
+            types => types.to_vec(),
+        };
+        let record = Record::from_rdata(
+            name.clone(),
+            self.negative_ttl,
+            RData::DNSSEC(DNSSECRData::NSEC(NSEC::new(
+                next,
+                types
+                    .into_iter()
+                    .chain([RecordType::NSEC, RecordType::RRSIG]),
+            ))),
+        );
+        let mut rrset = RecordSet::from(record);
+        self.sign(&mut rrset)?;
+        Ok(rrset)
+    }
+}
+
+/// Writes a new secret key file, which only the owner can read on unix.
+fn write_key_file(path: &Path, key: &[u8]) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use n0_error::Result;
+
+    use super::*;
+
+    #[test]
+    fn load_or_generate_key() -> Result {
+        let dir = tempfile::tempdir()?;
+        let key_file = dir.path().join("keys/zone.key");
+        let origins = [
+            Name::from_utf8("dns.example.").anyerr()?,
+            Name::from_utf8("example.").anyerr()?,
+        ];
+        let config = DnssecConfig::default();
+
+        let signer = ZoneSigner::load_or_generate(&config, &key_file, &origins, 30)?;
+        assert!(key_file.exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&key_file)?.permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let dnskey = signer.dnskey(&origins[0]).expect("origin apex");
+
+        // the key is loaded from disk on restart
+        let signer = ZoneSigner::load_or_generate(&config, &key_file, &origins, 30)?;
+        assert_eq!(signer.dnskey(&origins[0]), Some(dnskey));
+        assert!(
+            signer
+                .dnskey(&Name::from_utf8("a.example.").anyerr()?)
+                .is_none()
+        );
+
+        // the most specific origin signs
+        let name = Name::from_utf8("a.dns.example.").anyerr()?;
+        let mut rrset = signer.nsec(&name, &[]).anyerr()?;
+        let rrsig = match &rrset.rrsigs()[0].data {
+            RData::DNSSEC(DNSSECRData::RRSIG(rrsig)) => rrsig.clone(),
+            _ => panic!("expected RRSIG"),
+        };
+        assert_eq!(rrsig.input().signer_name, origins[0]);
+        assert_eq!(rrsig.input().type_covered, RecordType::NSEC);
+
+        // signing replaces the previous signature
+        signer.sign(&mut rrset).anyerr()?;
+        assert_eq!(rrset.rrsigs().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn nsec_type_bitmap() -> Result {
+        let dir = tempfile::tempdir()?;
+        let origin = Name::from_utf8("example.").anyerr()?;
+        let config = DnssecConfig::default();
+        let signer =
+            ZoneSigner::load_or_generate(&config, &dir.path().join("zone.key"), &[origin], 30)?;
+        let name = Name::from_utf8("a.example.").anyerr()?;
+        let types = |rrset: RecordSet| {
+            let record = rrset.records_without_rrsigs().next().expect("NSEC record");
+            match &record.data {
+                RData::DNSSEC(DNSSECRData::NSEC(nsec)) => nsec.type_bit_maps().collect::<Vec<_>>(),
+                _ => panic!("expected NSEC"),
+            }
+        };
+
+        // NODATA: the existing types plus the denial itself
+        let nodata = types(signer.nsec(&name, &[RecordType::TXT]).anyerr()?);
+        for typ in [RecordType::TXT, RecordType::NSEC, RecordType::RRSIG] {
+            assert!(nodata.contains(&typ), "{typ} missing in {nodata:?}");
+        }
+        assert!(!nodata.contains(&NXNAME));
+
+        // NXDOMAIN: NXNAME plus the denial itself, see RFC 9824
+        let nxname = types(signer.nsec(&name, &[]).anyerr()?);
+        assert_eq!(nxname.len(), 3, "{nxname:?}");
+        for typ in [NXNAME, RecordType::NSEC, RecordType::RRSIG] {
+            assert!(nxname.contains(&typ), "{typ} missing in {nxname:?}");
+        }
+        Ok(())
+    }
+}
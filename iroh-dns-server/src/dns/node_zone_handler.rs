@@ -2,73 +2,122 @@ use std::{fmt, sync::Arc};
 
 use async_trait::async_trait;
 use hickory_server::{
+    dnssec::NxProofKind,
     proto::{
+        ProtoError,
+        dnssec::Nsec3HashAlgorithm,
         op::ResponseCode,
-        rr::{LowerName, Name, RecordType, TSigResponseContext},
+        rr::{LowerName, Name, RData, Record, RecordSet, RecordType, TSigResponseContext, rdata},
     },
     server::{Request, RequestInfo},
     store::in_memory::InMemoryZoneHandler,
     zone_handler::{
         AuthLookup, AxfrPolicy, LookupControlFlow, LookupError, LookupOptions, LookupRecords,
-        ZoneHandler, ZoneType,
+        Nsec3QueryInfo, ZoneHandler, ZoneType,
     },
 };
+use iroh_dns::pkarr::SignedPacket;
 use n0_error::{Result, StackResultExt, StdResultExt, bail_any};
 use tracing::{debug, trace};
 
+use super::{DEFAULT_SOA_TTL, dnssec::ZoneSigner};
 use crate::{
     store::ZoneStore,
-    util::{PublicKeyBytes, record_set_append_origin},
+    util::{
+        PublicKeyBytes, record_set_append_origin, signed_packet_to_hickory_records_without_origin,
+    },
 };
 
+/// Serves the pkarr zones published under a single origin.
 #[derive(derive_more::Debug)]
 pub(super) struct NodeZoneHandler {
-    serial: u32,
-    origins: Vec<Name>,
+    origin: Name,
+    lower_origin: LowerName,
+    /// SOA record data of the zone, the serial is taken from the [`ZoneStore`].
+    soa: rdata::SOA,
     #[debug("InMemoryZoneHandler")]
-    static_zone_handler: InMemoryZoneHandler,
+    static_zone_handler: Arc<InMemoryZoneHandler>,
     zones: ZoneStore,
-    // TODO: This is used by ZoneHandler::origin
-    // Find out what exactly this is used for - we don't have a primary origin.
-    first_origin: LowerName,
+    signer: Option<Arc<ZoneSigner>>,
+    /// Always NSEC3, see [`Self::nsec3_records`].
+    nx_proof_kind: NxProofKind,
 }
 
 impl NodeZoneHandler {
     pub(super) fn new(
         zones: ZoneStore,
-        static_zone_handler: InMemoryZoneHandler,
-        origins: Vec<Name>,
-        serial: u32,
-    ) -> Result<Self> {
-        if origins.is_empty() {
-            bail_any!("at least one origin is required");
-        }
-        let first_origin = LowerName::from(&origins[0]);
-        Ok(Self {
+        static_zone_handler: Arc<InMemoryZoneHandler>,
+        origin: Name,
+        soa: rdata::SOA,
+        signer: Option<Arc<ZoneSigner>>,
+    ) -> Self {
+        Self {
+            lower_origin: LowerName::from(&origin),
+            origin,
+            soa,
             static_zone_handler,
-            origins,
-            serial,
             zones,
-            first_origin,
-        })
+            signer,
+            nx_proof_kind: NxProofKind::Nsec3 {
+                algorithm: Nsec3HashAlgorithm::SHA1,
+                salt: Arc::from([]),
+                iterations: 0,
+                opt_out: false,
+            },
+        }
+    }
+
+    pub(super) fn origin_name(&self) -> &Name {
+        &self.origin
     }
 
-    pub(super) fn origins(&self) -> impl Iterator<Item = &Name> {
-        self.origins.iter()
+    pub(super) fn zones(&self) -> &ZoneStore {
+        &self.zones
     }
 
+    /// Returns the current serial of the zone.
     pub(super) fn serial(&self) -> u32 {
-        self.serial
+        self.zones.serial()
+    }
+
+    /// Returns the SOA record of the zone with the current serial.
+    pub(super) fn soa(&self) -> Record {
+        let mut soa = self.soa.clone();
+        soa.serial = self.serial();
+        Record::from_rdata(self.origin.clone(), DEFAULT_SOA_TTL, RData::SOA(soa))
+    }
+
+    /// Returns the records at the zone apex, except for the SOA record.
+    pub(super) async fn apex_records(&self) -> Vec<Record> {
+        self.static_zone_handler
+            .records()
+            .await
+            .values()
+            .filter(|rrset| rrset.name() == &self.origin && rrset.record_type() != RecordType::SOA)
+            .flat_map(|rrset| rrset.records_without_rrsigs().cloned())
+            .collect()
+    }
+
+    /// Returns the records that a stored packet publishes in the zone.
+    pub(super) fn packet_records(&self, packet: &SignedPacket) -> Result<Vec<Record>, ProtoError> {
+        let (label, rrsets) = signed_packet_to_hickory_records_without_origin(packet, |_| true)?;
+        let origin = Name::from_labels([label])?.append_name(&self.origin)?;
+        let mut records = Vec::new();
+        for rrset in rrsets.values() {
+            let rrset = record_set_append_origin(rrset, &origin, self.serial())?;
+            records.extend(rrset.records_without_rrsigs().cloned());
+        }
+        Ok(records)
     }
 
     async fn resolve_pkarr(
         &self,
         name: Name,
         pubkey: PublicKeyBytes,
-        origin: Name,
         record_type: RecordType,
         lookup_options: LookupOptions,
     ) -> Result<AuthLookup, LookupError> {
+        let origin = &self.origin;
         debug!(%origin, %pubkey, %name, "resolve in pkarr zones");
         match self
             .zones
@@ -79,7 +128,7 @@ impl NodeZoneHandler {
             Some(pkarr_set) => {
                 debug!(%origin, %pubkey, %name, "found {} records in pkarr zone", pkarr_set.records_without_rrsigs().count());
                 let new_origin =
-                    Name::parse(&pubkey.to_z32(), Some(&origin)).map_err(err_refused)?;
+                    Name::parse(&pubkey.to_z32(), Some(origin)).map_err(err_refused)?;
                 let record_set = record_set_append_origin(&pkarr_set, &new_origin, self.serial())
                     .map_err(err_refused)?;
                 let records = LookupRecords::new(lookup_options, Arc::new(record_set));
@@ -89,23 +138,8 @@ impl NodeZoneHandler {
             None => Err(err_nx_domain("not found")),
         }
     }
-}
-
-#[async_trait]
-impl ZoneHandler for NodeZoneHandler {
-    fn zone_type(&self) -> ZoneType {
-        ZoneType::Primary
-    }
 
-    fn axfr_policy(&self) -> AxfrPolicy {
-        AxfrPolicy::Deny
-    }
-
-    fn origin(&self) -> &LowerName {
-        &self.first_origin
-    }
-
-    async fn lookup(
+    async fn lookup_unsigned(
         &self,
         name: &LowerName,
         record_type: RecordType,
@@ -114,15 +148,25 @@ impl ZoneHandler for NodeZoneHandler {
     ) -> LookupControlFlow<AuthLookup> {
         debug!(name=%name, "lookup in node authority");
         match record_type {
+            RecordType::DNSKEY
+                if let Some(dnskey) = self.signer.as_ref().and_then(|s| s.dnskey(&name.into())) =>
+            {
+                let records = LookupRecords::new(lookup_options, Arc::new(dnskey));
+                LookupControlFlow::Continue(Ok(AuthLookup::answers(records, None)))
+            }
+            RecordType::SOA if name == &self.lower_origin => {
+                let records = LookupRecords::new(lookup_options, Arc::new(self.soa().into()));
+                LookupControlFlow::Continue(Ok(AuthLookup::answers(records, None)))
+            }
             RecordType::SOA | RecordType::NS => {
                 self.static_zone_handler
                     .lookup(name, record_type, request_info, lookup_options)
                     .await
             }
-            _ => match parse_name_as_pkarr_with_origin(name, &self.origins) {
-                Ok((name, pubkey, origin)) => {
+            _ => match parse_name_as_pkarr(name, &self.origin) {
+                Ok((name, pubkey)) => {
                     let res = self
-                        .resolve_pkarr(name, pubkey, origin, record_type, lookup_options)
+                        .resolve_pkarr(name, pubkey, record_type, lookup_options)
                         .await;
                     LookupControlFlow::Continue(res)
                 }
@@ -136,6 +180,90 @@ impl ZoneHandler for NodeZoneHandler {
         }
     }
 
+    /// Signs all records of a successful lookup if DNSSEC is enabled and requested.
+    ///
+    /// Names that do not exist are turned into NODATA answers, which are then proven by
+    /// [`Self::nsec3_records`].
+    fn sign(
+        &self,
+        res: LookupControlFlow<AuthLookup>,
+        lookup_options: LookupOptions,
+    ) -> LookupControlFlow<AuthLookup> {
+        let Some(signer) = self.signer.as_ref().filter(|_| lookup_options.dnssec_ok) else {
+            return res;
+        };
+        let sign = |res: Result<AuthLookup, LookupError>| match res {
+            Ok(AuthLookup::Records {
+                answers,
+                additionals,
+            }) => {
+                let answers = sign_records(signer, &answers, lookup_options)?;
+                let additionals = additionals
+                    .map(|records| sign_records(signer, &records, lookup_options))
+                    .transpose()?;
+                Ok(AuthLookup::answers(answers, additionals))
+            }
+            Err(err) if err.is_nx_domain() => Err(LookupError::NameExists),
+            res => res,
+        };
+        match res {
+            LookupControlFlow::Continue(res) => LookupControlFlow::Continue(sign(res)),
+            LookupControlFlow::Break(res) => LookupControlFlow::Break(sign(res)),
+            LookupControlFlow::Skip => LookupControlFlow::Skip,
+        }
+    }
+
+    /// Returns the record types that exist at `name`.
+    async fn record_types(&self, name: &LowerName) -> Result<Vec<RecordType>, LookupError> {
+        if let Ok((name, pubkey)) = parse_name_as_pkarr(name, &self.origin) {
+            return self
+                .zones
+                .record_types(&pubkey, &name)
+                .await
+                .map_err(err_refused);
+        }
+        let mut types: Vec<_> = self
+            .static_zone_handler
+            .records()
+            .await
+            .keys()
+            .filter(|key| &key.name == name)
+            .map(|key| key.record_type)
+            .collect();
+        if let Some(dnskey) = self.signer.as_ref().and_then(|s| s.dnskey(&name.into())) {
+            types.push(dnskey.record_type());
+        }
+        Ok(types)
+    }
+}
+
+#[async_trait]
+impl ZoneHandler for NodeZoneHandler {
+    fn zone_type(&self) -> ZoneType {
+        ZoneType::Primary
+    }
+
+    fn axfr_policy(&self) -> AxfrPolicy {
+        AxfrPolicy::Deny
+    }
+
+    fn origin(&self) -> &LowerName {
+        &self.lower_origin
+    }
+
+    async fn lookup(
+        &self,
+        name: &LowerName,
+        record_type: RecordType,
+        request_info: Option<&RequestInfo<'_>>,
+        lookup_options: LookupOptions,
+    ) -> LookupControlFlow<AuthLookup> {
+        let res = self
+            .lookup_unsigned(name, record_type, request_info, lookup_options)
+            .await;
+        self.sign(res, lookup_options)
+    }
+
     async fn search(
         &self,
         request: &Request,
@@ -150,16 +278,16 @@ impl ZoneHandler for NodeZoneHandler {
         let record_type: RecordType = request_info.query.query_type();
         let result = match record_type {
             RecordType::SOA => {
-                self.static_zone_handler
-                    .lookup(
-                        self.origin(),
-                        record_type,
-                        Some(&request_info),
-                        lookup_options,
-                    )
-                    .await
+                self.lookup(
+                    self.origin(),
+                    record_type,
+                    Some(&request_info),
+                    lookup_options,
+                )
+                .await
             }
-            RecordType::AXFR => {
+            // Zone transfers are served by `ZoneTransfers` if enabled.
+            RecordType::AXFR | RecordType::IXFR => {
                 LookupControlFlow::Continue(Err(LookupError::from(ResponseCode::Refused)))
             }
             _ => {
@@ -182,33 +310,90 @@ impl ZoneHandler for NodeZoneHandler {
     ) -> LookupControlFlow<AuthLookup> {
         LookupControlFlow::Skip
     }
+
+    /// Returns the compact denial of existence for the queried name and type.
+    ///
+    /// The [`Catalog`] calls this for all answers of handlers with the NSEC3 proof kind,
+    /// including positive ones, and only calls [`Self::nsec_records`] for wildcard matches.
+    /// We therefore report NSEC3, and return the NSEC records for negative answers here.
+    ///
+    /// [`Catalog`]: hickory_server::zone_handler::Catalog
+    async fn nsec3_records(
+        &self,
+        info: Nsec3QueryInfo<'_>,
+        lookup_options: LookupOptions,
+    ) -> LookupControlFlow<AuthLookup> {
+        let Some(signer) = self.signer.as_ref().filter(|_| lookup_options.dnssec_ok) else {
+            return LookupControlFlow::Continue(Ok(AuthLookup::Empty));
+        };
+        let types = match self.record_types(info.qname).await {
+            Ok(types) => types,
+            Err(err) => return LookupControlFlow::Continue(Err(err)),
+        };
+        if types.contains(&info.qtype) || types.contains(&RecordType::CNAME) {
+            return LookupControlFlow::Continue(Ok(AuthLookup::Empty));
+        }
+        let res = signer
+            .nsec(&info.qname.into(), &types)
+            .map(|nsec| {
+                AuthLookup::answers(LookupRecords::new(lookup_options, Arc::new(nsec)), None)
+            })
+            .map_err(LookupError::from);
+        LookupControlFlow::Continue(res)
+    }
+
+    fn nx_proof_kind(&self) -> Option<&NxProofKind> {
+        Some(&self.nx_proof_kind)
+    }
 }
 
-fn parse_name_as_pkarr_with_origin(
-    name: impl Into<Name>,
-    allowed_origins: &[Name],
-) -> Result<(Name, PublicKeyBytes, Name)> {
+/// Splits a name below `origin` into the pkarr public key and the name within its zone.
+fn parse_name_as_pkarr(name: impl Into<Name>, origin: &Name) -> Result<(Name, PublicKeyBytes)> {
     let name = name.into();
-    trace!("resolve {name}");
-    for origin in allowed_origins.iter() {
-        trace!("try {origin}");
-        if !origin.zone_of(&name) {
+    trace!("resolve {name} in {origin}");
+    if !origin.zone_of(&name) {
+        bail_any!("name does not match the origin");
+    }
+    if name.num_labels() < origin.num_labels() + 1 {
+        bail_any!("not a valid pkarr name: missing pubkey");
+    }
+    let labels = name.iter().rev();
+    let mut labels_without_origin = labels.skip(origin.num_labels() as usize);
+    let pkey_label = labels_without_origin.next().expect("length checked above");
+    let pkey_str = std::str::from_utf8(pkey_label).anyerr()?;
+    let pkey =
+        PublicKeyBytes::from_z32(pkey_str).context("not a valid pkarr name: invalid pubkey")?;
+    let remaining_name = Name::from_labels(labels_without_origin.rev()).anyerr()?;
+    Ok((remaining_name, pkey))
+}
+
+/// Regroups the records of a lookup into record sets and signs them.
+fn sign_records(
+    signer: &ZoneSigner,
+    records: &LookupRecords,
+    lookup_options: LookupOptions,
+) -> Result<LookupRecords, LookupError> {
+    let mut rrsets: Vec<RecordSet> = Vec::new();
+    for record in records.iter() {
+        if record.record_type() == RecordType::RRSIG {
             continue;
         }
-        if name.num_labels() < origin.num_labels() + 1 {
-            bail_any!("not a valid pkarr name: missing pubkey");
+        match rrsets
+            .iter_mut()
+            .find(|set| set.name() == &record.name && set.record_type() == record.record_type())
+        {
+            Some(rrset) => {
+                rrset.insert(record.clone(), 0);
+            }
+            None => rrsets.push(record.clone().into()),
         }
-        trace!("parse {origin}");
-        let labels = name.iter().rev();
-        let mut labels_without_origin = labels.skip(origin.num_labels() as usize);
-        let pkey_label = labels_without_origin.next().expect("length checked above");
-        let pkey_str = std::str::from_utf8(pkey_label).anyerr()?;
-        let pkey =
-            PublicKeyBytes::from_z32(pkey_str).context("not a valid pkarr name: invalid pubkey")?;
-        let remaining_name = Name::from_labels(labels_without_origin.rev()).anyerr()?;
-        return Ok((remaining_name, pkey, origin.clone()));
-    }
-    bail_any!("name does not match any allowed origin");
+    }
+    let mut signed = Vec::with_capacity(rrsets.len());
+    for mut rrset in rrsets {
+        signer.sign(&mut rrset)?;
+        signed.push(Arc::new(rrset));
+    }
+    Ok(LookupRecords::many(lookup_options, signed))
 }
 
 fn err_refused(e: impl fmt::Debug) -> LookupError {
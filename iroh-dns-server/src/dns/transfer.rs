@@ -0,0 +1,784 @@
+//! Zone transfers to secondary name servers.
+//!
+//! Secondaries mirror the zones of all configured origins with full zone transfers (AXFR,
+//! [RFC 5936]). The server keeps no journal of changes, so incremental transfers (IXFR,
+//! [RFC 1995]) are answered with the full zone, or with just the SOA record if the secondary
+//! is up to date. Transfer requests must be signed with one of the configured TSIG keys
+//! ([RFC 8945]).
+//!
+//! The zone serial is incremented whenever a stored packet changes. Configured secondaries
+//! are sent a NOTIFY message ([RFC 1996]) after changes, so they don't have to wait for the
+//! refresh interval of the SOA record.
+//!
+//! [RFC 1995]: https://www.rfc-editor.org/rfc/rfc1995.html
+//! [RFC 1996]: https://www.rfc-editor.org/rfc/rfc1996.html
+//! [RFC 5936]: https://www.rfc-editor.org/rfc/rfc5936.html
+//! [RFC 8945]: https://www.rfc-editor.org/rfc/rfc8945.html
+
+use std::{
+    iter,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use data_encoding::BASE64;
+use hickory_server::{
+    net::xfer::Protocol,
+    proto::{
+        ProtoError,
+        op::{Header, HeaderCounts, Message, Metadata, OpCode, Query, ResponseCode},
+        rr::{
+            Name, RData, Record, RecordType, SerialNumber, TSigResponseContext, TSigner,
+            rdata::tsig::{TSIG, TsigAlgorithm, TsigError, make_tsig_record},
+        },
+        serialize::binary::{BinEncodable, BinEncoder},
+    },
+    server::{Request, ResponseHandler, ResponseInfo},
+    zone_handler::MessageResponseBuilder,
+};
+use n0_error::{Result, StdResultExt, anyerr, ensure_any};
+use n0_future::{BufferedStreamExt, StreamExt, stream, task::AbortOnDropHandle};
+use serde::{Deserialize, Serialize};
+use tokio::{net::UdpSocket, sync::watch};
+use tracing::{Instrument, debug, info_span, warn};
+
+use super::node_zone_handler::NodeZoneHandler;
+use crate::{metrics::Metrics, replication::MAX_PAGE_SIZE, store::ZoneStore, util::PublicKeyBytes};
+
+/// Size after which the records of a zone transfer are split into a new message.
+const MAX_MESSAGE_SIZE: usize = 16 * 1024;
+
+/// Allowed clock skew of TSIG signatures, in seconds.
+const TSIG_FUDGE: u16 = 300;
+
+/// Timeout for the response of a secondary to a NOTIFY message.
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Number of times a NOTIFY message is sent before giving up on a secondary.
+const NOTIFY_ATTEMPTS: usize = 3;
+
+/// Size of the buffer for responses to NOTIFY messages.
+const MAX_NOTIFY_RESPONSE_SIZE: usize = 4096;
+
+/// Number of NOTIFY messages sent concurrently.
+const NOTIFY_CONCURRENCY: usize = 16;
+
+/// Configuration for zone transfers to secondary name servers.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct ZoneTransferConfig {
+    /// TSIG keys that authorize zone transfers.
+    ///
+    /// Transfer requests must be signed with one of these keys. NOTIFY messages are signed
+    /// with the first key.
+    pub tsig_keys: Vec<TsigKeyConfig>,
+    /// Addresses of secondary name servers that are notified when the zones change.
+    #[serde(default)]
+    pub notify: Vec<SocketAddr>,
+    /// Delay between a change and sending NOTIFY messages, to batch frequent changes.
+    ///
+    /// Defaults to five seconds.
+    #[serde(default = "default_notify_delay", with = "humantime_serde")]
+    pub notify_delay: Duration,
+}
+
+impl ZoneTransferConfig {
+    /// Creates a new [`ZoneTransferConfig`] that authorizes transfers with `tsig_keys`.
+    ///
+    /// No secondaries are notified by default.
+    pub fn new(tsig_keys: Vec<TsigKeyConfig>) -> Self {
+        Self {
+            tsig_keys,
+            notify: Vec::new(),
+            notify_delay: default_notify_delay(),
+        }
+    }
+}
+
+fn default_notify_delay() -> Duration {
+    Duration::from_secs(5)
+}
+
+/// A TSIG key shared with secondary name servers.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct TsigKeyConfig {
+    /// Name of the key, which must match the name configured on the secondaries.
+    pub name: String,
+    /// The base64 encoded secret, as generated by `tsig-keygen`.
+    pub secret: String,
+    /// MAC algorithm of the key.
+    #[serde(default)]
+    pub algorithm: TsigKeyAlgorithm,
+}
+
+impl TsigKeyConfig {
+    /// Creates a new HMAC-SHA256 [`TsigKeyConfig`] from a base64 encoded secret.
+    pub fn new(name: impl Into<String>, secret: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            secret: secret.into(),
+            algorithm: TsigKeyAlgorithm::default(),
+        }
+    }
+
+    fn signer(&self) -> Result<TSigner> {
+        let name = Name::from_utf8(&self.name).std_context("invalid TSIG key name")?;
+        let secret = BASE64
+            .decode(self.secret.trim().as_bytes())
+            .std_context("invalid TSIG key secret")?;
+        TSigner::new(secret, self.algorithm.to_hickory(), name, TSIG_FUDGE)
+            .std_context("invalid TSIG key")
+    }
+}
+
+/// MAC algorithm of a TSIG key.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum TsigKeyAlgorithm {
+    /// HMAC-SHA256.
+    #[default]
+    HmacSha256,
+    /// HMAC-SHA384.
+    HmacSha384,
+    /// HMAC-SHA512.
+    HmacSha512,
+}
+
+impl TsigKeyAlgorithm {
+    fn to_hickory(self) -> TsigAlgorithm {
+        match self {
+            TsigKeyAlgorithm::HmacSha256 => TsigAlgorithm::HmacSha256,
+            TsigKeyAlgorithm::HmacSha384 => TsigAlgorithm::HmacSha384,
+            TsigKeyAlgorithm::HmacSha512 => TsigAlgorithm::HmacSha512,
+        }
+    }
+}
+
+/// Serves zone transfers and notifies secondaries of changes.
+#[derive(derive_more::Debug)]
+pub(super) struct ZoneTransfers {
+    zones: Vec<Arc<NodeZoneHandler>>,
+    #[debug(skip)]
+    tsig_keys: Vec<TSigner>,
+    notify: Vec<SocketAddr>,
+    notify_delay: Duration,
+    serial: watch::Receiver<u32>,
+    metrics: Arc<Metrics>,
+}
+
+impl ZoneTransfers {
+    pub(super) fn new(
+        config: &ZoneTransferConfig,
+        zones: Vec<Arc<NodeZoneHandler>>,
+        store: &ZoneStore,
+        metrics: Arc<Metrics>,
+    ) -> Result<Self> {
+        ensure_any!(
+            !config.tsig_keys.is_empty(),
+            "zone transfers require at least one TSIG key"
+        );
+        let tsig_keys = config
+            .tsig_keys
+            .iter()
+            .map(TsigKeyConfig::signer)
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            zones,
+            tsig_keys,
+            notify: config.notify.clone(),
+            notify_delay: config.notify_delay,
+            serial: store.subscribe_serial(),
+            metrics,
+        })
+    }
+
+    /// Returns whether `request` asks for a zone transfer.
+    pub(super) fn is_transfer(request: &Request) -> bool {
+        request.metadata.op_code == OpCode::Query
+            && request
+                .queries
+                .queries()
+                .iter()
+                .any(|query| matches!(query.query_type(), RecordType::AXFR | RecordType::IXFR))
+    }
+
+    /// Answers a zone transfer request.
+    pub(super) async fn handle<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        response_handle: R,
+        now: u64,
+    ) -> ResponseInfo {
+        let query = match request.request_info() {
+            Ok(info) => info.query,
+            Err(_) => {
+                return send_error(request, ResponseCode::FormErr, None, response_handle).await;
+            }
+        };
+        let Some(zone) = self
+            .zones
+            .iter()
+            .find(|zone| zone.origin_name() == &Name::from(query.name()))
+        else {
+            return send_error(request, ResponseCode::NotAuth, None, response_handle).await;
+        };
+        // Zone transfers are streamed in multiple messages, which requires a stream transport.
+        let streaming = matches!(request.protocol(), Protocol::Tcp | Protocol::Tls);
+        if query.query_type() == RecordType::AXFR && !streaming {
+            return send_error(request, ResponseCode::Refused, None, response_handle).await;
+        }
+        let signer = match self.authorize(request, now) {
+            Ok(signer) => signer,
+            Err((code, tsig)) => return send_error(request, code, tsig, response_handle).await,
+        };
+
+        let mut sender = TransferSender {
+            request,
+            response_handle,
+            signer,
+            records: Vec::new(),
+            size: 0,
+            info: None,
+        };
+        let soa = zone.soa();
+        let up_to_date = ixfr_serial(request)
+            .is_some_and(|serial| SerialNumber::new(serial) >= SerialNumber::new(zone.serial()));
+        let res = if query.query_type() == RecordType::IXFR && (up_to_date || !streaming) {
+            // RFC 1995, Section 2: answer with just the current SOA record if the secondary
+            // is up to date, or if the transfer does not fit into a UDP response, which makes
+            // the secondary retry over TCP.
+            sender.push(soa).await
+        } else {
+            debug!(origin = %zone.origin_name(), serial = zone.serial(), "zone transfer");
+            self.metrics.dns_zone_transfers.inc();
+            transfer_zone(zone, soa, &mut sender).await
+        };
+        match res.and(sender.flush().await) {
+            Ok(()) => sender.info.expect("at least one message sent"),
+            Err(err) => {
+                warn!(origin = %zone.origin_name(), "zone transfer failed: {err:#}");
+                serve_failed(request)
+            }
+        }
+    }
+
+    /// Verifies the TSIG signature of a request.
+    ///
+    /// Returns the signer for the response, or the error to respond with.
+    fn authorize(
+        &self,
+        request: &Request,
+        now: u64,
+    ) -> Result<ResponseSigner, (ResponseCode, Option<TSigResponseContext>)> {
+        let request_id = request.metadata.id;
+        let Some(tsig) = request.signature.as_deref() else {
+            debug!(src = %request.src(), "refused unsigned zone transfer");
+            return Err((ResponseCode::Refused, None));
+        };
+        let Some(signer) = self
+            .tsig_keys
+            .iter()
+            .find(|signer| signer.signer_name() == &tsig.name)
+        else {
+            warn!(src = %request.src(), key = %tsig.name, "zone transfer with unknown TSIG key");
+            return Err((
+                ResponseCode::NotAuth,
+                Some(TSigResponseContext::unknown_key(
+                    request_id,
+                    now,
+                    tsig.name.clone(),
+                )),
+            ));
+        };
+        let Ok((_, _, range)) = signer.verify_message_byte(request.as_slice(), None, true) else {
+            warn!(src = %request.src(), "zone transfer with invalid TSIG signature");
+            return Err((
+                ResponseCode::NotAuth,
+                Some(TSigResponseContext::bad_signature(
+                    request_id,
+                    now,
+                    signer.clone(),
+                )),
+            ));
+        };
+        if !range.contains(&now) {
+            warn!(src = %request.src(), "zone transfer with expired TSIG signature");
+            return Err((
+                ResponseCode::NotAuth,
+                Some(TSigResponseContext::new(
+                    request_id,
+                    now,
+                    signer.clone(),
+                    tsig.data.mac.clone(),
+                    Some(TsigError::BadTime),
+                )),
+            ));
+        }
+        Ok(ResponseSigner {
+            signer: signer.clone(),
+            request_id,
+            time: now,
+            previous_mac: tsig.data.mac.clone(),
+            first: true,
+        })
+    }
+
+    /// Spawns a task that notifies the configured secondaries whenever the serial changes.
+    ///
+    /// The secondaries are also notified on startup. Returns `None` if no secondaries are
+    /// configured.
+    pub(super) fn spawn_notify(self: &Arc<Self>) -> Option<AbortOnDropHandle<()>> {
+        if self.notify.is_empty() {
+            return None;
+        }
+        let this = self.clone();
+        let mut serial = self.serial.clone();
+        serial.mark_changed();
+        let task = async move {
+            while serial.changed().await.is_ok() {
+                tokio::time::sleep(this.notify_delay).await;
+                // Changes during the delay are covered by this round of notifies.
+                serial.mark_unchanged();
+                this.notify_all().await;
+            }
+        };
+        let task = tokio::spawn(task.instrument(info_span!("notify")));
+        Some(AbortOnDropHandle::new(task))
+    }
+
+    async fn notify_all(&self) {
+        let mut notifies = Vec::with_capacity(self.notify.len() * self.zones.len());
+        for addr in &self.notify {
+            notifies.extend(self.zones.iter().map(|zone| (*addr, zone.clone())));
+        }
+        stream::iter(notifies)
+            .map(|(addr, zone)| async move {
+                match self.notify(addr, &zone).await {
+                    Ok(()) => {
+                        debug!(%addr, origin = %zone.origin_name(), "sent NOTIFY");
+                        self.metrics.dns_notify_success.inc();
+                    }
+                    Err(err) => {
+                        warn!(%addr, origin = %zone.origin_name(), "failed to send NOTIFY: {err:#}");
+                        self.metrics.dns_notify_error.inc();
+                    }
+                }
+            })
+            .buffered_unordered(NOTIFY_CONCURRENCY)
+            .for_each(|_| {})
+            .await;
+    }
+
+    /// Sends a NOTIFY for `zone` to a secondary and waits for its response.
+    ///
+    /// The message is retransmitted if the secondary does not respond in time.
+    async fn notify(&self, addr: SocketAddr, zone: &NodeZoneHandler) -> Result<()> {
+        let mut message = Message::query();
+        message.metadata.op_code = OpCode::Notify;
+        message.metadata.authoritative = true;
+        message.add_query(Query::query(zone.origin_name().clone(), RecordType::SOA));
+        message.add_answer(zone.soa());
+        let signer = self.tsig_keys.first().expect("checked in new");
+        let mut verifier = message
+            .finalize(signer, unix_time())
+            .anyerr()?
+            .expect("TSIG signers return a verifier");
+        let message = message.to_vec().anyerr()?;
+
+        let bind_addr = match addr {
+            SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+        };
+        let socket = UdpSocket::bind(bind_addr).await.anyerr()?;
+        socket.connect(addr).await.anyerr()?;
+        let mut buf = vec![0u8; MAX_NOTIFY_RESPONSE_SIZE];
+        for _ in 0..NOTIFY_ATTEMPTS {
+            socket.send(&message).await.anyerr()?;
+            let Ok(len) = tokio::time::timeout(NOTIFY_TIMEOUT, socket.recv(&mut buf)).await else {
+                continue;
+            };
+            let response = verifier.verify(&buf[..len.anyerr()?]).anyerr()?;
+            return match response.response_code {
+                // RFC 1996, Section 3.12: NOTIMP completes the NOTIFY transaction.
+                ResponseCode::NoError | ResponseCode::NotImp => Ok(()),
+                code => Err(anyerr!("secondary responded with {code}")),
+            };
+        }
+        Err(anyerr!("no response from secondary"))
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+/// Sends all records of `zone`, framed by its SOA record.
+async fn transfer_zone<R: ResponseHandler>(
+    zone: &NodeZoneHandler,
+    soa: Record,
+    sender: &mut TransferSender<'_, R>,
+) -> Result<()> {
+    sender.push(soa.clone()).await?;
+    for record in zone.apex_records().await {
+        sender.push(record).await?;
+    }
+    let mut after: Option<PublicKeyBytes> = None;
+    loop {
+        let packets = zone.zones().packets(after, MAX_PAGE_SIZE).await?;
+        let Some(last) = packets.last() else {
+            break;
+        };
+        after = Some(PublicKeyBytes::from_signed_packet(last));
+        for packet in packets {
+            let pubkey = PublicKeyBytes::from_signed_packet(&packet);
+            if zone.zones().is_blocked(&pubkey) {
+                continue;
+            }
+            match zone.packet_records(&packet) {
+                Ok(records) => {
+                    for record in records {
+                        sender.push(record).await?;
+                    }
+                }
+                Err(err) => debug!(%pubkey, "skip invalid packet in zone transfer: {err:#}"),
+            }
+        }
+    }
+    sender.push(soa).await
+}
+
+/// Returns the serial of the SOA record that a secondary sends with an IXFR request.
+fn ixfr_serial(request: &Request) -> Option<u32> {
+    request
+        .authorities
+        .iter()
+        .find_map(|record| match &record.data {
+            RData::SOA(soa) => Some(soa.serial),
+            _ => None,
+        })
+}
+
+/// Splits the records of a zone transfer into messages and sends them.
+struct TransferSender<'a, R> {
+    request: &'a Request,
+    response_handle: R,
+    signer: ResponseSigner,
+    /// Records of the message that is being assembled.
+    records: Vec<Record>,
+    /// Encoded size of `records`.
+    size: usize,
+    /// Info of the first message sent.
+    info: Option<ResponseInfo>,
+}
+
+impl<R: ResponseHandler> TransferSender<'_, R> {
+    async fn push(&mut self, record: Record) -> Result<()> {
+        let size = record.to_bytes().anyerr()?.len();
+        if self.size + size > MAX_MESSAGE_SIZE {
+            self.flush().await?;
+        }
+        self.size += size;
+        self.records.push(record);
+        Ok(())
+    }
+
+    /// Sends the assembled records as one message.
+    async fn flush(&mut self) -> Result<()> {
+        if self.records.is_empty() {
+            return Ok(());
+        }
+        let mut metadata = Metadata::response_from_request(&self.request.metadata);
+        metadata.authoritative = true;
+        let response = || {
+            MessageResponseBuilder::new(&self.request.queries, None).build(
+                metadata,
+                self.records.iter(),
+                iter::empty(),
+                iter::empty(),
+                iter::empty(),
+            )
+        };
+        let mut unsigned = Vec::with_capacity(self.size + 512);
+        response()
+            .destructive_emit(&mut BinEncoder::new(&mut unsigned))
+            .anyerr()?;
+        let mut message = response();
+        message.set_signature(self.signer.sign(&unsigned).anyerr()?);
+        let info = self.response_handle.send_response(message).await.anyerr()?;
+        self.info.get_or_insert(info);
+        self.records.clear();
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// Signs the messages of a response to a TSIG signed request.
+struct ResponseSigner {
+    signer: TSigner,
+    request_id: u16,
+    time: u64,
+    /// MAC of the request or the previous message.
+    previous_mac: Vec<u8>,
+    first: bool,
+}
+
+impl ResponseSigner {
+    /// Returns the TSIG record for an encoded message.
+    fn sign(&mut self, message: &[u8]) -> Result<Box<Record<TSIG>>, ProtoError> {
+        let tsig = TSIG::new(
+            self.signer.algorithm().clone(),
+            self.time,
+            self.signer.fudge(),
+            Vec::new(),
+            self.request_id,
+            None,
+            Vec::new(),
+        );
+        let tbs = if self.first {
+            self.signer
+                .encode_response_tbs(&self.previous_mac, message, &tsig)?
+        } else {
+            // RFC 8945, Section 5.3.1: subsequent messages only include the TSIG timers.
+            let mut tbs = Vec::with_capacity(self.previous_mac.len() + message.len() + 10);
+            let mut encoder = BinEncoder::new(&mut tbs);
+            encoder.emit_u16(self.previous_mac.len() as u16)?;
+            encoder.emit_vec(&self.previous_mac)?;
+            encoder.emit_vec(message)?;
+            encoder.emit_u16((self.time >> 32) as u16)?;
+            encoder.emit_u32(self.time as u32)?;
+            encoder.emit_u16(self.signer.fudge())?;
+            tbs
+        };
+        let mac = self
+            .signer
+            .sign(&tbs)
+            .map_err(|err| ProtoError::from(err.to_string()))?;
+        self.previous_mac = mac.clone();
+        self.first = false;
+        Ok(Box::new(make_tsig_record(
+            self.signer.signer_name().clone(),
+            tsig.set_mac(mac),
+        )))
+    }
+}
+
+/// Sends an error response, signed if `tsig` is set.
+async fn send_error<R: ResponseHandler>(
+    request: &Request,
+    response_code: ResponseCode,
+    tsig: Option<TSigResponseContext>,
+    mut response_handle: R,
+) -> ResponseInfo {
+    let response = || {
+        MessageResponseBuilder::new(&request.queries, None)
+            .error_msg(&request.metadata, response_code)
+    };
+    let mut message = response();
+    if let Some(tsig) = tsig {
+        let mut unsigned = Vec::with_capacity(512);
+        let signature = response()
+            .destructive_emit(&mut BinEncoder::new(&mut unsigned))
+            .and_then(|_| tsig.sign(&unsigned));
+        match signature {
+            Ok(signature) => message.set_signature(signature),
+            Err(err) => {
+                warn!("failed to sign error response: {err:#}");
+                return serve_failed(request);
+            }
+        }
+    }
+    match response_handle.send_response(message).await {
+        Ok(info) => info,
+        Err(err) => {
+            warn!("failed to send error response: {err:#}");
+            serve_failed(request)
+        }
+    }
+}
+
+fn serve_failed(request: &Request) -> ResponseInfo {
+    let mut metadata = Metadata::response_from_request(&request.metadata);
+    metadata.response_code = ResponseCode::ServFail;
+    ResponseInfo::from(Header {
+        metadata,
+        counts: HeaderCounts::default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use hickory_server::{
+        net::{
+            client::{Client, ClientHandle},
+            runtime::TokioRuntimeProvider,
+            tcp::TcpClientStream,
+            xfer::{DnsHandle, DnsMultiplexer},
+        },
+        proto::op::{DnsRequest, update_message},
+    };
+    use iroh_base::SecretKey;
+    use iroh_dns::pkarr::SignedPacket;
+    use n0_error::Result;
+
+    use super::*;
+    use crate::{
+        dns::{DnsHandler, DnsServer},
+        server::test_config,
+        store::{Options, PacketSource, ZoneStore},
+    };
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    async fn connect(
+        addr: SocketAddr,
+        signer: Option<TSigner>,
+    ) -> Result<Client<TokioRuntimeProvider>> {
+        let (stream, sender) = TcpClientStream::new(addr, None, None, TokioRuntimeProvider::new());
+        let mut multiplexer = DnsMultiplexer::new(stream.await.anyerr()?, sender);
+        if let Some(signer) = signer {
+            multiplexer = multiplexer.with_signer(signer);
+        }
+        let (client, background) = Client::from_sender(multiplexer);
+        tokio::spawn(background);
+        Ok(client)
+    }
+
+    /// Receives NOTIFY messages until one for `origin` arrives, and returns its serial.
+    ///
+    /// Each NOTIFY is acknowledged with a signed response, so that the server does not retry.
+    async fn recv_notify(socket: &UdpSocket, signer: &TSigner, origin: &Name) -> Result<u32> {
+        let mut buf = vec![0u8; 4096];
+        loop {
+            let (len, addr) = tokio::time::timeout(TIMEOUT, socket.recv_from(&mut buf))
+                .await
+                .anyerr()?
+                .anyerr()?;
+            let (mac, _, _) = signer
+                .verify_message_byte(&buf[..len], None, true)
+                .anyerr()?;
+            let message = Message::from_vec(&buf[..len]).anyerr()?;
+            assert_eq!(message.metadata.op_code, OpCode::Notify);
+
+            let mut response = Message::response(message.metadata.id, OpCode::Notify);
+            response.add_queries(message.queries.clone());
+            let mut response_signer = ResponseSigner {
+                signer: signer.clone(),
+                request_id: message.metadata.id,
+                time: unix_time(),
+                previous_mac: mac,
+                first: true,
+            };
+            let signature = response_signer
+                .sign(&response.to_vec().anyerr()?)
+                .anyerr()?;
+            response.set_signature(signature);
+            socket
+                .send_to(&response.to_vec().anyerr()?, addr)
+                .await
+                .anyerr()?;
+
+            if message.queries[0].name() != origin {
+                continue;
+            }
+            let RData::SOA(soa) = &message.answers[0].data else {
+                panic!("expected SOA");
+            };
+            return Ok(soa.serial);
+        }
+    }
+
+    #[tokio::test]
+    async fn zone_transfer_and_notify() -> Result {
+        const PACKETS: usize = 300;
+
+        let dir = tempfile::tempdir()?;
+        let store = ZoneStore::in_memory(Options::default(), Default::default())?;
+        for i in 0..PACKETS {
+            let packet = SignedPacket::from_txt_strings(
+                &SecretKey::generate(),
+                "_iroh",
+                [format!("relay=https://relay{i}.example.")],
+                30,
+            )
+            .anyerr()?;
+            store.insert(packet, PacketSource::PkarrPublish).await?;
+        }
+
+        let notify_socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let secret = BASE64.encode(&[7u8; 32]);
+        let mut transfer = ZoneTransferConfig::new(vec![TsigKeyConfig::new("xfr.", secret)]);
+        transfer.notify = vec![notify_socket.local_addr()?];
+        transfer.notify_delay = Duration::from_millis(10);
+        let signer = transfer.tsig_keys[0].signer()?;
+        let mut config = test_config(dir.path()).dns;
+        config.transfer = Some(transfer);
+        let handler = DnsHandler::new(store.clone(), &config, dir.path(), Default::default())?;
+        let server = DnsServer::spawn(config, handler, None).await?;
+        let origin = Name::from_utf8("irohdns.example.").anyerr()?;
+
+        // secondaries are notified on startup and after changes
+        let serial = recv_notify(&notify_socket, &signer, &origin).await?;
+        assert_eq!(serial, store.serial());
+        let packet = SignedPacket::from_txt_strings(
+            &SecretKey::generate(),
+            "_iroh",
+            ["relay=https://new.example."],
+            30,
+        )
+        .anyerr()?;
+        store.insert(packet, PacketSource::PkarrPublish).await?;
+        let new_serial = recv_notify(&notify_socket, &signer, &origin).await?;
+        assert_eq!(new_serial, serial + 1);
+
+        // a full transfer is split into multiple signed messages
+        let mut client = connect(server.local_addr(), Some(signer)).await?;
+        let responses: Vec<_> = client
+            .zone_transfer(origin.clone(), None)
+            .try_collect()
+            .await
+            .anyerr()?;
+        assert!(responses.len() > 1);
+        let records: Vec<_> = responses
+            .iter()
+            .flat_map(|response| response.answers.iter())
+            .collect();
+        assert_eq!(records[0].record_type(), RecordType::SOA);
+        assert_eq!(records[records.len() - 1].record_type(), RecordType::SOA);
+        let txt = records
+            .iter()
+            .filter(|record| record.record_type() == RecordType::TXT)
+            .count();
+        assert_eq!(txt, PACKETS + 1);
+
+        // an up to date secondary only receives the SOA record
+        let RData::SOA(soa) = &records[0].data else {
+            panic!("expected SOA");
+        };
+        let message = update_message::zone_transfer(origin.clone(), Some(soa.clone()));
+        let response = client
+            .send(DnsRequest::from(message))
+            .try_next()
+            .await
+            .anyerr()?
+            .expect("response");
+        assert_eq!(response.answers.len(), 1);
+        assert_eq!(response.answers[0].record_type(), RecordType::SOA);
+
+        // unsigned transfers are refused
+        let mut unsigned_client = connect(server.local_addr(), None).await?;
+        let res = unsigned_client.zone_transfer(origin, None).try_next().await;
+        assert!(
+            res.is_err()
+                || matches!(res, Ok(Some(response)) if response.response_code == ResponseCode::Refused)
+        );
+
+        server.shutdown().await?;
+        Ok(())
+    }
+}
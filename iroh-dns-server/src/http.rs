@@ -3,6 +3,7 @@
 use std::{
     net::{IpAddr, Ipv4Addr, SocketAddr},
     path::PathBuf,
+    sync::Arc,
     time::Instant,
 };
 
@@ -16,6 +17,7 @@ use axum::{
     routing::get,
 };
 use n0_error::{Result, StdResultExt, anyerr, bail_any};
+use rustls::server::ResolvesServerCert;
 use serde::{Deserialize, Serialize};
 use tokio::{net::TcpListener, task::JoinSet};
 use tower_http::{
@@ -24,14 +26,16 @@ use tower_http::{
 };
 use tracing::{Level, info, span, warn};
 
+mod admin;
 mod doh;
 mod error;
 mod pkarr;
 mod rate_limiting;
+mod replication;
 mod tls;
 
-pub use self::{rate_limiting::RateLimitConfig, tls::CertMode};
-use crate::state::AppState;
+pub use self::{admin::AdminConfig, rate_limiting::RateLimitConfig, tls::CertMode};
+use crate::{replication::REPLICATION_PATH, state::AppState};
 
 /// Configuration for the HTTP listener.
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -74,6 +78,7 @@ pub(crate) struct HttpServer {
     tasks: JoinSet<std::io::Result<()>>,
     http_addr: Option<SocketAddr>,
     https_addr: Option<SocketAddr>,
+    cert_resolver: Option<Arc<dyn ResolvesServerCert>>,
 }
 
 impl HttpServer {
@@ -82,6 +87,7 @@ impl HttpServer {
         http_config: Option<HttpConfig>,
         https_config: Option<HttpsConfig>,
         rate_limit_config: RateLimitConfig,
+        admin_config: Option<AdminConfig>,
         state: AppState,
         cert_cache_dir: PathBuf,
     ) -> Result<HttpServer> {
@@ -89,7 +95,7 @@ impl HttpServer {
             bail_any!("Either http or https config is required");
         }
 
-        let app = create_app(state, &rate_limit_config);
+        let app = create_app(state, &rate_limit_config, admin_config.as_ref());
 
         let mut tasks = JoinSet::new();
 
@@ -116,6 +122,7 @@ impl HttpServer {
         };
 
         // launch https
+        let mut cert_resolver = None;
         let https_addr = if let Some(config) = https_config {
             let bind_addr = SocketAddr::new(
                 config.bind_addr.unwrap_or(Ipv4Addr::UNSPECIFIED.into()),
@@ -140,6 +147,7 @@ impl HttpServer {
                     )
                     .await?
             };
+            cert_resolver = Some(acceptor.cert_resolver());
             let listener = TcpListener::bind(bind_addr)
                 .await
                 .anyerr()?
@@ -160,6 +168,7 @@ impl HttpServer {
             tasks,
             http_addr,
             https_addr,
+            cert_resolver,
         })
     }
 
@@ -173,6 +182,11 @@ impl HttpServer {
         self.https_addr
     }
 
+    /// Get the resolver for the certificates of the HTTPS listener.
+    pub(crate) fn cert_resolver(&self) -> Option<Arc<dyn ResolvesServerCert>> {
+        self.cert_resolver.clone()
+    }
+
     /// Shutdown the server and wait for all tasks to complete.
     pub(crate) async fn shutdown(mut self) -> Result<()> {
         // TODO: Graceful cancellation.
@@ -220,11 +234,15 @@ async fn healthz() -> Json<Health> {
     })
 }
 
-pub(crate) fn create_app(state: AppState, rate_limit_config: &RateLimitConfig) -> Router {
+pub(crate) fn create_app(
+    state: AppState,
+    rate_limit_config: &RateLimitConfig,
+    admin_config: Option<&AdminConfig>,
+) -> Router {
     // configure cors middleware
     let cors = CorsLayer::new()
         // allow `GET` and `POST` when accessing the resource
-        .allow_methods([Method::GET, Method::POST, Method::PUT])
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
         // allow requests from any origin
         .allow_origin(cors::Any);
 
@@ -263,8 +281,20 @@ pub(crate) fn create_app(state: AppState, rate_limit_config: &RateLimitConfig) -
         // Deprecated: use /healthz instead
         .route("/healthcheck", get(|| async { "OK" }))
         .route("/healthz", get(healthz))
-        .route("/", get(|| async { "Hi!" }))
-        .with_state(state.clone());
+        .route("/", get(|| async { "Hi!" }));
+    let router = if state.replication.is_some() {
+        router.route(
+            REPLICATION_PATH,
+            get(replication::get).put(replication::put),
+        )
+    } else {
+        router
+    };
+    let router = match admin_config {
+        Some(config) => router.nest("/admin", admin::router(config)),
+        None => router,
+    };
+    let router = router.with_state(state.clone());
 
     // configure app
     router
@@ -325,23 +355,30 @@ mod tests {
     use n0_tracing_test::traced_test;
     use rand::{RngExt, SeedableRng};
 
-    use crate::{http::HttpsConfig, server::Server};
+    use crate::{
+        config::DnsListenerConfig,
+        http::HttpsConfig,
+        server::{Server, test_config},
+    };
 
-    #[tokio::test]
-    #[traced_test]
-    async fn test_doh() -> n0_error::Result {
-        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(0);
-        let dir = tempfile::tempdir()?;
-        let https_config = HttpsConfig {
+    fn https_config() -> HttpsConfig {
+        HttpsConfig {
             port: 0,
             bind_addr: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
             domains: vec!["localhost".to_string()],
             cert_mode: crate::http::CertMode::SelfSigned,
             letsencrypt_contact: None,
             letsencrypt_prod: None,
-        };
+        }
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn test_doh() -> n0_error::Result {
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(0);
+        let dir = tempfile::tempdir()?;
         let server =
-            Server::spawn_for_tests_with_options(dir.path(), None, None, Some(https_config))
+            Server::spawn_for_tests_with_options(dir.path(), None, None, Some(https_config()))
                 .await?;
 
         const RELAY_URL: &str = "https://relay.example./";
@@ -444,6 +481,88 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    #[traced_test]
+    async fn test_dot_doq() -> n0_error::Result {
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        let dir = tempfile::tempdir()?;
+        let listener = DnsListenerConfig {
+            port: 0,
+            bind_addr: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+        };
+        let mut config = test_config(dir.path());
+        config.https = Some(https_config());
+        config.dns.tls = Some(listener.clone());
+        config.dns.quic = Some(listener);
+        let server = Server::bind(config).await?;
+
+        const RELAY_URL: &str = "https://relay.example./";
+        let secret_key = SecretKey::from_bytes(&rng.random());
+        let relay_url: RelayUrl = RELAY_URL.parse().expect("valid url");
+        let signed_packet = EndpointInfo::new(secret_key.public())
+            .with_relay_url(relay_url)
+            .to_pkarr_signed_packet(&secret_key, 30)?;
+        let http_url = server.http_url().expect("http is bound");
+        let tls_config = CaTlsConfig::default()
+            .client_config(default_provider())
+            .expect("infallible");
+        let pkarr = PkarrRelayClient::new(
+            format!("{http_url}pkarr").parse().anyerr()?,
+            tls_config,
+            DnsResolver::default(),
+        );
+        pkarr.publish(&signed_packet).await?;
+
+        let tls_addr = server.dns_tls_addr().expect("tls is bound");
+        let quic_addr = server.dns_quic_addr().expect("quic is bound");
+        let name_servers = [
+            (
+                NameServerConfig::tls(tls_addr.ip(), Arc::from("localhost")),
+                tls_addr.port(),
+            ),
+            (
+                NameServerConfig::quic(quic_addr.ip(), Arc::from("localhost")),
+                quic_addr.port(),
+            ),
+        ];
+        let name = format!("_iroh.{}.", secret_key.public().to_z32());
+        for (mut name_server, port) in name_servers {
+            for connection in &mut name_server.connections {
+                connection.port = port;
+            }
+            let config = ResolverConfig::from_parts(None, vec![], vec![name_server]);
+            let resolver = hickory_resolver::Resolver::builder_with_config(
+                config,
+                TokioRuntimeProvider::default(),
+            )
+            .with_tls_config(self::tls::insecure_tls_config())
+            .build()
+            .anyerr()?;
+            let res = resolver.txt_lookup(name.clone()).await.anyerr()?;
+            let records = res.answers();
+            assert_eq!(records.len(), 1);
+            let txt_data = match &records[0].data {
+                hickory_server::proto::rr::RData::TXT(txt) => &txt.txt_data,
+                other => panic!("expected TXT record, got {other:?}"),
+            };
+            assert_eq!(&txt_data[0][..], format!("relay={RELAY_URL}").as_bytes());
+        }
+        assert_eq!(server.metrics().dns_requests_tls.get(), 1);
+        assert_eq!(server.metrics().dns_requests_quic.get(), 1);
+
+        server.shutdown().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dot_requires_https() -> n0_error::Result {
+        let dir = tempfile::tempdir()?;
+        let mut config = test_config(dir.path());
+        config.dns.tls = Some(DnsListenerConfig::new(0));
+        assert!(Server::bind(config).await.is_err());
+        Ok(())
+    }
+
     mod tls {
         use std::sync::Arc;
 
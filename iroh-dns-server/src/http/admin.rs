@@ -0,0 +1,453 @@
+//! Token-protected admin API to manage the stored signed packets.
+//!
+//! All routes are nested under `/admin` and require an `Authorization: Bearer <token>`
+//! header with the token from [`AdminConfig`]:
+//!
+//! * `GET /admin/zones?after=<key>&limit=<n>`: list stored zones with their timestamps
+//! * `DELETE /admin/zones/{key}`: delete the packet for a key
+//! * `GET /admin/blocked`: list blocked keys
+//! * `PUT /admin/blocked/{key}`: block a key and delete its packet
+//! * `DELETE /admin/blocked/{key}`: unblock a key
+//! * `POST /admin/evict`: evict all expired packets now
+//! * `GET /admin/packets`: export all packets as a stream
+//! * `POST /admin/packets`: import packets from a stream
+//!
+//! Exported and imported packets are encoded as a sequence of `<2 bytes length><packet>`
+//! frames, where each packet is the signed packet including the public key and signature.
+
+use std::{io, sync::Arc};
+
+use axum::{
+    Json, Router,
+    body::Body,
+    extract::{DefaultBodyLimit, Path, Query, Request, State},
+    handler::Handler,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
+};
+use bytes::BytesMut;
+use http::{StatusCode, header};
+use n0_future::{StreamExt, stream};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use super::error::AppError;
+use crate::{
+    state::AppState,
+    store::PacketSource,
+    util::{PublicKeyBytes, bearer_token_matches, decode_packet, encode_packets},
+};
+
+/// Default number of zones returned by `GET /admin/zones`.
+const DEFAULT_LIST_LIMIT: usize = 100;
+/// Maximum number of zones returned by `GET /admin/zones`.
+const MAX_LIST_LIMIT: usize = 1000;
+/// Number of packets read from the store at once while exporting.
+const EXPORT_PAGE_SIZE: usize = 1024;
+
+/// Configuration for the admin API.
+///
+/// The admin API is served on the HTTP and HTTPS listeners. The token is sent as a bearer
+/// token, so the API should only be used over HTTPS or on a trusted network.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[non_exhaustive]
+pub struct AdminConfig {
+    /// Secret token required to access the admin API.
+    pub token: String,
+}
+
+impl AdminConfig {
+    /// Creates a new [`AdminConfig`] with the given token.
+    pub fn new(token: impl Into<String>) -> Self {
+        Self {
+            token: token.into(),
+        }
+    }
+}
+
+/// Creates the admin router, to be nested under `/admin`.
+pub(super) fn router(config: &AdminConfig) -> Router<AppState> {
+    let token: Arc<str> = config.token.as_str().into();
+    Router::new()
+        .route("/zones", get(list_zones))
+        .route("/zones/{key}", delete(delete_zone))
+        .route("/blocked", get(list_blocked))
+        .route("/blocked/{key}", put(block).delete(unblock))
+        .route("/evict", post(evict))
+        .route(
+            "/packets",
+            get(export).post(import.layer(DefaultBodyLimit::disable())),
+        )
+        .route_layer(middleware::from_fn_with_state(token, authorize))
+}
+
+async fn authorize(State(token): State<Arc<str>>, req: Request, next: Next) -> Response {
+    let authorization = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .map(|value| value.as_bytes());
+    if bearer_token_matches(authorization, &token) {
+        next.run(req).await
+    } else {
+        AppError::with_status(StatusCode::UNAUTHORIZED).into_response()
+    }
+}
+
+fn parse_key(key: &str) -> Result<PublicKeyBytes, AppError> {
+    PublicKeyBytes::from_z32(key)
+        .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, Some(format!("invalid key: {e}"))))
+}
+
+#[derive(Debug, Deserialize)]
+struct ListQuery {
+    /// Only list zones with a key greater than this z-base-32 encoded key.
+    after: Option<String>,
+    /// Maximum number of zones to list.
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ZoneList {
+    zones: Vec<ZoneInfo>,
+    /// Key to pass as `after` to fetch the next page, if there may be more zones.
+    next: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ZoneInfo {
+    /// The z-base-32 encoded public key.
+    public_key: String,
+    /// Timestamp of the signed packet, in microseconds since the unix epoch.
+    timestamp: u64,
+}
+
+async fn list_zones(
+    State(state): State<AppState>,
+    Query(query): Query<ListQuery>,
+) -> Result<Json<ZoneList>, AppError> {
+    let after = query.after.as_deref().map(parse_key).transpose()?;
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .min(MAX_LIST_LIMIT);
+    let packets = state.store.packets(after, limit).await?;
+    let zones: Vec<_> = packets
+        .iter()
+        .map(|packet| ZoneInfo {
+            public_key: packet.public_key().to_z32(),
+            timestamp: packet.timestamp().as_micros(),
+        })
+        .collect();
+    let next = match zones.last() {
+        Some(last) if zones.len() == limit => Some(last.public_key.clone()),
+        _ => None,
+    };
+    Ok(Json(ZoneList { zones, next }))
+}
+
+async fn delete_zone(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let key = parse_key(&key)?;
+    if state.store.remove(&key).await? {
+        info!(%key, "admin: deleted zone");
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::with_status(StatusCode::NOT_FOUND))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BlockedList {
+    keys: Vec<String>,
+}
+
+async fn list_blocked(State(state): State<AppState>) -> Json<BlockedList> {
+    let keys = state
+        .store
+        .blocked()
+        .into_iter()
+        .map(PublicKeyBytes::to_z32)
+        .collect();
+    Json(BlockedList { keys })
+}
+
+async fn block(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let key = parse_key(&key)?;
+    state.store.block(key).await?;
+    info!(%key, "admin: blocked key");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn unblock(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let key = parse_key(&key)?;
+    if state.store.unblock(&key).await? {
+        info!(%key, "admin: unblocked key");
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::with_status(StatusCode::NOT_FOUND))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EvictResult {
+    evicted: usize,
+}
+
+async fn evict(State(state): State<AppState>) -> Result<Json<EvictResult>, AppError> {
+    let evicted = state.store.evict_expired().await?;
+    info!(evicted, "admin: evicted expired packets");
+    Ok(Json(EvictResult { evicted }))
+}
+
+async fn export(State(state): State<AppState>) -> impl IntoResponse {
+    // The state is the key to continue after, or `None` once all packets were exported.
+    let pages = stream::unfold(Some(None), move |after| {
+        let store = state.store.clone();
+        async move {
+            let after = after?;
+            match store.packets(after, EXPORT_PAGE_SIZE).await {
+                Ok(packets) => {
+                    let next = match packets.last() {
+                        Some(last) if packets.len() == EXPORT_PAGE_SIZE => {
+                            Some(Some(PublicKeyBytes::from_signed_packet(last)))
+                        }
+                        _ => None,
+                    };
+                    Some((Ok(encode_packets(&packets)), next))
+                }
+                Err(err) => Some((Err(io::Error::other(err.to_string())), None)),
+            }
+        }
+    });
+    let headers = [(header::CONTENT_TYPE, "application/octet-stream")];
+    (headers, Body::from_stream(pages))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ImportResult {
+    /// Number of packets read from the request.
+    imported: usize,
+    /// Number of packets that updated the store.
+    updated: usize,
+}
+
+async fn import(State(state): State<AppState>, body: Body) -> Result<Json<ImportResult>, AppError> {
+    let mut chunks = body.into_data_stream();
+    let mut buf = BytesMut::new();
+    let mut result = ImportResult {
+        imported: 0,
+        updated: 0,
+    };
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk.map_err(|e| AppError::new(StatusCode::BAD_REQUEST, Some(e)))?;
+        buf.extend_from_slice(&chunk);
+        while let Some(packet) =
+            decode_packet(&mut buf).map_err(|e| AppError::new(StatusCode::BAD_REQUEST, Some(e)))?
+        {
+            result.imported += 1;
+            if state
+                .store
+                .insert(packet, PacketSource::Import)
+                .await?
+                .is_updated()
+            {
+                result.updated += 1;
+            }
+        }
+    }
+    if !buf.is_empty() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            Some("truncated packet"),
+        ));
+    }
+    info!(
+        imported = result.imported,
+        updated = result.updated,
+        "admin: imported packets"
+    );
+    Ok(Json(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh_base::SecretKey;
+    use iroh_dns::pkarr::SignedPacket;
+    use n0_error::{Result, StdResultExt};
+    use reqwest::{Client, Method, StatusCode};
+    use url::Url;
+
+    use super::*;
+    use crate::{
+        Server,
+        http::RateLimitConfig,
+        replication::http_client,
+        server::test_config,
+        util::{decode_packets, encode_packets},
+    };
+
+    const TOKEN: &str = "admin-secret";
+
+    async fn spawn_server(dir: &std::path::Path) -> Result<(Server, Url)> {
+        let mut config = test_config(dir);
+        config.admin = Some(AdminConfig::new(TOKEN));
+        config.pkarr_put_rate_limit = RateLimitConfig::Disabled;
+        let server = Server::bind(config).await?;
+        let url = server.http_url().expect("http is bound");
+        Ok((server, url))
+    }
+
+    fn signed_packet(relay: &str) -> Result<SignedPacket> {
+        SignedPacket::from_txt_strings(
+            &SecretKey::generate(),
+            "_iroh",
+            [format!("relay={relay}")],
+            30,
+        )
+        .anyerr()
+    }
+
+    async fn admin(
+        client: &Client,
+        method: Method,
+        url: &Url,
+        path: &str,
+    ) -> Result<reqwest::Response> {
+        client
+            .request(method, url.join(path).anyerr()?)
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .anyerr()
+    }
+
+    async fn publish(client: &Client, url: &Url, packet: &SignedPacket) -> Result<StatusCode> {
+        let url = url
+            .join(&format!("pkarr/{}", packet.public_key().to_z32()))
+            .anyerr()?;
+        let res = client
+            .put(url)
+            .body(packet.to_relay_payload())
+            .send()
+            .await
+            .anyerr()?;
+        Ok(res.status())
+    }
+
+    async fn resolve(client: &Client, url: &Url, packet: &SignedPacket) -> Result<StatusCode> {
+        let url = url
+            .join(&format!("pkarr/{}", packet.public_key().to_z32()))
+            .anyerr()?;
+        Ok(client.get(url).send().await.anyerr()?.status())
+    }
+
+    #[tokio::test]
+    async fn admin_api() -> Result {
+        let dir = tempfile::tempdir()?;
+        let (server, url) = spawn_server(dir.path()).await?;
+        let client = http_client()?;
+        let a = signed_packet("https://a.example.com")?;
+        let b = signed_packet("https://b.example.com")?;
+        let a_key = a.public_key().to_z32();
+        let b_key = b.public_key().to_z32();
+        assert_eq!(publish(&client, &url, &a).await?, StatusCode::NO_CONTENT);
+        assert_eq!(publish(&client, &url, &b).await?, StatusCode::NO_CONTENT);
+
+        // Requests without the token are rejected.
+        let res = client
+            .get(url.join("admin/zones").anyerr()?)
+            .send()
+            .await
+            .anyerr()?;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        // List zones, one page at a time.
+        let res = admin(&client, Method::GET, &url, "admin/zones?limit=1").await?;
+        let first: ZoneList = res.json().await.anyerr()?;
+        assert_eq!(first.zones.len(), 1);
+        let next = first.next.expect("more zones");
+        let res = admin(
+            &client,
+            Method::GET,
+            &url,
+            &format!("admin/zones?after={next}"),
+        )
+        .await?;
+        let second: ZoneList = res.json().await.anyerr()?;
+        assert_eq!(second.zones.len(), 1);
+        assert!(second.next.is_none());
+        let mut listed: Vec<_> = [&first.zones[0], &second.zones[0]]
+            .into_iter()
+            .map(|zone| (zone.public_key.clone(), zone.timestamp))
+            .collect();
+        listed.sort();
+        let mut expected = vec![
+            (a_key.clone(), a.timestamp().as_micros()),
+            (b_key.clone(), b.timestamp().as_micros()),
+        ];
+        expected.sort();
+        assert_eq!(listed, expected);
+
+        // Export all packets.
+        let res = admin(&client, Method::GET, &url, "admin/packets").await?;
+        assert_eq!(res.status(), StatusCode::OK);
+        let exported = decode_packets(res.bytes().await.anyerr()?)?;
+        assert_eq!(exported.len(), 2);
+
+        // Delete a zone.
+        let path = format!("admin/zones/{a_key}");
+        let res = admin(&client, Method::DELETE, &url, &path).await?;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(resolve(&client, &url, &a).await?, StatusCode::NOT_FOUND);
+        let res = admin(&client, Method::DELETE, &url, &path).await?;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        // Block a key: its packet is removed, and publishing it is refused.
+        let path = format!("admin/blocked/{b_key}");
+        let res = admin(&client, Method::PUT, &url, &path).await?;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(resolve(&client, &url, &b).await?, StatusCode::NOT_FOUND);
+        assert_eq!(publish(&client, &url, &b).await?, StatusCode::FORBIDDEN);
+        assert_eq!(server.metrics().pkarr_publish_blocked.get(), 1);
+        let res = admin(&client, Method::GET, &url, "admin/blocked").await?;
+        let blocked: BlockedList = res.json().await.anyerr()?;
+        assert_eq!(blocked.keys, vec![b_key.clone()]);
+
+        // Import the exported packets: only the packet for the unblocked key is stored.
+        let res = client
+            .post(url.join("admin/packets").anyerr()?)
+            .bearer_auth(TOKEN)
+            .body(encode_packets(&exported))
+            .send()
+            .await
+            .anyerr()?;
+        let imported: ImportResult = res.json().await.anyerr()?;
+        assert_eq!(imported.imported, 2);
+        assert_eq!(imported.updated, 1);
+        assert_eq!(resolve(&client, &url, &a).await?, StatusCode::OK);
+        assert_eq!(resolve(&client, &url, &b).await?, StatusCode::NOT_FOUND);
+
+        // Unblock the key.
+        let res = admin(&client, Method::DELETE, &url, &path).await?;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(publish(&client, &url, &b).await?, StatusCode::NO_CONTENT);
+        assert_eq!(resolve(&client, &url, &b).await?, StatusCode::OK);
+
+        // Nothing is expired yet.
+        let res = admin(&client, Method::POST, &url, "admin/evict").await?;
+        let evicted: EvictResult = res.json().await.anyerr()?;
+        assert_eq!(evicted.evicted, 0);
+
+        server.shutdown().await?;
+        Ok(())
+    }
+}
@@ -9,7 +9,11 @@ use iroh_dns::pkarr::SignedPacket;
 use tracing::info;
 
 use super::error::AppError;
-use crate::{state::AppState, store::PacketSource, util::PublicKeyBytes};
+use crate::{
+    state::AppState,
+    store::{InsertOutcome, PacketSource},
+    util::PublicKeyBytes,
+};
 
 pub(super) async fn put(
     State(state): State<AppState>,
@@ -19,17 +23,42 @@ pub(super) async fn put(
     let public_key = PublicKey::from_z32(&key)
         .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, Some(format!("invalid key: {e}"))))?;
     let label = key.get(..10).unwrap_or(&key);
+
     let signed_packet = SignedPacket::from_relay_payload(&public_key, &body).map_err(|e| {
         AppError::new(
             StatusCode::BAD_REQUEST,
             Some(format!("invalid body payload: {e}")),
         )
     })?;
-
-    let updated = state
+    let outcome = state
         .store
-        .insert(signed_packet, PacketSource::PkarrPublish)
+        .insert(signed_packet.clone(), PacketSource::PkarrPublish)
         .await?;
+    let updated = match outcome {
+        InsertOutcome::Updated => true,
+        InsertOutcome::Unchanged => false,
+        InsertOutcome::Blocked => {
+            return Err(AppError::new(
+                StatusCode::FORBIDDEN,
+                Some("public key is blocked"),
+            ));
+        }
+        InsertOutcome::Rejected(err) => {
+            info!(key = %label, "pkarr upsert rejected: {err}");
+            return Err(AppError::new(
+                StatusCode::FORBIDDEN,
+                Some(format!("rejected by publish policy: {err}")),
+            ));
+        }
+    };
+    if updated {
+        if let Some(replication) = &state.replication {
+            replication.publish(&signed_packet);
+        }
+        if let Some(dht_publisher) = &state.dht_publisher {
+            dht_publisher.publish(&signed_packet);
+        }
+    }
     info!(key = %label, ?updated, "pkarr upsert");
     Ok(StatusCode::NO_CONTENT)
 }
@@ -0,0 +1,77 @@
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+};
+use bytes::Bytes;
+use http::{HeaderMap, StatusCode, header};
+use iroh_dns::pkarr::SignedPacket;
+use serde::Deserialize;
+use tracing::debug;
+
+use super::error::AppError;
+use crate::{
+    replication::{MAX_PAGE_SIZE, Replication},
+    state::AppState,
+    store::PacketSource,
+    util::{PublicKeyBytes, encode_packets},
+};
+
+#[derive(Debug, Deserialize)]
+pub(super) struct PacketsQuery {
+    /// Only return packets with a key greater than this z-base-32 encoded key.
+    after: Option<String>,
+    /// Maximum number of packets to return, capped at [`MAX_PAGE_SIZE`].
+    limit: Option<usize>,
+}
+
+pub(super) async fn put(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<impl IntoResponse, AppError> {
+    authorize(&state, &headers)?;
+    let signed_packet = SignedPacket::from_bytes(&body).map_err(|e| {
+        AppError::new(
+            StatusCode::BAD_REQUEST,
+            Some(format!("invalid body payload: {e}")),
+        )
+    })?;
+    state.metrics.replication_packets_recv.inc();
+    let outcome = state
+        .store
+        .insert(signed_packet, PacketSource::Replication)
+        .await?;
+    debug!(?outcome, "replicated packet");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(super) async fn get(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<PacketsQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    authorize(&state, &headers)?;
+    let after = query
+        .after
+        .map(|key| PublicKeyBytes::from_z32(&key))
+        .transpose()
+        .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, Some(format!("invalid key: {e}"))))?;
+    let limit = query.limit.unwrap_or(MAX_PAGE_SIZE).min(MAX_PAGE_SIZE);
+    let packets = state.store.packets(after, limit).await?;
+    let headers = [(header::CONTENT_TYPE, "application/octet-stream")];
+    Ok((headers, encode_packets(&packets)))
+}
+
+fn authorize(state: &AppState, headers: &HeaderMap) -> Result<(), AppError> {
+    let authorized = state
+        .replication
+        .as_ref()
+        .is_some_and(|replication: &Replication| {
+            replication.is_authorized(headers.get(header::AUTHORIZATION).map(|v| v.as_bytes()))
+        });
+    if authorized {
+        Ok(())
+    } else {
+        Err(AppError::with_status(StatusCode::UNAUTHORIZED))
+    }
+}
@@ -14,6 +14,7 @@ use n0_future::{FutureExt, future::Boxed as BoxFuture};
 use rustls::{
     ServerConfig,
     pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    server::ResolvesServerCert,
 };
 use serde::{Deserialize, Serialize};
 use tokio::io::{AsyncRead, AsyncWrite};
@@ -58,8 +59,8 @@ impl CertMode {
 /// TLS Certificate Authority acceptor.
 #[derive(Clone)]
 pub(crate) enum TlsAcceptor {
-    LetsEncrypt(AxumAcceptor),
-    Manual(RustlsAcceptor),
+    LetsEncrypt(AxumAcceptor, Arc<dyn ResolvesServerCert>),
+    Manual(RustlsAcceptor, Arc<dyn ResolvesServerCert>),
 }
 
 impl<I: AsyncRead + AsyncWrite + Unpin + Send + 'static, S: Send + 'static> Accept<I, S>
@@ -71,13 +72,22 @@ impl<I: AsyncRead + AsyncWrite + Unpin + Send + 'static, S: Send + 'static> Acce
 
     fn accept(&self, stream: I, service: S) -> Self::Future {
         match self {
-            Self::LetsEncrypt(a) => a.accept(stream, service).boxed(),
-            Self::Manual(a) => a.accept(stream, service).boxed(),
+            Self::LetsEncrypt(a, _) => a.accept(stream, service).boxed(),
+            Self::Manual(a, _) => a.accept(stream, service).boxed(),
         }
     }
 }
 
 impl TlsAcceptor {
+    /// Returns the resolver for the certificates served by this acceptor.
+    ///
+    /// With Let's Encrypt, the resolver has no certificate until the ACME order has completed.
+    pub(crate) fn cert_resolver(&self) -> Arc<dyn ResolvesServerCert> {
+        match self {
+            Self::LetsEncrypt(_, resolver) | Self::Manual(_, resolver) => resolver.clone(),
+        }
+    }
+
     async fn self_signed(domains: Vec<String>) -> Result<Self> {
         let rcgen::CertifiedKey { cert, signing_key } =
             rcgen::generate_simple_self_signed(domains).anyerr()?;
@@ -92,8 +102,9 @@ impl TlsAcceptor {
                 .anyerr()?;
 
         config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        let resolver = config.cert_resolver.clone();
         let acceptor = RustlsAcceptor::new(RustlsConfig::from_config(Arc::new(config)));
-        Ok(Self::Manual(acceptor))
+        Ok(Self::Manual(acceptor, resolver))
     }
 
     async fn manual(domains: Vec<String>, dir: PathBuf) -> Result<Self> {
@@ -116,9 +127,10 @@ impl TlsAcceptor {
         let secret_key = load_secret_key(key_path).await?;
 
         let config = config.with_single_cert(certs, secret_key).anyerr()?;
+        let resolver = config.cert_resolver.clone();
         let config = RustlsConfig::from_config(Arc::new(config));
         let acceptor = RustlsAcceptor::new(config);
-        Ok(Self::Manual(acceptor))
+        Ok(Self::Manual(acceptor, resolver))
     }
 
     fn letsencrypt(
@@ -134,7 +146,8 @@ impl TlsAcceptor {
             .directory_lets_encrypt(is_production)
             .state();
 
-        let config = config.with_cert_resolver(state.resolver());
+        let resolver = state.resolver();
+        let config = config.with_cert_resolver(resolver.clone());
         let acceptor = state.acceptor();
 
         tokio::spawn(
@@ -150,7 +163,7 @@ impl TlsAcceptor {
         );
         let config = Arc::new(config);
         let acceptor = AxumAcceptor::new(acceptor, config);
-        Ok(Self::LetsEncrypt(acceptor))
+        Ok(Self::LetsEncrypt(acceptor, resolver))
     }
 }
 
@@ -6,7 +6,8 @@
 //! queries for the published names, including DNS-over-HTTPS at `/dns-query`.
 //!
 //! With the mainline fallback enabled, keys missing from the local store are
-//! looked up on the BitTorrent mainline DHT.
+//! looked up on the BitTorrent mainline DHT. The server can also publish the
+//! packets it stores to the DHT.
 //!
 //! # Example
 //!
@@ -25,20 +26,31 @@
 #![deny(missing_docs, rustdoc::broken_intra_doc_links, unreachable_pub)]
 
 pub mod config;
+mod dht_publish;
 mod dns;
 mod http;
 mod metrics;
+mod policy;
+mod replication;
 mod server;
 mod state;
 mod store;
 mod util;
 
-pub use crate::{metrics::Metrics, server::Server};
+pub use crate::{
+    metrics::Metrics,
+    policy::{PolicyViolation, PublishHook},
+    server::Server,
+    store::PacketStore,
+    util::{InvalidPublicKeyBytes, PublicKeyBytes},
+};
 
 #[cfg(test)]
 mod tests {
     use std::{
+        collections::BTreeMap,
         net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+        sync::Arc,
         time::Duration,
     };
 
@@ -49,15 +61,16 @@ mod tests {
         endpoint_info::EndpointInfo,
         tls::{CaTlsConfig, default_provider},
     };
-    use iroh_dns::pkarr::SignedPacket;
+    use iroh_dns::pkarr::{SignedPacket, Timestamp};
     use mainline::{DhtBuilder, MutableItem, Testnet};
     use n0_error::{Result, StdResultExt};
     use n0_tracing_test::traced_test;
     use rand::{CryptoRng, RngExt, SeedableRng};
 
     use crate::{
-        config::BootstrapOption,
-        server::Server,
+        PacketStore,
+        config::{BootstrapOption, DnssecConfig, StoreConfig},
+        server::{Server, test_config},
         store::{Options, PacketSource, ZoneStore},
         util::PublicKeyBytes,
     };
@@ -240,6 +253,282 @@ mod tests {
         Ok(())
     }
 
+    /// A [`PacketStore`] keeping packets in a map.
+    #[derive(Debug, Default, Clone)]
+    struct MapPacketStore(Arc<std::sync::Mutex<BTreeMap<PublicKeyBytes, SignedPacket>>>);
+
+    #[async_trait::async_trait]
+    impl PacketStore for MapPacketStore {
+        async fn upsert(&self, packet: SignedPacket) -> Result<bool> {
+            let mut packets = self.0.lock().unwrap();
+            let key = PublicKeyBytes::from_signed_packet(&packet);
+            if let Some(existing) = packets.get(&key)
+                && !packet.more_recent_than(existing)
+            {
+                return Ok(false);
+            }
+            packets.insert(key, packet);
+            Ok(true)
+        }
+
+        async fn get(&self, key: &PublicKeyBytes) -> Result<Option<SignedPacket>> {
+            Ok(self.0.lock().unwrap().get(key).cloned())
+        }
+
+        async fn packets(
+            &self,
+            after: Option<PublicKeyBytes>,
+            limit: usize,
+        ) -> Result<Vec<SignedPacket>> {
+            let packets = self.0.lock().unwrap();
+            Ok(packets
+                .iter()
+                .filter(|(key, _)| after.is_none_or(|after| **key > after))
+                .take(limit)
+                .map(|(_, packet)| packet.clone())
+                .collect())
+        }
+
+        async fn remove(&self, key: &PublicKeyBytes) -> Result<bool> {
+            Ok(self.0.lock().unwrap().remove(key).is_some())
+        }
+
+        async fn evict(&self, expired: Timestamp) -> Result<usize> {
+            let mut packets = self.0.lock().unwrap();
+            let len = packets.len();
+            packets.retain(|_, packet| packet.timestamp() >= expired);
+            Ok(len - packets.len())
+        }
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn integration_custom_packet_store() -> Result {
+        let dir = tempfile::tempdir()?;
+        let mut config = test_config(dir.path());
+        config.zone_store = Some(StoreConfig {
+            eviction: Duration::from_millis(500),
+            eviction_interval: Duration::from_millis(100),
+            ..Default::default()
+        });
+        let packet_store = MapPacketStore::default();
+        let server = Server::bind_with_packet_store(config, Box::new(packet_store.clone())).await?;
+
+        let pkarr_relay = {
+            let mut url = server.http_url().expect("http is bound");
+            url.set_path("/pkarr");
+            url
+        };
+        let origin = "irohdns.example.";
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0u64);
+        let secret_key = SecretKey::from_bytes(&rng.random());
+        let endpoint_id = secret_key.public();
+        let tls_config = CaTlsConfig::default()
+            .client_config(default_provider())
+            .expect("infallible");
+        let pkarr = PkarrRelayClient::new(pkarr_relay, tls_config, DnsResolver::default());
+        let relay_url: RelayUrl = "https://relay.example.".parse()?;
+        let endpoint_info = EndpointInfo::new(endpoint_id).with_relay_url(relay_url.clone());
+        let signed_packet = endpoint_info.to_pkarr_signed_packet(&secret_key, 30)?;
+        pkarr.publish(&signed_packet).await?;
+
+        // The packet is stored in and resolved from the custom store.
+        let key = PublicKeyBytes::from_signed_packet(&signed_packet);
+        assert!(packet_store.get(&key).await?.is_some());
+        let resolver = test_resolver(server.dns_addr());
+        let res = resolver.lookup_endpoint_by_id(&endpoint_id, origin).await?;
+        assert_eq!(res.relay_urls().next(), Some(&relay_url));
+
+        // The server evicts expired packets from the custom store.
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while packet_store.get(&key).await?.is_some() {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            n0_error::Ok(())
+        })
+        .await
+        .std_context("packet was not evicted")??;
+
+        server.shutdown().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn integration_services() -> Result {
+        use iroh::endpoint_info::EndpointService;
+        use iroh_dns::pkarr::{ZoneRecord, ZoneRecordData};
+
+        let dir = tempfile::tempdir()?;
+        let server = Server::spawn_for_tests(dir.path()).await?;
+        let pkarr_relay = {
+            let mut url = server.http_url().expect("http is bound");
+            url.set_path("/pkarr");
+            url
+        };
+        let tls_config = CaTlsConfig::default()
+            .client_config(default_provider())
+            .expect("infallible");
+        let pkarr = PkarrRelayClient::new(pkarr_relay, tls_config, DnsResolver::default());
+        let resolver = test_resolver(server.dns_addr());
+        let origin = "irohdns.example.";
+
+        // services are published next to the endpoint info
+        let secret_key = SecretKey::generate();
+        let endpoint_id = secret_key.public();
+        let service = EndpointService::new("chat".parse()?)
+            .with_alpn(b"/chat/0")
+            .with_attr("room", "lobby")
+            .with_ip_addr(Ipv4Addr::LOCALHOST.into());
+        let endpoint_info = EndpointInfo::new(endpoint_id)
+            .with_relay_url("https://relay.example.".parse()?)
+            .with_service(service.clone());
+        pkarr
+            .publish(&endpoint_info.to_pkarr_signed_packet(&secret_key, 30)?)
+            .await?;
+        let res = resolver
+            .lookup_endpoint_service(&endpoint_id, origin, service.name())
+            .await?;
+        assert_eq!(res, service);
+        let res = resolver.lookup_endpoint_by_id(&endpoint_id, origin).await?;
+        assert_eq!(res.relay_urls().count(), 1);
+
+        // wildcard records match names that are not published otherwise
+        let records = [
+            ZoneRecord::new("*.web", 30, ZoneRecordData::A(Ipv4Addr::LOCALHOST)),
+            ZoneRecord::new("www.web", 30, ZoneRecordData::Aaaa(Ipv6Addr::LOCALHOST)),
+            ZoneRecord::new("sub.www.web", 30, ZoneRecordData::Txt("hi".to_string())),
+        ];
+        pkarr
+            .publish(&SignedPacket::from_records(&secret_key, records)?)
+            .await?;
+        let zone = format!("{}.{origin}", endpoint_id.to_z32());
+        let res = resolver
+            .lookup_ipv4(format!("foo.web.{zone}"), DNS_TIMEOUT)
+            .await?;
+        assert_eq!(res.collect::<Vec<_>>(), vec![Ipv4Addr::LOCALHOST]);
+        let res = resolver
+            .lookup_ipv4(format!("foo.bar.web.{zone}"), DNS_TIMEOUT)
+            .await?;
+        assert_eq!(res.collect::<Vec<_>>(), vec![Ipv4Addr::LOCALHOST]);
+        // existing names and empty non-terminals are not matched by the wildcard
+        assert!(
+            resolver
+                .lookup_ipv4(format!("www.web.{zone}"), DNS_TIMEOUT)
+                .await
+                .is_err()
+        );
+        assert!(
+            resolver
+                .lookup_ipv4(format!("foo.www.web.{zone}"), DNS_TIMEOUT)
+                .await
+                .is_err()
+        );
+
+        server.shutdown().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn integration_dnssec() -> Result {
+        use hickory_resolver::{
+            config::{NameServerConfig, ResolverConfig},
+            net::runtime::TokioRuntimeProvider,
+            proto::rr::RecordType,
+        };
+        use iroh_dns::dns::{DnsProtocol, TrustAnchors};
+
+        let dir = tempfile::tempdir()?;
+        let mut config = test_config(dir.path());
+        config.dns.dnssec = Some(DnssecConfig::default());
+        let server = Server::bind(config).await?;
+        let origin = "irohdns.example.";
+
+        let secret_key = SecretKey::generate();
+        let endpoint_id = secret_key.public();
+        let tls_config = CaTlsConfig::default()
+            .client_config(default_provider())
+            .expect("infallible");
+        let pkarr_relay = format!("{}pkarr", server.http_url().expect("http is bound"));
+        let pkarr = PkarrRelayClient::new(
+            pkarr_relay.parse().anyerr()?,
+            tls_config,
+            DnsResolver::default(),
+        );
+        let relay_url: RelayUrl = "https://relay.example.".parse()?;
+        let endpoint_info = EndpointInfo::new(endpoint_id).with_relay_url(relay_url.clone());
+        pkarr
+            .publish(&endpoint_info.to_pkarr_signed_packet(&secret_key, 30)?)
+            .await?;
+
+        // fetch the zone key to use as trust anchor
+        let dns_addr = server.dns_addr();
+        let mut name_server = NameServerConfig::udp(dns_addr.ip());
+        name_server.connections[0].port = dns_addr.port();
+        let config = ResolverConfig::from_parts(None, vec![], vec![name_server]);
+        let hickory_resolver = hickory_resolver::Resolver::builder_with_config(
+            config,
+            TokioRuntimeProvider::default(),
+        )
+        .build()
+        .anyerr()?;
+        let res = hickory_resolver
+            .lookup(origin, RecordType::DNSKEY)
+            .await
+            .anyerr()?;
+        let dnskey = res.answers()[0].to_string();
+        let trust_anchors: TrustAnchors = dnskey.parse()?;
+
+        // answers are validated
+        let resolver = DnsResolver::builder()
+            .with_nameserver(dns_addr, DnsProtocol::Udp)
+            .with_dnssec_validation(trust_anchors.clone())
+            .build();
+        let res = resolver.lookup_endpoint_by_id(&endpoint_id, origin).await?;
+        assert_eq!(res.endpoint_id, endpoint_id);
+        assert_eq!(res.relay_urls().next(), Some(&relay_url));
+
+        // answers don't validate without a chain of trust
+        let resolver = DnsResolver::builder()
+            .with_nameserver(dns_addr, DnsProtocol::Udp)
+            .with_dnssec_validation(TrustAnchors::default())
+            .build();
+        assert!(
+            resolver
+                .lookup_endpoint_by_id(&endpoint_id, origin)
+                .await
+                .is_err()
+        );
+
+        // missing names are proven with a signed denial
+        let validating_resolver = {
+            let mut name_server = NameServerConfig::udp(dns_addr.ip());
+            name_server.connections[0].port = dns_addr.port();
+            let config = ResolverConfig::from_parts(None, vec![], vec![name_server]);
+            hickory_resolver::Resolver::builder_with_config(config, TokioRuntimeProvider::default())
+                .with_trust_anchor(Arc::new(dnskey.parse().anyerr()?))
+                .build()
+                .anyerr()?
+        };
+        let missing = SecretKey::generate().public();
+        let err = validating_resolver
+            .txt_lookup(format!("_iroh.{}.{origin}", missing.to_z32()))
+            .await
+            .expect_err("name does not exist");
+        assert!(err.is_no_records_found(), "{err:?}");
+
+        // missing types at existing names are proven with a signed denial
+        let err = validating_resolver
+            .ipv4_lookup(format!("_iroh.{}.{origin}", endpoint_id.to_z32()))
+            .await
+            .expect_err("no A record at the name");
+        assert!(err.is_no_records_found(), "{err:?}");
+
+        server.shutdown().await?;
+        Ok(())
+    }
+
     #[tokio::test]
     #[traced_test]
     async fn store_eviction() -> Result {
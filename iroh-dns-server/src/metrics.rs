@@ -11,18 +11,32 @@ pub struct Metrics {
     pub pkarr_publish_update: Counter,
     /// Number of pkarr relay puts that did not change the stored packet.
     pub pkarr_publish_noop: Counter,
+    /// Number of pkarr relay puts rejected because the key is blocked.
+    pub pkarr_publish_blocked: Counter,
+    /// Number of pkarr relay puts rejected by the publish policy.
+    pub pkarr_publish_rejected: Counter,
     /// Total number of DNS requests across all transports.
     pub dns_requests: Counter,
     /// Number of DNS requests received over UDP.
     pub dns_requests_udp: Counter,
     /// Number of DNS requests received over HTTPS (DoH).
     pub dns_requests_https: Counter,
+    /// Number of DNS requests received over TLS (DoT).
+    pub dns_requests_tls: Counter,
+    /// Number of DNS requests received over QUIC (DoQ).
+    pub dns_requests_quic: Counter,
     /// Number of DNS lookups that returned at least one answer.
     pub dns_lookup_success: Counter,
     /// Number of DNS lookups that returned no answers.
     pub dns_lookup_notfound: Counter,
     /// Number of DNS lookups that failed with an error.
     pub dns_lookup_error: Counter,
+    /// Number of full zone transfers served to secondary name servers.
+    pub dns_zone_transfers: Counter,
+    /// Number of NOTIFY messages acknowledged by secondary name servers.
+    pub dns_notify_success: Counter,
+    /// Number of NOTIFY messages that failed or were not acknowledged.
+    pub dns_notify_error: Counter,
     /// Number of HTTP requests served.
     pub http_requests: Counter,
     /// Number of HTTP requests that returned a 2xx status code.
@@ -33,14 +47,42 @@ pub struct Metrics {
     pub http_requests_duration_ms: Counter,
     /// Number of signed packets newly inserted into the store.
     pub store_packets_inserted: Counter,
-    /// Number of signed packets removed from the store.
-    ///
-    /// Currently always at 0 because the removal API is not used.
+    /// Number of signed packets removed from the store via the admin API.
     pub store_packets_removed: Counter,
     /// Number of times an existing signed packet was replaced by a newer one.
     pub store_packets_updated: Counter,
-    /// Number of signed packets removed by the eviction task.
+    /// Number of signed packets removed by the eviction task or a forced eviction.
     pub store_packets_expired: Counter,
+    /// Number of signed packets pushed to peer servers.
+    pub replication_packets_sent: Counter,
+    /// Number of signed packets received from peer servers, pushed or synced.
+    pub replication_packets_recv: Counter,
+    /// Number of signed packets not pushed to a peer server because its queue was full.
+    pub replication_packets_dropped: Counter,
+    /// Number of replicated packets that updated the stored packet.
+    pub replication_packets_update: Counter,
+    /// Number of replicated packets that did not change the stored packet.
+    pub replication_packets_noop: Counter,
+    /// Number of replicated packets rejected because the key is blocked.
+    pub replication_packets_blocked: Counter,
+    /// Number of replicated packets rejected by the publish policy.
+    pub replication_packets_rejected: Counter,
+    /// Number of failed replication requests to peer servers.
+    pub replication_errors: Counter,
+    /// Number of packets imported via the admin API that updated the stored packet.
+    pub import_packets_update: Counter,
+    /// Number of packets imported via the admin API that did not change the stored packet.
+    pub import_packets_noop: Counter,
+    /// Number of packets imported via the admin API rejected because the key is blocked.
+    pub import_packets_blocked: Counter,
+    /// Number of packets imported via the admin API rejected by the publish policy.
+    pub import_packets_rejected: Counter,
+    /// Number of signed packets published to the mainline DHT.
+    pub dht_publish_success: Counter,
+    /// Number of signed packets that failed to be published to the mainline DHT.
+    pub dht_publish_error: Counter,
+    /// Number of signed packets not published to the mainline DHT because the queue was full.
+    pub dht_publish_dropped: Counter,
     /// Current number of zones in the main cache
     pub cache_zones: Gauge,
     /// Current number of zones in the DHT cache
@@ -0,0 +1,301 @@
+//! Policy for packets stored by the server.
+
+use std::{collections::BTreeSet, fmt::Debug, sync::Arc};
+
+use hickory_server::proto::rr::{Name, RData};
+use iroh_dns::pkarr::SignedPacket;
+use n0_error::{Result, StdResultExt, e, stack_error};
+use serde::{Deserialize, Serialize};
+
+use crate::util::{PublicKeyBytes, signed_packet_to_hickory_message};
+
+/// Name used in [`PublishPolicyConfig::txt_names`] for records at the zone apex.
+const APEX_NAME: &str = "@";
+
+/// Configuration for the policy applied to packets stored by the server.
+///
+/// Every rule is optional. The policy applies to packets published via `PUT /pkarr`,
+/// received from replication peers, and imported via the admin API alike.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct PublishPolicyConfig {
+    /// z-base-32 encoded public keys that may publish.
+    ///
+    /// When `None`, every key that is not in [`Self::denied_keys`] may publish.
+    pub allowed_keys: Option<Vec<String>>,
+    /// z-base-32 encoded public keys that may not publish.
+    pub denied_keys: Vec<String>,
+    /// Maximum number of resource records in a published packet.
+    pub max_records: Option<usize>,
+    /// Names of the TXT records that are accepted, relative to the public key zone.
+    ///
+    /// Use `@` for records at the zone apex. When `None`, TXT records under any name
+    /// are accepted.
+    pub txt_names: Option<Vec<String>>,
+    /// Attributes that are accepted in TXT records.
+    ///
+    /// The attribute of a TXT string `key=value` is `key`; the attribute of a string
+    /// without `=` is the whole string. When `None`, any attribute is accepted.
+    pub txt_attributes: Option<Vec<String>>,
+}
+
+/// A custom rule for packets stored by the server.
+///
+/// Like the [`PublishPolicyConfig`], the hook applies to published, replicated and imported
+/// packets. Runs after the rules from [`PublishPolicyConfig`] accepted the packet. Install a hook
+/// with [`Server::bind_with_publish_hook`].
+///
+/// [`Server::bind_with_publish_hook`]: crate::Server::bind_with_publish_hook
+pub trait PublishHook: Debug + Send + Sync + 'static {
+    /// Returns an error if `packet` must not be published.
+    fn check(&self, packet: &SignedPacket) -> Result<(), PolicyViolation>;
+}
+
+/// Reason for rejecting a published packet.
+#[allow(missing_docs)]
+#[stack_error(derive, add_meta)]
+#[non_exhaustive]
+pub enum PolicyViolation {
+    #[error("public key is not allowed to publish")]
+    KeyNotAllowed,
+    #[error("public key is denied")]
+    KeyDenied,
+    #[error("packet has {count} records (max {max})")]
+    TooManyRecords { count: usize, max: usize },
+    #[error("TXT record name {name:?} is not allowed")]
+    TxtNameNotAllowed { name: String },
+    #[error("TXT attribute {attribute:?} is not allowed")]
+    TxtAttributeNotAllowed { attribute: String },
+    #[error("invalid DNS packet")]
+    InvalidPacket,
+    #[error("{reason}")]
+    Custom { reason: String },
+}
+
+impl PolicyViolation {
+    /// Creates a [`PolicyViolation::Custom`] for use in a [`PublishHook`].
+    #[track_caller]
+    pub fn custom(reason: impl Into<String>) -> Self {
+        e!(PolicyViolation::Custom {
+            reason: reason.into()
+        })
+    }
+}
+
+/// The rules from a [`PublishPolicyConfig`] and an optional [`PublishHook`].
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PublishPolicy(Arc<Inner>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    allowed_keys: Option<BTreeSet<PublicKeyBytes>>,
+    denied_keys: BTreeSet<PublicKeyBytes>,
+    max_records: Option<usize>,
+    txt_names: Option<BTreeSet<String>>,
+    txt_attributes: Option<BTreeSet<String>>,
+    hook: Option<Arc<dyn PublishHook>>,
+}
+
+impl PublishPolicy {
+    pub(crate) fn new(
+        config: PublishPolicyConfig,
+        hook: Option<Arc<dyn PublishHook>>,
+    ) -> Result<Self> {
+        let parse_keys = |keys: Vec<String>| -> Result<BTreeSet<PublicKeyBytes>> {
+            keys.iter()
+                .map(|key| {
+                    PublicKeyBytes::from_z32(key)
+                        .with_std_context(|_| format!("invalid key in publish policy: {key}"))
+                })
+                .collect()
+        };
+        Ok(Self(Arc::new(Inner {
+            allowed_keys: config.allowed_keys.map(parse_keys).transpose()?,
+            denied_keys: parse_keys(config.denied_keys)?,
+            max_records: config.max_records,
+            txt_names: config.txt_names.map(BTreeSet::from_iter),
+            txt_attributes: config.txt_attributes.map(BTreeSet::from_iter),
+            hook,
+        })))
+    }
+
+    /// Checks whether `packet` may be published.
+    pub(crate) fn check(&self, packet: &SignedPacket) -> Result<(), PolicyViolation> {
+        let inner = &self.0;
+        let key = PublicKeyBytes::from_signed_packet(packet);
+        if inner.denied_keys.contains(&key) {
+            return Err(e!(PolicyViolation::KeyDenied));
+        }
+        if let Some(allowed) = &inner.allowed_keys
+            && !allowed.contains(&key)
+        {
+            return Err(e!(PolicyViolation::KeyNotAllowed));
+        }
+        if inner.max_records.is_some()
+            || inner.txt_names.is_some()
+            || inner.txt_attributes.is_some()
+        {
+            inner.check_records(packet)?;
+        }
+        if let Some(hook) = &inner.hook {
+            hook.check(packet)?;
+        }
+        Ok(())
+    }
+}
+
+impl Inner {
+    fn check_records(&self, packet: &SignedPacket) -> Result<(), PolicyViolation> {
+        let message = signed_packet_to_hickory_message(packet)
+            .map_err(|_| e!(PolicyViolation::InvalidPacket))?;
+        if let Some(max) = self.max_records {
+            let count = message.answers.len();
+            if count > max {
+                return Err(e!(PolicyViolation::TooManyRecords { count, max }));
+            }
+        }
+        for record in &message.answers {
+            let RData::TXT(txt) = &record.data else {
+                continue;
+            };
+            if let Some(names) = &self.txt_names {
+                let name = relative_name(&record.name);
+                if !names.contains(&name) {
+                    return Err(e!(PolicyViolation::TxtNameNotAllowed { name }));
+                }
+            }
+            if let Some(attributes) = &self.txt_attributes {
+                for value in txt.txt_data.iter() {
+                    let value = String::from_utf8_lossy(value);
+                    let attribute = value.split_once('=').map_or(&*value, |(key, _)| key);
+                    if !attributes.contains(attribute) {
+                        return Err(e!(PolicyViolation::TxtAttributeNotAllowed {
+                            attribute: attribute.to_string()
+                        }));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns `name` without its last label, which is the public key zone.
+fn relative_name(name: &Name) -> String {
+    // `num_labels` does not count the wildcard label, so count the labels manually
+    let labels = name.iter().count().saturating_sub(1);
+    if labels == 0 {
+        return APEX_NAME.to_string();
+    }
+    name.iter()
+        .take(labels)
+        .map(|label| String::from_utf8_lossy(label).into_owned())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh_base::SecretKey;
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct RejectRelay;
+
+    impl PublishHook for RejectRelay {
+        fn check(&self, packet: &SignedPacket) -> Result<(), PolicyViolation> {
+            if packet
+                .txt_records("_iroh")
+                .iter()
+                .any(|v| v.starts_with("relay="))
+            {
+                return Err(PolicyViolation::custom("relays are not supported"));
+            }
+            Ok(())
+        }
+    }
+
+    fn packet(secret_key: &SecretKey, name: &str, values: &[&str]) -> Result<SignedPacket> {
+        SignedPacket::from_txt_strings(secret_key, name, values, 30).anyerr()
+    }
+
+    #[test]
+    fn publish_policy() -> Result {
+        let allowed = SecretKey::generate();
+        let denied = SecretKey::generate();
+        let other = SecretKey::generate();
+        let config = PublishPolicyConfig {
+            allowed_keys: Some(vec![allowed.public().to_z32(), denied.public().to_z32()]),
+            denied_keys: vec![denied.public().to_z32()],
+            max_records: Some(2),
+            txt_names: Some(vec!["_iroh".to_string()]),
+            txt_attributes: Some(vec!["relay".to_string(), "addr".to_string()]),
+        };
+        let policy = PublishPolicy::new(config, Some(Arc::new(RejectRelay)))?;
+
+        let valid = packet(&allowed, "_iroh", &["addr=127.0.0.1:1234"])?;
+        policy.check(&valid)?;
+
+        let check = |packet: SignedPacket| policy.check(&packet).unwrap_err();
+        assert!(matches!(
+            check(packet(&denied, "_iroh", &["addr=127.0.0.1:1234"])?),
+            PolicyViolation::KeyDenied { .. }
+        ));
+        assert!(matches!(
+            check(packet(&other, "_iroh", &["addr=127.0.0.1:1234"])?),
+            PolicyViolation::KeyNotAllowed { .. }
+        ));
+        assert!(matches!(
+            check(packet(&allowed, "_iroh", &["addr=a", "addr=b", "addr=c"])?),
+            PolicyViolation::TooManyRecords {
+                count: 3,
+                max: 2,
+                ..
+            }
+        ));
+        assert!(matches!(
+            check(packet(&allowed, "_other", &["addr=127.0.0.1:1234"])?),
+            PolicyViolation::TxtNameNotAllowed { name, .. } if name == "_other"
+        ));
+        assert!(matches!(
+            check(packet(&allowed, "_iroh", &["user-data=hi"])?),
+            PolicyViolation::TxtAttributeNotAllowed { attribute, .. } if attribute == "user-data"
+        ));
+        assert!(matches!(
+            check(packet(&allowed, "_iroh", &["relay=https://relay.example"])?),
+            PolicyViolation::Custom { reason, .. } if reason == "relays are not supported"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn publish_policy_wildcard_name() -> Result {
+        let key = SecretKey::generate();
+        let config = PublishPolicyConfig {
+            txt_names: Some(vec!["*.foo".to_string()]),
+            ..Default::default()
+        };
+        let policy = PublishPolicy::new(config, None)?;
+
+        policy.check(&packet(&key, "*.foo", &["addr=127.0.0.1:1234"])?)?;
+        let err = policy
+            .check(&packet(&key, "*.bar", &["addr=127.0.0.1:1234"])?)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            PolicyViolation::TxtNameNotAllowed { name, .. } if name == "*.bar"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn publish_policy_invalid_key() {
+        let config = PublishPolicyConfig {
+            denied_keys: vec!["not-a-key".to_string()],
+            ..Default::default()
+        };
+        assert!(PublishPolicy::new(config, None).is_err());
+    }
+}
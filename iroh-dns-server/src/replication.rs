@@ -0,0 +1,406 @@
+//! Replication of signed packets between iroh-dns-server instances.
+//!
+//! Every server configured with [`ReplicationConfig`] pushes the packets it accepts via
+//! `PUT /pkarr` to all of its peers, and periodically pages through the packets stored on
+//! its peers to backfill packets it missed, e.g. because it was offline.
+//!
+//! Peers authenticate each other with a shared bearer token. Signed packets are verified
+//! before they are stored, and a peer never forwards packets it received via replication,
+//! so replicas must be configured as a full mesh.
+
+use std::{sync::Arc, time::Duration};
+
+use iroh_dns::pkarr::SignedPacket;
+use n0_error::{Result, StdResultExt, ensure_any};
+use n0_future::task::AbortOnDropHandle;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::{Instrument, debug, info, info_span, warn};
+use url::Url;
+
+use crate::{
+    metrics::Metrics,
+    store::{PacketSource, ZoneStore},
+    util::{PublicKeyBytes, bearer_token_matches, decode_packets},
+};
+
+/// Path of the replication endpoint, relative to a peer's base URL.
+pub(crate) const REPLICATION_PATH: &str = "/replication/packets";
+
+/// Maximum number of packets returned from a single replication `GET` request.
+pub(crate) const MAX_PAGE_SIZE: usize = 1024;
+
+/// Number of packets queued per peer before new packets are dropped.
+const PEER_QUEUE_SIZE: usize = 1024;
+
+/// Timeout for a single replication request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Configuration for replicating signed packets between servers.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[non_exhaustive]
+pub struct ReplicationConfig {
+    /// Shared secret that authenticates replicas to each other.
+    ///
+    /// All replicas must be configured with the same token. The token is sent as a bearer
+    /// token, so peers should be reached over HTTPS.
+    pub token: String,
+    /// Base URLs of the peer servers, e.g. `https://dns2.example.org`.
+    ///
+    /// Replicas must be configured as a full mesh: every server needs to list every other
+    /// server as a peer.
+    #[serde(default)]
+    pub peers: Vec<Url>,
+    /// Interval between full syncs from all peers.
+    ///
+    /// A full sync also runs when the server starts. Defaults to one hour.
+    #[serde(default = "default_resync_interval", with = "humantime_serde")]
+    pub resync_interval: Duration,
+}
+
+fn default_resync_interval() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+impl ReplicationConfig {
+    /// Creates a new [`ReplicationConfig`] with the default resync interval.
+    pub fn new(token: impl Into<String>, peers: Vec<Url>) -> Self {
+        Self {
+            token: token.into(),
+            peers,
+            resync_interval: default_resync_interval(),
+        }
+    }
+}
+
+/// Handle to the replication tasks.
+///
+/// The tasks are aborted once the last clone of the handle is dropped.
+#[derive(Debug, Clone)]
+pub(crate) struct Replication(Arc<Inner>);
+
+#[derive(Debug)]
+struct Inner {
+    token: String,
+    peers: Vec<mpsc::Sender<SignedPacket>>,
+    metrics: Arc<Metrics>,
+    _tasks: Vec<AbortOnDropHandle<()>>,
+}
+
+impl Replication {
+    /// Spawns push and sync tasks for all configured peers.
+    pub(crate) fn spawn(
+        config: ReplicationConfig,
+        store: ZoneStore,
+        metrics: Arc<Metrics>,
+    ) -> Result<Self> {
+        let client = http_client()?;
+        let mut peers = Vec::new();
+        let mut tasks = Vec::new();
+        for url in config.peers {
+            info!(peer = %url, "replicating with peer");
+            let peer = Peer {
+                url: url.join(REPLICATION_PATH).anyerr()?,
+                token: config.token.clone(),
+                client: client.clone(),
+                store: store.clone(),
+                metrics: metrics.clone(),
+            };
+            let (send, recv) = mpsc::channel(PEER_QUEUE_SIZE);
+            peers.push(send);
+            let span = info_span!("replication", peer = %url);
+            tasks.push(AbortOnDropHandle::new(tokio::spawn(
+                peer.clone().push_loop(recv).instrument(span.clone()),
+            )));
+            tasks.push(AbortOnDropHandle::new(tokio::spawn(
+                peer.sync_loop(config.resync_interval).instrument(span),
+            )));
+        }
+        Ok(Self(Arc::new(Inner {
+            token: config.token,
+            peers,
+            metrics,
+            _tasks: tasks,
+        })))
+    }
+
+    /// Queues a packet that was published to this server to be pushed to all peers.
+    pub(crate) fn publish(&self, packet: &SignedPacket) {
+        for peer in &self.0.peers {
+            if peer.try_send(packet.clone()).is_err() {
+                self.0.metrics.replication_packets_dropped.inc();
+            }
+        }
+    }
+
+    /// Returns whether an `Authorization` header value carries the replication token.
+    pub(crate) fn is_authorized(&self, authorization: Option<&[u8]>) -> bool {
+        bearer_token_matches(authorization, &self.0.token)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Peer {
+    url: Url,
+    token: String,
+    client: reqwest::Client,
+    store: ZoneStore,
+    metrics: Arc<Metrics>,
+}
+
+impl Peer {
+    /// Pushes queued packets to the peer.
+    ///
+    /// Packets that fail to be pushed are not retried, they are picked up by the next sync.
+    async fn push_loop(self, mut recv: mpsc::Receiver<SignedPacket>) {
+        while let Some(packet) = recv.recv().await {
+            match self.push(&packet).await {
+                Ok(()) => {
+                    self.metrics.replication_packets_sent.inc();
+                }
+                Err(err) => {
+                    warn!("failed to push packet: {err:#}");
+                    self.metrics.replication_errors.inc();
+                }
+            }
+        }
+    }
+
+    async fn push(&self, packet: &SignedPacket) -> Result<()> {
+        let res = self
+            .client
+            .put(self.url.clone())
+            .bearer_auth(&self.token)
+            .body(packet.as_bytes().to_vec())
+            .send()
+            .await
+            .anyerr()?;
+        ensure_any!(
+            res.status().is_success(),
+            "unexpected response status {}",
+            res.status()
+        );
+        Ok(())
+    }
+
+    /// Syncs all packets from the peer, once on startup and then in the configured interval.
+    async fn sync_loop(self, interval: Duration) {
+        loop {
+            match self.sync().await {
+                Ok(count) => info!("synced {count} packets"),
+                Err(err) => {
+                    warn!("failed to sync: {err:#}");
+                    self.metrics.replication_errors.inc();
+                }
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// Pages through all packets stored on the peer and stores them locally.
+    ///
+    /// Returns the number of packets that updated the local store.
+    async fn sync(&self) -> Result<usize> {
+        let mut after: Option<PublicKeyBytes> = None;
+        let mut updated = 0;
+        loop {
+            let mut url = self.url.clone();
+            if let Some(after) = after {
+                url.query_pairs_mut().append_pair("after", &after.to_z32());
+            }
+            let res = self
+                .client
+                .get(url)
+                .bearer_auth(&self.token)
+                .send()
+                .await
+                .anyerr()?;
+            ensure_any!(
+                res.status().is_success(),
+                "unexpected response status {}",
+                res.status()
+            );
+            let packets = decode_packets(res.bytes().await.anyerr()?)?;
+            debug!(len = packets.len(), "received page");
+            let Some(last) = packets.last() else {
+                break;
+            };
+            after = Some(PublicKeyBytes::from_signed_packet(last));
+            for packet in packets {
+                self.metrics.replication_packets_recv.inc();
+                if self
+                    .store
+                    .insert(packet, PacketSource::Replication)
+                    .await?
+                    .is_updated()
+                {
+                    updated += 1;
+                }
+            }
+        }
+        Ok(updated)
+    }
+}
+
+/// Creates an HTTP client that verifies server certificates against the webpki roots.
+pub(crate) fn http_client() -> Result<reqwest::Client> {
+    let roots = rustls::RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let tls_config = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .anyerr()?
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+    reqwest::Client::builder()
+        .tls_backend_preconfigured(tls_config)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .anyerr()
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh_base::SecretKey;
+    use n0_error::anyerr;
+    use n0_tracing_test::traced_test;
+
+    use super::*;
+    use crate::{Server, server::test_config, util::encode_packets};
+
+    const TOKEN: &str = "replication-secret";
+
+    async fn spawn_replica(dir: &std::path::Path, peers: Vec<Url>) -> Result<Server> {
+        let mut config = test_config(dir);
+        config.replication = Some(ReplicationConfig::new(TOKEN, peers));
+        Server::bind(config).await
+    }
+
+    async fn publish(server: &Server, packet: &SignedPacket) -> Result<()> {
+        let url = format!(
+            "{}pkarr/{}",
+            server.http_url().expect("http is bound"),
+            packet.public_key().to_z32()
+        );
+        let res = http_client()?
+            .put(url)
+            .body(packet.to_relay_payload())
+            .send()
+            .await
+            .anyerr()?;
+        ensure_any!(
+            res.status().is_success(),
+            "publish failed: {}",
+            res.status()
+        );
+        Ok(())
+    }
+
+    async fn wait_for_packet(server: &Server, packet: &SignedPacket) -> Result<()> {
+        let url = format!(
+            "{}pkarr/{}",
+            server.http_url().expect("http is bound"),
+            packet.public_key().to_z32()
+        );
+        let client = http_client()?;
+        for _ in 0..100 {
+            let res = client.get(&url).send().await.anyerr()?;
+            if res.status().is_success() && res.bytes().await.anyerr()? == packet.to_relay_payload()
+            {
+                return Ok(());
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        Err(anyerr!("packet was not replicated"))
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn replication_backfill_and_push() -> Result {
+        let dir_a = tempfile::tempdir()?;
+        let dir_b = tempfile::tempdir()?;
+        let packet_a = SignedPacket::from_txt_strings(
+            &SecretKey::generate(),
+            "_iroh",
+            ["relay=https://a.example.com"],
+            30,
+        )
+        .anyerr()?;
+        let packet_b = SignedPacket::from_txt_strings(
+            &SecretKey::generate(),
+            "_iroh",
+            ["relay=https://b.example.com"],
+            30,
+        )
+        .anyerr()?;
+
+        // A packet published to `a` before `b` exists is backfilled by `b`.
+        let a = spawn_replica(dir_a.path(), vec![]).await?;
+        publish(&a, &packet_a).await?;
+        let b = spawn_replica(dir_b.path(), vec![a.http_url().expect("http is bound")]).await?;
+        wait_for_packet(&b, &packet_a).await?;
+
+        // A packet published to `b` is pushed to `a`.
+        publish(&b, &packet_b).await?;
+        wait_for_packet(&a, &packet_b).await?;
+        assert_eq!(b.metrics().replication_packets_sent.get(), 1);
+        assert_eq!(a.metrics().replication_packets_recv.get(), 1);
+
+        a.shutdown().await?;
+        b.shutdown().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replication_requires_token() -> Result {
+        let dir = tempfile::tempdir()?;
+        let server = spawn_replica(dir.path(), vec![]).await?;
+        let url = server
+            .http_url()
+            .expect("http is bound")
+            .join(REPLICATION_PATH)
+            .anyerr()?;
+        let client = http_client()?;
+
+        let res = client.get(url.clone()).send().await.anyerr()?;
+        assert_eq!(res.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let res = client
+            .get(url.clone())
+            .bearer_auth("wrong")
+            .send()
+            .await
+            .anyerr()?;
+        assert_eq!(res.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let res = client.get(url).bearer_auth(TOKEN).send().await.anyerr()?;
+        assert!(res.status().is_success());
+        assert!(decode_packets(res.bytes().await.anyerr()?)?.is_empty());
+
+        server.shutdown().await?;
+        Ok(())
+    }
+
+    #[test]
+    fn packets_roundtrip() -> Result {
+        let packets = (0..3)
+            .map(|i| {
+                SignedPacket::from_txt_strings(
+                    &SecretKey::generate(),
+                    "_iroh",
+                    [format!("relay=https://{i}.example.com")],
+                    30,
+                )
+                .anyerr()
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let decoded = decode_packets(encode_packets(&packets))?;
+        assert_eq!(decoded.len(), packets.len());
+        for (a, b) in packets.iter().zip(&decoded) {
+            assert_eq!(a.as_bytes(), b.as_bytes());
+        }
+
+        let mut truncated = encode_packets(&packets);
+        truncated.truncate(truncated.len() - 1);
+        assert!(decode_packets(truncated).is_err());
+        Ok(())
+    }
+}
@@ -4,6 +4,7 @@ use std::path::Path;
 use std::{net::SocketAddr, sync::Arc};
 
 use n0_error::Result;
+use n0_future::task::AbortOnDropHandle;
 use tracing::info;
 #[cfg(test)]
 use url::Url;
@@ -12,11 +13,14 @@ use url::Url;
 use crate::http::HttpsConfig;
 use crate::{
     config::Config,
+    dht_publish::DhtPublisher,
     dns::{DnsHandler, DnsServer},
     http::HttpServer,
     metrics::Metrics,
+    policy::{PublishHook, PublishPolicy},
+    replication::Replication,
     state::AppState,
-    store::ZoneStore,
+    store::{Blocklist, PacketStore, ZoneStore},
 };
 
 /// A running iroh-dns server.
@@ -29,46 +33,129 @@ pub struct Server {
     dns_server: DnsServer,
     metrics_server: Option<iroh_metrics::service::MetricsServer>,
     metrics: Arc<Metrics>,
+    _eviction: Option<AbortOnDropHandle<()>>,
 }
 
 impl Server {
     /// Binds and spawns the server from a [`Config`].
     ///
-    /// Opens the persistent signed-packet store, enables the mainline DHT
-    /// fallback when configured, and spawns the DNS, HTTP(S), and metrics tasks.
+    /// Opens the persistent signed-packet store and blocklist, enables the mainline DHT
+    /// fallback and publishing when configured, and spawns the DNS, HTTP(S), and metrics
+    /// tasks.
     /// Returns once all listeners are bound.
     pub async fn bind(config: Config) -> Result<Self> {
+        Self::bind_inner(config, None, None).await
+    }
+
+    /// Binds and spawns the server like [`Self::bind`], checking published packets with `hook`.
+    ///
+    /// The hook runs for every packet published via `PUT /pkarr`, replicated, or imported,
+    /// that passes the rules configured in [`Config::publish_policy`].
+    pub async fn bind_with_publish_hook(config: Config, hook: impl PublishHook) -> Result<Self> {
+        Self::bind_inner(config, None, Some(Arc::new(hook))).await
+    }
+
+    /// Binds and spawns the server like [`Self::bind`], storing packets in `packet_store`.
+    ///
+    /// [`StoreConfig::backend`] is ignored. Expired packets are evicted from `packet_store`
+    /// every [`StoreConfig::eviction_interval`].
+    ///
+    /// [`StoreConfig::backend`]: crate::config::StoreConfig::backend
+    /// [`StoreConfig::eviction_interval`]: crate::config::StoreConfig::eviction_interval
+    pub async fn bind_with_packet_store(
+        config: Config,
+        packet_store: Box<dyn PacketStore>,
+    ) -> Result<Self> {
+        Self::bind_inner(config, Some(packet_store), None).await
+    }
+
+    async fn bind_inner(
+        config: Config,
+        packet_store: Option<Box<dyn PacketStore>>,
+        hook: Option<Arc<dyn PublishHook>>,
+    ) -> Result<Self> {
         let metrics = Arc::new(Metrics::default());
-        let mut store = ZoneStore::persistent(
-            config.signed_packet_store_path()?,
-            config.zone_store.clone().unwrap_or_default().into(),
-            metrics.clone(),
-        )?;
+        let store_config = config.zone_store.clone().unwrap_or_default();
+        let eviction_interval = store_config.eviction_interval;
+        let (mut store, eviction) = match packet_store {
+            Some(packet_store) => {
+                let store = ZoneStore::with_packet_store(
+                    packet_store,
+                    store_config.into(),
+                    metrics.clone(),
+                );
+                let eviction = store.spawn_eviction(eviction_interval);
+                (store, Some(eviction))
+            }
+            None => {
+                let store = ZoneStore::persistent(
+                    config.signed_packet_store_path()?,
+                    store_config.backend,
+                    store_config.into(),
+                    metrics.clone(),
+                )?;
+                (store, None)
+            }
+        };
         if let Some(bootstrap) = config.mainline_enabled() {
             info!("mainline fallback enabled");
             store = store.with_mainline_fallback(bootstrap);
         };
-        Self::bind_with_store(config, store, metrics).await
+        let blocklist = Blocklist::persistent(config.data_dir()?.join("blocked-keys.txt"))?;
+        let store = store.with_blocklist(blocklist);
+        let mut server = Self::bind_with_store(config, store, hook, metrics).await?;
+        server._eviction = eviction;
+        Ok(server)
     }
 
     /// Spawn the server.
     ///
     /// This will spawn several background tasks:
-    /// * A DNS server task
+    /// * A DNS server task, with DNS-over-TLS and DNS-over-QUIC listeners if configured
     /// * A HTTP server task, if `config.http` is not empty
     /// * A HTTPS server task, if `config.https` is not empty
+    /// * Replication tasks for each peer, if `config.replication` is not empty
+    /// * Mainline DHT publishing tasks, if enabled in `config.mainline`
     async fn bind_with_store(
         config: Config,
         store: ZoneStore,
+        hook: Option<Arc<dyn PublishHook>>,
         metrics: Arc<Metrics>,
     ) -> Result<Self> {
+        let policy = PublishPolicy::new(config.publish_policy.clone().unwrap_or_default(), hook)?;
+        let store = store.with_policy(policy);
+        store.load_serial().await?;
         let cert_cache_dir = config.data_dir()?.join("cert_cache");
-        let dns_handler = DnsHandler::new(store.clone(), &config.dns, metrics.clone())?;
+        let dns_handler = DnsHandler::new(
+            store.clone(),
+            &config.dns,
+            &config.data_dir()?,
+            metrics.clone(),
+        )?;
+        let replication = config
+            .replication
+            .clone()
+            .map(|config| Replication::spawn(config, store.clone(), metrics.clone()))
+            .transpose()?;
+        let dht_publisher = match (config.mainline_publish(), store.dht()) {
+            (Some(republish_interval), Some(dht)) => {
+                info!("mainline publishing enabled");
+                Some(DhtPublisher::spawn(
+                    dht,
+                    store.clone(),
+                    republish_interval,
+                    metrics.clone(),
+                ))
+            }
+            _ => None,
+        };
 
         let state = AppState {
             store,
             dns_handler,
             metrics: metrics.clone(),
+            replication,
+            dht_publisher,
         };
 
         let metrics_server = if let Some(addr) = config.metrics_addr() {
@@ -85,17 +172,24 @@ impl Server {
             config.http,
             config.https,
             config.pkarr_put_rate_limit,
+            config.admin,
             state.clone(),
             cert_cache_dir,
         )
         .await?;
-        let dns_server = DnsServer::spawn(config.dns, state.dns_handler.clone()).await?;
+        let dns_server = DnsServer::spawn(
+            config.dns,
+            state.dns_handler.clone(),
+            http_server.cert_resolver(),
+        )
+        .await?;
 
         Ok(Self {
             http_server,
             dns_server,
             metrics_server,
             metrics,
+            _eviction: None,
         })
     }
 
@@ -150,25 +244,15 @@ impl Server {
         options: Option<crate::store::Options>,
         https: Option<HttpsConfig>,
     ) -> Result<Self> {
-        use std::net::{IpAddr, Ipv4Addr};
-
-        use crate::config::MetricsConfig;
-
-        let mut config = Config::default();
-        config.dns.port = 0;
-        config.dns.bind_addr = Some(IpAddr::V4(Ipv4Addr::LOCALHOST));
-        config.http.as_mut().unwrap().port = 0;
-        config.http.as_mut().unwrap().bind_addr = Some(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let mut config = test_config(dir.as_ref());
         config.https = https;
-        config.metrics = Some(MetricsConfig::disabled());
-        config.data_dir = Some(dir.as_ref().to_owned());
 
         let mut store = ZoneStore::in_memory(options.unwrap_or_default(), Default::default())?;
         if let Some(bootstrap) = mainline {
             info!("mainline fallback enabled");
             store = store.with_mainline_fallback(bootstrap);
         }
-        let server = Self::bind_with_store(config, store, Default::default()).await?;
+        let server = Self::bind_with_store(config, store, None, Default::default()).await?;
         Ok(server)
     }
 
@@ -177,6 +261,18 @@ impl Server {
         self.dns_server.local_addr()
     }
 
+    /// Returns the local address of the DNS-over-TLS listener, or `None` if no
+    /// DNS-over-TLS listener was configured.
+    pub fn dns_tls_addr(&self) -> Option<SocketAddr> {
+        self.dns_server.tls_addr()
+    }
+
+    /// Returns the local address of the DNS-over-QUIC listener, or `None` if no
+    /// DNS-over-QUIC listener was configured.
+    pub fn dns_quic_addr(&self) -> Option<SocketAddr> {
+        self.dns_server.quic_addr()
+    }
+
     /// Returns the local address of the HTTP listener, or `None` if no HTTP
     /// listener was configured.
     pub fn http_addr(&self) -> Option<SocketAddr> {
@@ -209,3 +305,24 @@ impl Server {
         )
     }
 }
+
+/// Returns a config suitable for testing.
+///
+/// The DNS and HTTP listeners bind to random ports on localhost, HTTPS and the metrics
+/// server are disabled, and data is stored in `dir`.
+#[cfg(test)]
+pub(crate) fn test_config(dir: &Path) -> Config {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use crate::config::MetricsConfig;
+
+    let mut config = Config::default();
+    config.dns.port = 0;
+    config.dns.bind_addr = Some(IpAddr::V4(Ipv4Addr::LOCALHOST));
+    config.http.as_mut().unwrap().port = 0;
+    config.http.as_mut().unwrap().bind_addr = Some(IpAddr::V4(Ipv4Addr::LOCALHOST));
+    config.https = None;
+    config.metrics = Some(MetricsConfig::disabled());
+    config.data_dir = Some(dir.to_owned());
+    config
+}
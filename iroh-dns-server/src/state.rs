@@ -2,7 +2,10 @@
 
 use std::sync::Arc;
 
-use crate::{dns::DnsHandler, metrics::Metrics, store::ZoneStore};
+use crate::{
+    dht_publish::DhtPublisher, dns::DnsHandler, metrics::Metrics, replication::Replication,
+    store::ZoneStore,
+};
 
 /// The shared app state.
 #[derive(Clone)]
@@ -13,4 +16,8 @@ pub(crate) struct AppState {
     pub dns_handler: DnsHandler,
     /// Metrics collector.
     pub metrics: Arc<Metrics>,
+    /// Replication to peer servers, if enabled.
+    pub replication: Option<Replication>,
+    /// Publishing to the mainline DHT, if enabled.
+    pub dht_publisher: Option<DhtPublisher>,
 }
@@ -1,68 +1,203 @@
 //! Pkarr packet store used to resolve DNS queries.
 
-use std::{collections::BTreeMap, num::NonZeroUsize, path::Path, sync::Arc, time::Duration};
+use std::{
+    collections::BTreeMap,
+    num::NonZeroUsize,
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
+use async_trait::async_trait;
 use hickory_server::proto::{
     ProtoError,
-    rr::{Name, RecordSet, RecordType, RrKey},
+    rr::{Name, RecordSet, RecordType, RrKey, SerialNumber},
 };
 use iroh_dns::pkarr::{SignedPacket, SignedPacketVerifyError, Timestamp};
 use lru::LruCache;
 use mainline::{Dht, DhtBuilder, MutableItem};
 use n0_error::{Result, StdResultExt};
+use n0_future::task::AbortOnDropHandle;
 pub(crate) use signed_packets::Options;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, watch};
 use tracing::{debug, trace, warn};
 use ttl_cache::TtlCache;
 
-use self::signed_packets::SignedPacketStore;
+pub(crate) use self::blocklist::Blocklist;
+use self::{signed_packets::SignedPacketStore, sqlite::SqlitePacketStore};
 use crate::{
-    config::BootstrapOption,
+    config::{BootstrapOption, StoreBackend},
     metrics::Metrics,
+    policy::{PolicyViolation, PublishPolicy},
     util::{PublicKeyBytes, signed_packet_to_hickory_records_without_origin},
 };
 
+mod blocklist;
 mod signed_packets;
+mod sqlite;
 
 /// Cache up to 1 million pkarr zones by default
 const DEFAULT_CACHE_CAPACITY: usize = 1024 * 1024;
 /// Default TTL for DHT cache entries
 const DHT_CACHE_TTL: Duration = Duration::from_secs(300);
+/// Number of serials reserved in the packet store with a single write.
+///
+/// The packet store holds the end of the reserved range, and is only written again once
+/// the serial reaches it. After a restart the serial continues after the reserved range,
+/// so it does not go back even though not every increment is persisted.
+const SERIAL_RESERVATION: u32 = 1024;
 
 /// Where a new pkarr packet comes from
+#[derive(Debug, Clone, Copy)]
 pub(crate) enum PacketSource {
     /// Received via HTTPS relay PUT
     PkarrPublish,
+    /// Received from a peer server via replication
+    Replication,
+    /// Imported via the admin API
+    Import,
+}
+
+/// The result of [inserting](ZoneStore::insert) a packet.
+#[derive(Debug)]
+pub(crate) enum InsertOutcome {
+    /// The packet is stored as the newest packet for its key.
+    Updated,
+    /// The packet was not stored, the stored packet for its key is more recent.
+    Unchanged,
+    /// The packet was not stored, its key is blocked.
+    Blocked,
+    /// The packet was not stored, it violates the publish policy.
+    Rejected(PolicyViolation),
+}
+
+impl InsertOutcome {
+    /// Returns whether the packet is stored as the newest packet for its key.
+    pub(crate) fn is_updated(&self) -> bool {
+        matches!(self, Self::Updated)
+    }
+}
+
+/// A persistent storage backend for pkarr signed packets.
+///
+/// A backend stores at most one packet per public key. It only replaces a stored packet with
+/// one that is [more recent](SignedPacket::more_recent_than).
+///
+/// The built-in backends are selected with [`StoreConfig::backend`]. Other backends are
+/// implemented with the [`async_trait`] macro and passed to
+/// [`Server::bind_with_packet_store`], which [evicts](Self::evict) expired packets every
+/// [`StoreConfig::eviction_interval`].
+///
+/// [`StoreConfig::backend`]: crate::config::StoreConfig::backend
+/// [`StoreConfig::eviction_interval`]: crate::config::StoreConfig::eviction_interval
+/// [`Server::bind_with_packet_store`]: crate::Server::bind_with_packet_store
+/// [`async_trait`]: https://docs.rs/async-trait
+#[async_trait]
+pub trait PacketStore: std::fmt::Debug + Send + Sync + 'static {
+    /// Inserts a packet, unless a more recent packet for the same key is already stored.
+    ///
+    /// Returns whether the packet was stored.
+    async fn upsert(&self, packet: SignedPacket) -> Result<bool>;
+
+    /// Returns the packet stored for a key.
+    async fn get(&self, key: &PublicKeyBytes) -> Result<Option<SignedPacket>>;
+
+    /// Returns up to `limit` stored packets, ordered by key.
+    ///
+    /// If `after` is set, only packets with a key greater than `after` are returned. This
+    /// allows to page through all stored packets.
+    async fn packets(
+        &self,
+        after: Option<PublicKeyBytes>,
+        limit: usize,
+    ) -> Result<Vec<SignedPacket>>;
+
+    /// Removes the packet stored for a key.
+    ///
+    /// Returns whether a packet was removed.
+    async fn remove(&self, key: &PublicKeyBytes) -> Result<bool>;
+
+    /// Removes all packets with a timestamp older than `expired`.
+    ///
+    /// Returns the number of removed packets.
+    async fn evict(&self, expired: Timestamp) -> Result<usize>;
+
+    /// Returns the serial stored with [`Self::set_serial`].
+    ///
+    /// Serials are compared with the serial number arithmetic of RFC 1982.
+    ///
+    /// The server continues after this serial when it restarts, so that secondary name
+    /// servers notice changes. The default implementation does not persist the serial, the
+    /// serial then restarts at the current unix time.
+    async fn serial(&self) -> Result<Option<u32>> {
+        Ok(None)
+    }
+
+    /// Stores the serial of the zones served from this store, unless a greater serial is
+    /// already stored.
+    ///
+    /// The server does not call this for every change, but reserves a range of serials
+    /// ahead of the zones' serial.
+    async fn set_serial(&self, _serial: u32) -> Result<()> {
+        Ok(())
+    }
 }
 
 /// A store for pkarr signed packets.
 ///
-/// Packets are stored in the persistent `SignedPacketStore`, and cached on-demand in an in-memory LRU
+/// Packets are stored in a persistent [`PacketStore`], and cached on-demand in an in-memory LRU
 /// cache used for resolving DNS queries.
 #[derive(Debug, Clone)]
 pub(crate) struct ZoneStore {
     cache: Arc<Mutex<ZoneCache>>,
-    store: Arc<SignedPacketStore>,
+    store: Arc<dyn PacketStore>,
+    serial: Arc<watch::Sender<u32>>,
+    /// The end of the serials reserved in the packet store, once reserved.
+    reserved_serial: Arc<std::sync::Mutex<Option<u32>>>,
+    blocklist: Blocklist,
+    policy: PublishPolicy,
+    eviction: Duration,
     dht: Option<Dht>,
     metrics: Arc<Metrics>,
 }
 
 impl ZoneStore {
-    /// Create a persistent store
+    /// Create a persistent store using the given backend.
     pub(crate) fn persistent(
         path: impl AsRef<Path>,
+        backend: StoreBackend,
         options: Options,
         metrics: Arc<Metrics>,
     ) -> Result<Self> {
-        let packet_store = SignedPacketStore::persistent(path, options, metrics.clone())?;
-        Ok(Self::new(packet_store, metrics))
+        let packet_store: Arc<dyn PacketStore> = match backend {
+            StoreBackend::Redb => Arc::new(SignedPacketStore::persistent(
+                path,
+                options,
+                metrics.clone(),
+            )?),
+            StoreBackend::Sqlite => Arc::new(SqlitePacketStore::persistent(
+                path,
+                options,
+                metrics.clone(),
+            )?),
+        };
+        Ok(Self::new(packet_store, options, metrics))
+    }
+
+    /// Create a store using a custom backend.
+    pub(crate) fn with_packet_store(
+        store: Box<dyn PacketStore>,
+        options: Options,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        Self::new(Arc::from(store), options, metrics)
     }
 
     /// Create an in-memory store.
     #[cfg(test)]
     pub(crate) fn in_memory(options: Options, metrics: Arc<Metrics>) -> Result<Self> {
         let packet_store = SignedPacketStore::in_memory(options, metrics.clone())?;
-        Ok(Self::new(packet_store, metrics))
+        Ok(Self::new(Arc::new(packet_store), options, metrics))
     }
 
     /// Configure a mainline DHT client for resolution of packets as a fallback.
@@ -83,17 +218,111 @@ impl ZoneStore {
         }
     }
 
+    /// Returns the mainline DHT client, if the mainline fallback is configured.
+    pub(crate) fn dht(&self) -> Option<&Dht> {
+        self.dht.as_ref()
+    }
+
+    /// Use a blocklist to refuse storing and resolving packets for blocked keys.
+    pub(crate) fn with_blocklist(self, blocklist: Blocklist) -> Self {
+        Self { blocklist, ..self }
+    }
+
+    /// Use a policy to refuse storing packets, regardless of their [source](PacketSource).
+    pub(crate) fn with_policy(self, policy: PublishPolicy) -> Self {
+        Self { policy, ..self }
+    }
+
     /// Create a new zone store.
-    fn new(store: SignedPacketStore, metrics: Arc<Metrics>) -> Self {
+    fn new(store: Arc<dyn PacketStore>, options: Options, metrics: Arc<Metrics>) -> Self {
         let zone_cache = ZoneCache::new(DEFAULT_CACHE_CAPACITY, metrics.clone());
+        // Start at the current time, so that the serial keeps increasing across restarts even
+        // if the packet store does not persist it.
+        let serial = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|time| time.as_secs() as u32)
+            .unwrap_or_default();
         Self {
-            store: Arc::new(store),
+            store,
+            serial: Arc::new(watch::Sender::new(serial)),
+            reserved_serial: Default::default(),
+            blocklist: Blocklist::default(),
+            policy: PublishPolicy::default(),
+            eviction: options.eviction,
             cache: Arc::new(Mutex::new(zone_cache)),
             dht: None,
             metrics,
         }
     }
 
+    /// Loads the serial persisted in the packet store.
+    ///
+    /// The [serial](Self::serial) continues after the persisted serial, or at the current
+    /// unix time if that is greater.
+    pub(crate) async fn load_serial(&self) -> Result<()> {
+        if let Some(serial) = self.store.serial().await? {
+            self.raise_serial(serial.wrapping_add(1));
+        }
+        Ok(())
+    }
+
+    /// Returns the serial number of the zones served from this store.
+    ///
+    /// The serial starts at the current unix time, or after the serial persisted in the
+    /// packet store once [loaded](Self::load_serial). It is incremented whenever a packet is
+    /// inserted, removed or evicted.
+    pub(crate) fn serial(&self) -> u32 {
+        *self.serial.borrow()
+    }
+
+    /// Returns a receiver that is notified when the [serial](Self::serial) changes.
+    pub(crate) fn subscribe_serial(&self) -> watch::Receiver<u32> {
+        self.serial.subscribe()
+    }
+
+    /// Raises the [serial](Self::serial) to `serial` if it is lower.
+    ///
+    /// Serials are compared with the serial number arithmetic of RFC 1982.
+    pub(crate) fn raise_serial(&self, serial: u32) {
+        self.serial.send_if_modified(|current| {
+            let raise = SerialNumber::new(serial) > SerialNumber::new(*current);
+            if raise {
+                *current = serial;
+            }
+            raise
+        });
+    }
+
+    /// Increments the [serial](Self::serial).
+    ///
+    /// Once the serial reaches the end of the serials reserved in the packet store, the
+    /// next [`SERIAL_RESERVATION`] serials are reserved. A failure to reserve them is only
+    /// logged, the change that incremented the serial has already been applied.
+    async fn increment_serial(&self) {
+        let mut serial = 0;
+        self.serial.send_modify(|current| {
+            *current = current.wrapping_add(1);
+            serial = *current;
+        });
+        let reserved = *self.reserved_serial.lock().expect("poisoned");
+        if reserved.is_some_and(|reserved| SerialNumber::new(serial) < SerialNumber::new(reserved))
+        {
+            return;
+        }
+        let reserve = serial.wrapping_add(SERIAL_RESERVATION);
+        match self.store.set_serial(reserve).await {
+            Ok(()) => {
+                let mut reserved = self.reserved_serial.lock().expect("poisoned");
+                if reserved
+                    .is_none_or(|reserved| SerialNumber::new(reserve) > SerialNumber::new(reserved))
+                {
+                    *reserved = Some(reserve);
+                }
+            }
+            Err(err) => warn!(serial = reserve, "failed to reserve zone serials: {err:#}"),
+        }
+    }
+
     /// Resolve a DNS query.
     #[tracing::instrument("resolve", skip_all, fields(pubkey=%pubkey,name=%name,typ=%record_type))]
     pub(crate) async fn resolve(
@@ -102,17 +331,47 @@ impl ZoneStore {
         name: &Name,
         record_type: RecordType,
     ) -> Result<Option<Arc<RecordSet>>> {
+        self.resolve_in_zone(pubkey, |zone| zone.resolve(name, record_type))
+            .await
+    }
+
+    /// Returns the record types that exist at `name` in the zone of `pubkey`.
+    ///
+    /// Returns an empty list if the zone or the name does not exist.
+    #[tracing::instrument("record_types", skip_all, fields(pubkey=%pubkey,name=%name))]
+    pub(crate) async fn record_types(
+        &self,
+        pubkey: &PublicKeyBytes,
+        name: &Name,
+    ) -> Result<Vec<RecordType>> {
+        let types = self
+            .resolve_in_zone(pubkey, |zone| {
+                let types = zone.record_types(name);
+                (!types.is_empty()).then_some(types)
+            })
+            .await?;
+        Ok(types.unwrap_or_default())
+    }
+
+    /// Finds the zone for `pubkey` and applies `f` to it.
+    async fn resolve_in_zone<T>(
+        &self,
+        pubkey: &PublicKeyBytes,
+        f: impl Fn(&CachedZone) -> Option<T>,
+    ) -> Result<Option<T>> {
         trace!("store resolve");
 
+        if self.blocklist.contains(pubkey) {
+            debug!("key is blocked");
+            return Ok(None);
+        }
+
         // Check cache first (short lock scope)
         {
             let mut cache = self.cache.lock().await;
-            if let Some(rset) = cache.resolve(pubkey, name, record_type) {
-                debug!(
-                    len = rset.records_without_rrsigs().count(),
-                    "resolved from cache"
-                );
-                return Ok(Some(rset));
+            if let Some(res) = cache.resolve(pubkey, &f) {
+                debug!("resolved from cache");
+                return Ok(Some(res));
             }
         }
 
@@ -120,14 +379,11 @@ impl ZoneStore {
         if let Some(packet) = self.store.get(pubkey).await? {
             trace!(packet_timestamp = ?packet.timestamp(), "store hit");
             let mut cache = self.cache.lock().await;
-            let result = cache.insert_and_resolve(&packet, name, record_type);
+            let result = cache.insert_and_resolve(&packet, &f);
             return match result {
-                Ok(Some(rset)) => {
-                    debug!(
-                        len = rset.records_without_rrsigs().count(),
-                        "resolved from store"
-                    );
-                    Ok(Some(rset))
+                Ok(Some(res)) => {
+                    debug!("resolved from store");
+                    Ok(Some(res))
                 }
                 Ok(None) => {
                     debug!("resolved to zone, but no matching records in zone");
@@ -151,11 +407,7 @@ impl ZoneStore {
                 && let Ok(packet) = mutable_item_to_signed_packet(&item)
             {
                 debug!("DHT resolve successful {:?}", packet);
-                return self
-                    .cache
-                    .lock()
-                    .await
-                    .insert_and_resolve_dht(&packet, name, record_type);
+                return self.cache.lock().await.insert_and_resolve_dht(&packet, &f);
             }
             debug!("DHT resolve failed");
         }
@@ -169,34 +421,160 @@ impl ZoneStore {
         &self,
         pubkey: &PublicKeyBytes,
     ) -> Result<Option<SignedPacket>> {
+        if self.blocklist.contains(pubkey) {
+            return Ok(None);
+        }
         self.store.get(pubkey).await
     }
 
+    /// Get up to `limit` signed packets with a key greater than `after`, ordered by key.
+    pub(crate) async fn packets(
+        &self,
+        after: Option<PublicKeyBytes>,
+        limit: usize,
+    ) -> Result<Vec<SignedPacket>> {
+        self.store.packets(after, limit).await
+    }
+
     /// Insert a signed packet into the cache and the store.
     ///
-    /// Returns whether this produced an update, i.e. whether the packet is the newest for its
-    /// pubkey.
-    // allow unused async: this will be async soon.
-    #[allow(clippy::unused_async)]
+    /// Packets for blocked keys and packets which violate the publish policy are not stored.
     pub(crate) async fn insert(
         &self,
         signed_packet: SignedPacket,
-        _source: PacketSource,
-    ) -> Result<bool> {
+        source: PacketSource,
+    ) -> Result<InsertOutcome> {
+        let outcome = self.insert_inner(signed_packet, source).await?;
+        self.count_insert(source, &outcome);
+        Ok(outcome)
+    }
+
+    async fn insert_inner(
+        &self,
+        signed_packet: SignedPacket,
+        source: PacketSource,
+    ) -> Result<InsertOutcome> {
         let pubkey = PublicKeyBytes::from_signed_packet(&signed_packet);
+        if self.blocklist.contains(&pubkey) {
+            debug!(%pubkey, ?source, "insert skip: key is blocked");
+            return Ok(InsertOutcome::Blocked);
+        }
+        if let Err(err) = self.policy.check(&signed_packet) {
+            debug!(%pubkey, ?source, "insert skip: rejected by publish policy: {err}");
+            return Ok(InsertOutcome::Rejected(err));
+        }
         if self.store.upsert(signed_packet).await? {
-            self.metrics.pkarr_publish_update.inc();
             self.cache.lock().await.remove(&pubkey);
-            Ok(true)
+            self.increment_serial().await;
+            Ok(InsertOutcome::Updated)
         } else {
-            self.metrics.pkarr_publish_noop.inc();
-            Ok(false)
+            Ok(InsertOutcome::Unchanged)
         }
     }
+
+    fn count_insert(&self, source: PacketSource, outcome: &InsertOutcome) {
+        let metrics = &self.metrics;
+        let [updated, unchanged, blocked, rejected] = match source {
+            PacketSource::PkarrPublish => [
+                &metrics.pkarr_publish_update,
+                &metrics.pkarr_publish_noop,
+                &metrics.pkarr_publish_blocked,
+                &metrics.pkarr_publish_rejected,
+            ],
+            PacketSource::Replication => [
+                &metrics.replication_packets_update,
+                &metrics.replication_packets_noop,
+                &metrics.replication_packets_blocked,
+                &metrics.replication_packets_rejected,
+            ],
+            PacketSource::Import => [
+                &metrics.import_packets_update,
+                &metrics.import_packets_noop,
+                &metrics.import_packets_blocked,
+                &metrics.import_packets_rejected,
+            ],
+        };
+        let counter = match outcome {
+            InsertOutcome::Updated => updated,
+            InsertOutcome::Unchanged => unchanged,
+            InsertOutcome::Blocked => blocked,
+            InsertOutcome::Rejected(_) => rejected,
+        };
+        counter.inc();
+    }
+
+    /// Remove the signed packet for a pubkey from the cache and the store.
+    ///
+    /// Returns whether a packet was stored for the pubkey.
+    pub(crate) async fn remove(&self, pubkey: &PublicKeyBytes) -> Result<bool> {
+        let removed = self.store.remove(pubkey).await?;
+        self.cache.lock().await.remove(pubkey);
+        if removed {
+            self.increment_serial().await;
+        }
+        Ok(removed)
+    }
+
+    /// Returns whether a pubkey is blocked.
+    pub(crate) fn is_blocked(&self, pubkey: &PublicKeyBytes) -> bool {
+        self.blocklist.contains(pubkey)
+    }
+
+    /// Returns all blocked pubkeys.
+    pub(crate) fn blocked(&self) -> Vec<PublicKeyBytes> {
+        self.blocklist.keys()
+    }
+
+    /// Block a pubkey and remove its signed packet.
+    ///
+    /// Returns whether the pubkey was not blocked before.
+    pub(crate) async fn block(&self, pubkey: PublicKeyBytes) -> Result<bool> {
+        let added = self.blocklist.insert(pubkey).await?;
+        self.remove(&pubkey).await?;
+        Ok(added)
+    }
+
+    /// Unblock a pubkey.
+    ///
+    /// Returns whether the pubkey was blocked.
+    pub(crate) async fn unblock(&self, pubkey: &PublicKeyBytes) -> Result<bool> {
+        self.blocklist.remove(pubkey).await
+    }
+
+    /// Spawns a task which [evicts expired packets](Self::evict_expired) every `interval`.
+    ///
+    /// The built-in backends evict on their own, this is needed for custom backends only.
+    pub(crate) fn spawn_eviction(&self, interval: Duration) -> AbortOnDropHandle<()> {
+        let store = self.clone();
+        AbortOnDropHandle::new(tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                match store.evict_expired().await {
+                    Ok(evicted) => debug!(evicted, "evicted expired packets"),
+                    Err(err) => warn!("failed to evict expired packets: {err:#}"),
+                }
+            }
+        }))
+    }
+
+    /// Remove all signed packets that are older than the eviction period right away.
+    ///
+    /// Returns the number of removed packets.
+    pub(crate) async fn evict_expired(&self) -> Result<usize> {
+        let expiry_us = self.eviction.as_micros() as u64;
+        let expired =
+            Timestamp::from_micros(Timestamp::now().as_micros().saturating_sub(expiry_us));
+        let evicted = self.store.evict(expired).await?;
+        if evicted > 0 {
+            self.cache.lock().await.clear();
+            self.increment_serial().await;
+        }
+        Ok(evicted)
+    }
 }
 
 /// Convert a mainline [`MutableItem`] to a [`SignedPacket`].
-fn mutable_item_to_signed_packet(
+pub(crate) fn mutable_item_to_signed_packet(
     item: &MutableItem,
 ) -> Result<SignedPacket, SignedPacketVerifyError> {
     SignedPacket::from_parts_unchecked(
@@ -207,6 +585,17 @@ fn mutable_item_to_signed_packet(
     )
 }
 
+/// Convert a [`SignedPacket`] to a mainline [`MutableItem`].
+pub(crate) fn signed_packet_to_mutable_item(packet: &SignedPacket) -> MutableItem {
+    MutableItem::new_signed_unchecked(
+        *packet.public_key().as_bytes(),
+        packet.signature().to_bytes(),
+        packet.encoded_packet(),
+        packet.timestamp().as_micros() as i64,
+        None,
+    )
+}
+
 #[derive(derive_more::Debug)]
 struct ZoneCache {
     /// Cache for explicitly added entries
@@ -230,12 +619,11 @@ impl ZoneCache {
         }
     }
 
-    fn resolve(
+    fn resolve<T>(
         &mut self,
         pubkey: &PublicKeyBytes,
-        name: &Name,
-        record_type: RecordType,
-    ) -> Option<Arc<RecordSet>> {
+        f: impl Fn(&CachedZone) -> Option<T>,
+    ) -> Option<T> {
         let zone = if let Some(zone) = self.cache.get(pubkey) {
             trace!("cache hit {}", pubkey.to_z32());
             zone
@@ -245,29 +633,27 @@ impl ZoneCache {
         } else {
             return None;
         };
-        zone.resolve(name, record_type)
+        f(zone)
     }
 
-    fn insert_and_resolve(
+    fn insert_and_resolve<T>(
         &mut self,
         signed_packet: &SignedPacket,
-        name: &Name,
-        record_type: RecordType,
-    ) -> Result<Option<Arc<RecordSet>>> {
+        f: impl Fn(&CachedZone) -> Option<T>,
+    ) -> Result<Option<T>> {
         let pubkey = PublicKeyBytes::from_signed_packet(signed_packet);
         self.insert(signed_packet)?;
-        Ok(self.resolve(&pubkey, name, record_type))
+        Ok(self.resolve(&pubkey, f))
     }
 
-    fn insert_and_resolve_dht(
+    fn insert_and_resolve_dht<T>(
         &mut self,
         signed_packet: &SignedPacket,
-        name: &Name,
-        record_type: RecordType,
-    ) -> Result<Option<Arc<RecordSet>>> {
+        f: impl Fn(&CachedZone) -> Option<T>,
+    ) -> Result<Option<T>> {
         let pubkey = PublicKeyBytes::from_signed_packet(signed_packet);
         let zone = CachedZone::from_signed_packet(signed_packet).anyerr()?;
-        let res = zone.resolve(name, record_type);
+        let res = f(&zone);
         self.dht_cache.insert(pubkey, zone, DHT_CACHE_TTL);
         self.metrics
             .cache_zones_dht
@@ -296,6 +682,13 @@ impl ZoneCache {
         }
     }
 
+    fn clear(&mut self) {
+        self.cache.clear();
+        self.dht_cache.clear();
+        self.metrics.cache_zones.set(0);
+        self.metrics.cache_zones_dht.set(0);
+    }
+
     fn remove(&mut self, pubkey: &PublicKeyBytes) {
         self.cache.pop(pubkey);
         self.dht_cache.remove(pubkey);
@@ -329,6 +722,304 @@ impl CachedZone {
     fn resolve(&self, name: &Name, record_type: RecordType) -> Option<Arc<RecordSet>> {
         trace!(name=%name, typ=%record_type, "resolve in zone");
         let key = RrKey::new(name.into(), record_type);
-        self.records.get(&key).cloned()
+        if let Some(set) = self.records.get(&key) {
+            return Some(set.clone());
+        }
+        let wildcard = self.wildcard(name)?;
+        let set = self
+            .records
+            .get(&RrKey::new(wildcard.into(), record_type))?;
+        trace!(name=%name, wildcard=%set.name(), "resolved from wildcard");
+        let mut synthesized = RecordSet::new(name.clone(), record_type, set.serial());
+        for record in set.records_without_rrsigs() {
+            let mut record = record.clone();
+            record.name = name.clone();
+            synthesized.insert(record, set.serial());
+        }
+        Some(Arc::new(synthesized))
+    }
+
+    fn record_types(&self, name: &Name) -> Vec<RecordType> {
+        let types = self.record_types_at(name);
+        match self.wildcard(name) {
+            Some(wildcard) if types.is_empty() => self.record_types_at(&wildcard),
+            _ => types,
+        }
+    }
+
+    fn record_types_at(&self, name: &Name) -> Vec<RecordType> {
+        self.records
+            .values()
+            .filter(|set| set.name() == name)
+            .map(|set| set.record_type())
+            .collect()
+    }
+
+    /// Returns the wildcard name that matches `name`, if `name` does not exist in the zone.
+    ///
+    /// As defined in RFC 4592, the wildcard is `*.<closest encloser>`, where the closest
+    /// encloser is the nearest ancestor of `name` that exists in the zone.
+    fn wildcard(&self, name: &Name) -> Option<Name> {
+        if self.exists(name) {
+            return None;
+        }
+        let mut encloser = name.base_name();
+        while encloser.num_labels() > 0 && !self.exists(&encloser) {
+            encloser = encloser.base_name();
+        }
+        let wildcard = encloser.prepend_label("*").ok()?;
+        self.exists(&wildcard).then_some(wildcard)
+    }
+
+    /// Returns whether `name` exists in the zone, as the owner of records or as an empty
+    /// non-terminal.
+    fn exists(&self, name: &Name) -> bool {
+        self.records.values().any(|set| name.zone_of(set.name()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh_base::SecretKey;
+    use rand::{RngExt, SeedableRng};
+    use rand_chacha::ChaCha12Rng;
+
+    use super::*;
+
+    async fn signed_packet(secret_key: &SecretKey, relay: &str) -> Result<SignedPacket> {
+        // Sleep so that subsequently created packets have increasing timestamps.
+        tokio::time::sleep(Duration::from_millis(2)).await;
+        SignedPacket::from_txt_strings(secret_key, "_iroh", [format!("relay={relay}")], 30).anyerr()
+    }
+
+    async fn check_packet_store(store: &dyn PacketStore) -> Result {
+        let mut rng = ChaCha12Rng::seed_from_u64(0);
+        let a = SecretKey::from_bytes(&rng.random());
+        let b = SecretKey::from_bytes(&rng.random());
+        let a_old = signed_packet(&a, "https://old.example.com").await?;
+        let a_new = signed_packet(&a, "https://new.example.com").await?;
+        let key_a = PublicKeyBytes::from_signed_packet(&a_new);
+
+        assert!(store.upsert(a_old.clone()).await?);
+        assert!(store.upsert(a_new.clone()).await?);
+        assert!(
+            !store.upsert(a_old).await?,
+            "older packet must not replace newer one"
+        );
+        let stored = store.get(&key_a).await?.expect("packet is stored");
+        assert_eq!(stored.as_bytes(), a_new.as_bytes());
+
+        let b_packet = signed_packet(&b, "https://b.example.com").await?;
+        let key_b = PublicKeyBytes::from_signed_packet(&b_packet);
+        store.upsert(b_packet).await?;
+        let (first, second) = if key_a.as_bytes() < key_b.as_bytes() {
+            (key_a, key_b)
+        } else {
+            (key_b, key_a)
+        };
+        let page = store.packets(None, 1).await?;
+        assert_eq!(page.len(), 1);
+        assert_eq!(PublicKeyBytes::from_signed_packet(&page[0]), first);
+        let page = store.packets(Some(first), 10).await?;
+        assert_eq!(page.len(), 1);
+        assert_eq!(PublicKeyBytes::from_signed_packet(&page[0]), second);
+        assert!(store.packets(Some(second), 10).await?.is_empty());
+        assert!(store.remove(&key_b).await?);
+
+        assert!(store.remove(&key_a).await?);
+        assert!(store.get(&key_a).await?.is_none());
+        assert!(!store.remove(&key_a).await?);
+
+        let a_packet = signed_packet(&a, "https://a.example.com").await?;
+        let expired = signed_packet(&b, "https://expired.example.com")
+            .await?
+            .timestamp();
+        let b_packet = signed_packet(&b, "https://b.example.com").await?;
+        let key_b = PublicKeyBytes::from_signed_packet(&b_packet);
+        store.upsert(a_packet).await?;
+        store.upsert(b_packet).await?;
+        assert_eq!(store.evict(expired).await?, 1);
+        assert!(store.get(&key_a).await?.is_none());
+        assert!(store.get(&key_b).await?.is_some());
+        assert_eq!(store.evict(expired).await?, 0);
+
+        assert_eq!(store.serial().await?, None);
+        store.set_serial(10).await?;
+        assert_eq!(store.serial().await?, Some(10));
+        store.set_serial(5).await?;
+        assert_eq!(store.serial().await?, Some(10), "serial must not decrease");
+        // Serials are compared with RFC 1982 serial number arithmetic, so they can wrap.
+        store.set_serial(0x7fff_fff0).await?;
+        store.set_serial(0xbfff_fff0).await?;
+        store.set_serial(0xffff_fff0).await?;
+        store.set_serial(5).await?;
+        assert_eq!(store.serial().await?, Some(5), "serial must wrap");
+        store.set_serial(0xffff_fff0).await?;
+        assert_eq!(store.serial().await?, Some(5), "serial must not decrease");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn packet_store_redb() -> Result {
+        let store = SignedPacketStore::in_memory(Options::default(), Default::default())?;
+        check_packet_store(&store).await
+    }
+
+    #[tokio::test]
+    async fn packet_store_sqlite() -> Result {
+        let store = SqlitePacketStore::in_memory(Options::default(), Default::default())?;
+        check_packet_store(&store).await
+    }
+
+    #[tokio::test]
+    async fn packet_store_sqlite_persistent() -> Result {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("signed-packets-1.sqlite");
+        let mut rng = ChaCha12Rng::seed_from_u64(1);
+        let packet =
+            signed_packet(&SecretKey::from_bytes(&rng.random()), "https://example.com").await?;
+        let key = PublicKeyBytes::from_signed_packet(&packet);
+
+        let store = ZoneStore::persistent(
+            &path,
+            StoreBackend::Sqlite,
+            Options::default(),
+            Default::default(),
+        )?;
+        assert!(
+            store
+                .insert(packet.clone(), PacketSource::PkarrPublish)
+                .await?
+                .is_updated()
+        );
+        drop(store);
+
+        let store = ZoneStore::persistent(
+            &path,
+            StoreBackend::Sqlite,
+            Options::default(),
+            Default::default(),
+        )?;
+        let stored = store
+            .get_signed_packet(&key)
+            .await?
+            .expect("packet persisted");
+        assert_eq!(stored.as_bytes(), packet.as_bytes());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn insert_checks_policy_for_every_source() -> Result {
+        let mut rng = ChaCha12Rng::seed_from_u64(2);
+        let denied = SecretKey::from_bytes(&rng.random());
+        let config = crate::policy::PublishPolicyConfig {
+            denied_keys: vec![denied.public().to_z32()],
+            ..Default::default()
+        };
+        let metrics = Arc::new(Metrics::default());
+        let store = ZoneStore::in_memory(Options::default(), metrics.clone())?
+            .with_policy(PublishPolicy::new(config, None)?);
+
+        for source in [
+            PacketSource::PkarrPublish,
+            PacketSource::Replication,
+            PacketSource::Import,
+        ] {
+            let packet = signed_packet(&denied, "https://example.com").await?;
+            let outcome = store.insert(packet, source).await?;
+            assert!(
+                matches!(outcome, InsertOutcome::Rejected(_)),
+                "{source:?}: {outcome:?}"
+            );
+            let key = SecretKey::from_bytes(&rng.random());
+            let old = signed_packet(&key, "https://old.example.com").await?;
+            let new = signed_packet(&key, "https://new.example.com").await?;
+            let outcome = store.insert(new, source).await?;
+            assert!(outcome.is_updated(), "{source:?}: {outcome:?}");
+            let outcome = store.insert(old, source).await?;
+            assert!(
+                matches!(outcome, InsertOutcome::Unchanged),
+                "{source:?}: {outcome:?}"
+            );
+        }
+
+        // Every source is counted separately.
+        for counters in [
+            [
+                &metrics.pkarr_publish_update,
+                &metrics.pkarr_publish_noop,
+                &metrics.pkarr_publish_rejected,
+            ],
+            [
+                &metrics.replication_packets_update,
+                &metrics.replication_packets_noop,
+                &metrics.replication_packets_rejected,
+            ],
+            [
+                &metrics.import_packets_update,
+                &metrics.import_packets_noop,
+                &metrics.import_packets_rejected,
+            ],
+        ] {
+            assert_eq!(counters.map(|counter| counter.get()), [1, 1, 1]);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn serial_reservation() -> Result {
+        let mut rng = ChaCha12Rng::seed_from_u64(4);
+        let store = ZoneStore::in_memory(Options::default(), Default::default())?;
+        let start = store.serial();
+        for relay in ["https://a.example.com", "https://b.example.com"] {
+            let packet = signed_packet(&SecretKey::from_bytes(&rng.random()), relay).await?;
+            store.insert(packet, PacketSource::PkarrPublish).await?;
+        }
+        assert_eq!(store.serial(), start.wrapping_add(2));
+        // Only the first insert reserved serials in the packet store.
+        assert_eq!(
+            store.store.serial().await?,
+            Some(start.wrapping_add(1 + SERIAL_RESERVATION))
+        );
+
+        // Raising follows RFC 1982 serial number arithmetic across the wrap.
+        let wrapped = start.wrapping_add(0xe000_0000);
+        store.raise_serial(start.wrapping_add(0x7000_0000));
+        store.raise_serial(wrapped);
+        assert_eq!(store.serial(), wrapped);
+        store.raise_serial(start.wrapping_add(0x7000_0000));
+        assert_eq!(store.serial(), wrapped);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn serial_survives_restart() -> Result {
+        let mut rng = ChaCha12Rng::seed_from_u64(3);
+        for backend in [StoreBackend::Redb, StoreBackend::Sqlite] {
+            let dir = tempfile::tempdir()?;
+            let path = dir.path().join("signed-packets");
+            let open =
+                || ZoneStore::persistent(&path, backend, Options::default(), Default::default());
+
+            let store = open()?;
+            store.load_serial().await?;
+            for relay in ["https://a.example.com", "https://b.example.com"] {
+                let packet = signed_packet(&SecretKey::from_bytes(&rng.random()), relay).await?;
+                store.insert(packet, PacketSource::PkarrPublish).await?;
+            }
+            let serial = store.serial();
+            drop(store);
+
+            // The serial continues after the persisted serial, even though the restart
+            // happens within the same second as the serial was started.
+            let store = open()?;
+            store.load_serial().await?;
+            assert!(
+                store.serial() > serial,
+                "{backend:?}: serial {} after restart is not greater than {serial}",
+                store.serial()
+            );
+        }
+        Ok(())
     }
 }
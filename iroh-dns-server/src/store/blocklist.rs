@@ -0,0 +1,151 @@
+//! Public keys blocked by an operator.
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use n0_error::{Result, StdResultExt};
+use tokio::sync::Mutex;
+use tracing::info;
+
+use crate::util::PublicKeyBytes;
+
+/// A set of public keys whose packets are neither stored nor resolved.
+///
+/// If created with [`Blocklist::persistent`], the set is written to a file with one
+/// z-base-32 encoded key per line whenever it changes.  A change only takes effect once it
+/// was written.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Blocklist {
+    keys: Arc<RwLock<BTreeSet<PublicKeyBytes>>>,
+    /// Serializes changes, so that concurrent changes are not lost.
+    update: Arc<Mutex<()>>,
+    path: Option<Arc<Path>>,
+}
+
+impl Blocklist {
+    /// Loads the blocklist from `path`, or creates an empty one if the file does not exist.
+    pub(crate) fn persistent(path: PathBuf) -> Result<Self> {
+        let mut keys = BTreeSet::new();
+        match std::fs::read_to_string(&path) {
+            Ok(content) => {
+                for line in content.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                    let key = PublicKeyBytes::from_z32(line)
+                        .with_std_context(|_| format!("invalid key in blocklist: {line}"))?;
+                    keys.insert(key);
+                }
+                info!("loaded {} blocked keys from {}", keys.len(), path.display());
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => {
+                return Err(err).with_std_context(|_| format!("failed to read {}", path.display()));
+            }
+        }
+        Ok(Self {
+            keys: Arc::new(RwLock::new(keys)),
+            update: Default::default(),
+            path: Some(path.into()),
+        })
+    }
+
+    pub(crate) fn contains(&self, key: &PublicKeyBytes) -> bool {
+        self.keys.read().expect("poisoned").contains(key)
+    }
+
+    /// Returns all blocked keys, ordered by key.
+    pub(crate) fn keys(&self) -> Vec<PublicKeyBytes> {
+        self.keys
+            .read()
+            .expect("poisoned")
+            .iter()
+            .copied()
+            .collect()
+    }
+
+    /// Adds a key. Returns whether the key was not blocked before.
+    pub(crate) async fn insert(&self, key: PublicKeyBytes) -> Result<bool> {
+        self.update(|keys| keys.insert(key)).await
+    }
+
+    /// Removes a key. Returns whether the key was blocked.
+    pub(crate) async fn remove(&self, key: &PublicKeyBytes) -> Result<bool> {
+        self.update(|keys| keys.remove(key)).await
+    }
+
+    /// Applies `change` to a copy of the keys, saves it and then swaps it in.
+    ///
+    /// The keys are not locked while saving, so lookups are not blocked by the write. If
+    /// saving fails, the current keys are kept.
+    async fn update(
+        &self,
+        change: impl FnOnce(&mut BTreeSet<PublicKeyBytes>) -> bool,
+    ) -> Result<bool> {
+        let _update = self.update.lock().await;
+        let mut keys = self.keys.read().expect("poisoned").clone();
+        if !change(&mut keys) {
+            return Ok(false);
+        }
+        self.save(&keys).await?;
+        *self.keys.write().expect("poisoned") = keys;
+        Ok(true)
+    }
+
+    async fn save(&self, keys: &BTreeSet<PublicKeyBytes>) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let content: String = keys.iter().map(|key| key.to_z32() + "\n").collect();
+        // Write to a temporary file first so that a crash never leaves a truncated file.
+        let tmp_path = path.with_extension("tmp");
+        tokio::fs::write(&tmp_path, content)
+            .await
+            .with_std_context(|_| format!("failed to write {}", tmp_path.display()))?;
+        tokio::fs::rename(&tmp_path, path)
+            .await
+            .with_std_context(|_| format!("failed to write {}", path.display()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh_base::SecretKey;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn blocklist_persists() -> Result {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("blocked-keys.txt");
+        let a = PublicKeyBytes::new_unchecked(*SecretKey::generate().public().as_bytes());
+        let b = PublicKeyBytes::new_unchecked(*SecretKey::generate().public().as_bytes());
+
+        let blocklist = Blocklist::persistent(path.clone())?;
+        assert!(blocklist.insert(a).await?);
+        assert!(!blocklist.insert(a).await?);
+        assert!(blocklist.insert(b).await?);
+        assert!(blocklist.remove(&b).await?);
+        assert!(!blocklist.remove(&b).await?);
+
+        let blocklist = Blocklist::persistent(path)?;
+        assert!(blocklist.contains(&a));
+        assert!(!blocklist.contains(&b));
+        assert_eq!(blocklist.keys(), vec![a]);
+        Ok(())
+    }
+    #[tokio::test]
+    async fn blocklist_unchanged_on_write_error() -> Result {
+        let dir = tempfile::tempdir()?;
+        // The parent directory does not exist, so saving fails.
+        let path = dir.path().join("missing").join("blocked-keys.txt");
+        let a = PublicKeyBytes::new_unchecked(*SecretKey::generate().public().as_bytes());
+
+        let blocklist = Blocklist::persistent(path)?;
+        assert!(blocklist.insert(a).await.is_err());
+        assert!(!blocklist.contains(&a));
+        assert!(blocklist.keys().is_empty());
+        Ok(())
+    }
+}
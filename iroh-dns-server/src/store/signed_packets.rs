@@ -1,18 +1,25 @@
 use std::{
     future::Future,
+    ops::Bound,
     path::Path,
     result,
     sync::Arc,
     time::{Duration, SystemTime},
 };
 
+use async_trait::async_trait;
+use hickory_server::proto::rr::SerialNumber;
 use iroh_dns::pkarr::{SignedPacket, Timestamp};
 use n0_error::{Result, StackResultExt, StdResultExt, anyerr};
-use redb::{Database, MultimapTableDefinition, ReadableDatabase, ReadableTable, TableDefinition};
+use redb::{
+    Database, MultimapTableDefinition, ReadableDatabase, ReadableMultimapTable, ReadableTable,
+    TableDefinition,
+};
 use tokio::sync::{mpsc, oneshot};
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, trace, warn};
 
+use super::PacketStore;
 use crate::{metrics::Metrics, util::PublicKeyBytes};
 
 type SignedPacketsKey = [u8; 32];
@@ -21,6 +28,10 @@ const SIGNED_PACKETS_TABLE: TableDefinition<&SignedPacketsKey, &[u8]> =
     TableDefinition::new("signed-packets-1");
 const UPDATE_TIME_TABLE: MultimapTableDefinition<[u8; 8], SignedPacketsKey> =
     MultimapTableDefinition::new("update-time-1");
+const METADATA_TABLE: TableDefinition<&str, u32> = TableDefinition::new("metadata-1");
+
+/// Key of the zone serial in the [`METADATA_TABLE`].
+const SERIAL_KEY: &str = "serial";
 
 #[derive(Debug)]
 pub(super) struct SignedPacketStore {
@@ -48,11 +59,19 @@ enum Message {
         key: PublicKeyBytes,
         res: oneshot::Sender<Option<SignedPacket>>,
     },
-    #[cfg(test)]
+    Packets {
+        after: Option<PublicKeyBytes>,
+        limit: usize,
+        res: oneshot::Sender<Vec<SignedPacket>>,
+    },
     Remove {
         key: PublicKeyBytes,
         res: oneshot::Sender<bool>,
     },
+    Evict {
+        expired: Timestamp,
+        res: oneshot::Sender<usize>,
+    },
     Snapshot {
         #[debug(skip)]
         res: oneshot::Sender<Snapshot>,
@@ -61,6 +80,13 @@ enum Message {
         time: Timestamp,
         key: PublicKeyBytes,
     },
+    Serial {
+        res: oneshot::Sender<Option<u32>>,
+    },
+    SetSerial {
+        serial: u32,
+        res: oneshot::Sender<()>,
+    },
 }
 
 struct Actor {
@@ -193,7 +219,22 @@ impl Actor {
                 }
                 res.send(true).ok();
             }
-            #[cfg(test)]
+            Message::Packets { after, limit, res } => {
+                trace!("packets after {after:?}");
+                let range = match after {
+                    Some(after) => tables.signed_packets.range::<&SignedPacketsKey>((
+                        Bound::Excluded(after.as_bytes()),
+                        Bound::Unbounded,
+                    )),
+                    None => tables.signed_packets.range::<&SignedPacketsKey>(..),
+                };
+                let mut packets = Vec::new();
+                for item in range.anyerr()?.take(limit) {
+                    let (_key, value) = item.std_context("database fetch failed")?;
+                    packets.push(deserialize(value.value())?);
+                }
+                res.send(packets).ok();
+            }
             Message::Remove { key, res } => {
                 trace!("remove {}", key);
                 let updated = match tables.signed_packets.remove(key.as_bytes()).anyerr()? {
@@ -210,48 +251,86 @@ impl Actor {
                 };
                 res.send(updated).ok();
             }
+            Message::Evict { expired, res } => {
+                trace!("evict packets older than {}", fmt_time(expired));
+                let mut candidates = Vec::new();
+                for item in tables.update_time.range(..expired.to_be_bytes()).anyerr()? {
+                    let (time, keys) = item.anyerr()?;
+                    let time = Timestamp::from_be_bytes(time.value());
+                    for key in keys {
+                        let key = PublicKeyBytes::new_unchecked(key.anyerr()?.value());
+                        candidates.push((time, key));
+                    }
+                }
+                let mut evicted = 0;
+                for (time, key) in candidates {
+                    if self.check_expired(tables, time, key, expired)? {
+                        evicted += 1;
+                    }
+                }
+                res.send(evicted).ok();
+            }
             Message::Snapshot { res } => {
                 trace!("snapshot");
                 res.send(Snapshot::new(&self.db)?).ok();
             }
             Message::CheckExpired { key, time } => {
                 trace!("check expired {} at {}", key, fmt_time(time));
-                match get_packet(&tables.signed_packets, &key)? {
-                    Some(packet) => {
-                        let expiry_us = self.options.eviction.as_micros() as u64;
-                        let expired = Timestamp::from_micros(
-                            Timestamp::now().as_micros().saturating_sub(expiry_us),
-                        );
-                        if packet.timestamp() < expired {
-                            tables
-                                .update_time
-                                .remove(&time.to_be_bytes(), key.as_bytes())
-                                .anyerr()?;
-                            let _ = tables.signed_packets.remove(key.as_bytes()).anyerr()?;
-                            self.metrics.store_packets_expired.inc();
-                            debug!("removed expired packet {key}");
-                        } else {
-                            debug!(
-                                "packet {key} is no longer expired, removing obsolete expiry entry"
-                            );
-                            tables
-                                .update_time
-                                .remove(&time.to_be_bytes(), key.as_bytes())
-                                .anyerr()?;
-                        }
-                    }
-                    None => {
-                        debug!("expired packet {key} not found, remove from expiry table");
-                        tables
-                            .update_time
-                            .remove(&time.to_be_bytes(), key.as_bytes())
-                            .anyerr()?;
-                    }
+                let expiry_us = self.options.eviction.as_micros() as u64;
+                let expired =
+                    Timestamp::from_micros(Timestamp::now().as_micros().saturating_sub(expiry_us));
+                self.check_expired(tables, time, key, expired)?;
+            }
+            Message::Serial { res } => {
+                let serial = tables.metadata.get(SERIAL_KEY).anyerr()?;
+                res.send(serial.map(|serial| serial.value())).ok();
+            }
+            Message::SetSerial { serial, res } => {
+                trace!("set serial {serial}");
+                let stored = tables.metadata.get(SERIAL_KEY).anyerr()?;
+                if stored.is_none_or(|stored| {
+                    SerialNumber::new(stored.value()) < SerialNumber::new(serial)
+                }) {
+                    tables.metadata.insert(SERIAL_KEY, serial).anyerr()?;
                 }
+                res.send(()).ok();
             }
         }
         Ok(())
     }
+
+    /// Removes the packet for `key` if it is older than `expired`, and the `time` entry
+    /// for `key` from the update time index.
+    ///
+    /// Returns whether the packet was removed.
+    fn check_expired(
+        &self,
+        tables: &mut Tables,
+        time: Timestamp,
+        key: PublicKeyBytes,
+        expired: Timestamp,
+    ) -> Result<bool> {
+        tables
+            .update_time
+            .remove(&time.to_be_bytes(), key.as_bytes())
+            .anyerr()?;
+        match get_packet(&tables.signed_packets, &key)? {
+            Some(packet) if packet.timestamp() < expired => {
+                let _ = tables.signed_packets.remove(key.as_bytes()).anyerr()?;
+                self.metrics.store_packets_expired.inc();
+                debug!("removed expired packet {key}");
+                Ok(true)
+            }
+            Some(_) => {
+                debug!("packet {key} is no longer expired, removed obsolete expiry entry");
+                Ok(false)
+            }
+            None => {
+                debug!("expired packet {key} not found, removed from expiry table");
+                Ok(false)
+            }
+        }
+    }
 }
 
 fn fmt_time(t: Timestamp) -> String {
@@ -264,6 +343,7 @@ fn fmt_time(t: Timestamp) -> String {
 struct Tables<'a> {
     pub signed_packets: redb::Table<'a, &'static SignedPacketsKey, &'static [u8]>,
     pub update_time: redb::MultimapTable<'a, [u8; 8], SignedPacketsKey>,
+    pub metadata: redb::Table<'a, &'static str, u32>,
 }
 
 impl<'txn> Tables<'txn> {
@@ -271,6 +351,7 @@ impl<'txn> Tables<'txn> {
         Ok(Self {
             signed_packets: tx.open_table(SIGNED_PACKETS_TABLE)?,
             update_time: tx.open_multimap_table(UPDATE_TIME_TABLE)?,
+            metadata: tx.open_table(METADATA_TABLE)?,
         })
     }
 }
@@ -352,8 +433,11 @@ impl SignedPacketStore {
             _evict_thread,
         })
     }
+}
 
-    pub(crate) async fn upsert(&self, packet: SignedPacket) -> Result<bool> {
+#[async_trait]
+impl PacketStore for SignedPacketStore {
+    async fn upsert(&self, packet: SignedPacket) -> Result<bool> {
         let (tx, rx) = oneshot::channel();
         self.send
             .send(Message::Upsert { packet, res: tx })
@@ -362,7 +446,7 @@ impl SignedPacketStore {
         rx.await.anyerr()
     }
 
-    pub(crate) async fn get(&self, key: &PublicKeyBytes) -> Result<Option<SignedPacket>> {
+    async fn get(&self, key: &PublicKeyBytes) -> Result<Option<SignedPacket>> {
         let (tx, rx) = oneshot::channel();
         self.send
             .send(Message::Get { key: *key, res: tx })
@@ -371,8 +455,24 @@ impl SignedPacketStore {
         rx.await.anyerr()
     }
 
-    #[cfg(test)]
-    pub(crate) async fn remove(&self, key: &PublicKeyBytes) -> Result<bool> {
+    async fn packets(
+        &self,
+        after: Option<PublicKeyBytes>,
+        limit: usize,
+    ) -> Result<Vec<SignedPacket>> {
+        let (tx, rx) = oneshot::channel();
+        self.send
+            .send(Message::Packets {
+                after,
+                limit,
+                res: tx,
+            })
+            .await
+            .anyerr()?;
+        rx.await.anyerr()
+    }
+
+    async fn remove(&self, key: &PublicKeyBytes) -> Result<bool> {
         let (tx, rx) = oneshot::channel();
         self.send
             .send(Message::Remove { key: *key, res: tx })
@@ -380,6 +480,30 @@ impl SignedPacketStore {
             .anyerr()?;
         rx.await.anyerr()
     }
+
+    async fn evict(&self, expired: Timestamp) -> Result<usize> {
+        let (tx, rx) = oneshot::channel();
+        self.send
+            .send(Message::Evict { expired, res: tx })
+            .await
+            .anyerr()?;
+        rx.await.anyerr()
+    }
+
+    async fn serial(&self) -> Result<Option<u32>> {
+        let (tx, rx) = oneshot::channel();
+        self.send.send(Message::Serial { res: tx }).await.anyerr()?;
+        rx.await.anyerr()
+    }
+
+    async fn set_serial(&self, serial: u32) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.send
+            .send(Message::SetSerial { serial, res: tx })
+            .await
+            .anyerr()?;
+        rx.await.anyerr()
+    }
 }
 
 /// Serialize a signed packet for storage: `<8 bytes last_seen><packet bytes>`.
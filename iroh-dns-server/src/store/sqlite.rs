@@ -0,0 +1,288 @@
+//! [`PacketStore`] backed by an SQLite database.
+
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use iroh_dns::pkarr::{SignedPacket, Timestamp};
+use n0_error::{Result, StdResultExt, anyerr};
+use n0_future::task::AbortOnDropHandle;
+use rusqlite::{Connection, OptionalExtension, params};
+use tracing::{debug, info, trace, warn};
+
+use super::{Options, PacketStore};
+use crate::{metrics::Metrics, util::PublicKeyBytes};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS signed_packets (
+        key BLOB PRIMARY KEY NOT NULL,
+        timestamp INTEGER NOT NULL,
+        packet BLOB NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS signed_packets_timestamp ON signed_packets (timestamp);
+    CREATE TABLE IF NOT EXISTS metadata (
+        key TEXT PRIMARY KEY NOT NULL,
+        value INTEGER NOT NULL
+    );
+";
+
+/// Key of the zone serial in the `metadata` table.
+const SERIAL_KEY: &str = "serial";
+
+/// A signed packet store using SQLite.
+///
+/// Every operation runs in its own transaction on a blocking thread. Expired packets are
+/// removed by a background task every [`Options::eviction_interval`].
+#[derive(Debug)]
+pub(super) struct SqlitePacketStore {
+    inner: Arc<Inner>,
+    _evict_task: AbortOnDropHandle<()>,
+}
+
+#[derive(derive_more::Debug)]
+struct Inner {
+    #[debug("Connection")]
+    conn: Mutex<Connection>,
+    #[debug("metrics")]
+    metrics: Arc<Metrics>,
+}
+
+impl SqlitePacketStore {
+    pub(crate) fn persistent(
+        path: impl AsRef<Path>,
+        options: Options,
+        metrics: Arc<Metrics>,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        info!(
+            "loading sqlite packet database from {}",
+            path.to_string_lossy()
+        );
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).with_std_context(|_| {
+                format!(
+                    "failed to create database directory at {}",
+                    path.to_string_lossy()
+                )
+            })?;
+        }
+        let conn = Connection::open(path).std_context("failed to open packet database")?;
+        conn.pragma_update(None, "journal_mode", "WAL").anyerr()?;
+        conn.pragma_update(None, "synchronous", "NORMAL").anyerr()?;
+        Self::open(conn, options, metrics)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn in_memory(options: Options, metrics: Arc<Metrics>) -> Result<Self> {
+        info!("using in-memory sqlite packet database");
+        let conn = Connection::open_in_memory().anyerr()?;
+        Self::open(conn, options, metrics)
+    }
+
+    fn open(conn: Connection, options: Options, metrics: Arc<Metrics>) -> Result<Self> {
+        conn.execute_batch(SCHEMA)
+            .std_context("failed to create tables")?;
+        let inner = Arc::new(Inner {
+            conn: Mutex::new(conn),
+            metrics,
+        });
+        let evict_task = tokio::runtime::Handle::try_current()
+            .std_context("get tokio handle")?
+            .spawn(evict_task(inner.clone(), options));
+        Ok(Self {
+            inner,
+            _evict_task: AbortOnDropHandle::new(evict_task),
+        })
+    }
+
+    /// Runs `f` with the database connection on a blocking thread.
+    async fn with_conn<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Inner) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let inner = self.inner.clone();
+        tokio::task::spawn_blocking(move || f(&inner))
+            .await
+            .std_context("packet store task panicked")?
+    }
+}
+
+#[async_trait]
+impl PacketStore for SqlitePacketStore {
+    async fn upsert(&self, packet: SignedPacket) -> Result<bool> {
+        self.with_conn(move |inner| inner.upsert(&packet)).await
+    }
+
+    async fn get(&self, key: &PublicKeyBytes) -> Result<Option<SignedPacket>> {
+        let key = *key;
+        self.with_conn(move |inner| {
+            let conn = inner.conn.lock().expect("poisoned");
+            get_packet(&conn, &key)
+        })
+        .await
+    }
+
+    async fn packets(
+        &self,
+        after: Option<PublicKeyBytes>,
+        limit: usize,
+    ) -> Result<Vec<SignedPacket>> {
+        self.with_conn(move |inner| inner.packets(after, limit))
+            .await
+    }
+
+    async fn remove(&self, key: &PublicKeyBytes) -> Result<bool> {
+        let key = *key;
+        self.with_conn(move |inner| inner.remove(&key)).await
+    }
+
+    async fn evict(&self, expired: Timestamp) -> Result<usize> {
+        self.with_conn(move |inner| inner.evict(expired)).await
+    }
+
+    async fn serial(&self) -> Result<Option<u32>> {
+        self.with_conn(|inner| {
+            let conn = inner.conn.lock().expect("poisoned");
+            conn.query_row(
+                "SELECT value FROM metadata WHERE key = ?1",
+                params![SERIAL_KEY],
+                |row| row.get::<_, u32>(0),
+            )
+            .optional()
+            .std_context("database fetch failed")
+        })
+        .await
+    }
+
+    async fn set_serial(&self, serial: u32) -> Result<()> {
+        self.with_conn(move |inner| {
+            let conn = inner.conn.lock().expect("poisoned");
+            conn.execute(
+                "INSERT INTO metadata (key, value) VALUES (?1, ?2)
+                 ON CONFLICT (key) DO UPDATE SET value = excluded.value
+                 WHERE ((excluded.value - value) & 0xFFFFFFFF) BETWEEN 1 AND 0x7FFFFFFF",
+                params![SERIAL_KEY, serial],
+            )
+            .std_context("database insert failed")?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+impl Inner {
+    fn upsert(&self, packet: &SignedPacket) -> Result<bool> {
+        let key = PublicKeyBytes::from_signed_packet(packet);
+        trace!("upsert {}", key);
+        let mut conn = self.conn.lock().expect("poisoned");
+        let tx = conn.transaction().anyerr()?;
+        let replaced = match get_packet(&tx, &key)? {
+            Some(existing) if existing.more_recent_than(packet) => return Ok(false),
+            Some(_) => true,
+            None => false,
+        };
+        tx.execute(
+            "INSERT OR REPLACE INTO signed_packets (key, timestamp, packet) VALUES (?1, ?2, ?3)",
+            params![
+                &key.as_bytes()[..],
+                packet.timestamp().as_micros() as i64,
+                packet.as_bytes()
+            ],
+        )
+        .std_context("database insert failed")?;
+        tx.commit().std_context("database commit failed")?;
+        if replaced {
+            self.metrics.store_packets_updated.inc();
+        } else {
+            self.metrics.store_packets_inserted.inc();
+        }
+        Ok(true)
+    }
+
+    fn packets(&self, after: Option<PublicKeyBytes>, limit: usize) -> Result<Vec<SignedPacket>> {
+        let conn = self.conn.lock().expect("poisoned");
+        // Every key is greater than the empty blob.
+        let after = after.map(|key| key.as_bytes().to_vec()).unwrap_or_default();
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT packet FROM signed_packets WHERE key > ?1 ORDER BY key LIMIT ?2",
+            )
+            .anyerr()?;
+        let rows = stmt
+            .query_map(params![after, limit as i64], |row| row.get::<_, Vec<u8>>(0))
+            .std_context("database fetch failed")?;
+        let mut packets = Vec::new();
+        for bytes in rows {
+            let bytes = bytes.std_context("database fetch failed")?;
+            packets.push(decode_packet(&bytes)?);
+        }
+        Ok(packets)
+    }
+
+    fn remove(&self, key: &PublicKeyBytes) -> Result<bool> {
+        trace!("remove {}", key);
+        let conn = self.conn.lock().expect("poisoned");
+        let removed = conn
+            .execute(
+                "DELETE FROM signed_packets WHERE key = ?1",
+                params![&key.as_bytes()[..]],
+            )
+            .std_context("database delete failed")?;
+        if removed > 0 {
+            self.metrics.store_packets_removed.inc();
+        }
+        Ok(removed > 0)
+    }
+
+    fn evict(&self, expired: Timestamp) -> Result<usize> {
+        let conn = self.conn.lock().expect("poisoned");
+        let evicted = conn
+            .execute(
+                "DELETE FROM signed_packets WHERE timestamp < ?1",
+                params![expired.as_micros() as i64],
+            )
+            .std_context("database delete failed")?;
+        self.metrics.store_packets_expired.inc_by(evicted as u64);
+        Ok(evicted)
+    }
+}
+
+fn get_packet(conn: &Connection, key: &PublicKeyBytes) -> Result<Option<SignedPacket>> {
+    let Some(bytes) = conn
+        .query_row(
+            "SELECT packet FROM signed_packets WHERE key = ?1",
+            params![&key.as_bytes()[..]],
+            |row| row.get::<_, Vec<u8>>(0),
+        )
+        .optional()
+        .std_context("database fetch failed")?
+    else {
+        return Ok(None);
+    };
+    Ok(Some(decode_packet(&bytes)?))
+}
+
+fn decode_packet(bytes: &[u8]) -> Result<SignedPacket> {
+    SignedPacket::from_bytes_unchecked(bytes)
+        .map_err(|err| anyerr!("Failed to decode stored packet: {err:#}"))
+}
+
+/// Periodically remove expired packets.
+async fn evict_task(inner: Arc<Inner>, options: Options) {
+    info!("starting evict task");
+    let expiry_us = options.eviction.as_micros() as u64;
+    loop {
+        let expired =
+            Timestamp::from_micros(Timestamp::now().as_micros().saturating_sub(expiry_us));
+        let inner = inner.clone();
+        match tokio::task::spawn_blocking(move || inner.evict(expired)).await {
+            Ok(Ok(0)) => {}
+            Ok(Ok(evicted)) => debug!("evicted {evicted} expired packets"),
+            Ok(Err(err)) => warn!("failed to evict expired packets: {err:#}"),
+            Err(err) => warn!("evict task panicked: {err:#}"),
+        }
+        tokio::time::sleep(options.eviction_interval).await;
+    }
+}
@@ -5,6 +5,7 @@ use std::{
     sync::Arc,
 };
 
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use hickory_server::proto::{
     ProtoError,
     op::Message,
@@ -16,7 +17,7 @@ use hickory_server::proto::{
 };
 use iroh_base::PublicKey;
 use iroh_dns::pkarr::SignedPacket;
-use n0_error::{e, stack_error};
+use n0_error::{Result as AnyResult, anyerr, e, ensure_any, stack_error};
 
 /// A lightweight `[u8; 32]` wrapper used as a key for caches and database lookups.
 ///
@@ -25,12 +26,15 @@ use n0_error::{e, stack_error};
 /// will panic. In practice, bytes always originate from a validated `PublicKey`
 /// or a database that was written from one.
 #[derive(derive_more::Into, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy)]
-pub(crate) struct PublicKeyBytes([u8; 32]);
+pub struct PublicKeyBytes([u8; 32]);
 
+/// Error when parsing [`PublicKeyBytes`].
 #[stack_error(derive, add_meta, from_sources)]
-pub(crate) enum InvalidPublicKeyBytes {
+pub enum InvalidPublicKeyBytes {
+    /// The string is not a z-base-32 encoded public key.
     #[error("invalid z-base-32 encoding")]
     InvalidEncoding,
+    /// The bytes are not 32 bytes long.
     #[error("invalid length, must be 32 bytes")]
     InvalidLength,
 }
@@ -46,20 +50,24 @@ impl PublicKeyBytes {
         Self(bytes)
     }
 
-    pub(crate) fn from_z32(s: &str) -> Result<Self, InvalidPublicKeyBytes> {
+    /// Parses a z-base-32 encoded public key.
+    pub fn from_z32(s: &str) -> Result<Self, InvalidPublicKeyBytes> {
         let pk = PublicKey::from_z32(s).map_err(|_| e!(InvalidPublicKeyBytes::InvalidEncoding))?;
         Ok(Self(*pk.as_bytes()))
     }
 
-    pub(crate) fn to_z32(self) -> String {
+    /// Returns the z-base-32 encoding of the public key.
+    pub fn to_z32(self) -> String {
         PublicKey::from_bytes(&self.0).expect("valid key").to_z32()
     }
 
-    pub(crate) fn as_bytes(&self) -> &[u8; 32] {
+    /// Returns the raw bytes of the public key.
+    pub fn as_bytes(&self) -> &[u8; 32] {
         &self.0
     }
 
-    pub(crate) fn from_signed_packet(packet: &SignedPacket) -> Self {
+    /// Returns the public key of a signed packet.
+    pub fn from_signed_packet(packet: &SignedPacket) -> Self {
         Self(*packet.public_key().as_bytes())
     }
 }
@@ -112,8 +120,10 @@ pub(crate) fn signed_packet_to_hickory_records_without_origin(
             continue;
         }
         // expect the z32 encoded pubkey as root name
+        // `num_labels` does not count the wildcard label, so count the labels manually
         let name = &record.name;
-        if name.num_labels() < 1 {
+        let num_labels = name.iter().count();
+        if num_labels < 1 {
             continue;
         }
         let zone = name.iter().next_back().unwrap().into_label()?;
@@ -124,8 +134,7 @@ pub(crate) fn signed_packet_to_hickory_records_without_origin(
             continue;
         }
 
-        let name_without_zone =
-            Name::from_labels(name.iter().take(name.num_labels() as usize - 1))?;
+        let name_without_zone = Name::from_labels(name.iter().take(num_labels - 1))?;
         record.name = name_without_zone;
 
         let rrkey = RrKey::new(record.name.clone().into(), record.record_type());
@@ -160,3 +169,58 @@ pub(crate) fn record_set_append_origin(
     }
     Ok(output)
 }
+
+/// Encodes packets as a sequence of `<2 bytes length><packet bytes>` frames.
+pub(crate) fn encode_packets(packets: &[SignedPacket]) -> Bytes {
+    let len = packets.iter().map(|p| 2 + p.as_bytes().len()).sum();
+    let mut out = BytesMut::with_capacity(len);
+    for packet in packets {
+        out.put_u16(packet.as_bytes().len() as u16);
+        out.put_slice(packet.as_bytes());
+    }
+    out.freeze()
+}
+
+/// Decodes and verifies packets encoded with [`encode_packets`].
+pub(crate) fn decode_packets(bytes: Bytes) -> AnyResult<Vec<SignedPacket>> {
+    let mut buf = BytesMut::from(bytes);
+    let mut packets = Vec::new();
+    while let Some(packet) = decode_packet(&mut buf)? {
+        packets.push(packet);
+    }
+    ensure_any!(buf.is_empty(), "truncated packet");
+    Ok(packets)
+}
+
+/// Decodes and verifies the next packet encoded with [`encode_packets`] from `buf`.
+///
+/// Returns `None` if `buf` does not contain a complete packet yet.
+pub(crate) fn decode_packet(buf: &mut BytesMut) -> AnyResult<Option<SignedPacket>> {
+    if buf.len() < 2 {
+        return Ok(None);
+    }
+    let len = u16::from_be_bytes([buf[0], buf[1]]) as usize;
+    if buf.len() < 2 + len {
+        return Ok(None);
+    }
+    buf.advance(2);
+    let packet = SignedPacket::from_bytes(&buf.split_to(len))
+        .map_err(|err| anyerr!("invalid signed packet: {err:#}"))?;
+    Ok(Some(packet))
+}
+
+/// Returns whether an `Authorization` header value is a bearer token matching `token`.
+///
+/// The token is compared in constant time.
+pub(crate) fn bearer_token_matches(authorization: Option<&[u8]>, token: &str) -> bool {
+    let Some(candidate) = authorization.and_then(|value| value.strip_prefix(b"Bearer ")) else {
+        return false;
+    };
+    let token = token.as_bytes();
+    candidate.len() == token.len()
+        && candidate
+            .iter()
+            .zip(token)
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
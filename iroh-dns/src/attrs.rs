@@ -11,7 +11,7 @@
 
 use std::{collections::BTreeMap, fmt::Display, hash::Hash, str::FromStr};
 
-use iroh_base::{EndpointId, SecretKey};
+use iroh_base::EndpointId;
 use n0_error::{e, stack_error};
 
 use crate::pkarr;
@@ -28,6 +28,10 @@ pub enum EncodingError {
         #[error(std_err)]
         source: pkarr::SignedPacketBuildError,
     },
+    #[error("ALPN of service `{service}` is not valid UTF-8")]
+    InvalidAlpn { service: String },
+    #[error("Invalid attribute key `{key}` in service `{service}`")]
+    InvalidServiceAttr { service: String, key: String },
 }
 
 #[allow(missing_docs)]
@@ -86,6 +90,8 @@ pub(crate) enum IrohAttr {
     Addr,
     /// User-defined data
     UserData,
+    /// Signed succession by a new key.
+    Successor,
 }
 
 /// Attributes parsed from [`IROH_TXT_NAME`] TXT records.
@@ -173,21 +179,9 @@ impl<T: FromStr + Display + Hash + Ord> TxtAttrs<T> {
             .flat_map(move |(k, vs)| vs.iter().map(move |v| format!("{k}={v}")))
     }
 
-    /// Creates a [`pkarr::SignedPacket`]
-    ///
-    /// This constructs a DNS packet and signs it with a [`SecretKey`].
-    pub(crate) fn to_pkarr_signed_packet(
-        &self,
-        secret_key: &SecretKey,
-        ttl: u32,
-    ) -> Result<pkarr::SignedPacket, EncodingError> {
-        let signed_packet = pkarr::SignedPacket::from_txt_strings(
-            secret_key,
-            IROH_TXT_NAME,
-            self.to_txt_strings(),
-            ttl,
-        )
-        .map_err(|err| e!(EncodingError::FailedBuildingPacket, err))?;
-        Ok(signed_packet)
+    /// Converts to [`IROH_TXT_NAME`] TXT records in the zone of the endpoint.
+    pub(crate) fn to_zone_records(&self, ttl: u32) -> impl Iterator<Item = pkarr::ZoneRecord> + '_ {
+        self.to_txt_strings()
+            .map(move |s| pkarr::ZoneRecord::new(IROH_TXT_NAME, ttl, pkarr::ZoneRecordData::Txt(s)))
     }
 }
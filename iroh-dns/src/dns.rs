@@ -20,7 +20,7 @@ use hickory_resolver::{
     TokioResolver,
     config::{ConnectionConfig, ResolverConfig, ResolverOpts},
     net::runtime::TokioRuntimeProvider,
-    proto::rr::RData,
+    proto::rr::{RData, Record},
 };
 use iroh_base::EndpointId;
 use n0_error::{AnyError, StackError, StdResultExt, e, stack_error};
@@ -34,7 +34,10 @@ use tokio::sync::Notify;
 use tracing::warn;
 use url::Url;
 
-use crate::{attrs::ParseError, endpoint_info::EndpointInfo};
+use crate::{
+    attrs::ParseError,
+    endpoint_info::{EndpointInfo, EndpointService, ServiceName},
+};
 
 /// Default DNS query timeout.
 pub const DNS_TIMEOUT: Duration = Duration::from_secs(3);
@@ -96,6 +99,8 @@ pub enum DnsError {
     Resolve { source: AnyError },
     #[error("Invalid DNS response: not a query for _iroh.z32encodedpubkey")]
     InvalidResponse {},
+    #[error("DNSSEC validation failed")]
+    Insecure {},
 }
 
 /// Potential errors related to DNS endpoint address lookups.
@@ -131,6 +136,43 @@ pub struct Builder {
     nameservers: Vec<(SocketAddr, DnsProtocol)>,
     #[cfg(with_crypto_provider)]
     tls_client_config: Option<rustls::ClientConfig>,
+    #[cfg(with_crypto_provider)]
+    dnssec_trust_anchors: Option<TrustAnchors>,
+}
+
+/// Trust anchors for DNSSEC validation.
+///
+/// The [`Default`] trust anchors are the key signing keys of the DNS root zone. Other
+/// trust anchors can be parsed from `DNSKEY` records in zone file format, for example to
+/// validate the answers of an iroh-dns-server whose zone is not securely delegated:
+///
+/// ```text
+/// dns.example. 3600 IN DNSKEY 257 3 15 <base64 encoded public key>
+/// ```
+#[cfg(with_crypto_provider)]
+#[derive(Clone, Default, derive_more::Debug)]
+#[debug("TrustAnchors({} keys)", _0.len())]
+pub struct TrustAnchors(Arc<hickory_resolver::proto::dnssec::TrustAnchors>);
+
+/// Error returned when parsing [`TrustAnchors`] fails.
+#[cfg(with_crypto_provider)]
+#[stack_error(derive, add_meta)]
+#[error("Invalid DNSSEC trust anchor")]
+pub struct InvalidTrustAnchorError {
+    #[error(std_err)]
+    source: hickory_resolver::proto::serialize::txt::ParseError,
+}
+
+#[cfg(with_crypto_provider)]
+impl std::str::FromStr for TrustAnchors {
+    type Err = InvalidTrustAnchorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let anchors = s
+            .parse()
+            .map_err(|source| e!(InvalidTrustAnchorError { source }))?;
+        Ok(Self(Arc::new(anchors)))
+    }
 }
 
 /// Protocols over which DNS records can be resolved.
@@ -215,6 +257,17 @@ impl Builder {
         self
     }
 
+    /// Enables DNSSEC validation against the given trust anchors.
+    ///
+    /// Lookups fail with [`DnsError::Insecure`] unless all answers are signed and can be
+    /// validated through a chain of trust from one of the `trust_anchors`. Requires
+    /// enabling either the ring or aws-lc-rs feature.
+    #[cfg(with_crypto_provider)]
+    pub fn with_dnssec_validation(mut self, trust_anchors: TrustAnchors) -> Self {
+        self.dnssec_trust_anchors = Some(trust_anchors);
+        self
+    }
+
     /// Builds the DNS resolver.
     pub fn build(self) -> DnsResolver {
         DnsResolver::custom(HickoryResolver::new(self))
@@ -653,6 +706,37 @@ impl DnsResolver {
         Ok(info)
     }
 
+    /// Looks up a service offered by the endpoint with [`EndpointId`] in the origin domain.
+    ///
+    /// Resolves the TXT, A and AAAA records at `_<service>._iroh.<z32-endpoint-id>.<origin>`.
+    /// Fails if neither TXT records nor IP addresses are found.
+    pub async fn lookup_endpoint_service(
+        &self,
+        endpoint_id: &EndpointId,
+        origin: &str,
+        service: &ServiceName,
+    ) -> Result<EndpointService, LookupError> {
+        let name = format!("_{service}._iroh.{}.{}", endpoint_id.to_z32(), origin);
+        let (txt, ip_addrs) = tokio::join!(
+            self.lookup_txt(name.clone(), DNS_TIMEOUT),
+            self.lookup_ipv4_ipv6(name, DNS_TIMEOUT)
+        );
+        let mut endpoint_service = EndpointService::new(service.clone());
+        for addr in ip_addrs.into_iter().flatten() {
+            endpoint_service = endpoint_service.with_ip_addr(addr);
+        }
+        match txt {
+            Ok(txt) => {
+                for record in txt {
+                    endpoint_service.add_txt_string(&record.to_string());
+                }
+            }
+            Err(err) if endpoint_service.ip_addrs().next().is_none() => return Err(err.into()),
+            Err(_) => {}
+        }
+        Ok(endpoint_service)
+    }
+
     /// Looks up endpoint info by DNS name.
     pub async fn lookup_endpoint_by_domain_name(
         &self,
@@ -756,9 +840,22 @@ impl HickoryResolver {
             hickory_builder = hickory_builder.with_tls_config(client_config);
         }
 
+        #[cfg(with_crypto_provider)]
+        if let Some(trust_anchors) = &builder.dnssec_trust_anchors {
+            hickory_builder = hickory_builder.with_trust_anchor(trust_anchors.0.clone());
+        }
+
         hickory_builder.build().expect("config works")
     }
 
+    /// Returns whether answers must be DNSSEC-validated.
+    fn validate(&self) -> bool {
+        #[cfg(with_crypto_provider)]
+        return self.builder.dnssec_trust_anchors.is_some();
+        #[cfg(not(with_crypto_provider))]
+        false
+    }
+
     fn system_config() -> Result<(ResolverConfig, ResolverOpts), hickory_resolver::net::NetError> {
         #[cfg(target_os = "android")]
         let (system_config, options) = crate::android::read_system_conf()?;
@@ -786,8 +883,10 @@ impl HickoryResolver {
 impl Resolver for HickoryResolver {
     fn lookup_ipv4(&self, host: String) -> BoxFuture<Result<BoxIter<Ipv4Addr>, DnsError>> {
         let resolver = self.resolver.clone();
+        let validate = self.validate();
         Box::pin(async move {
             let lookup = resolver.ipv4_lookup(host).await.anyerr()?;
+            ensure_secure(lookup.answers(), validate)?;
             let iter: BoxIter<Ipv4Addr> =
                 Box::new(lookup.answers().to_vec().into_iter().filter_map(|record| {
                     match &record.data {
@@ -801,8 +900,10 @@ impl Resolver for HickoryResolver {
 
     fn lookup_ipv6(&self, host: String) -> BoxFuture<Result<BoxIter<Ipv6Addr>, DnsError>> {
         let resolver = self.resolver.clone();
+        let validate = self.validate();
         Box::pin(async move {
             let lookup = resolver.ipv6_lookup(host).await.anyerr()?;
+            ensure_secure(lookup.answers(), validate)?;
             let iter: BoxIter<Ipv6Addr> =
                 Box::new(lookup.answers().to_vec().into_iter().filter_map(|record| {
                     match &record.data {
@@ -816,8 +917,10 @@ impl Resolver for HickoryResolver {
 
     fn lookup_txt(&self, host: String) -> BoxFuture<Result<BoxIter<TxtRecordData>, DnsError>> {
         let resolver = self.resolver.clone();
+        let validate = self.validate();
         Box::pin(async move {
             let lookup = resolver.txt_lookup(host).await.anyerr()?;
+            ensure_secure(lookup.answers(), validate)?;
             let iter: BoxIter<TxtRecordData> =
                 Box::new(lookup.answers().to_vec().into_iter().filter_map(|record| {
                     match &record.data {
@@ -847,6 +950,20 @@ impl Resolver for HickoryResolver {
     }
 }
 
+/// Fails with [`DnsError::Insecure`] if `validate` is set and not all `records` are
+/// DNSSEC-validated.
+#[cfg_attr(
+    not(with_crypto_provider),
+    expect(unused_variables, reason = "validation requires a crypto provider")
+)]
+fn ensure_secure(records: &[Record], validate: bool) -> Result<(), DnsError> {
+    #[cfg(with_crypto_provider)]
+    if validate && !records.iter().all(|record| record.proof.is_secure()) {
+        return Err(e!(DnsError::Insecure));
+    }
+    Ok(())
+}
+
 /// Record data for a TXT record.
 ///
 /// This contains a list of character strings, as defined in [RFC 1035 Section 3.3.14].
@@ -32,38 +32,51 @@
 //! - `addr=<addr> <addr>`: A space-separated list of sockets addresses for this iroh endpoint.
 //!   Each address is an IPv4 or IPv6 address with a port.
 //!
+//! - `successor=<endpoint-id> <signature>`: The [`EndpointId`] which replaced this endpoint's
+//!   key, and the signature of this endpoint's key over the [`KeySuccession`], both hex encoded.
+//!
+//! Endpoints can additionally publish the services they offer as [`EndpointService`]s.
+//! Each service is published under `_<service>._iroh.<z32-endpoint-id>.<origin-domain>`,
+//! with `alpn=<alpn>` and `<key>=<value>` TXT records and optional A and AAAA records.
+//!
 //! [Pkarr]: https://app.pkarr.org
 //! [z-base-32]: https://philzimmermann.com/docs/human-oriented-base-32-encoding.txt
 //! [RFC1464]: https://www.rfc-editor.org/rfc/rfc1464
 //! [`RelayUrl`]: iroh_base::RelayUrl
+//! [`KeySuccession`]: iroh_base::KeySuccession
 //! [`IROH_TXT_NAME`]: crate::IROH_TXT_NAME
 //! [`N0_DNS_ENDPOINT_ORIGIN_PROD`]: crate::dns::N0_DNS_ENDPOINT_ORIGIN_PROD
 //! [`N0_DNS_ENDPOINT_ORIGIN_STAGING`]: crate::dns::N0_DNS_ENDPOINT_ORIGIN_STAGING
 
 use std::{
     borrow::Cow,
-    collections::{BTreeSet, HashSet},
+    collections::{BTreeMap, BTreeSet, HashSet},
     fmt::{self, Display},
     hash::Hash,
-    net::SocketAddr,
+    net::{IpAddr, SocketAddr},
     str::FromStr,
     sync::Arc,
 };
 
-use iroh_base::{EndpointAddr, EndpointId, RelayUrl, SecretKey, TransportAddr};
-use n0_error::{ensure, stack_error};
+use data_encoding::HEXLOWER;
+use iroh_base::{
+    EndpointAddr, EndpointId, KeySuccession, PublicKey, RelayUrl, Signature, Signer, TransportAddr,
+};
+use n0_error::{e, ensure, stack_error};
 use url::Url;
 
 use crate::{
+    IROH_TXT_NAME,
     attrs::{EncodingError, IrohAttr, ParseError, TxtAttrs},
-    pkarr,
+    pkarr::{self, ZoneRecord, ZoneRecordData},
 };
 
 /// Data about an endpoint that may be published to and resolved from discovery services.
 ///
-/// This includes an optional [`RelayUrl`], a set of direct addresses, and the optional
+/// This includes an optional [`RelayUrl`], a set of direct addresses, the optional
 /// [`UserData`], a string that can be set by applications and is not parsed or used by iroh
-/// itself.
+/// itself, an optional [`KeySuccession`] if the endpoint replaced its key, and the
+/// [`EndpointService`]s the endpoint offers.
 ///
 /// This struct does not include the endpoint's [`EndpointId`], only the data *about* a certain
 /// endpoint. See [`EndpointInfo`] for a struct that contains a [`EndpointId`] with associated [`EndpointData`].
@@ -73,6 +86,12 @@ pub struct EndpointData {
     addrs: Vec<TransportAddr>,
     /// Optional user-defined [`UserData`] for this endpoint.
     user_data: Option<UserData>,
+    /// The succession to a new key, if this endpoint replaced its key.
+    ///
+    /// Boxed, as it is rare and would otherwise make every [`EndpointData`] much larger.
+    successor: Option<Box<KeySuccession>>,
+    /// Services offered by this endpoint, with unique names.
+    services: Vec<EndpointService>,
 }
 
 fn dedup<T: Eq + Hash + Clone>(items: &mut Vec<T>) -> HashSet<T> {
@@ -94,6 +113,8 @@ impl EndpointData {
         Self {
             addrs,
             user_data: None,
+            successor: None,
+            services: Vec::new(),
         }
     }
 
@@ -107,6 +128,22 @@ impl EndpointData {
         self
     }
 
+    /// Sets the key succession and returns the updated endpoint data.
+    ///
+    /// See also [`Self::set_successor`].
+    pub fn with_successor(mut self, successor: KeySuccession) -> Self {
+        self.successor = Some(Box::new(successor));
+        self
+    }
+
+    /// Adds a service and returns the updated endpoint data.
+    ///
+    /// See also [`Self::add_service`].
+    pub fn with_service(mut self, service: EndpointService) -> Self {
+        self.add_service(service);
+        self
+    }
+
     /// Adds the relay URL to the end of the endpoint data, unless it already existed.
     pub fn add_relay_url(&mut self, relay_url: RelayUrl) {
         let addr = TransportAddr::Relay(relay_url);
@@ -136,6 +173,31 @@ impl EndpointData {
         self.user_data = user_data;
     }
 
+    /// Sets the succession of the endpoint's key to a new key.
+    ///
+    /// When published, the [`KeySuccession::predecessor`] must be the [`EndpointId`] the
+    /// data is published for, otherwise the succession is dropped when parsing.
+    pub fn set_successor(&mut self, successor: Option<KeySuccession>) {
+        self.successor = successor.map(Box::new);
+    }
+
+    /// Adds a service offered by the endpoint.
+    ///
+    /// Replaces a previously added service with the same name. Services are published in the
+    /// same signed packet as the addresses of the endpoint, so they count towards its size
+    /// limit of [`pkarr::SignedPacket::MAX_BYTES`].
+    pub fn add_service(&mut self, service: EndpointService) {
+        match self.services.iter_mut().find(|s| s.name == service.name) {
+            Some(existing) => *existing = service,
+            None => self.services.push(service),
+        }
+    }
+
+    /// Removes all services from the endpoint data.
+    pub fn clear_services(&mut self) {
+        self.services.clear();
+    }
+
     /// Removes all direct addresses from the endpoint data.
     pub fn clear_ip_addrs(&mut self) {
         self.addrs
@@ -161,6 +223,21 @@ impl EndpointData {
         self.user_data.as_ref()
     }
 
+    /// Returns the succession of the endpoint's key to a new key, if any.
+    pub fn successor(&self) -> Option<&KeySuccession> {
+        self.successor.as_deref()
+    }
+
+    /// Returns the services offered by the endpoint.
+    pub fn services(&self) -> impl Iterator<Item = &EndpointService> {
+        self.services.iter()
+    }
+
+    /// Returns the service with the given name, if the endpoint offers it.
+    pub fn service(&self, name: &ServiceName) -> Option<&EndpointService> {
+        self.services.iter().find(|service| &service.name == name)
+    }
+
     /// Returns the direct addresses of the endpoint.
     pub fn ip_addrs(&self) -> impl Iterator<Item = &SocketAddr> {
         self.addrs.iter().filter_map(|addr| match addr {
@@ -193,6 +270,8 @@ impl EndpointData {
             Cow::Owned(addrs) => {
                 let mut data = EndpointData::new(addrs);
                 data.set_user_data(self.user_data.clone());
+                data.set_successor(self.successor.as_deref().copied());
+                data.services = self.services.clone();
                 Cow::Owned(data)
             }
         }
@@ -206,6 +285,8 @@ impl From<BTreeSet<TransportAddr>> for EndpointData {
         Self {
             addrs: addrs.into_iter().collect(),
             user_data: None,
+            successor: None,
+            services: Vec::new(),
         }
     }
 }
@@ -215,6 +296,8 @@ impl From<BTreeSet<SocketAddr>> for EndpointData {
         Self {
             addrs: addrs.into_iter().map(TransportAddr::Ip).collect(),
             user_data: None,
+            successor: None,
+            services: Vec::new(),
         }
     }
 }
@@ -290,6 +373,8 @@ impl From<EndpointAddr> for EndpointData {
             // No need to check for duplicates - we already know they can't have duplicates
             addrs: endpoint_addr.addrs.into_iter().collect(),
             user_data: None,
+            successor: None,
+            services: Vec::new(),
         }
     }
 }
@@ -350,6 +435,239 @@ impl AsRef<str> for UserData {
     }
 }
 
+/// The name of an [`EndpointService`].
+///
+/// Service names are used as a DNS label prefixed with an underscore, so they may only
+/// contain lowercase ASCII letters, digits and hyphens, must not start or end with a hyphen
+/// and are at most [`ServiceName::MAX_LENGTH`] bytes long.
+///
+/// `ServiceName` implements [`FromStr`] and [`TryFrom<String>`].
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct ServiceName(String);
+
+impl ServiceName {
+    /// The max byte length of a service name.
+    ///
+    /// DNS labels are at most 63 bytes, minus the leading underscore.
+    pub const MAX_LENGTH: usize = 62;
+
+    fn is_valid(name: &str) -> bool {
+        !name.is_empty()
+            && name.len() <= Self::MAX_LENGTH
+            && !name.starts_with('-')
+            && !name.ends_with('-')
+            && name
+                .bytes()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == b'-')
+    }
+
+    /// Parses the service name from a record name relative to the endpoint zone.
+    fn from_record_name(name: &str) -> Option<Self> {
+        let label = name.strip_suffix(IROH_TXT_NAME)?.strip_suffix('.')?;
+        label.strip_prefix('_')?.to_ascii_lowercase().parse().ok()
+    }
+
+    /// Returns the name of the records of this service, relative to the endpoint zone.
+    fn record_name(&self) -> String {
+        format!("_{}.{IROH_TXT_NAME}", self.0)
+    }
+}
+
+/// Error returned when parsing an invalid [`ServiceName`].
+#[allow(missing_docs)]
+#[stack_error(derive, add_meta)]
+#[error("invalid service name")]
+pub struct InvalidServiceNameError {}
+
+impl TryFrom<String> for ServiceName {
+    type Error = InvalidServiceNameError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        ensure!(Self::is_valid(&value), InvalidServiceNameError);
+        Ok(Self(value))
+    }
+}
+
+impl FromStr for ServiceName {
+    type Err = InvalidServiceNameError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        ensure!(Self::is_valid(s), InvalidServiceNameError);
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl fmt::Display for ServiceName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl AsRef<str> for ServiceName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// A service offered by an endpoint, published in the zone of the endpoint.
+///
+/// A service is published under `_<name>._iroh.<z32-endpoint-id>` with the following
+/// records:
+///
+/// - `alpn=<alpn>` TXT records for the ALPNs the endpoint accepts for this service.
+/// - `<key>=<value>` TXT records for user-defined attributes.
+/// - A and AAAA records for the IP addresses of the service.
+///
+/// Like [`UserData`], iroh does not examine the published services.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct EndpointService {
+    name: ServiceName,
+    alpns: Vec<Vec<u8>>,
+    attrs: BTreeMap<String, Vec<String>>,
+    ip_addrs: Vec<IpAddr>,
+}
+
+impl EndpointService {
+    /// The attribute key of ALPNs in the TXT records of a service.
+    const ALPN_KEY: &str = "alpn";
+
+    /// Creates a new service without any records.
+    pub fn new(name: ServiceName) -> Self {
+        Self {
+            name,
+            alpns: Vec::new(),
+            attrs: BTreeMap::new(),
+            ip_addrs: Vec::new(),
+        }
+    }
+
+    /// Adds an ALPN and returns the updated service.
+    ///
+    /// The ALPN must be valid UTF-8 to be published.
+    pub fn with_alpn(mut self, alpn: impl AsRef<[u8]>) -> Self {
+        let alpn = alpn.as_ref().to_vec();
+        if !self.alpns.contains(&alpn) {
+            self.alpns.push(alpn);
+        }
+        self
+    }
+
+    /// Adds a user-defined attribute and returns the updated service.
+    ///
+    /// Each attribute is published as a `<key>=<value>` TXT record, so a key may have multiple
+    /// values. The key must not be empty, must not contain `=` and must not be `alpn`, and
+    /// each record must fit into 255 bytes.
+    pub fn with_attr(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.attrs.entry(key.into()).or_default().push(value.into());
+        self
+    }
+
+    /// Adds an IP address and returns the updated service.
+    pub fn with_ip_addr(mut self, addr: IpAddr) -> Self {
+        if !self.ip_addrs.contains(&addr) {
+            self.ip_addrs.push(addr);
+        }
+        self
+    }
+
+    /// Returns the name of the service.
+    pub fn name(&self) -> &ServiceName {
+        &self.name
+    }
+
+    /// Returns the ALPNs of the service.
+    pub fn alpns(&self) -> impl Iterator<Item = &[u8]> {
+        self.alpns.iter().map(Vec::as_slice)
+    }
+
+    /// Returns all user-defined attributes of the service.
+    pub fn attrs(&self) -> &BTreeMap<String, Vec<String>> {
+        &self.attrs
+    }
+
+    /// Returns the values of the user-defined attribute `key`.
+    pub fn attr(&self, key: &str) -> impl Iterator<Item = &str> {
+        self.attrs
+            .get(key)
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+    }
+
+    /// Returns the IP addresses of the service.
+    pub fn ip_addrs(&self) -> impl Iterator<Item = &IpAddr> {
+        self.ip_addrs.iter()
+    }
+
+    /// Converts to records in the zone of the endpoint.
+    fn to_zone_records(&self, ttl: u32) -> Result<Vec<ZoneRecord>, EncodingError> {
+        let service = || self.name.to_string();
+        let name = self.name.record_name();
+        let mut records = Vec::new();
+        for alpn in &self.alpns {
+            let alpn = std::str::from_utf8(alpn)
+                .map_err(|_| e!(EncodingError::InvalidAlpn { service: service() }))?;
+            let value = format!("{}={alpn}", Self::ALPN_KEY);
+            records.push(ZoneRecord::new(&name, ttl, ZoneRecordData::Txt(value)));
+        }
+        for (key, values) in &self.attrs {
+            if key.is_empty() || key.contains('=') || key == Self::ALPN_KEY {
+                return Err(e!(EncodingError::InvalidServiceAttr {
+                    service: service(),
+                    key: key.clone()
+                }));
+            }
+            for value in values {
+                let value = format!("{key}={value}");
+                records.push(ZoneRecord::new(&name, ttl, ZoneRecordData::Txt(value)));
+            }
+        }
+        for addr in &self.ip_addrs {
+            let data = match addr {
+                IpAddr::V4(addr) => ZoneRecordData::A(*addr),
+                IpAddr::V6(addr) => ZoneRecordData::Aaaa(*addr),
+            };
+            records.push(ZoneRecord::new(&name, ttl, data));
+        }
+        Ok(records)
+    }
+
+    /// Adds a `<key>=<value>` TXT string, ignoring strings without a `=`.
+    pub(crate) fn add_txt_string(&mut self, s: &str) {
+        match s.split_once('=') {
+            Some((Self::ALPN_KEY, alpn)) => {
+                self.alpns.push(alpn.as_bytes().to_vec());
+            }
+            Some((key, value)) => {
+                self.attrs
+                    .entry(key.to_string())
+                    .or_default()
+                    .push(value.to_string());
+            }
+            None => {}
+        }
+    }
+
+    /// Parses all services from the records of a signed packet.
+    fn from_zone_records(records: &[ZoneRecord]) -> Vec<Self> {
+        let mut services: BTreeMap<ServiceName, Self> = BTreeMap::new();
+        for record in records {
+            let Some(name) = ServiceName::from_record_name(&record.name) else {
+                continue;
+            };
+            let service = services
+                .entry(name.clone())
+                .or_insert_with(|| Self::new(name));
+            match &record.data {
+                ZoneRecordData::Txt(s) => service.add_txt_string(s),
+                ZoneRecordData::A(addr) => service.ip_addrs.push(IpAddr::V4(*addr)),
+                ZoneRecordData::Aaaa(addr) => service.ip_addrs.push(IpAddr::V6(*addr)),
+            }
+        }
+        services.into_values().collect()
+    }
+}
+
 /// Information about an endpoint that may be published to and resolved from discovery services.
 ///
 /// This struct couples a [`EndpointId`] with its associated [`EndpointData`].
@@ -405,6 +723,12 @@ impl EndpointInfo {
         self
     }
 
+    /// Adds a service and returns the updated endpoint info.
+    pub fn with_service(mut self, service: EndpointService) -> Self {
+        self.data.add_service(service);
+        self
+    }
+
     /// Converts into a [`EndpointAddr`] by cloning the needed fields.
     pub fn to_endpoint_addr(&self) -> EndpointAddr {
         EndpointAddr {
@@ -442,15 +766,28 @@ impl EndpointInfo {
         self.data.user_data()
     }
 
+    /// Returns the succession of the endpoint's key to a new key, if any.
+    pub fn successor(&self) -> Option<&KeySuccession> {
+        self.data.successor()
+    }
+
     /// Returns the direct addresses of the endpoint.
     pub fn ip_addrs(&self) -> impl Iterator<Item = &SocketAddr> {
         self.data.ip_addrs()
     }
 
+    /// Returns the services offered by the endpoint.
+    pub fn services(&self) -> impl Iterator<Item = &EndpointService> {
+        self.data.services()
+    }
+
     /// Parses a [`EndpointInfo`] from DNS TXT lookup results.
     ///
     /// The `domain_name` is the queried DNS name (e.g. `_iroh.<z32>.<origin>`).
     /// The `lookup` iterator yields TXT record values that implement [`Display`].
+    ///
+    /// Services are published under separate names and are not part of the result, see
+    /// [`DnsResolver::lookup_endpoint_service`](crate::dns::DnsResolver::lookup_endpoint_service).
     pub fn from_txt_lookup(
         domain_name: String,
         lookup: impl Iterator<Item = impl Display>,
@@ -462,18 +799,26 @@ impl EndpointInfo {
     /// Parses a [`EndpointInfo`] from a [`pkarr::SignedPacket`].
     pub fn from_pkarr_signed_packet(packet: &pkarr::SignedPacket) -> Result<Self, ParseError> {
         let attrs: TxtAttrs<IrohAttr> = TxtAttrs::from_pkarr_signed_packet(packet)?;
-        Ok(endpoint_info_from_attrs(&attrs))
+        let mut info = endpoint_info_from_attrs(&attrs);
+        info.data.services = EndpointService::from_zone_records(&packet.records());
+        Ok(info)
     }
 
     /// Creates a [`pkarr::SignedPacket`].
     ///
-    /// This constructs a DNS packet and signs it with a [`SecretKey`].
+    /// This constructs a DNS packet and signs it with `signer`, usually a
+    /// [`SecretKey`](iroh_base::SecretKey).
     pub fn to_pkarr_signed_packet(
         &self,
-        secret_key: &SecretKey,
+        signer: &(impl Signer + ?Sized),
         ttl: u32,
     ) -> Result<pkarr::SignedPacket, EncodingError> {
-        self.to_attrs().to_pkarr_signed_packet(secret_key, ttl)
+        let mut records: Vec<_> = self.to_attrs().to_zone_records(ttl).collect();
+        for service in &self.data.services {
+            records.extend(service.to_zone_records(ttl)?);
+        }
+        pkarr::SignedPacket::from_records(signer, records)
+            .map_err(|err| e!(EncodingError::FailedBuildingPacket, err))
     }
 
     /// Converts into a list of `{key}={value}` strings.
@@ -497,6 +842,9 @@ fn endpoint_info_to_attrs(info: &EndpointInfo) -> TxtAttrs<IrohAttr> {
     if let Some(user_data) = &info.data.user_data {
         attrs.push((IrohAttr::UserData, user_data.to_string()));
     }
+    if let Some(successor) = &info.data.successor {
+        attrs.push((IrohAttr::Successor, successor_to_string(successor)));
+    }
     TxtAttrs::from_parts(info.endpoint_id, attrs.into_iter())
 }
 
@@ -532,13 +880,38 @@ fn endpoint_info_from_attrs(attrs: &TxtAttrs<IrohAttr>) -> EndpointInfo {
         .flatten()
         .next()
         .and_then(|s| UserData::from_str(s).ok());
+    // Successions which are not signed by the key of this endpoint are ignored.
+    let successor = a
+        .get(&IrohAttr::Successor)
+        .into_iter()
+        .flatten()
+        .find_map(|s| successor_from_str(endpoint_id, s));
     let mut data = EndpointData::default();
     data.set_user_data(user_data);
+    data.set_successor(successor);
     data.add_addrs(relay_urls.chain(addrs));
 
     EndpointInfo { endpoint_id, data }
 }
 
+/// Encodes the successor key and signature of a [`KeySuccession`].
+fn successor_to_string(succession: &KeySuccession) -> String {
+    format!(
+        "{} {}",
+        succession.successor(),
+        HEXLOWER.encode(&succession.signature().to_bytes())
+    )
+}
+
+/// Parses a [`KeySuccession`] from `predecessor`, verifying the signature.
+fn successor_from_str(predecessor: EndpointId, s: &str) -> Option<KeySuccession> {
+    let (successor, signature) = s.split_once(' ')?;
+    let successor = PublicKey::from_str(successor).ok()?;
+    let signature = HEXLOWER.decode(signature.as_bytes()).ok()?;
+    let signature = Signature::from_bytes(signature.as_slice().try_into().ok()?);
+    KeySuccession::from_parts(predecessor, successor, signature).ok()
+}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
@@ -553,11 +926,11 @@ mod tests {
             },
         },
     };
-    use iroh_base::{EndpointId, SecretKey, TransportAddr};
+    use iroh_base::{EndpointId, KeySuccession, SecretKey, TransportAddr};
     use n0_error::{Result, StdResultExt};
 
-    use super::{EndpointData, EndpointInfo};
-    use crate::dns::TxtRecordData;
+    use super::{EndpointData, EndpointInfo, EndpointService, ServiceName};
+    use crate::{EncodingError, dns::TxtRecordData};
 
     #[test]
     fn txt_attr_roundtrip() {
@@ -590,6 +963,103 @@ mod tests {
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn signed_packet_roundtrip_with_successor() {
+        let old = SecretKey::from_bytes(&[1u8; 32]);
+        let new = SecretKey::from_bytes(&[2u8; 32]);
+        let succession = KeySuccession::new(&old, new.public()).unwrap();
+        let endpoint_data =
+            EndpointData::from_iter([TransportAddr::Relay("https://example.com".parse().unwrap())])
+                .with_successor(succession);
+        let expected = EndpointInfo::from_parts(old.public(), endpoint_data);
+        let packet = expected.to_pkarr_signed_packet(&old, 30).unwrap();
+        let actual = EndpointInfo::from_pkarr_signed_packet(&packet).unwrap();
+        assert_eq!(actual.successor(), Some(&succession));
+        assert_eq!(expected, actual);
+
+        // A succession published for another endpoint is dropped.
+        let other = SecretKey::from_bytes(&[3u8; 32]);
+        let forged = EndpointInfo::from_parts(other.public(), expected.data.clone());
+        let packet = forged.to_pkarr_signed_packet(&other, 30).unwrap();
+        let actual = EndpointInfo::from_pkarr_signed_packet(&packet).unwrap();
+        assert_eq!(actual.successor(), None);
+        assert_eq!(actual.relay_urls().count(), 1);
+    }
+
+    #[test]
+    fn signed_packet_roundtrip_with_services() -> Result {
+        let secret_key = SecretKey::from_bytes(&[1u8; 32]);
+        let chat = EndpointService::new("chat".parse()?)
+            .with_alpn(b"/chat/1")
+            .with_alpn(b"/chat/0")
+            .with_attr("room", "lobby")
+            .with_attr("room", "random")
+            .with_attr("motd", "a=b")
+            .with_ip_addr("192.0.2.1".parse().unwrap())
+            .with_ip_addr("2001:db8::1".parse().unwrap());
+        let blobs = EndpointService::new("blobs".parse()?).with_alpn(b"/iroh-bytes/4");
+        let endpoint_data =
+            EndpointData::from_iter([TransportAddr::Relay("https://example.com".parse().unwrap())])
+                .with_user_data("foobar".parse().unwrap())
+                .with_service(chat.clone())
+                .with_service(blobs.clone());
+        let expected = EndpointInfo::from_parts(secret_key.public(), endpoint_data);
+        let packet = expected.to_pkarr_signed_packet(&secret_key, 30)?;
+        let actual = EndpointInfo::from_pkarr_signed_packet(&packet)?;
+        assert_eq!(actual.relay_urls().count(), 1);
+        assert_eq!(actual.data.service(chat.name()), Some(&chat));
+        assert_eq!(actual.data.service(blobs.name()), Some(&blobs));
+        assert_eq!(actual.services().count(), 2);
+        assert_eq!(
+            actual
+                .data
+                .service(chat.name())
+                .unwrap()
+                .attr("motd")
+                .next(),
+            Some("a=b")
+        );
+
+        // a service with the same name replaces the previous one
+        let data = expected
+            .data
+            .clone()
+            .with_service(blobs.clone().with_alpn(b"/iroh-bytes/5"));
+        assert_eq!(data.services().count(), 2);
+        assert_eq!(data.service(blobs.name()).unwrap().alpns().count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn service_encoding_errors() -> Result {
+        assert!("chat-2".parse::<ServiceName>().is_ok());
+        assert!("Chat".parse::<ServiceName>().is_err());
+        assert!("-chat".parse::<ServiceName>().is_err());
+        assert!("".parse::<ServiceName>().is_err());
+        assert!("a".repeat(63).parse::<ServiceName>().is_err());
+
+        let secret_key = SecretKey::from_bytes(&[1u8; 32]);
+        let service = EndpointService::new("chat".parse()?).with_attr("alpn", "/chat/0");
+        let info = EndpointInfo::new(secret_key.public()).with_service(service);
+        assert!(matches!(
+            info.to_pkarr_signed_packet(&secret_key, 30),
+            Err(EncodingError::InvalidServiceAttr { .. })
+        ));
+
+        // services count towards the size limit of the signed packet
+        let mut info = EndpointInfo::new(secret_key.public());
+        for i in 0..20 {
+            let service = EndpointService::new(format!("service-{i}").parse()?)
+                .with_attr("data", "x".repeat(100));
+            info = info.with_service(service);
+        }
+        assert!(matches!(
+            info.to_pkarr_signed_packet(&secret_key, 30),
+            Err(EncodingError::FailedBuildingPacket { .. })
+        ));
+        Ok(())
+    }
+
     #[test]
     fn txt_attr_roundtrip_with_custom_addr() {
         use iroh_base::CustomAddr;
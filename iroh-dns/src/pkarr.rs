@@ -6,13 +6,17 @@
 
 use std::{
     fmt::{self, Display, Formatter},
+    net::{Ipv4Addr, Ipv6Addr},
     sync::atomic::Ordering,
 };
 
-use iroh_base::{PublicKey, SecretKey, Signature};
+use iroh_base::{PublicKey, Signature, Signer};
 use n0_error::{AnyError, anyerr, e, stack_error};
 use portable_atomic::AtomicU64;
-use simple_dns::{CLASS, Name, Packet, ResourceRecord, rdata::RData};
+use simple_dns::{
+    CLASS, Name, Packet, ResourceRecord,
+    rdata::{RData, TXT},
+};
 
 /// Maximum size of the encoded DNS packet within a signed packet.
 const MAX_DNS_PACKET_SIZE: usize = 1000;
@@ -41,28 +45,50 @@ impl SignedPacket {
     /// Create a signed packet containing TXT records under a single name.
     ///
     /// This is the common case: multiple TXT values under the same DNS name (e.g. `"_iroh"`).
+    /// The packet is signed by `signer`, usually a [`SecretKey`](iroh_base::SecretKey).
     pub fn from_txt_strings(
-        secret_key: &SecretKey,
+        signer: &(impl Signer + ?Sized),
         name: &str,
         values: impl IntoIterator<Item = impl AsRef<str>>,
         ttl: u32,
     ) -> Result<SignedPacket, SignedPacketBuildError> {
-        let public_key = secret_key.public();
+        let records = values.into_iter().map(|value| {
+            ZoneRecord::new(name, ttl, ZoneRecordData::Txt(value.as_ref().to_string()))
+        });
+        Self::from_records(signer, records)
+    }
+
+    /// Create a signed packet from records in the zone of the signer.
+    ///
+    /// The record names are normalized relative to the signer's z-base-32 public key, see
+    /// [`ZoneRecord::name`]. The packet is signed by `signer`, usually a
+    /// [`SecretKey`](iroh_base::SecretKey).
+    pub fn from_records(
+        signer: &(impl Signer + ?Sized),
+        records: impl IntoIterator<Item = ZoneRecord>,
+    ) -> Result<SignedPacket, SignedPacketBuildError> {
+        let public_key = signer.public_key();
         let origin = public_key.to_z32();
-        let normalized = normalize_name(&origin, name.to_string());
-        let dns_name = Name::new_unchecked(&normalized).into_owned();
         let mut packet = Packet::new_reply(0);
 
-        for value in values {
-            let mut txt = simple_dns::rdata::TXT::new();
-            txt.add_string(value.as_ref())
-                .map_err(|e| e!(SignedPacketBuildError::DnsError, anyerr!(e)))?;
-            packet.answers.push(ResourceRecord::new(
-                dns_name.clone(),
-                CLASS::IN,
-                ttl,
-                RData::TXT(txt.into_owned()),
-            ));
+        for record in records {
+            let normalized = normalize_name(&origin, record.name);
+            let dns_name = Name::new_unchecked(&normalized);
+            if !is_valid_zone_name(&dns_name) {
+                return Err(e!(SignedPacketBuildError::InvalidName { name: normalized }));
+            }
+            let rdata = match &record.data {
+                ZoneRecordData::Txt(value) => RData::TXT(
+                    TXT::new()
+                        .with_string(value)
+                        .map_err(|e| e!(SignedPacketBuildError::DnsError, anyerr!(e)))?,
+                ),
+                ZoneRecordData::A(addr) => RData::A((*addr).into()),
+                ZoneRecordData::Aaaa(addr) => RData::AAAA((*addr).into()),
+            };
+            packet
+                .answers
+                .push(ResourceRecord::new(dns_name, CLASS::IN, record.ttl, rdata).into_owned());
         }
 
         let encoded_packet = packet
@@ -76,7 +102,9 @@ impl SignedPacket {
         }
 
         let timestamp = Timestamp::now();
-        let signature = secret_key.sign(&signable(timestamp.as_micros(), &encoded_packet));
+        let signature = signer
+            .sign(&signable(timestamp.as_micros(), &encoded_packet))
+            .map_err(|err| e!(SignedPacketBuildError::Sign, err))?;
 
         let mut bytes = Vec::with_capacity(HEADER_SIZE + encoded_packet.len());
         bytes.extend_from_slice(public_key.as_bytes());
@@ -248,6 +276,39 @@ impl SignedPacket {
             .collect()
     }
 
+    /// Return all TXT, A and AAAA records in the zone of the signer.
+    ///
+    /// The record names are relative to the z-base-32 public key zone, with an empty name
+    /// for the zone apex. Records of other types and TXT records that are not valid UTF-8
+    /// are skipped.
+    pub fn records(&self) -> Vec<ZoneRecord> {
+        let origin = self.public_key().to_z32();
+        let Ok(packet) = Packet::parse(self.encoded_packet()) else {
+            return Vec::new();
+        };
+        let Ok(zone) = Name::new(&origin) else {
+            return Vec::new();
+        };
+        packet
+            .answers
+            .iter()
+            .filter_map(|rr| {
+                let name = rr.name.without(&zone)?.to_string();
+                let data = match &rr.rdata {
+                    RData::TXT(txt) => ZoneRecordData::Txt(String::try_from(txt.clone()).ok()?),
+                    RData::A(a) => ZoneRecordData::A(Ipv4Addr::from(a.address)),
+                    RData::AAAA(aaaa) => ZoneRecordData::Aaaa(Ipv6Addr::from(aaaa.address)),
+                    _ => return None,
+                };
+                Some(ZoneRecord {
+                    name,
+                    ttl: rr.ttl,
+                    data,
+                })
+            })
+            .collect()
+    }
+
     /// Reconstruct a signed packet from its raw parts without verifying the signature.
     ///
     /// This is useful for reconstructing a packet from storage or DHT mutable items
@@ -316,6 +377,55 @@ fn normalize_name(origin: &str, name: String) -> String {
     format!("{name}.{origin}")
 }
 
+/// Returns whether `name` is valid for a record in a pkarr zone.
+///
+/// All labels must be valid DNS labels, except for the leftmost label, which may also be
+/// the wildcard label `*`.
+fn is_valid_zone_name(name: &Name<'_>) -> bool {
+    name.get_labels()
+        .iter()
+        .enumerate()
+        .all(|(i, label)| label.is_valid() || (i == 0 && label.as_ref() == b"*"))
+}
+
+/// A resource record in the zone of a [`SignedPacket`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZoneRecord {
+    /// The name of the record, relative to the z-base-32 public key of the zone.
+    ///
+    /// An empty name or `@` is the zone apex. Names that already end with the z-base-32
+    /// public key are used as is. The leftmost label may be `*` to publish a wildcard record
+    /// that matches all names below it which are not published otherwise.
+    pub name: String,
+    /// The time to live of the record in seconds.
+    pub ttl: u32,
+    /// The record data.
+    pub data: ZoneRecordData,
+}
+
+impl ZoneRecord {
+    /// Creates a new [`ZoneRecord`].
+    pub fn new(name: impl Into<String>, ttl: u32, data: ZoneRecordData) -> Self {
+        Self {
+            name: name.into(),
+            ttl,
+            data,
+        }
+    }
+}
+
+/// The data of a [`ZoneRecord`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ZoneRecordData {
+    /// A TXT record with a single string of at most 255 bytes.
+    Txt(String),
+    /// An A record with an IPv4 address.
+    A(Ipv4Addr),
+    /// An AAAA record with an IPv6 address.
+    Aaaa(Ipv6Addr),
+}
+
 /// A pkarr timestamp in microseconds since the UNIX epoch.
 ///
 /// Used as the `seq` field in BEP_0044 DHT mutable items. Per the spec, a new
@@ -389,8 +499,15 @@ impl Timestamp {
 pub enum SignedPacketBuildError {
     #[error("DNS packet too large: {len} bytes (max {MAX_DNS_PACKET_SIZE})")]
     PacketTooLarge { len: usize },
+    #[error("Invalid record name `{name}`")]
+    InvalidName { name: String },
     #[error("DNS encoding error")]
     DnsError { source: AnyError },
+    #[error("Failed to sign the packet")]
+    Sign {
+        #[error(std_err)]
+        source: iroh_base::SignError,
+    },
 }
 
 /// Error verifying a signed packet.
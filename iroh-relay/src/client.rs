@@ -10,7 +10,7 @@ use std::{
 };
 
 use conn::Conn;
-use iroh_base::{RelayUrl, SecretKey};
+use iroh_base::{RelayUrl, Signer};
 #[cfg(not(wasm_browser))]
 use iroh_dns::dns::{DnsError, DnsResolver};
 #[cfg(wasm_browser)]
@@ -25,6 +25,10 @@ use tracing::{debug, trace};
 use url::Url;
 
 pub use self::conn::{RecvError, SendError};
+#[cfg(not(wasm_browser))]
+use self::streams::{MaybeTlsStream, ProxyStream};
+#[cfg(all(not(wasm_browser), feature = "server"))]
+use crate::protos::streams::WsBytesFramed;
 use crate::{
     KeyCache,
     http::{ProtocolVersion, RELAY_PATH},
@@ -150,8 +154,8 @@ pub struct ClientBuilder {
     tls_config: Option<rustls::ClientConfig>,
     /// HTTP Proxy
     proxy_url: Option<Url>,
-    /// The secret key of this client.
-    secret_key: SecretKey,
+    /// Signs for the key of this client.
+    signer: Arc<dyn Signer>,
     /// Optional authorization token.
     ///
     /// Sent as an `Authorization: Bearer` header on native targets and as
@@ -165,9 +169,12 @@ pub struct ClientBuilder {
 
 impl ClientBuilder {
     /// Create a new [`ClientBuilder`]
+    ///
+    /// The client authenticates with the key of `signer`, usually a
+    /// [`SecretKey`](iroh_base::SecretKey).
     pub fn new(
         url: impl Into<RelayUrl>,
-        secret_key: SecretKey,
+        signer: impl Signer,
         #[cfg(not(wasm_browser))] dns_resolver: DnsResolver,
     ) -> Self {
         ClientBuilder {
@@ -175,7 +182,7 @@ impl ClientBuilder {
             url: url.into(),
             tls_config: None,
             proxy_url: None,
-            secret_key,
+            signer: Arc::new(signer),
             #[cfg(not(wasm_browser))]
             dns_resolver,
             key_cache: KeyCache::new(128),
@@ -255,6 +262,82 @@ impl ClientBuilder {
     /// Establishes a new connection to the relay server.
     #[cfg(not(wasm_browser))]
     pub async fn connect(&self) -> Result<Client, ConnectError> {
+        let (conn, server_protocol, local_addr) = self
+            .connect_websocket(RELAY_PATH, ProtocolVersion::all_as_header_value())
+            .await?;
+
+        let protocol_version = server_protocol
+            .as_deref()
+            .and_then(ProtocolVersion::match_from_str)
+            .ok_or_else(|| {
+                e!(ConnectError::BadVersionHeader {
+                    server_version: server_protocol
+                })
+            })?;
+
+        let conn = Conn::new(
+            conn,
+            self.key_cache.clone(),
+            &*self.signer,
+            protocol_version,
+        )
+        .await?;
+
+        trace!("connect done");
+
+        Ok(Client {
+            conn,
+            local_addr: Some(local_addr),
+        })
+    }
+
+    /// Establishes a mesh link to the relay server, authenticating as a peer relay.
+    ///
+    /// Returns the websocket stream after the handshake completed, ready to speak the
+    /// [`mesh`] protocol.
+    ///
+    /// [`mesh`]: crate::protos::mesh
+    #[cfg(all(not(wasm_browser), feature = "server"))]
+    pub(crate) async fn connect_mesh(
+        &self,
+    ) -> Result<WsBytesFramed<MaybeTlsStream<ProxyStream>>, ConnectError> {
+        use http::HeaderValue;
+
+        use crate::{http::RELAY_MESH_PATH, protos::mesh::MESH_PROTOCOL};
+
+        let (io, server_protocol, _local_addr) = self
+            .connect_websocket(RELAY_MESH_PATH, HeaderValue::from_static(MESH_PROTOCOL))
+            .await?;
+        n0_error::ensure!(
+            server_protocol.as_deref() == Some(MESH_PROTOCOL),
+            ConnectError::BadVersionHeader {
+                server_version: server_protocol
+            }
+        );
+
+        let mut conn = WsBytesFramed { io };
+        handshake::clientside(&mut conn, &*self.signer).await?;
+        trace!("mesh connect done");
+        Ok(conn)
+    }
+
+    /// Dials the relay server and upgrades to a websocket connection on `path`.
+    ///
+    /// Offers `protocols` as the websocket sub-protocols and returns the one selected by
+    /// the server, together with the local address of the connection.
+    #[cfg(not(wasm_browser))]
+    async fn connect_websocket(
+        &self,
+        path: &str,
+        protocols: http::HeaderValue,
+    ) -> Result<
+        (
+            tokio_websockets::WebSocketStream<MaybeTlsStream<ProxyStream>>,
+            Option<String>,
+            SocketAddr,
+        ),
+        ConnectError,
+    > {
         use http::header::{AUTHORIZATION, HeaderValue, SEC_WEBSOCKET_PROTOCOL};
         use n0_error::StdResultExt;
         use tls::MaybeTlsStreamBuilder;
@@ -265,7 +348,7 @@ impl ClientBuilder {
         };
 
         let mut dial_url = (*self.url).clone();
-        dial_url.set_path(RELAY_PATH);
+        dial_url.set_path(path);
         // The relay URL is exchanged with the http(s) scheme in tickets and similar.
         // We need to use the ws:// or wss:// schemes when connecting with websockets, though.
         dial_url
@@ -306,10 +389,7 @@ impl ClientBuilder {
                     url: dial_url.clone()
                 })
             })?
-            .add_header(
-                SEC_WEBSOCKET_PROTOCOL,
-                ProtocolVersion::all_as_header_value(),
-            )
+            .add_header(SEC_WEBSOCKET_PROTOCOL, protocols)
             .expect("valid header name and value")
             .limits(tokio_websockets::Limits::default().max_payload_len(Some(MAX_FRAME_SIZE)))
             // We turn off automatic flushing after a threshold (the default would be after 8KB).
@@ -325,7 +405,7 @@ impl ClientBuilder {
                 .expect("valid header name");
         }
 
-        if let Some(client_auth) = KeyMaterialClientAuth::new(&self.secret_key, &stream) {
+        if let Some(client_auth) = KeyMaterialClientAuth::new(&*self.signer, &stream) {
             debug!("Using TLS key export for relay client authentication");
             builder = builder
                 .add_header(CLIENT_AUTH_HEADER, client_auth.into_header_value())
@@ -342,32 +422,13 @@ impl ClientBuilder {
             }
         );
 
-        let protocol_version_str = response
+        let server_protocol = response
             .headers()
             .get(SEC_WEBSOCKET_PROTOCOL)
-            .and_then(|s| s.to_str().ok());
-        let protocol_version = protocol_version_str
-            .and_then(ProtocolVersion::match_from_str)
-            .ok_or_else(|| {
-                e!(ConnectError::BadVersionHeader {
-                    server_version: protocol_version_str.map(ToOwned::to_owned)
-                })
-            })?;
+            .and_then(|s| s.to_str().ok())
+            .map(ToOwned::to_owned);
 
-        let conn = Conn::new(
-            conn,
-            self.key_cache.clone(),
-            &self.secret_key,
-            protocol_version,
-        )
-        .await?;
-
-        trace!("connect done");
-
-        Ok(Client {
-            conn,
-            local_addr: Some(local_addr),
-        })
+        Ok((conn, server_protocol, local_addr))
     }
 
     /// Reports whether IPv4 dials should be slightly
@@ -429,7 +490,7 @@ impl ClientBuilder {
         let conn = Conn::new(
             ws_stream,
             self.key_cache.clone(),
-            &self.secret_key,
+            &*self.signer,
             protocol_version,
         )
         .await?;
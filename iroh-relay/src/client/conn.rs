@@ -7,7 +7,7 @@ use std::{
     task::{Context, Poll, ready},
 };
 
-use iroh_base::SecretKey;
+use iroh_base::Signer;
 use n0_error::{AnyError, anyerr, ensure, stack_error};
 use n0_future::{Sink, Stream};
 use tracing::trace;
@@ -87,14 +87,14 @@ impl Conn {
         >,
         #[cfg(wasm_browser)] io: ws_stream_wasm::WsStream,
         key_cache: KeyCache,
-        secret_key: &SecretKey,
+        signer: &dyn Signer,
         protocol_version: ProtocolVersion,
     ) -> Result<Self, handshake::Error> {
         let mut conn = WsBytesFramed { io };
 
         // exchange information with the server
         trace!("server_handshake: started");
-        handshake::clientside(&mut conn, secret_key).await?;
+        handshake::clientside(&mut conn, signer).await?;
         trace!("server_handshake: done");
 
         Ok(Self {
@@ -11,6 +11,8 @@ pub(crate) const SUPPORTED_WEBSOCKET_VERSION: &str = "13";
 /// The HTTP path under which the relay accepts relaying connections
 /// (over websockets and a custom upgrade protocol).
 pub const RELAY_PATH: &str = "/relay";
+/// The HTTP path under which the relay accepts connections from other relays in its mesh.
+pub const RELAY_MESH_PATH: &str = "/relay/mesh";
 /// The HTTP path under which the relay allows doing latency queries for testing.
 pub const RELAY_PROBE_PATH: &str = "/ping";
 
@@ -37,6 +37,7 @@ mod key_cache;
 mod ping_tracker;
 pub mod protos;
 pub mod quic;
+mod relay_manifest;
 mod relay_map;
 #[cfg(feature = "server")]
 pub mod server;
@@ -48,6 +49,7 @@ pub use crate::{
     key_cache::KeyCache,
     ping_tracker::PingTracker,
     protos::relay::MAX_PACKET_SIZE,
+    relay_manifest::{RelayManifest, RelayManifestEntry, RelayManifestError, SignedRelayManifest},
     relay_map::{RelayConfig, RelayMap, RelayQuicConfig},
 };
 
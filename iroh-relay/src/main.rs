@@ -8,24 +8,25 @@ use std::{
     num::NonZeroU32,
     path::{Path, PathBuf},
     sync::Arc,
+    time::{Duration, SystemTime},
 };
 
 use clap::Parser;
 use http::StatusCode;
-use iroh_base::EndpointId;
+use iroh_base::{EndpointId, RelayUrl, SecretKey};
 use iroh_relay::{
     defaults::{
         DEFAULT_HTTP_PORT, DEFAULT_HTTPS_PORT, DEFAULT_METRICS_PORT, DEFAULT_RELAY_QUIC_PORT,
     },
     server::{
         self as relay, Access, AccessControl, AcmeConfig, ClientRateLimit, ClientRequest,
-        DEFAULT_CERT_RELOAD_INTERVAL, QuicConfig, reloading_resolver,
+        DEFAULT_CERT_RELOAD_INTERVAL, QuicConfig, Quota, reloading_resolver,
     },
     tls::CaTlsConfig,
 };
 use n0_error::{AnyError, Result, StdResultExt, bail_any};
 use serde::{Deserialize, Serialize};
-use tracing::{debug, warn};
+use tracing::{debug, info, warn};
 use tracing_subscriber::{EnvFilter, prelude::*};
 use url::Url;
 use webpki_types::{CertificateDer, PrivateKeyDer, pem::PemObject};
@@ -38,6 +39,12 @@ const X_IROH_ENDPOINT_ID: &str = "X-Iroh-NodeId";
 const ENV_HTTP_BEARER_TOKEN: &str = "IROH_RELAY_HTTP_BEARER_TOKEN";
 /// Environment variable to verify relay access (without an external auth service)
 const ENV_RELAY_ACCESS_TOKEN: &str = "IROH_RELAY_ACCESS_TOKEN";
+/// Environment variable to read the secret key for mesh links from.
+const ENV_MESH_SECRET_KEY: &str = "IROH_RELAY_MESH_SECRET_KEY";
+/// Interval at which the config file is checked for changes.
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Environment variable to read the bearer token for the admin API from.
+const ENV_ADMIN_TOKEN: &str = "IROH_RELAY_ADMIN_TOKEN";
 /// Environment variable to override the ACME directory URL.
 const ENV_ACME_URL: &str = "IROH_RELAY_ACME_URL";
 /// Environment variable to trust an additional CA for the ACME server's TLS certificate.
@@ -55,6 +62,9 @@ struct Cli {
     #[clap(long, default_value_t = false)]
     dev: bool,
     /// Path to the configuration file.
+    ///
+    /// The access control, limits and key cache capacity are reloaded when the file
+    /// changes or the process receives `SIGHUP`.  Other changes require a restart.
     #[clap(long, short)]
     config_path: Option<PathBuf>,
 }
@@ -153,6 +163,126 @@ struct Config {
     /// This controls which endpoints are allowed to relay connections, other endpoints are not controlled by this.
     #[serde(default)]
     access: AccessConfig,
+    /// Forwarding of datagrams to and from other relays in a mesh.
+    ///
+    /// Disabled if not present.
+    mesh: Option<MeshConfig>,
+    /// The admin API to inspect and manage connected clients.
+    ///
+    /// Disabled if not present.
+    admin: Option<AdminConfig>,
+}
+
+/// Configuration for forwarding datagrams between relays in a mesh.
+///
+/// Each relay in the mesh dials every peer and forwards datagrams for endpoints connected
+/// to that peer, so several relays can serve the same hostname behind a load balancer.
+///
+/// # Example
+///
+/// ```toml
+/// [mesh]
+/// secret_key_path = "/etc/iroh-relay/mesh.key"
+///
+/// [[mesh.peers]]
+/// endpoint_id = "<hex-encoded endpoint id>"
+/// url = "https://relay-1.internal.example.com"
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+struct MeshConfig {
+    /// Path to a file holding the secret key this relay authenticates with to its peers.
+    ///
+    /// The secret key can also be set by the `IROH_RELAY_MESH_SECRET_KEY` environment
+    /// variable, which takes precedence over this file.
+    secret_key_path: Option<PathBuf>,
+    /// The relays in the mesh.
+    ///
+    /// May include this relay itself, so all relays can share the same list.
+    #[serde(default)]
+    peers: Vec<MeshPeerConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+struct MeshPeerConfig {
+    /// The endpoint id of the peer relay's mesh secret key.
+    endpoint_id: EndpointId,
+    /// The URL of the individual peer relay instance, which must use `https`.
+    url: RelayUrl,
+}
+
+impl MeshConfig {
+    async fn load(&self) -> Result<relay::MeshConfig> {
+        let secret_key = match std::env::var(ENV_MESH_SECRET_KEY) {
+            Ok(secret_key) => secret_key,
+            Err(_) => {
+                let Some(path) = &self.secret_key_path else {
+                    bail_any!(
+                        "mesh.secret_key_path or {ENV_MESH_SECRET_KEY} must be set to enable the mesh"
+                    );
+                };
+                tokio::fs::read_to_string(path)
+                    .await
+                    .std_context("failed to read mesh secret key")?
+            }
+        };
+        let secret_key: SecretKey = secret_key
+            .trim()
+            .parse()
+            .std_context("invalid mesh secret key")?;
+        let peers = self
+            .peers
+            .iter()
+            .map(|peer| relay::MeshPeer::new(peer.endpoint_id, peer.url.clone()))
+            .collect();
+        Ok(relay::MeshConfig::new(secret_key, peers))
+    }
+}
+
+/// Configuration for the admin API.
+///
+/// Requests must be authorized with a bearer token, see [`relay::admin`] for the routes.
+///
+/// # Example
+///
+/// ```toml
+/// [admin]
+/// bind_addr = "127.0.0.1:9091"
+/// token_path = "/etc/iroh-relay/admin.token"
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+struct AdminConfig {
+    /// The socket address to serve the admin API on.
+    ///
+    /// The API is served over plain HTTP, this should not be reachable from the internet.
+    bind_addr: SocketAddr,
+    /// Path to a file holding the bearer token requests must be authorized with.
+    ///
+    /// The token can also be set by the `IROH_RELAY_ADMIN_TOKEN` environment variable,
+    /// which takes precedence over this file.
+    token_path: Option<PathBuf>,
+}
+
+impl AdminConfig {
+    async fn load(&self) -> Result<relay::AdminConfig> {
+        let token = match std::env::var(ENV_ADMIN_TOKEN) {
+            Ok(token) => token,
+            Err(_) => {
+                let Some(path) = &self.token_path else {
+                    bail_any!(
+                        "admin.token_path or {ENV_ADMIN_TOKEN} must be set to enable the admin API"
+                    );
+                };
+                tokio::fs::read_to_string(path)
+                    .await
+                    .std_context("failed to read admin token")?
+            }
+        };
+        let token = token.trim();
+        if token.is_empty() {
+            bail_any!("the admin token must not be empty");
+        }
+        Ok(relay::AdminConfig::new(self.bind_addr, token))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
@@ -359,6 +489,8 @@ impl Default for Config {
             metrics_bind_addr: None,
             key_cache_capacity: Default::default(),
             access: AccessConfig::Everyone,
+            mesh: None,
+            admin: None,
         }
     }
 }
@@ -506,10 +638,37 @@ where
 struct Limits {
     /// Rate limit for accepting new connection. Unlimited if not set.
     accept_conn_limit: Option<f64>,
-    /// Burst limit for accepting new connection. Unlimited if not set.
+    /// Burst limit for accepting new connection. Defaults to the rate limit.
     accept_conn_burst: Option<usize>,
+    /// Rate limit for accepting new connection from a single IP address. Unlimited if not set.
+    accept_conn_limit_per_ip: Option<f64>,
+    /// Burst limit for accepting new connection from a single IP address. Defaults to the
+    /// per-IP rate limit.
+    accept_conn_burst_per_ip: Option<usize>,
     /// Rate limiting configuration per client.
     client: Option<PerClientRateLimitConfig>,
+    /// Quota and weight of each endpoint.
+    endpoint: Option<EndpointLimitsConfig>,
+}
+
+/// Limits for the traffic of each endpoint.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct EndpointLimitsConfig {
+    /// Number of bytes relayed from and to an endpoint per quota period. Unlimited if not set.
+    quota_bytes: Option<u64>,
+    /// The period after which the quota resets. Defaults to daily.
+    #[serde(default)]
+    quota_period: QuotaPeriodConfig,
+    /// Weight of an endpoint's datagrams when the recipient is busy. Defaults to 1.
+    weight: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum QuotaPeriodConfig {
+    #[default]
+    Daily,
+    Monthly,
 }
 
 /// Rate limit configuration for each connected client.
@@ -601,17 +760,144 @@ async fn main() -> Result<()> {
     debug!("{relay_config:#?}");
 
     let mut relay = relay::Server::spawn(relay_config).await?;
+    let mut watcher = ConfigWatcher::new(cli.config_path.clone()).await;
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    let terminate = terminate_signal();
+    tokio::pin!(ctrl_c, terminate);
+    let drain = loop {
+        tokio::select! {
+            biased;
+            _ = &mut ctrl_c => break false,
+            _ = &mut terminate => break true,
+            _ = relay.join() => break false,
+            _ = watcher.changed() => reload_config(&cli, &relay).await,
+        }
+    };
 
-    tokio::select! {
-        biased;
-        _ = tokio::signal::ctrl_c() => (),
-        _ = relay.join() => (),
+    if drain {
+        info!("received SIGTERM, draining relay");
+        relay.drain(relay::DrainConfig::default()).await?;
+    } else {
+        relay.shutdown().await?;
     }
-
-    relay.shutdown().await?;
     Ok(())
 }
 
+/// Resolves once the process receives `SIGTERM`, which drains the relay.
+#[cfg(unix)]
+async fn terminate_signal() {
+    use tokio::signal::unix::{SignalKind, signal};
+    match signal(SignalKind::terminate()) {
+        Ok(mut signal) => {
+            signal.recv().await;
+        }
+        Err(err) => {
+            warn!("failed to listen for SIGTERM: {err:#}");
+            std::future::pending().await
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn terminate_signal() {
+    std::future::pending().await
+}
+
+/// Reloads the access control and limits of `relay` from the config file.
+///
+/// If the new config is invalid, the current settings are kept.
+async fn reload_config(cli: &Cli, relay: &relay::Server) {
+    let Some(path) = &cli.config_path else {
+        warn!("no config file to reload");
+        return;
+    };
+    let res: Result<()> = async {
+        let cfg = Config::read_from_file(path).await?;
+        relay.reload(build_reload_config(&cfg)?)?;
+        Ok(())
+    }
+    .await;
+    match res {
+        Ok(()) => info!(path = %path.display(), "reloaded config"),
+        Err(err) => warn!("failed to reload config, keeping the current settings: {err:#}"),
+    }
+}
+
+/// Watches the config file for changes, and the process for `SIGHUP`.
+struct ConfigWatcher {
+    path: Option<PathBuf>,
+    /// The modification time of the config file when it was last checked.
+    modified: Option<SystemTime>,
+    #[cfg(unix)]
+    hangup: Option<tokio::signal::unix::Signal>,
+}
+
+impl ConfigWatcher {
+    async fn new(path: Option<PathBuf>) -> Self {
+        let modified = match &path {
+            Some(path) => file_modified(path).await,
+            None => None,
+        };
+        #[cfg(unix)]
+        let hangup = {
+            use tokio::signal::unix::{SignalKind, signal};
+            signal(SignalKind::hangup())
+                .inspect_err(|err| warn!("failed to listen for SIGHUP: {err:#}"))
+                .ok()
+        };
+        Self {
+            path,
+            modified,
+            #[cfg(unix)]
+            hangup,
+        }
+    }
+
+    /// Resolves once the config file changed, or the process received `SIGHUP`.
+    async fn changed(&mut self) {
+        let poll = async {
+            let Some(path) = &self.path else {
+                return std::future::pending().await;
+            };
+            loop {
+                tokio::time::sleep(CONFIG_POLL_INTERVAL).await;
+                if file_modified(path).await != self.modified {
+                    debug!(path = %path.display(), "config file changed");
+                    return;
+                }
+            }
+        };
+        #[cfg(unix)]
+        let hangup = async {
+            match &mut self.hangup {
+                Some(signal) => {
+                    signal.recv().await;
+                    info!("received SIGHUP, reloading config");
+                }
+                None => std::future::pending().await,
+            }
+        };
+        #[cfg(not(unix))]
+        let hangup = std::future::pending::<()>();
+        tokio::select! {
+            _ = poll => {}
+            _ = hangup => {}
+        }
+        if let Some(path) = &self.path {
+            self.modified = file_modified(path).await;
+        }
+    }
+}
+
+/// Returns the modification time of the file at `path`, if available.
+async fn file_modified(path: &Path) -> Option<SystemTime> {
+    tokio::fs::metadata(path)
+        .await
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
 async fn load_cert_config(tls: &TlsConfig) -> Result<relay::CertConfig> {
     let server_config = rustls::ServerConfig::builder_with_provider(std::sync::Arc::new(
         rustls::crypto::ring::default_provider(),
@@ -724,6 +1010,37 @@ async fn build_relay_config(cfg: Config) -> Result<relay::ServerConfig> {
         (None, None)
     };
 
+    let limits = build_limits(&cfg)?;
+
+    let relay_config = if cfg.enable_relay {
+        let mut relay_config = relay::RelayConfig::new(cfg.http_bind_addr());
+        relay_config.tls = tls_config;
+        relay_config.limits = limits;
+        relay_config.key_cache_capacity = cfg.key_cache_capacity;
+        relay_config.access = cfg.access.clone().try_into()?;
+        if let Some(mesh) = &cfg.mesh {
+            relay_config.mesh = Some(mesh.load().await?);
+        }
+        Some(relay_config)
+    } else {
+        None
+    };
+
+    let mut server_config = relay::ServerConfig::default();
+    server_config.relay = relay_config;
+    server_config.quic = quic_config;
+    if let Some(admin) = &cfg.admin {
+        server_config.admin = Some(admin.load().await?);
+    }
+    #[cfg(feature = "metrics")]
+    {
+        server_config.metrics_addr = Some(cfg.metrics_bind_addr()).filter(|_| cfg.enable_metrics);
+    }
+    Ok(server_config)
+}
+
+/// Convert the TOML-loaded limits to [`relay::Limits`].
+fn build_limits(cfg: &Config) -> Result<relay::Limits> {
     let limits = match cfg.limits {
         Some(ref limits) => {
             let client_rx = match &limits.client {
@@ -753,31 +1070,37 @@ async fn build_relay_config(cfg: Config) -> Result<relay::ServerConfig> {
             let mut out = relay::Limits::default();
             out.accept_conn_limit = limits.accept_conn_limit;
             out.accept_conn_burst = limits.accept_conn_burst;
+            out.accept_conn_limit_per_ip = limits.accept_conn_limit_per_ip;
+            out.accept_conn_burst_per_ip = limits.accept_conn_burst_per_ip;
             out.client_rx = client_rx;
+            if let Some(endpoint) = &limits.endpoint {
+                out.endpoint.quota =
+                    endpoint
+                        .quota_bytes
+                        .map(|bytes| match endpoint.quota_period {
+                            QuotaPeriodConfig::Daily => Quota::daily(bytes),
+                            QuotaPeriodConfig::Monthly => Quota::monthly(bytes),
+                        });
+                if let Some(weight) = endpoint.weight {
+                    out.endpoint.weight = weight
+                        .try_into()
+                        .std_context("endpoint weight must be non-zero")?;
+                }
+            }
             out
         }
         None => Default::default(),
     };
+    Ok(limits)
+}
 
-    let relay_config = if cfg.enable_relay {
-        let mut relay_config = relay::RelayConfig::new(cfg.http_bind_addr());
-        relay_config.tls = tls_config;
-        relay_config.limits = limits;
-        relay_config.key_cache_capacity = cfg.key_cache_capacity;
-        relay_config.access = cfg.access.clone().try_into()?;
-        Some(relay_config)
-    } else {
-        None
-    };
-
-    let mut server_config = relay::ServerConfig::default();
-    server_config.relay = relay_config;
-    server_config.quic = quic_config;
-    #[cfg(feature = "metrics")]
-    {
-        server_config.metrics_addr = Some(cfg.metrics_bind_addr()).filter(|_| cfg.enable_metrics);
-    }
-    Ok(server_config)
+/// Convert the reloadable parts of the TOML-loaded config to a [`relay::ReloadConfig`].
+fn build_reload_config(cfg: &Config) -> Result<relay::ReloadConfig> {
+    let mut reload_config = relay::ReloadConfig::default();
+    reload_config.limits = build_limits(cfg)?;
+    reload_config.key_cache_capacity = cfg.key_cache_capacity;
+    reload_config.access = cfg.access.clone().try_into()?;
+    Ok(reload_config)
 }
 
 #[cfg(test)]
@@ -814,6 +1137,24 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_endpoint_limits_config() -> Result {
+        let config = "
+            [limits.endpoint]
+            quota_bytes = 1000000
+            quota_period = \"monthly\"
+            weight = 3
+        ";
+        let config = Config::from_str(config)?;
+        let relay_config = build_relay_config(config).await?;
+
+        let relay = relay_config.relay.expect("no relay config");
+        assert_eq!(relay.limits.endpoint.quota, Some(Quota::monthly(1_000_000)));
+        assert_eq!(relay.limits.endpoint.weight, NonZeroU32::new(3).unwrap());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_rate_limit_default() -> Result {
         let config = Config::from_str("")?;
@@ -911,6 +1252,54 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_mesh_config() -> Result {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let secret_key = SecretKey::from_bytes(&rng.random());
+        let peer_id = SecretKey::from_bytes(&rng.random()).public();
+
+        let dir = tempfile::tempdir()?;
+        let key_path = dir.path().join("mesh.key");
+        tokio::fs::write(
+            &key_path,
+            data_encoding::HEXLOWER.encode(&secret_key.to_bytes()),
+        )
+        .await?;
+        let config = format!(
+            r#"
+            [mesh]
+            secret_key_path = "{}"
+
+            [[mesh.peers]]
+            endpoint_id = "{peer_id}"
+            url = "https://relay-1.example.com"
+        "#,
+            key_path.display()
+        );
+        let config = Config::from_str(&config)?;
+        let relay = build_relay_config(config)
+            .await?
+            .relay
+            .expect("no relay config");
+        let mesh = relay.mesh.expect("no mesh config");
+        assert_eq!(mesh.secret_key.public(), secret_key.public());
+        assert_eq!(
+            mesh.peers,
+            vec![relay::MeshPeer::new(
+                peer_id,
+                "https://relay-1.example.com".parse().unwrap()
+            )]
+        );
+
+        let config = Config::from_str("[mesh]")?;
+        assert!(
+            build_relay_config(config).await.is_err(),
+            "mesh without a secret key should be rejected at startup"
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_access_token_empty_is_rejected() -> Result {
         let config = r#"
@@ -933,6 +1322,36 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_reload_config() -> Result {
+        let config = Config::from_str("http_bind_addr = \"127.0.0.1:0\"")?;
+        let relay = relay::Server::spawn(build_relay_config(config).await?).await?;
+
+        let config = "
+            http_bind_addr = \"127.0.0.1:0\"
+            key_cache_capacity = 16
+            access.denylist = []
+
+            [limits]
+            accept_conn_limit = 10.0
+        ";
+        let reload_config = build_reload_config(&Config::from_str(config)?)?;
+        assert_eq!(reload_config.key_cache_capacity, Some(16));
+        assert_eq!(reload_config.limits.accept_conn_limit, Some(10.0));
+        relay.reload(reload_config)?;
+
+        // Invalid limits are rejected by the running server.
+        let config = "
+            [limits]
+            accept_conn_limit = -1.0
+        ";
+        let reload_config = build_reload_config(&Config::from_str(config)?)?;
+        assert!(relay.reload(reload_config).is_err());
+
+        relay.shutdown().await?;
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_enable_relay_config() -> Result {
         let config = "
@@ -2,5 +2,7 @@
 
 pub mod common;
 pub mod handshake;
+#[cfg(feature = "server")]
+pub(crate) mod mesh;
 pub mod relay;
 pub mod streams;
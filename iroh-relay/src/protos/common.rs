@@ -62,6 +62,31 @@ pub enum FrameType {
     ///
     /// [`Status`]: super::relay::Status
     Status = 13,
+
+    /// Sent between mesh relays to forward a datagram to an endpoint connected to the
+    /// receiving relay.
+    ///
+    /// 32B dest pub key + 32B src pub key + ECN byte + one datagram's content
+    MeshDatagram = 14,
+    /// 32B dest pub key + 32B src pub key + ECN byte + segment size u16 + datagrams contents
+    MeshDatagramBatch = 15,
+    /// Sent between mesh relays to announce that an endpoint is connected to the sender.
+    ///
+    /// 32B pub key of the connected endpoint
+    MeshEndpointConnected = 16,
+    /// Sent between mesh relays to announce that an endpoint disconnected from the sender.
+    ///
+    /// 32B pub key of the endpoint that's gone
+    MeshEndpointGone = 17,
+    /// Sent by the dialing relay on a new mesh link, for the accepting relay to prove that
+    /// it owns the mesh key the dialing relay expects.
+    ///
+    /// 16B random challenge
+    MeshPeerChallenge = 18,
+    /// Sent by the accepting relay in response to a [`FrameType::MeshPeerChallenge`].
+    ///
+    /// 64B signature of the challenge with the accepting relay's mesh key
+    MeshPeerAuth = 19,
 }
 
 #[stack_error(derive, add_meta)]
@@ -33,7 +33,7 @@ use data_encoding::BASE32HEX_NOPAD as HEX;
 use http::HeaderValue;
 #[cfg(feature = "server")]
 use iroh_base::Signature;
-use iroh_base::{PublicKey, SecretKey};
+use iroh_base::{PublicKey, Signer};
 use n0_error::{AnyError, anyerr, e, ensure, stack_error};
 use n0_future::{SinkExt, TryStreamExt};
 #[cfg(feature = "server")]
@@ -158,6 +158,11 @@ pub enum Error {
     },
     #[error("The relay denied our authentication ({reason})")]
     ServerDeniedAuth { reason: String },
+    #[error("Failed to sign the challenge")]
+    Sign {
+        #[error(std_err)]
+        source: iroh_base::SignError,
+    },
     #[error("Unexpected tag, got {frame_type:?}, but expected one of {expected_types:?}")]
     UnexpectedFrameType {
         frame_type: FrameType,
@@ -222,11 +227,14 @@ impl ServerChallenge {
 
 impl ClientAuth {
     /// Generates a signature for the given challenge from the server.
-    pub(crate) fn new(secret_key: &SecretKey, challenge: &ServerChallenge) -> Self {
-        Self {
-            public_key: secret_key.public(),
-            signature: secret_key.sign(&challenge.message_to_sign()).to_bytes(),
-        }
+    pub(crate) fn new(
+        signer: &(impl Signer + ?Sized),
+        challenge: &ServerChallenge,
+    ) -> Result<Self, iroh_base::SignError> {
+        Ok(Self {
+            public_key: signer.public_key(),
+            signature: signer.sign(&challenge.message_to_sign())?.to_bytes(),
+        })
     }
 
     /// Verifies this client's authentication given the challenge this was sent in response to.
@@ -251,19 +259,25 @@ impl ClientAuth {
 impl KeyMaterialClientAuth {
     /// Generates a client's authentication, similar to [`ClientAuth`], but by using TLS keying material
     /// instead of a received challenge.
-    pub(crate) fn new(secret_key: &SecretKey, io: &impl ExportKeyingMaterial) -> Option<Self> {
-        let public_key = secret_key.public();
+    ///
+    /// Returns `None` if keying material can not be exported or signing fails, in which case
+    /// the client authenticates with a challenge instead.
+    pub(crate) fn new(
+        signer: &(impl Signer + ?Sized),
+        io: &impl ExportKeyingMaterial,
+    ) -> Option<Self> {
+        let public_key = signer.public_key();
         let key_material = io.export_keying_material(
             [0u8; 32],
             DOMAIN_SEP_TLS_EXPORT_LABEL,
-            Some(secret_key.public().as_bytes()),
+            Some(public_key.as_bytes()),
         )?;
         // We split the export and only sign the first 16 bytes, and
         // pass through the last 16 bytes. See also the note in [Self::verify].
         let (message, suffix) = key_material.split_at(16);
         Some(Self {
             public_key,
-            signature: secret_key.sign(message).to_bytes(),
+            signature: signer.sign(message).ok()?.to_bytes(),
             key_material_suffix: suffix.try_into().expect("hardcoded length"),
         })
     }
@@ -336,10 +350,10 @@ impl KeyMaterialClientAuth {
 /// This is already after having potentially transferred a [`KeyMaterialClientAuth`],
 /// but before having received a response for whether that worked or not.
 ///
-/// This requires access to the client's secret key to sign a challenge.
+/// This requires a signer for the client's key to sign a challenge.
 pub(crate) async fn clientside(
     io: &mut (impl BytesStreamSink + ExportKeyingMaterial),
-    secret_key: &SecretKey,
+    signer: &(impl Signer + ?Sized),
 ) -> Result<ServerConfirmsAuth, Error> {
     let (tag, frame) = read_frame(
         io,
@@ -354,7 +368,8 @@ pub(crate) async fn clientside(
     let (tag, frame) = if tag == ServerChallenge::TAG {
         let challenge: ServerChallenge = deserialize_frame(frame)?;
 
-        let client_info = ClientAuth::new(secret_key, &challenge);
+        let client_info =
+            ClientAuth::new(signer, &challenge).map_err(|err| e!(Error::Sign, err))?;
         write_frame(io, client_info).await?;
 
         read_frame(io, &[ServerConfirmsAuth::TAG, ServerDeniesAuth::TAG]).await?
@@ -845,7 +860,7 @@ mod tests {
         let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0u64);
         let secret_key = SecretKey::from_bytes(&rng.random());
         let challenge = ServerChallenge::new(&mut rng);
-        let client_auth = ClientAuth::new(&secret_key, &challenge);
+        let client_auth = ClientAuth::new(&secret_key, &challenge).unwrap();
 
         let bytes = postcard::to_allocvec(&client_auth).anyerr()?;
         let decoded: ClientAuth = postcard::from_bytes(&bytes).anyerr()?;
@@ -883,7 +898,7 @@ mod tests {
         let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0u64);
         let secret_key = SecretKey::from_bytes(&rng.random());
         let challenge = ServerChallenge::new(&mut rng);
-        let client_auth = ClientAuth::new(&secret_key, &challenge);
+        let client_auth = ClientAuth::new(&secret_key, &challenge).unwrap();
         assert!(client_auth.verify(&challenge).is_ok());
 
         Ok(())
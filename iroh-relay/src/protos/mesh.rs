@@ -0,0 +1,292 @@
+//! This module implements the relay-to-relay mesh protocol.
+//!
+//! Mesh links are websocket connections between relay servers, established on the
+//! [`RELAY_MESH_PATH`] after the regular [`handshake`] authenticated the dialing relay.
+//!
+//! Protocol flow:
+//!  * the dialing relay sends a [`FrameType::MeshPeerChallenge`], the accepting relay responds
+//!    with a [`FrameType::MeshPeerAuth`] signing the challenge with its mesh key, so that the
+//!    dialing relay knows it reached the peer it expected
+//!  * the dialing relay sends [`FrameType::Ping`] now and then, the accepting relay responds
+//!    with a [`FrameType::Pong`]
+//!  * the accepting relay sends [`FrameType::MeshEndpointConnected`] for every endpoint
+//!    connected to it, first for all endpoints connected when the link is established and
+//!    then whenever a new endpoint connects
+//!  * the accepting relay sends [`FrameType::MeshEndpointGone`] when an endpoint disconnects
+//!  * the dialing relay sends [`FrameType::MeshDatagram`] or [`FrameType::MeshDatagramBatch`]
+//!    for endpoints announced by the accepting relay
+//!
+//! Links are unidirectional for datagrams: a relay only forwards datagrams over the links it
+//! dialed, and only delivers datagrams received on links it accepted.
+//!
+//! [`RELAY_MESH_PATH`]: crate::http::RELAY_MESH_PATH
+//! [`handshake`]: super::handshake
+
+use bytes::{BufMut, Bytes, BytesMut};
+use iroh_base::{EndpointId, SecretKey, Signature};
+use n0_error::{e, ensure};
+
+use super::{
+    common::FrameType,
+    relay::{Datagrams, Error, MAX_PACKET_SIZE},
+};
+use crate::KeyCache;
+
+/// The websocket sub-protocol spoken on mesh links.
+pub(crate) const MESH_PROTOCOL: &str = "iroh-relay-mesh-v1";
+
+/// Domain separation string for the [`MeshMsg::PeerAuth`] signature.
+const DOMAIN_SEP_PEER_AUTH: &str = "iroh-relay mesh v1 peer auth signature";
+
+/// The messages exchanged between relays on a mesh link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum MeshMsg {
+    /// Datagrams to deliver to an endpoint connected to the receiving relay.
+    Datagrams {
+        /// The endpoint to deliver the datagrams to.
+        dst_endpoint_id: EndpointId,
+        /// The endpoint that originally sent the datagrams.
+        src_endpoint_id: EndpointId,
+        /// The datagrams and related metadata.
+        datagrams: Datagrams,
+    },
+    /// The endpoint is connected to the sending relay.
+    EndpointConnected(EndpointId),
+    /// The endpoint is no longer connected to the sending relay.
+    EndpointGone(EndpointId),
+    /// Request to reply with a [`MeshMsg::Pong`] with the given payload.
+    Ping([u8; 8]),
+    /// Reply to a [`MeshMsg::Ping`] with the payload sent in the ping.
+    Pong([u8; 8]),
+    /// Request for the receiving relay to prove its identity with a [`MeshMsg::PeerAuth`].
+    PeerChallenge([u8; 16]),
+    /// Signature of a [`MeshMsg::PeerChallenge`] with the sending relay's mesh key.
+    PeerAuth([u8; 64]),
+}
+
+impl MeshMsg {
+    /// Returns this frame's corresponding frame type.
+    pub(crate) fn typ(&self) -> FrameType {
+        match self {
+            Self::Datagrams { datagrams, .. } => {
+                if datagrams.segment_size.is_some() {
+                    FrameType::MeshDatagramBatch
+                } else {
+                    FrameType::MeshDatagram
+                }
+            }
+            Self::EndpointConnected(_) => FrameType::MeshEndpointConnected,
+            Self::EndpointGone(_) => FrameType::MeshEndpointGone,
+            Self::Ping(_) => FrameType::Ping,
+            Self::Pong(_) => FrameType::Pong,
+            Self::PeerChallenge(_) => FrameType::MeshPeerChallenge,
+            Self::PeerAuth(_) => FrameType::MeshPeerAuth,
+        }
+    }
+
+    /// Creates the [`MeshMsg::PeerAuth`] answering `challenge`.
+    pub(crate) fn peer_auth(secret_key: &SecretKey, challenge: &[u8; 16]) -> Self {
+        Self::PeerAuth(secret_key.sign(&peer_auth_message(challenge)).to_bytes())
+    }
+
+    pub(crate) fn to_bytes(&self) -> BytesMut {
+        self.write_to(BytesMut::with_capacity(self.encoded_len()))
+    }
+
+    /// Encodes this frame for sending over websockets.
+    pub(crate) fn write_to<O: BufMut>(&self, mut dst: O) -> O {
+        dst = self.typ().write_to(dst);
+        match self {
+            Self::Datagrams {
+                dst_endpoint_id,
+                src_endpoint_id,
+                datagrams,
+            } => {
+                dst.put(dst_endpoint_id.as_ref());
+                dst.put(src_endpoint_id.as_ref());
+                dst = datagrams.write_to(dst);
+            }
+            Self::EndpointConnected(endpoint_id) | Self::EndpointGone(endpoint_id) => {
+                dst.put(endpoint_id.as_ref());
+            }
+            Self::Ping(data) | Self::Pong(data) => {
+                dst.put(&data[..]);
+            }
+            Self::PeerChallenge(challenge) => {
+                dst.put(&challenge[..]);
+            }
+            Self::PeerAuth(signature) => {
+                dst.put(&signature[..]);
+            }
+        }
+        dst
+    }
+
+    pub(crate) fn encoded_len(&self) -> usize {
+        let payload_len = match self {
+            Self::Datagrams { datagrams, .. } => {
+                32 // dst endpoint id
+                + 32 // src endpoint id
+                + datagrams.encoded_len()
+            }
+            Self::EndpointConnected(_) | Self::EndpointGone(_) => 32,
+            Self::Ping(_) | Self::Pong(_) => 8,
+            Self::PeerChallenge(_) => 16,
+            Self::PeerAuth(_) => 64,
+        };
+        self.typ().encoded_len() + payload_len
+    }
+
+    /// Tries to decode a frame received over websockets.
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn from_bytes(mut content: Bytes, cache: &KeyCache) -> Result<Self, Error> {
+        let frame_type = FrameType::from_bytes(&mut content)?;
+        let frame_len = content.len();
+        ensure!(
+            frame_len <= MAX_PACKET_SIZE + 2 * EndpointId::LENGTH,
+            Error::FrameTooLarge { frame_len }
+        );
+
+        let res = match frame_type {
+            FrameType::MeshDatagram | FrameType::MeshDatagramBatch => {
+                ensure!(content.len() >= 2 * EndpointId::LENGTH, Error::InvalidFrame);
+                let dst_endpoint_id = cache.key_from_slice(&content[..EndpointId::LENGTH])?;
+                let src_endpoint_id =
+                    cache.key_from_slice(&content[EndpointId::LENGTH..2 * EndpointId::LENGTH])?;
+                let datagrams = Datagrams::from_bytes(
+                    content.slice(2 * EndpointId::LENGTH..),
+                    frame_type == FrameType::MeshDatagramBatch,
+                )?;
+                Self::Datagrams {
+                    dst_endpoint_id,
+                    src_endpoint_id,
+                    datagrams,
+                }
+            }
+            FrameType::MeshEndpointConnected | FrameType::MeshEndpointGone => {
+                ensure!(content.len() == EndpointId::LENGTH, Error::InvalidFrame);
+                let endpoint_id = cache.key_from_slice(content.as_ref())?;
+                if frame_type == FrameType::MeshEndpointConnected {
+                    Self::EndpointConnected(endpoint_id)
+                } else {
+                    Self::EndpointGone(endpoint_id)
+                }
+            }
+            FrameType::Ping | FrameType::Pong => {
+                ensure!(content.len() == 8, Error::InvalidFrame);
+                let mut data = [0u8; 8];
+                data.copy_from_slice(&content[..8]);
+                if frame_type == FrameType::Ping {
+                    Self::Ping(data)
+                } else {
+                    Self::Pong(data)
+                }
+            }
+            FrameType::MeshPeerChallenge => {
+                let challenge = content
+                    .as_ref()
+                    .try_into()
+                    .map_err(|_| e!(Error::InvalidFrame))?;
+                Self::PeerChallenge(challenge)
+            }
+            FrameType::MeshPeerAuth => {
+                let signature = content
+                    .as_ref()
+                    .try_into()
+                    .map_err(|_| e!(Error::InvalidFrame))?;
+                Self::PeerAuth(signature)
+            }
+            _ => {
+                return Err(e!(Error::InvalidFrameType { frame_type }));
+            }
+        };
+        Ok(res)
+    }
+}
+
+/// Returns whether `signature` is a valid [`MeshMsg::PeerAuth`] of `challenge` by `peer`.
+pub(crate) fn verify_peer_auth(
+    peer: &EndpointId,
+    challenge: &[u8; 16],
+    signature: &[u8; 64],
+) -> bool {
+    peer.verify(
+        &peer_auth_message(challenge),
+        &Signature::from_bytes(signature),
+    )
+    .is_ok()
+}
+
+/// The message signed in a [`MeshMsg::PeerAuth`].
+///
+/// Like in the client [`handshake`], a key derived from the challenge is signed instead of
+/// the challenge itself, which separates these signatures from any other use of the key.
+///
+/// [`handshake`]: super::handshake
+fn peer_auth_message(challenge: &[u8; 16]) -> [u8; 32] {
+    blake3::derive_key(DOMAIN_SEP_PEER_AUTH, challenge)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU16;
+
+    use iroh_base::SecretKey;
+    use n0_error::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_mesh_frames_roundtrip() -> Result {
+        let a = SecretKey::from_bytes(&[42u8; 32]).public();
+        let b = SecretKey::from_bytes(&[43u8; 32]).public();
+        let cache = KeyCache::test();
+
+        let msgs = [
+            MeshMsg::Datagrams {
+                dst_endpoint_id: a,
+                src_endpoint_id: b,
+                datagrams: Datagrams {
+                    ecn: Some(noq::EcnCodepoint::Ce),
+                    segment_size: NonZeroU16::new(6),
+                    contents: "Hello World!".into(),
+                },
+            },
+            MeshMsg::Datagrams {
+                dst_endpoint_id: b,
+                src_endpoint_id: a,
+                datagrams: Datagrams::from(b"hello"),
+            },
+            MeshMsg::EndpointConnected(a),
+            MeshMsg::EndpointGone(b),
+            MeshMsg::Ping([1u8; 8]),
+            MeshMsg::Pong([2u8; 8]),
+            MeshMsg::PeerChallenge([3u8; 16]),
+            MeshMsg::PeerAuth([4u8; 64]),
+        ];
+        for msg in msgs {
+            let bytes = msg.to_bytes().freeze();
+            assert_eq!(bytes.len(), msg.encoded_len());
+            assert_eq!(MeshMsg::from_bytes(bytes, &cache)?, msg);
+        }
+
+        // The first byte is the frame type, followed by the destination key.
+        let bytes = MeshMsg::EndpointGone(a).to_bytes();
+        assert_eq!(bytes[0], 17);
+        assert_eq!(&bytes[1..], a.as_bytes());
+        Ok(())
+    }
+    #[test]
+    fn test_peer_auth() {
+        let relay = SecretKey::from_bytes(&[42u8; 32]);
+        let other = SecretKey::from_bytes(&[43u8; 32]);
+        let challenge = [7u8; 16];
+
+        let MeshMsg::PeerAuth(signature) = MeshMsg::peer_auth(&relay, &challenge) else {
+            panic!("not a peer auth");
+        };
+        assert!(verify_peer_auth(&relay.public(), &challenge, &signature));
+        assert!(!verify_peer_auth(&other.public(), &challenge, &signature));
+        assert!(!verify_peer_auth(&relay.public(), &[8u8; 16], &signature));
+    }
+}
@@ -128,6 +128,12 @@ pub enum Status {
         "Another endpoint connected with the same endpoint id. No more messages will be received."
     )]
     SameEndpointIdConnected,
+    /// The endpoint used up its relay quota. Datagrams from and to it are dropped until the
+    /// quota period ends.
+    #[display(
+        "The relay quota of this endpoint is exhausted. Datagrams are dropped until the quota is reset."
+    )]
+    QuotaExceeded,
     /// Placeholder for backwards-compatibility for future new health status variants.
     #[display("Unsupported health message ({_0})")]
     Unknown(u8),
@@ -139,6 +145,7 @@ impl Status {
         match self {
             Status::Healthy => dst.put_u8(0),
             Status::SameEndpointIdConnected => dst.put_u8(1),
+            Status::QuotaExceeded => dst.put_u8(2),
             Status::Unknown(discriminant) => dst.put_u8(*discriminant),
         }
         dst
@@ -155,6 +162,7 @@ impl Status {
         match discriminant {
             0 => Ok(Self::Healthy),
             1 => Ok(Self::SameEndpointIdConnected),
+            2 => Ok(Self::QuotaExceeded),
             n => Ok(Self::Unknown(n)),
         }
     }
@@ -249,7 +257,7 @@ impl Datagrams {
         }
     }
 
-    fn write_to<O: BufMut>(&self, mut dst: O) -> O {
+    pub(super) fn write_to<O: BufMut>(&self, mut dst: O) -> O {
         let ecn = self.ecn.map_or(0, |ecn| ecn as u8);
         dst.put_u8(ecn);
         if let Some(segment_size) = self.segment_size {
@@ -259,14 +267,14 @@ impl Datagrams {
         dst
     }
 
-    fn encoded_len(&self) -> usize {
+    pub(super) fn encoded_len(&self) -> usize {
         1 // ECN byte
         + self.segment_size.map_or(0, |_| 2) // segment size, when None, then a packed representation is assumed
         + self.contents.len()
     }
 
     #[allow(clippy::len_zero, clippy::result_large_err)]
-    fn from_bytes(mut bytes: Bytes, is_batch: bool) -> Result<Self, Error> {
+    pub(super) fn from_bytes(mut bytes: Bytes, is_batch: bool) -> Result<Self, Error> {
         if is_batch {
             // 1 bytes ECN, 2 bytes segment size
             ensure!(bytes.len() >= 3, Error::InvalidFrame);
@@ -800,7 +808,12 @@ mod proptests {
                 s.len() < MAX_PACKET_SIZE // a single unicode character can match a regex "." but take up multiple bytes
             })
             .prop_map(|problem| RelayToClientMsg::Health { problem });
-        let health = Just(Status::SameEndpointIdConnected).prop_map(RelayToClientMsg::Status);
+        let health = prop_oneof![
+            Just(Status::Healthy),
+            Just(Status::SameEndpointIdConnected),
+            Just(Status::QuotaExceeded),
+        ]
+        .prop_map(RelayToClientMsg::Status);
         let restarting = (any::<u32>(), any::<u32>()).prop_map(|(reconnect_in, try_for)| {
             RelayToClientMsg::Restarting {
                 reconnect_in: Duration::from_millis(reconnect_in.into()),
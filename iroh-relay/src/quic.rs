@@ -15,6 +15,8 @@ pub const QUIC_ADDR_DISC_CLOSE_REASON: &[u8] = b"finished";
 
 #[cfg(feature = "server")]
 pub(crate) mod server {
+    use std::sync::RwLock;
+
     use n0_error::e;
     use noq::{
         ApplicationClose, ConnectionError,
@@ -25,14 +27,18 @@ pub(crate) mod server {
     use tracing::{Instrument, debug, info, info_span};
 
     use super::*;
-    use crate::server::Metrics;
+    use crate::server::{Metrics, accept_limit::AcceptLimiter};
 
     pub(crate) struct QuicServer {
         bind_addr: SocketAddr,
         cancel: CancellationToken,
+        accept_limiter: SharedAcceptLimiter,
         handle: AbortOnDropHandle<()>,
     }
 
+    /// The accept rate limiter of the server, replaced by [`ServerHandle::reload_accept_limiter`].
+    type SharedAcceptLimiter = Arc<RwLock<Option<Arc<AcceptLimiter>>>>;
+
     /// Server spawn errors
     #[allow(missing_docs)]
     #[stack_error(derive, add_meta)]
@@ -65,6 +71,7 @@ pub(crate) mod server {
         pub(crate) fn handle(&self) -> ServerHandle {
             ServerHandle {
                 cancel_token: self.cancel.clone(),
+                accept_limiter: self.accept_limiter.clone(),
             }
         }
 
@@ -97,6 +104,7 @@ pub(crate) mod server {
         pub(crate) fn spawn(
             bind_addr: SocketAddr,
             mut server_config: rustls::ServerConfig,
+            accept_limiter: Option<AcceptLimiter>,
             metrics: Arc<Metrics>,
         ) -> Result<Self, QuicSpawnError> {
             server_config.alpn_protocols = vec![crate::quic::ALPN_QUIC_ADDR_DISC.to_vec()];
@@ -120,6 +128,9 @@ pub(crate) mod server {
 
             let cancel = CancellationToken::new();
             let cancel_accept_loop = cancel.clone();
+            let accept_limiter: SharedAcceptLimiter =
+                Arc::new(RwLock::new(accept_limiter.map(Arc::new)));
+            let current_accept_limiter = accept_limiter.clone();
 
             let task = tokio::task::spawn(
                 async move {
@@ -142,10 +153,20 @@ pub(crate) mod server {
                             }
                             res = endpoint.accept() => match res {
                                 Some(incoming) => {
-                                     let remote_addr = incoming.remote_address();
-                                     set.spawn(
-                                         handle_connection(incoming, metrics.clone()).instrument(info_span!("qad-conn", %remote_addr))
-                                     );                                }
+                                    let remote_addr = incoming.remote_address();
+                                    let limiter = current_accept_limiter.read().expect("poisoned").clone();
+                                    if let Some(limiter) = limiter
+                                        && limiter.check(Some(remote_addr.ip())).is_err()
+                                    {
+                                        debug!(%remote_addr, "refusing connection: accept rate limit exceeded");
+                                        metrics.qad_incoming_ratelimited.inc();
+                                        incoming.refuse();
+                                        continue;
+                                    }
+                                    set.spawn(
+                                        handle_connection(incoming, metrics.clone()).instrument(info_span!("qad-conn", %remote_addr))
+                                    );
+                                }
                                 None => {
                                     debug!("endpoint closed");
                                     break;
@@ -172,6 +193,7 @@ pub(crate) mod server {
             Ok(Self {
                 bind_addr,
                 cancel,
+                accept_limiter,
                 handle: AbortOnDropHandle::new(task),
             })
         }
@@ -194,6 +216,7 @@ pub(crate) mod server {
     #[derive(Debug, Clone)]
     pub(crate) struct ServerHandle {
         cancel_token: CancellationToken,
+        accept_limiter: SharedAcceptLimiter,
     }
 
     impl ServerHandle {
@@ -201,6 +224,18 @@ pub(crate) mod server {
         pub(crate) fn shutdown(&self) {
             self.cancel_token.cancel()
         }
+
+        /// Replaces the accept rate limiter for new connections.
+        ///
+        /// The current limiter is kept if `limiter` enforces the same rates, so that
+        /// reloading does not refill the token buckets.
+        pub(crate) fn reload_accept_limiter(&self, limiter: Option<AcceptLimiter>) {
+            let mut current = self.accept_limiter.write().expect("poisoned");
+            match (limiter, &*current) {
+                (Some(new), Some(current)) if new.has_same_rates(current) => {}
+                (new, _) => *current = new.map(Arc::new),
+            }
+        }
     }
 
     /// Handle the connection from the client.
@@ -389,7 +424,7 @@ mod tests {
         // create a server config with self signed certificates
         let (_, server_config) = super::super::server::testing::self_signed_tls_certs_and_config();
         let bind_addr = SocketAddr::new(host.into(), 0);
-        let quic_server = QuicServer::spawn(bind_addr, server_config, Default::default())?;
+        let quic_server = QuicServer::spawn(bind_addr, server_config, None, Default::default())?;
 
         // create a client-side endpoint
         let client_endpoint =
@@ -414,6 +449,48 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    #[traced_test]
+    #[cfg(feature = "test-utils")]
+    async fn quic_reload_accept_limiter() -> Result {
+        use super::server::QuicServer;
+        use crate::server::{Limits, accept_limit::AcceptLimiter};
+
+        let host: Ipv4Addr = "127.0.0.1".parse().unwrap();
+        let (_, server_config) = super::super::server::testing::self_signed_tls_certs_and_config();
+        let bind_addr = SocketAddr::new(host.into(), 0);
+        let limits = Limits {
+            accept_conn_limit: Some(0.001),
+            accept_conn_burst: Some(1),
+            ..Default::default()
+        };
+        let limiter = || AcceptLimiter::new(&limits).unwrap();
+        let quic_server =
+            QuicServer::spawn(bind_addr, server_config, limiter(), Default::default())?;
+        let handle = quic_server.handle();
+
+        let client_endpoint =
+            noq::Endpoint::client(SocketAddr::new(host.into(), 0)).std_context("client")?;
+        let client_config = crate::tls::make_dangerous_client_config();
+        let quic_client = QuicClient::new(client_endpoint.clone(), client_config);
+        let get_addr = || quic_client.get_addr_and_latency(quic_server.bind_addr(), "127.0.0.1");
+
+        get_addr().await?;
+        assert!(get_addr().await.is_err());
+
+        // Reloading the same limits does not refill the bucket.
+        handle.reload_accept_limiter(limiter());
+        assert!(get_addr().await.is_err());
+
+        // Removing the limits stops refusing connections.
+        handle.reload_accept_limiter(None);
+        get_addr().await?;
+
+        client_endpoint.wait_idle().await;
+        quic_server.shutdown().await;
+        Ok(())
+    }
+
     #[tokio::test(start_paused = true)]
     #[traced_test]
     async fn test_qad_client_closes_unresponsive_fast() -> Result {
@@ -0,0 +1,224 @@
+//! Signed relay manifests, to distribute a [`RelayMap`] to endpoints.
+//!
+//! A [`RelayManifest`] lists relay servers along with their configuration. The operator of
+//! the relays signs the manifest with a key which is pinned in the endpoints, and serves the
+//! resulting [`SignedRelayManifest`] over HTTPS. Endpoints fetch and verify the manifest
+//! periodically, which allows adding and draining relays without updating the endpoints.
+//!
+//! The encoded form of a [`SignedRelayManifest`] is the 64 byte ed25519 signature followed by
+//! the [postcard] encoded [`RelayManifest`]. The signature covers a domain separation tag
+//! followed by the encoded manifest.
+//!
+//! [postcard]: https://docs.rs/postcard
+
+use iroh_base::{PublicKey, RelayUrl, SignError, Signature, Signer};
+use n0_error::{e, stack_error};
+use serde::{Deserialize, Serialize};
+
+use crate::{RelayConfig, RelayMap, RelayQuicConfig};
+
+/// Domain separation tag for relay manifest signatures.
+const DOMAIN: &[u8] = b"iroh-relay-manifest-v1";
+
+/// Error when decoding or verifying a [`SignedRelayManifest`].
+#[allow(missing_docs)]
+#[stack_error(derive, add_meta)]
+#[non_exhaustive]
+pub enum RelayManifestError {
+    #[error("Signed relay manifest is too short")]
+    TooShort {},
+    #[error("Invalid relay manifest signature")]
+    InvalidSignature {
+        #[error(std_err)]
+        source: iroh_base::SignatureError,
+    },
+    #[error("Failed to decode relay manifest")]
+    Decode {
+        #[error(std_err)]
+        source: postcard::Error,
+    },
+}
+
+/// A list of relay servers, to be signed by the relay operator.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct RelayManifest {
+    /// Serial number of the manifest.
+    ///
+    /// Endpoints ignore manifests with a serial lower than the serial of a manifest they
+    /// already applied, so a replayed older manifest can not bring back removed relays.
+    pub serial: u64,
+    /// The relay servers.
+    pub relays: Vec<RelayManifestEntry>,
+}
+
+/// A relay server in a [`RelayManifest`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct RelayManifestEntry {
+    /// The [`RelayUrl`] where this relay server can be dialed.
+    pub url: RelayUrl,
+    /// The port of the QUIC address discovery endpoint, if the relay offers one.
+    pub quic_port: Option<u16>,
+    /// Authorization token sent to the relay, see [`RelayConfig::with_auth_token`].
+    pub auth_token: Option<String>,
+    /// The region of the relay, informational only.
+    pub region: Option<String>,
+}
+
+impl RelayManifestEntry {
+    /// Creates an entry for the relay at `url`, with QUIC address discovery on the default
+    /// port.
+    pub fn new(url: RelayUrl) -> Self {
+        Self {
+            url,
+            quic_port: Some(RelayQuicConfig::default().port),
+            auth_token: None,
+            region: None,
+        }
+    }
+
+    /// Sets the port of the QUIC address discovery endpoint, `None` disables it.
+    pub fn with_quic_port(mut self, quic_port: Option<u16>) -> Self {
+        self.quic_port = quic_port;
+        self
+    }
+
+    /// Sets the authorization token.
+    pub fn with_auth_token(mut self, auth_token: impl Into<String>) -> Self {
+        self.auth_token = Some(auth_token.into());
+        self
+    }
+
+    /// Sets the region.
+    pub fn with_region(mut self, region: impl Into<String>) -> Self {
+        self.region = Some(region.into());
+        self
+    }
+
+    /// Returns the [`RelayConfig`] to connect to this relay.
+    pub fn relay_config(&self) -> RelayConfig {
+        let config = RelayConfig::new(self.url.clone(), self.quic_port.map(RelayQuicConfig::new));
+        match &self.auth_token {
+            Some(token) => config.with_auth_token(token.clone()),
+            None => config,
+        }
+    }
+}
+
+impl RelayManifest {
+    /// Creates a manifest with the given serial number and relays.
+    pub fn new(serial: u64, relays: impl IntoIterator<Item = RelayManifestEntry>) -> Self {
+        Self {
+            serial,
+            relays: relays.into_iter().collect(),
+        }
+    }
+
+    /// Returns a new [`RelayMap`] with the relays of this manifest.
+    pub fn relay_map(&self) -> RelayMap {
+        self.relays
+            .iter()
+            .map(|entry| entry.relay_config())
+            .collect()
+    }
+
+    /// Signs the manifest with the key of `signer`.
+    pub fn sign(&self, signer: &(impl Signer + ?Sized)) -> Result<SignedRelayManifest, SignError> {
+        let manifest = postcard::to_stdvec(self).expect("serialization is infallible");
+        let signature = signer.sign(&signed_message(&manifest))?;
+        Ok(SignedRelayManifest {
+            signature,
+            manifest,
+        })
+    }
+}
+
+/// A [`RelayManifest`] with the signature of the relay operator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedRelayManifest {
+    signature: Signature,
+    /// The encoded [`RelayManifest`].
+    manifest: Vec<u8>,
+}
+
+impl SignedRelayManifest {
+    /// Decodes a signed manifest, without verifying it.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, RelayManifestError> {
+        let Some((signature, manifest)) = bytes.split_first_chunk::<{ Signature::LENGTH }>() else {
+            return Err(e!(RelayManifestError::TooShort));
+        };
+        Ok(Self {
+            signature: Signature::from_bytes(signature),
+            manifest: manifest.to_vec(),
+        })
+    }
+
+    /// Encodes the signed manifest.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Signature::LENGTH + self.manifest.len());
+        bytes.extend_from_slice(&self.signature.to_bytes());
+        bytes.extend_from_slice(&self.manifest);
+        bytes
+    }
+
+    /// Verifies the signature with the pinned `public_key` and decodes the manifest.
+    pub fn verify(&self, public_key: &PublicKey) -> Result<RelayManifest, RelayManifestError> {
+        public_key
+            .verify(&signed_message(&self.manifest), &self.signature)
+            .map_err(|err| e!(RelayManifestError::InvalidSignature, err))?;
+        postcard::from_bytes(&self.manifest).map_err(|err| e!(RelayManifestError::Decode, err))
+    }
+}
+
+fn signed_message(manifest: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(DOMAIN.len() + manifest.len());
+    message.extend_from_slice(DOMAIN);
+    message.extend_from_slice(manifest);
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh_base::SecretKey;
+
+    use super::*;
+
+    #[test]
+    fn relay_manifest_roundtrip() {
+        let key = SecretKey::generate();
+        let manifest = RelayManifest::new(
+            3,
+            [
+                RelayManifestEntry::new("https://relay1.example.org".parse().unwrap())
+                    .with_region("eu"),
+                RelayManifestEntry::new("https://relay2.example.org".parse().unwrap())
+                    .with_quic_port(None)
+                    .with_auth_token("secret"),
+            ],
+        );
+        let signed = manifest.sign(&key).unwrap();
+        let decoded = SignedRelayManifest::from_bytes(&signed.to_bytes()).unwrap();
+        assert_eq!(decoded.verify(&key.public()).unwrap(), manifest);
+
+        let relay_map = manifest.relay_map();
+        assert_eq!(relay_map.len(), 2);
+        let relay2 = relay_map
+            .get(&"https://relay2.example.org".parse().unwrap())
+            .unwrap();
+        assert_eq!(relay2.quic, None);
+        assert_eq!(relay2.auth_token.as_deref(), Some("secret"));
+
+        // Another key did not sign this manifest.
+        let other = SecretKey::generate();
+        assert!(decoded.verify(&other.public()).is_err());
+
+        // Tampering with the manifest invalidates the signature.
+        let mut bytes = signed.to_bytes();
+        *bytes.last_mut().unwrap() ^= 1;
+        let tampered = SignedRelayManifest::from_bytes(&bytes).unwrap();
+        assert!(tampered.verify(&key.public()).is_err());
+
+        assert!(SignedRelayManifest::from_bytes(&[0u8; 10]).is_err());
+    }
+}
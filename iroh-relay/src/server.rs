@@ -26,6 +26,7 @@ use std::{
         Arc,
         atomic::{AtomicU64, Ordering},
     },
+    time::Duration,
 };
 
 use derive_more::Debug;
@@ -36,9 +37,7 @@ use http::{
 };
 use http_body_util::Full;
 use hyper::body::Incoming;
-use iroh_base::EndpointId;
-#[cfg(feature = "test-utils")]
-use iroh_base::RelayUrl;
+use iroh_base::{EndpointId, RelayUrl};
 use n0_error::{e, stack_error};
 use n0_future::{StreamExt, task::AbortOnDropHandle};
 use rustls::server::WantsServerCert;
@@ -51,9 +50,12 @@ use tokio_rustls_acme::{
     acme::{LETS_ENCRYPT_PRODUCTION_DIRECTORY, LETS_ENCRYPT_STAGING_DIRECTORY},
     caches::DirCache,
 };
-use tracing::{Instrument, debug, error, info, info_span, instrument};
+use tracing::{Instrument, debug, error, info, info_span, instrument, warn};
 
-use self::http_server::{BytesBody, HyperError, HyperResult};
+use self::{
+    accept_limit::AcceptLimiter,
+    http_server::{BytesBody, HyperError, HyperResult},
+};
 use crate::{
     defaults::DEFAULT_KEY_CACHE_CAPACITY,
     http::{AUTH_TOKEN_URL_QUERY_PARAM, ProtocolVersion, RELAY_PROBE_PATH},
@@ -61,17 +63,24 @@ use crate::{
     tls::CaTlsConfig,
 };
 
+pub(crate) mod accept_limit;
+pub mod admin;
 pub mod client;
 pub mod clients;
+mod fair_queue;
 pub mod http_server;
+pub mod mesh;
 mod metrics;
+mod quota;
 pub(crate) mod resolver;
 pub mod streams;
 #[cfg(feature = "test-utils")]
 pub mod testing;
 
 pub use self::{
+    admin::AdminConfig,
     http_server::{Handlers, RelayService},
+    mesh::{MeshConfig, MeshPeer},
     metrics::{Metrics, RelayMetrics},
     resolver::{DEFAULT_CERT_RELOAD_INTERVAL, reloading_resolver},
 };
@@ -116,6 +125,10 @@ pub struct ServerConfig {
     /// Socket to serve metrics on.
     #[cfg(feature = "metrics")]
     pub metrics_addr: Option<SocketAddr>,
+    /// Configuration for the admin API, disabled if `None`.
+    ///
+    /// Only served if the Relay server is enabled, see [`admin`] for details.
+    pub admin: Option<AdminConfig>,
 }
 
 /// Configuration for the Relay HTTP and HTTPS server.
@@ -141,17 +154,23 @@ pub struct RelayConfig {
     /// Rate limits.
     pub limits: Limits,
     /// Key cache capacity.
+    ///
+    /// Mesh links keep the key cache the server was spawned with.
     pub key_cache_capacity: Option<usize>,
     /// Access control for incoming connections.
     pub access: Arc<dyn DynAccessControl>,
+    /// Mesh forwarding to peer relays, disabled if `None`.
+    ///
+    /// See [`mesh`] for details.
+    pub mesh: Option<MeshConfig>,
 }
 
 impl RelayConfig {
     /// Creates a new [`RelayConfig`] bound to `http_bind_addr` with default settings.
     ///
-    /// TLS is disabled, default [`Limits`] are used, the key cache capacity is unset, and
-    /// access defaults to [`AllowAll`]. Adjust any of these by assigning to the
-    /// corresponding fields after construction.
+    /// TLS is disabled, default [`Limits`] are used, the key cache capacity is unset,
+    /// access defaults to [`AllowAll`] and mesh forwarding is disabled. Adjust any of these
+    /// by assigning to the corresponding fields after construction.
     pub fn new(http_bind_addr: impl Into<SocketAddr>) -> Self {
         Self {
             http_bind_addr: http_bind_addr.into(),
@@ -159,6 +178,33 @@ impl RelayConfig {
             limits: Limits::default(),
             key_cache_capacity: None,
             access: Arc::new(AllowAll),
+            mesh: None,
+        }
+    }
+}
+
+/// The settings of a running Relay server which can be changed with [`Server::reload`].
+///
+/// The defaults are the defaults of a [`RelayConfig`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ReloadConfig {
+    /// Rate limits.
+    ///
+    /// The accept limits also apply to the QUIC server, if it is enabled.
+    pub limits: Limits,
+    /// Key cache capacity.
+    pub key_cache_capacity: Option<usize>,
+    /// Access control for incoming connections.
+    pub access: Arc<dyn DynAccessControl>,
+}
+
+impl Default for ReloadConfig {
+    fn default() -> Self {
+        Self {
+            limits: Limits::default(),
+            key_cache_capacity: None,
+            access: Arc::new(AllowAll),
         }
     }
 }
@@ -302,6 +348,21 @@ pub trait AccessControl: std::fmt::Debug + Send + Sync + 'static {
     fn on_disconnect(&self, endpoint_id: EndpointId, connection_id: ConnectionId) {
         let _ = (endpoint_id, connection_id);
     }
+
+    /// Decides the [`EndpointLimits`] for an admitted connection.
+    ///
+    /// Called once per connection admitted by [`Self::on_connect`]. Returns `None` to
+    /// apply the [`Limits::endpoint`] configured for the relay, which is the default.
+    ///
+    /// Can be implemented as
+    /// `async fn endpoint_limits(&self, request: &ClientRequest) -> Option<EndpointLimits>`.
+    fn endpoint_limits(
+        &self,
+        request: &ClientRequest,
+    ) -> impl Future<Output = Option<EndpointLimits>> + Send {
+        let _ = request;
+        std::future::ready(None)
+    }
 }
 
 /// A dyn-compatible version of [`AccessControl`] that returns boxed futures.
@@ -318,6 +379,12 @@ pub trait DynAccessControl: std::fmt::Debug + Send + Sync + 'static {
 
     /// See [`AccessControl::on_disconnect`].
     fn on_disconnect(&self, endpoint_id: EndpointId, connection_id: ConnectionId);
+
+    /// See [`AccessControl::endpoint_limits`].
+    fn endpoint_limits<'a>(
+        &'a self,
+        request: &'a ClientRequest,
+    ) -> Pin<Box<dyn Future<Output = Option<EndpointLimits>> + Send + 'a>>;
 }
 
 impl<T: AccessControl> DynAccessControl for T {
@@ -331,6 +398,13 @@ impl<T: AccessControl> DynAccessControl for T {
     fn on_disconnect(&self, endpoint_id: EndpointId, connection_id: ConnectionId) {
         <Self as AccessControl>::on_disconnect(self, endpoint_id, connection_id)
     }
+
+    fn endpoint_limits<'a>(
+        &'a self,
+        request: &'a ClientRequest,
+    ) -> Pin<Box<dyn Future<Output = Option<EndpointLimits>> + Send + 'a>> {
+        Box::pin(<Self as AccessControl>::endpoint_limits(self, request))
+    }
 }
 
 /// An [`AccessControl`] that admits every endpoint.
@@ -483,20 +557,37 @@ impl TlsConfig {
 }
 
 /// Rate limits.
-// TODO: accept_conn_limit and accept_conn_burst are not currently implemented.
+///
+/// The `accept_conn_*` limits apply separately to the relay HTTP(S) server and the QUIC
+/// server. The relay server rejects connections over the limit with `429 Too Many
+/// Requests`, the QUIC server refuses them. Connections from peer relays in a [`mesh`]
+/// are not limited.
 #[derive(Debug, Default)]
 #[non_exhaustive]
 pub struct Limits {
     /// Rate limits for incoming traffic from a client connection.
     pub client_rx: Option<ClientRateLimit>,
-    /// Rate limit for accepting new connections. Unlimited if not set.
-    ///
-    /// Not currently implemented, setting this has no effect.
+    /// Number of new connections accepted per second. Unlimited if not set.
     pub accept_conn_limit: Option<f64>,
-    /// Burst limit for accepting new connections. Unlimited if not set.
+    /// Number of new connections accepted in a burst above [`Self::accept_conn_limit`].
     ///
-    /// Not currently implemented, setting this has no effect.
+    /// Defaults to [`Self::accept_conn_limit`] rounded up. Ignored if the limit is not set.
     pub accept_conn_burst: Option<usize>,
+    /// Number of new connections accepted per second from a single IP address. Unlimited
+    /// if not set.
+    ///
+    /// IPv6 addresses are limited per /64 prefix.
+    pub accept_conn_limit_per_ip: Option<f64>,
+    /// Number of new connections accepted in a burst from a single IP address above
+    /// [`Self::accept_conn_limit_per_ip`].
+    ///
+    /// Defaults to [`Self::accept_conn_limit_per_ip`] rounded up. Ignored if the limit is
+    /// not set.
+    pub accept_conn_burst_per_ip: Option<usize>,
+    /// Limits for each connecting endpoint.
+    ///
+    /// Used for all endpoints for which [`AccessControl::endpoint_limits`] returns `None`.
+    pub endpoint: EndpointLimits,
 }
 
 /// Per-client rate limit configuration.
@@ -521,6 +612,103 @@ impl ClientRateLimit {
     }
 }
 
+/// Limits for the traffic of a single endpoint.
+///
+/// The defaults are no quota and a weight of `1`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct EndpointLimits {
+    /// Byte budget for the datagrams relayed from and to the endpoint.
+    ///
+    /// The usage is tracked per [`EndpointId`], across all connections of the endpoint,
+    /// including reconnects. Once the budget is used up, datagrams from and to the endpoint
+    /// are dropped until the period ends, and the endpoint is notified with
+    /// [`Status::QuotaExceeded`]. Unlimited if not set.
+    ///
+    /// [`Status::QuotaExceeded`]: crate::protos::relay::Status::QuotaExceeded
+    pub quota: Option<Quota>,
+    /// Weight of the endpoint's datagrams in the send queues of the clients it sends to.
+    ///
+    /// Each client sends the datagrams queued for it in a weighted round robin across
+    /// the senders, so an endpoint with weight `2` gets twice the bandwidth of an endpoint
+    /// with weight `1` when both send to the same busy client.
+    pub weight: NonZeroU32,
+}
+
+impl Default for EndpointLimits {
+    fn default() -> Self {
+        Self {
+            quota: None,
+            weight: NonZeroU32::MIN,
+        }
+    }
+}
+
+/// A byte budget per period, see [`EndpointLimits::quota`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Quota {
+    /// Number of bytes that may be relayed per period.
+    pub bytes: u64,
+    /// The period after which the budget is reset.
+    pub period: QuotaPeriod,
+}
+
+impl Quota {
+    /// Creates a quota of `bytes` per UTC day.
+    pub fn daily(bytes: u64) -> Self {
+        Self {
+            bytes,
+            period: QuotaPeriod::Daily,
+        }
+    }
+
+    /// Creates a quota of `bytes` per UTC calendar month.
+    pub fn monthly(bytes: u64) -> Self {
+        Self {
+            bytes,
+            period: QuotaPeriod::Monthly,
+        }
+    }
+}
+
+/// The period of a [`Quota`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum QuotaPeriod {
+    /// Resets at midnight UTC.
+    Daily,
+    /// Resets at midnight UTC on the first day of each month.
+    Monthly,
+}
+
+/// Configuration for draining a relay server, see [`Server::drain`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct DrainConfig {
+    /// Maximum delay after which clients are asked to reconnect.
+    ///
+    /// Each client is sent a random delay up to this duration, to smear out the reconnects.
+    pub reconnect_in: Duration,
+    /// How long clients are asked to keep trying to reconnect.
+    ///
+    /// This should not be more than a few seconds.
+    pub try_for: Duration,
+    /// How long to wait for the clients to disconnect, before closing the remaining
+    /// connections.
+    pub timeout: Duration,
+}
+
+impl Default for DrainConfig {
+    fn default() -> Self {
+        Self {
+            reconnect_in: Duration::from_secs(5),
+            try_for: Duration::from_secs(5),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
 /// TLS certificate configuration.
 #[derive(Debug)]
 #[non_exhaustive]
@@ -624,6 +812,8 @@ pub struct Server {
     https_addr: Option<SocketAddr>,
     /// The address of the QUIC server, if configured.
     quic_addr: Option<SocketAddr>,
+    /// The address of the admin API, if configured.
+    admin_addr: Option<SocketAddr>,
     /// Handle to the relay server.
     relay_handle: Option<http_server::ServerHandle>,
     /// Handle to the relay service for runtime control.
@@ -661,6 +851,15 @@ pub enum SpawnError {
         source: std::io::Error,
         addr: SocketAddr,
     },
+    #[error("Failed to build mesh TLS client config")]
+    MeshTlsConfig {
+        #[error(std_err)]
+        source: std::io::Error,
+    },
+    #[error("Mesh peer {url} does not use TLS")]
+    InsecureMeshPeer { url: RelayUrl },
+    #[error("Invalid accept limit of {limit} connections per second with burst {burst:?}")]
+    InvalidAcceptLimit { limit: f64, burst: Option<usize> },
     #[error("Error starting metrics server")]
     Metrics {
         #[error(std_err)]
@@ -668,6 +867,17 @@ pub enum SpawnError {
     },
 }
 
+/// Errors when reloading the settings of a running server, see [`Server::reload`].
+#[allow(missing_docs)]
+#[stack_error(derive, add_meta)]
+#[non_exhaustive]
+pub enum ReloadError {
+    #[error("Invalid limits")]
+    Limits { source: SpawnError },
+    #[error("The Relay server is not enabled")]
+    RelayDisabled {},
+}
+
 /// Server task errors
 #[allow(missing_docs)]
 #[stack_error(derive, add_meta)]
@@ -709,6 +919,14 @@ impl Server {
             None
         };
 
+        // The QUIC server applies the accept limits of the relay server.
+        let quic_accept_limiter = match &config.relay {
+            Some(relay_config) if config.quic.is_some() => {
+                AcceptLimiter::new(&relay_config.limits)?
+            }
+            _ => None,
+        };
+
         let (relay_server, http_addr, tls_config) = match config.relay {
             Some(relay_config) => {
                 debug!("Starting Relay server");
@@ -733,6 +951,7 @@ impl Server {
                     .headers(headers)
                     .key_cache_capacity(key_cache_capacity)
                     .access(relay_config.access)
+                    .endpoint_limits(relay_config.limits.endpoint)
                     .request_handler(Method::GET, "/", Box::new(root_handler))
                     .request_handler(Method::GET, "/index.html", Box::new(root_handler))
                     .request_handler(Method::GET, RELAY_PROBE_PATH, Box::new(probe_handler))
@@ -741,6 +960,12 @@ impl Server {
                 if let Some(cfg) = relay_config.limits.client_rx {
                     builder = builder.client_rx_ratelimit(cfg);
                 }
+                if let Some(limiter) = AcceptLimiter::new(&relay_config.limits)? {
+                    builder = builder.accept_limiter(limiter);
+                }
+                if let Some(cfg) = relay_config.mesh {
+                    builder = builder.mesh(cfg);
+                }
                 let (http_addr, tls_config) = match relay_config.tls {
                     Some(tls_config) => {
                         let server_tls_config = match tls_config.cert {
@@ -838,6 +1063,33 @@ impl Server {
         let relay_handle = relay_server.as_ref().map(|srv| srv.handle());
         let relay_service = relay_server.as_ref().map(|srv| srv.service().clone());
 
+        let admin_addr = match (config.admin, &relay_service) {
+            (Some(admin_config), Some(relay_service)) => {
+                debug!("Starting admin API");
+                let addr = admin_config.bind_addr;
+                let listener = TcpListener::bind(addr)
+                    .await
+                    .map_err(|err| e!(SpawnError::BindTcpListener { addr }, err))?;
+                let admin_addr = listener
+                    .local_addr()
+                    .map_err(|err| e!(SpawnError::NoLocalAddr, err))?;
+                let clients = relay_service.clients().clone();
+                tasks.spawn(
+                    async move {
+                        admin::run_admin_service(listener, &admin_config.token, clients).await;
+                        Ok(())
+                    }
+                    .instrument(info_span!("admin-service", addr = %admin_addr)),
+                );
+                Some(admin_addr)
+            }
+            (Some(_), None) => {
+                warn!("admin API is only served with the Relay server enabled");
+                None
+            }
+            (None, _) => None,
+        };
+
         let quic_server = match config.quic {
             Some(quic_config) => {
                 debug!("Starting QUIC server {}", quic_config.bind_addr);
@@ -848,8 +1100,13 @@ impl Server {
                         e!(SpawnError::QuicSpawn, e!(QuicSpawnError::TlsNotConfigured))
                     })?;
                 Some(
-                    QuicServer::spawn(quic_config.bind_addr, server_config, metrics.server.clone())
-                        .map_err(|err| e!(SpawnError::QuicSpawn, err))?,
+                    QuicServer::spawn(
+                        quic_config.bind_addr,
+                        server_config,
+                        quic_accept_limiter,
+                        metrics.server.clone(),
+                    )
+                    .map_err(|err| e!(SpawnError::QuicSpawn, err))?,
                 )
             }
             None => None,
@@ -863,6 +1120,7 @@ impl Server {
             http_addr: http_addr.or(relay_addr),
             https_addr: http_addr.and(relay_addr),
             quic_addr,
+            admin_addr,
             relay_handle,
             relay_service,
             quic_handle,
@@ -890,6 +1148,46 @@ impl Server {
         self.supervisor.await?
     }
 
+    /// Drains the server and shuts it down.
+    ///
+    /// New relay connections are rejected, and all connected clients are told that the
+    /// relay is restarting, so they move to another relay. Once all clients disconnected,
+    /// or after [`DrainConfig::timeout`], the server is shut down like with
+    /// [`Self::shutdown`].
+    pub async fn drain(self, config: DrainConfig) -> Result<(), SupervisorError> {
+        if let Some(service) = &self.relay_service {
+            service.start_drain(&config);
+            if tokio::time::timeout(config.timeout, service.drained())
+                .await
+                .is_err()
+            {
+                warn!(
+                    clients = service.clients().len(),
+                    "clients did not disconnect before the drain timeout"
+                );
+            }
+        }
+        self.shutdown().await
+    }
+
+    /// Changes the access control and limits of the Relay server while it is running.
+    ///
+    /// The new settings apply to connections accepted from now on, established
+    /// connections are not touched.  See [`RelayService::reload`].  The accept limits are
+    /// applied to the QUIC server as well.
+    pub fn reload(&self, config: ReloadConfig) -> Result<(), ReloadError> {
+        let Some(service) = &self.relay_service else {
+            return Err(e!(ReloadError::RelayDisabled));
+        };
+        let quic_accept_limiter =
+            AcceptLimiter::new(&config.limits).map_err(|err| e!(ReloadError::Limits, err))?;
+        service.reload(config)?;
+        if let Some(quic_handle) = &self.quic_handle {
+            quic_handle.reload_accept_limiter(quic_accept_limiter);
+        }
+        Ok(())
+    }
+
     /// Waits for the server's supervisor task to finish.
     ///
     /// Returns the exit result of the supervisor task. Unlike [`Self::shutdown`], this does
@@ -916,6 +1214,11 @@ impl Server {
         self.quic_addr
     }
 
+    /// The socket address the admin API is listening on.
+    pub fn admin_addr(&self) -> Option<SocketAddr> {
+        self.admin_addr
+    }
+
     /// Get the server's https [`RelayUrl`].
     ///
     /// This uses [`Self::https_addr`] so it's mostly useful for local development.
@@ -1200,14 +1503,16 @@ mod tests {
     use url::Url;
 
     use super::{
-        Access, AccessControl, ClientRequest, NO_CONTENT_CHALLENGE_HEADER,
-        NO_CONTENT_RESPONSE_HEADER, RelayConfig, Server, ServerConfig, SpawnError,
+        Access, AccessControl, AdminConfig, ClientRequest, DrainConfig, EndpointLimits,
+        NO_CONTENT_CHALLENGE_HEADER, NO_CONTENT_RESPONSE_HEADER, Quota, RelayConfig, ReloadConfig,
+        Server, ServerConfig, SpawnError,
     };
     use crate::{
         client::{ClientBuilder, ConnectError},
+        http::RELAY_PATH,
         protos::{
             handshake,
-            relay::{ClientToRelayMsg, Datagrams, RelayToClientMsg},
+            relay::{ClientToRelayMsg, Datagrams, RelayToClientMsg, Status},
         },
         test_utils::static_resolver,
         tls::{self, CaTlsConfig, default_provider},
@@ -1230,6 +1535,7 @@ mod tests {
             relay: Some(relay),
             quic: None,
             metrics_addr: None,
+            admin: None,
         })
         .await
     }
@@ -1293,6 +1599,7 @@ mod tests {
             relay: Some(relay),
             quic: None,
             metrics_addr: Some((Ipv4Addr::LOCALHOST, 1234).into()),
+            admin: None,
         })
         .await;
         assert!(res.is_err()); // AddrInUse
@@ -1435,6 +1742,7 @@ mod tests {
             relay: Some(relay),
             quic: None,
             metrics_addr: None,
+            admin: None,
         })
         .await?;
 
@@ -1492,6 +1800,83 @@ mod tests {
         Ok(())
     }
 
+    /// Grants a quota of 8 bytes per day to a single endpoint.
+    #[derive(Debug)]
+    struct TieredAccess {
+        limited: EndpointId,
+    }
+
+    impl AccessControl for TieredAccess {
+        async fn on_connect(&self, _request: &ClientRequest) -> Access {
+            Access::Allow
+        }
+
+        async fn endpoint_limits(&self, request: &ClientRequest) -> Option<EndpointLimits> {
+            (request.endpoint_id() == self.limited).then(|| EndpointLimits {
+                quota: Some(Quota::daily(8)),
+                ..Default::default()
+            })
+        }
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn test_relay_endpoint_limits() -> Result<()> {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0u64);
+        let client_config = CaTlsConfig::default()
+            .client_config(default_provider())
+            .unwrap();
+
+        let a_secret_key = SecretKey::from_bytes(&rng.random());
+        let a_key = a_secret_key.public();
+        let b_secret_key = SecretKey::from_bytes(&rng.random());
+        let b_key = b_secret_key.public();
+
+        let mut relay = RelayConfig::new((Ipv4Addr::LOCALHOST, 0));
+        relay.limits.endpoint.quota = Some(Quota::monthly(1_000_000));
+        relay.access = Arc::new(TieredAccess { limited: a_key });
+        let server = Server::spawn(ServerConfig {
+            relay: Some(relay),
+            quic: None,
+            metrics_addr: None,
+            admin: None,
+        })
+        .await?;
+        let relay_url: RelayUrl = format!("http://{}", server.http_addr().unwrap()).parse()?;
+
+        let mut client_a = ClientBuilder::new(relay_url.clone(), a_secret_key, dns_resolver())
+            .tls_client_config(client_config.clone())
+            .connect()
+            .await?;
+        let mut client_b = ClientBuilder::new(relay_url, b_secret_key, dns_resolver())
+            .tls_client_config(client_config)
+            .connect()
+            .await?;
+
+        let msg = Datagrams::from("hello, b");
+        let res = try_send_recv(&mut client_a, &mut client_b, b_key, msg.clone()).await?;
+        assert!(matches!(res, RelayToClientMsg::Datagrams { .. }));
+
+        // `a` used up the quota decided by access control, `b` has the configured default.
+        let clients = server.relay_service().unwrap().clients();
+        assert_eq!(clients.quota_usage(a_key), Some(8));
+        assert_eq!(clients.quota_usage(b_key), Some(8));
+
+        client_a
+            .send(ClientToRelayMsg::Datagrams {
+                dst_endpoint_id: b_key,
+                datagrams: msg,
+            })
+            .await?;
+        let res = tokio::time::timeout(Duration::from_secs(5), client_a.next())
+            .await
+            .anyerr()?
+            .expect("stream finished")?;
+        assert_eq!(res, RelayToClientMsg::Status(Status::QuotaExceeded));
+
+        Ok(())
+    }
+
     /// Verifies that [`ClientBuilder::auth_token`] forwards a token to the
     /// relay so the [`AccessControl::on_connect`] hook can read it via
     /// [`ClientRequest::auth_token`].
@@ -1518,6 +1903,7 @@ mod tests {
             relay: Some(relay),
             quic: None,
             metrics_addr: None,
+            admin: None,
         })
         .await?;
 
@@ -1603,6 +1989,270 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    #[traced_test]
+    async fn test_relay_accept_limit() -> Result<()> {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0u64);
+        let mut relay = RelayConfig::new((Ipv4Addr::LOCALHOST, 0));
+        relay.limits.accept_conn_limit_per_ip = Some(0.01);
+        relay.limits.accept_conn_burst_per_ip = Some(1);
+        let server = Server::spawn(ServerConfig {
+            relay: Some(relay),
+            quic: None,
+            metrics_addr: None,
+            admin: None,
+        })
+        .await?;
+        let http_addr = server.http_addr().unwrap();
+        let relay_url: RelayUrl = format!("http://{http_addr}").parse()?;
+
+        let client_config = CaTlsConfig::default()
+            .client_config(default_provider())
+            .unwrap();
+        let resolver = dns_resolver();
+        let _client_a = ClientBuilder::new(
+            relay_url.clone(),
+            SecretKey::from_bytes(&rng.random()),
+            resolver.clone(),
+        )
+        .tls_client_config(client_config.clone())
+        .connect()
+        .await?;
+        let res = ClientBuilder::new(relay_url, SecretKey::from_bytes(&rng.random()), resolver)
+            .tls_client_config(client_config)
+            .connect()
+            .await;
+        assert!(res.is_err());
+
+        let client = reqwest::Client::builder()
+            .use_preconfigured_tls(ring_config())
+            .build()
+            .anyerr()?;
+        let response = client
+            .get(format!("http://{http_addr}{RELAY_PATH}"))
+            .send()
+            .await
+            .anyerr()?;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key(http::header::RETRY_AFTER));
+        // Other services are not limited.
+        let response = client
+            .get(format!("http://{http_addr}/healthz"))
+            .send()
+            .await
+            .anyerr()?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        assert_eq!(server.metrics().server.http_accepts_ratelimited.get(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn test_relay_drain() -> Result<()> {
+        let server = spawn_local_relay().await?;
+        let relay_url: RelayUrl = format!("http://{}", server.http_addr().unwrap()).parse()?;
+        let client_config = CaTlsConfig::default()
+            .client_config(default_provider())
+            .unwrap();
+        let resolver = dns_resolver();
+        let mut client =
+            ClientBuilder::new(relay_url.clone(), SecretKey::generate(), resolver.clone())
+                .tls_client_config(client_config.clone())
+                .connect()
+                .await?;
+
+        let config = DrainConfig {
+            reconnect_in: Duration::from_secs(2),
+            try_for: Duration::from_secs(1),
+            timeout: Duration::from_secs(60),
+        };
+        let service = server.relay_service().unwrap().clone();
+        let metrics = server.metrics().server.clone();
+        let drain = tokio::spawn(server.drain(config.clone()));
+
+        let msg = tokio::time::timeout(Duration::from_secs(5), client.next())
+            .await
+            .std_context("timeout")?
+            .expect("stream finished")?;
+        let RelayToClientMsg::Restarting {
+            reconnect_in,
+            try_for,
+        } = msg
+        else {
+            panic!("expected restarting, got {msg:?}");
+        };
+        assert!(reconnect_in <= config.reconnect_in);
+        assert_eq!(try_for, config.try_for);
+        assert!(service.is_draining());
+
+        // New clients are rejected while draining.
+        let res = ClientBuilder::new(relay_url, SecretKey::generate(), resolver)
+            .tls_client_config(client_config)
+            .connect()
+            .await;
+        assert!(res.is_err());
+
+        // The server shuts down once the last client left.
+        drop(client);
+        tokio::time::timeout(Duration::from_secs(10), drain)
+            .await
+            .std_context("drain timeout")?
+            .anyerr()??;
+        assert_eq!(metrics.http_accepts_draining.get(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn test_admin_api() -> Result<()> {
+        let client_config = CaTlsConfig::default()
+            .client_config(default_provider())
+            .unwrap();
+        let a_secret_key = SecretKey::generate();
+        let a_key = a_secret_key.public();
+        let b_secret_key = SecretKey::generate();
+        let b_key = b_secret_key.public();
+
+        let server = Server::spawn(ServerConfig {
+            relay: Some(RelayConfig::new((Ipv4Addr::LOCALHOST, 0))),
+            quic: None,
+            metrics_addr: None,
+            admin: Some(AdminConfig::new((Ipv4Addr::LOCALHOST, 0), "secret")),
+        })
+        .await?;
+        let relay_url: RelayUrl = format!("http://{}", server.http_addr().unwrap()).parse()?;
+        let admin_url = format!("http://{}", server.admin_addr().unwrap());
+        let connect = async |secret_key: SecretKey| {
+            ClientBuilder::new(relay_url.clone(), secret_key, dns_resolver())
+                .tls_client_config(client_config.clone())
+                .connect()
+                .await
+        };
+        let mut client_a = connect(a_secret_key.clone()).await?;
+        let mut client_b = connect(b_secret_key).await?;
+        let msg = Datagrams::from("hello, b");
+        try_send_recv(&mut client_a, &mut client_b, b_key, msg).await?;
+
+        let http = reqwest::Client::builder()
+            .use_preconfigured_tls(ring_config())
+            .build()
+            .anyerr()?;
+        let admin = |method: reqwest::Method, path: &str| {
+            http.request(method, format!("{admin_url}{path}"))
+                .bearer_auth("secret")
+                .send()
+        };
+
+        let res = http
+            .get(format!("{admin_url}/clients"))
+            .bearer_auth("wrong")
+            .send()
+            .await
+            .anyerr()?;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let res = admin(reqwest::Method::GET, "/clients").await.anyerr()?;
+        assert_eq!(res.status(), StatusCode::OK);
+        let clients: Vec<serde_json::Value> = res.json().await.anyerr()?;
+        assert_eq!(clients.len(), 2);
+        let client = |key: EndpointId| {
+            clients
+                .iter()
+                .find(|c| c["endpoint_id"] == key.to_string())
+                .expect("client listed")
+        };
+        assert!(client(a_key)["bytes_recv"].as_u64().unwrap() >= 8);
+        assert!(client(b_key)["bytes_sent"].as_u64().unwrap() >= 8);
+        assert_eq!(client(b_key)["active"], true);
+
+        // Disconnecting closes the connection of `b`.
+        let res = admin(
+            reqwest::Method::POST,
+            &format!("/clients/{b_key}/disconnect"),
+        )
+        .await
+        .anyerr()?;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        wait_disconnected(&mut client_b).await?;
+        let unknown = SecretKey::generate().public();
+        let res = admin(
+            reqwest::Method::POST,
+            &format!("/clients/{unknown}/disconnect"),
+        )
+        .await
+        .anyerr()?;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        // A banned endpoint is disconnected and can not connect again until unbanned.
+        let res = admin(reqwest::Method::PUT, &format!("/bans/{a_key}"))
+            .await
+            .anyerr()?;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        wait_disconnected(&mut client_a).await?;
+        let res = admin(reqwest::Method::GET, "/bans").await.anyerr()?;
+        let banned: Vec<String> = res.json().await.anyerr()?;
+        assert_eq!(banned, vec![a_key.to_string()]);
+        assert!(connect(a_secret_key.clone()).await.is_err());
+
+        let res = admin(reqwest::Method::DELETE, &format!("/bans/{a_key}"))
+            .await
+            .anyerr()?;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        connect(a_secret_key).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn test_relay_reload() -> Result<()> {
+        let client_config = CaTlsConfig::default()
+            .client_config(default_provider())
+            .unwrap();
+        let a_secret_key = SecretKey::generate();
+        let b_secret_key = SecretKey::generate();
+        let b_key = b_secret_key.public();
+
+        let server = spawn_local_relay().await?;
+        let relay_url: RelayUrl = format!("http://{}", server.http_addr().unwrap()).parse()?;
+        let connect = async |secret_key: SecretKey| {
+            ClientBuilder::new(relay_url.clone(), secret_key, dns_resolver())
+                .tls_client_config(client_config.clone())
+                .connect()
+                .await
+        };
+        let mut client_a = connect(a_secret_key.clone()).await?;
+        let mut client_b = connect(b_secret_key).await?;
+
+        // Deny everyone from now on.
+        let config = ReloadConfig {
+            access: Arc::new(TestAccess(Box::new(|_| Access::Deny { reason: None }))),
+            ..Default::default()
+        };
+        server.reload(config)?;
+        assert!(connect(a_secret_key).await.is_err());
+
+        // Established connections keep working.
+        let msg = Datagrams::from("hello, b");
+        let res = try_send_recv(&mut client_a, &mut client_b, b_key, msg).await?;
+        assert!(matches!(res, RelayToClientMsg::Datagrams { .. }));
+
+        // Invalid limits are rejected.
+        let mut config = ReloadConfig::default();
+        config.limits.accept_conn_limit = Some(-1.0);
+        assert!(server.reload(config).is_err());
+        Ok(())
+    }
+
+    /// Waits until the relay closed the connection of `client`.
+    async fn wait_disconnected(client: &mut crate::client::Client) -> Result<()> {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while let Some(Ok(_)) = client.next().await {}
+        })
+        .await
+        .std_context("not disconnected")
+    }
+
     /// Regression test: A relay client that prefers IPv6 falls back to IPv4
     /// when the advertised IPv6 address is unreachable.
     #[tokio::test]
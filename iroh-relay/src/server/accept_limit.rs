@@ -0,0 +1,258 @@
+//! Rate limits for accepting new connections.
+//!
+//! Each listener of the relay server owns an [`AcceptLimiter`], configured from the
+//! `accept_conn_*` fields of [`Limits`]. Both the global and the per-IP limits are token
+//! buckets, which refill continuously at the configured rate up to the configured burst.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv6Addr},
+    sync::Mutex,
+};
+
+use n0_error::e;
+use tokio::time::{Duration, Instant};
+
+use super::{Limits, SpawnError};
+
+/// Number of per-IP buckets above which full buckets are pruned.
+const MIN_PRUNE_LEN: usize = 1024;
+
+/// Limits the rate at which a listener accepts new connections.
+#[derive(Debug)]
+pub(crate) struct AcceptLimiter {
+    global: Option<(Rate, Mutex<TokenBucket>)>,
+    per_ip: Option<(Rate, Mutex<PerIp>)>,
+}
+
+impl AcceptLimiter {
+    /// Creates a limiter from `limits`, or `None` if no accept limits are configured.
+    pub(crate) fn new(limits: &Limits) -> Result<Option<Self>, SpawnError> {
+        let now = Instant::now();
+        let global = Rate::new(limits.accept_conn_limit, limits.accept_conn_burst)?
+            .map(|rate| (rate, Mutex::new(TokenBucket::new(rate, now))));
+        let per_ip = Rate::new(
+            limits.accept_conn_limit_per_ip,
+            limits.accept_conn_burst_per_ip,
+        )?
+        .map(|rate| {
+            let per_ip = PerIp {
+                buckets: HashMap::new(),
+                prune_len: MIN_PRUNE_LEN,
+            };
+            (rate, Mutex::new(per_ip))
+        });
+        if global.is_none() && per_ip.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Self { global, per_ip }))
+    }
+
+    /// Returns whether `other` enforces the same rates as `self`.
+    pub(crate) fn has_same_rates(&self, other: &Self) -> bool {
+        let global = |limiter: &Self| limiter.global.as_ref().map(|(rate, _)| *rate);
+        let per_ip = |limiter: &Self| limiter.per_ip.as_ref().map(|(rate, _)| *rate);
+        global(self) == global(other) && per_ip(self) == per_ip(other)
+    }
+
+    /// Takes a token for a new connection from `remote_ip`.
+    ///
+    /// The per-IP limit is checked first, so that connections rejected by it do not count
+    /// against the global limit. The per-IP limit is skipped if the remote IP is unknown.
+    ///
+    /// Returns the time after which a new attempt may succeed if the connection must be
+    /// rejected.
+    pub(crate) fn check(&self, remote_ip: Option<IpAddr>) -> Result<(), Duration> {
+        let now = Instant::now();
+        if let Some((rate, per_ip)) = &self.per_ip
+            && let Some(ip) = remote_ip
+        {
+            per_ip
+                .lock()
+                .expect("poisoned")
+                .try_take(ip_key(ip), *rate, now)?;
+        }
+        if let Some((rate, bucket)) = &self.global {
+            bucket.lock().expect("poisoned").try_take(*rate, now)?;
+        }
+        Ok(())
+    }
+}
+
+/// The refill rate and capacity of a [`TokenBucket`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Rate {
+    per_second: f64,
+    burst: f64,
+}
+
+impl Rate {
+    fn new(limit: Option<f64>, burst: Option<usize>) -> Result<Option<Self>, SpawnError> {
+        let Some(per_second) = limit else {
+            return Ok(None);
+        };
+        if !per_second.is_finite() || per_second <= 0.0 || burst == Some(0) {
+            return Err(e!(SpawnError::InvalidAcceptLimit {
+                limit: per_second,
+                burst
+            }));
+        }
+        let burst = burst.map_or(per_second.ceil(), |burst| burst as f64);
+        Ok(Some(Self { per_second, burst }))
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: Rate, now: Instant) -> Self {
+        Self {
+            tokens: rate.burst,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, rate: Rate, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * rate.per_second).min(rate.burst);
+        self.last_refill = now;
+    }
+
+    fn try_take(&mut self, rate: Rate, now: Instant) -> Result<(), Duration> {
+        self.refill(rate, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / rate.per_second,
+            ))
+        }
+    }
+
+    fn is_full(&mut self, rate: Rate, now: Instant) -> bool {
+        self.refill(rate, now);
+        self.tokens >= rate.burst
+    }
+}
+
+#[derive(Debug)]
+struct PerIp {
+    buckets: HashMap<IpAddr, TokenBucket>,
+    /// Number of buckets at which full buckets are pruned next.
+    prune_len: usize,
+}
+
+impl PerIp {
+    fn try_take(&mut self, ip: IpAddr, rate: Rate, now: Instant) -> Result<(), Duration> {
+        if self.buckets.len() >= self.prune_len {
+            // A full bucket behaves the same as a new one, so it can be dropped.
+            self.buckets.retain(|_, bucket| !bucket.is_full(rate, now));
+            self.prune_len = (self.buckets.len() * 2).max(MIN_PRUNE_LEN);
+        }
+        self.buckets
+            .entry(ip)
+            .or_insert_with(|| TokenBucket::new(rate, now))
+            .try_take(rate, now)
+    }
+}
+
+/// Returns the key of the per-IP bucket for `ip`.
+///
+/// IPv6 addresses are limited per /64 prefix, which is usually assigned to a single host.
+fn ip_key(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => ip,
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => {
+                let prefix = u128::from(v6) & !(u128::from(u64::MAX));
+                IpAddr::V6(Ipv6Addr::from(prefix))
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn limits(
+        global: Option<(f64, usize)>,
+        per_ip: Option<(f64, usize)>,
+    ) -> Result<Option<AcceptLimiter>, SpawnError> {
+        let limits = Limits {
+            accept_conn_limit: global.map(|(limit, _)| limit),
+            accept_conn_burst: global.map(|(_, burst)| burst),
+            accept_conn_limit_per_ip: per_ip.map(|(limit, _)| limit),
+            accept_conn_burst_per_ip: per_ip.map(|(_, burst)| burst),
+            ..Default::default()
+        };
+        AcceptLimiter::new(&limits)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_accept_limiter() {
+        let limiter = limits(Some((2.0, 4)), Some((1.0, 2))).unwrap().unwrap();
+        let a = Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)));
+        let b = Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2)));
+        let c = Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 3)));
+
+        // Per-IP burst of 2.
+        assert!(limiter.check(a).is_ok());
+        assert!(limiter.check(a).is_ok());
+        assert_eq!(limiter.check(a), Err(Duration::from_secs(1)));
+        // Global burst of 4, the rejected connection from `a` did not count.
+        assert!(limiter.check(b).is_ok());
+        assert!(limiter.check(b).is_ok());
+        assert_eq!(limiter.check(c), Err(Duration::from_millis(500)));
+
+        tokio::time::advance(Duration::from_millis(500)).await;
+        assert!(limiter.check(c).is_ok());
+        assert!(limiter.check(None).is_err());
+        tokio::time::advance(Duration::from_millis(500)).await;
+        // Connections from unknown IPs are only subject to the global limit.
+        assert!(limiter.check(None).is_ok());
+        assert!(limiter.check(a).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_accept_limiter_ipv6_prefix() {
+        let limiter = limits(None, Some((0.5, 1))).unwrap().unwrap();
+        let a: IpAddr = "2001:db8:0:1::1".parse().unwrap();
+        let b: IpAddr = "2001:db8:0:1::2".parse().unwrap();
+        let c: IpAddr = "2001:db8:0:2::1".parse().unwrap();
+
+        assert!(limiter.check(Some(a)).is_ok());
+        assert_eq!(limiter.check(Some(b)), Err(Duration::from_secs(2)));
+        assert!(limiter.check(Some(c)).is_ok());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_accept_limiter_prunes() {
+        let limiter = limits(None, Some((10.0, 1))).unwrap().unwrap();
+        for i in 0..MIN_PRUNE_LEN as u32 {
+            let ip = IpAddr::V4(Ipv4Addr::from(i));
+            assert!(limiter.check(Some(ip)).is_ok());
+        }
+        tokio::time::advance(Duration::from_secs(1)).await;
+        let ip = IpAddr::V4(Ipv4Addr::from(u32::MAX));
+        assert!(limiter.check(Some(ip)).is_ok());
+        let per_ip = limiter.per_ip.as_ref().unwrap().1.lock().unwrap();
+        assert_eq!(per_ip.buckets.len(), 1);
+    }
+
+    #[test]
+    fn test_accept_limiter_config() {
+        assert!(limits(None, None).unwrap().is_none());
+        assert!(limits(Some((0.0, 1)), None).is_err());
+        assert!(limits(None, Some((f64::NAN, 1))).is_err());
+        assert!(limits(Some((1.0, 0)), None).is_err());
+    }
+}
@@ -0,0 +1,256 @@
+//! An HTTP API for operators to inspect and manage the clients of a relay server.
+//!
+//! The API is served on its own socket, see [`AdminConfig::bind_addr`], and every request
+//! must carry the configured [`AdminConfig::token`] in an `Authorization: Bearer <token>`
+//! header.  Requests without a valid token are answered with `401 Unauthorized`.
+//!
+//! Endpoints are identified by their [`EndpointId`] in hex, the following routes are served:
+//!
+//! - `GET /clients`: lists all client connections as a JSON array, with their endpoint id,
+//!   connection id, protocol version, connect time in seconds since the UNIX epoch, bytes
+//!   received and sent, and their rate limit and quota state.
+//! - `POST /clients/{endpoint_id}/disconnect`: disconnects all connections of the endpoint.
+//! - `GET /bans`: lists the banned endpoints as a JSON array.
+//! - `PUT /bans/{endpoint_id}`: bans the endpoint and disconnects it.
+//! - `DELETE /bans/{endpoint_id}`: lifts the ban of the endpoint.
+//!
+//! Changes succeed with `204 No Content`, or `404 Not Found` if the endpoint is not
+//! connected respectively not banned.  Bans only last until the relay server is restarted.
+
+use std::{net::SocketAddr, time::UNIX_EPOCH};
+
+use http::{HeaderValue, Method, Request, Response, StatusCode, header};
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use iroh_base::EndpointId;
+use serde::Serialize;
+use tokio::{net::TcpListener, task::JoinSet};
+use tracing::{debug, error, info, warn};
+
+use super::clients::{Clients, ConnectionInfo};
+
+/// Configuration for the admin API of the relay server.
+///
+/// See the [module docs](self) for the served routes.
+#[derive(derive_more::Debug, Clone)]
+#[non_exhaustive]
+pub struct AdminConfig {
+    /// The socket address on which the admin API is served.
+    ///
+    /// The API is served over plain HTTP, so this should not be reachable from the
+    /// internet.
+    pub bind_addr: SocketAddr,
+    /// The bearer token that every request must be authorized with.
+    #[debug("..")]
+    pub token: String,
+}
+
+impl AdminConfig {
+    /// Creates a new [`AdminConfig`] serving on `bind_addr`, authorized with `token`.
+    pub fn new(bind_addr: impl Into<SocketAddr>, token: impl Into<String>) -> Self {
+        Self {
+            bind_addr: bind_addr.into(),
+            token: token.into(),
+        }
+    }
+}
+
+/// A client connection as listed by `GET /clients`.
+#[derive(Debug, Serialize)]
+struct Connection {
+    endpoint_id: String,
+    connection_id: u64,
+    protocol_version: &'static str,
+    connected_at: u64,
+    active: bool,
+    bytes_recv: u64,
+    bytes_sent: u64,
+    bytes_rx_ratelimited: u64,
+    quota_used: Option<u64>,
+    quota_exceeded: bool,
+}
+
+impl From<ConnectionInfo> for Connection {
+    fn from(info: ConnectionInfo) -> Self {
+        Self {
+            endpoint_id: info.endpoint_id.to_string(),
+            connection_id: info.connection_id.0,
+            protocol_version: info.protocol_version.to_str(),
+            connected_at: info
+                .connected_at
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            active: info.active,
+            bytes_recv: info.bytes_recv,
+            bytes_sent: info.bytes_sent,
+            bytes_rx_ratelimited: info.bytes_rx_ratelimited,
+            quota_used: info.quota_used,
+            quota_exceeded: info.quota_exceeded,
+        }
+    }
+}
+
+/// Serves the admin API on `listener` until dropped.
+pub(super) async fn run_admin_service(listener: TcpListener, token: &str, clients: Clients) {
+    info!("serving");
+    let service = AdminService {
+        token_hash: blake3::hash(token.as_bytes()),
+        clients,
+    };
+
+    // If this future is cancelled, this is dropped and all tasks are aborted.
+    let mut tasks = JoinSet::new();
+
+    loop {
+        tokio::select! {
+            biased;
+
+            Some(res) = tasks.join_next() => {
+                if let Err(err) = res
+                    && err.is_panic()
+                {
+                    panic!("task panicked: {err:#?}");
+                }
+            }
+
+            res = listener.accept() => {
+                match res {
+                    Ok((stream, peer_addr)) => {
+                        debug!(%peer_addr, "Connection opened");
+                        let service = service.clone();
+                        tasks.spawn(async move {
+                            let stream = hyper_util::rt::TokioIo::new(stream);
+                            if let Err(err) = hyper::server::conn::http1::Builder::new()
+                                .serve_connection(stream, service)
+                                .await
+                            {
+                                debug!("Failed to serve connection: {err:?}");
+                            }
+                        });
+                    }
+                    Err(err) => {
+                        error!("failed to accept connection: {err:#}");
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct AdminService {
+    /// Hash of the bearer token, so tokens are compared in constant time.
+    token_hash: blake3::Hash,
+    clients: Clients,
+}
+
+impl AdminService {
+    fn is_authorized<B>(&self, req: &Request<B>) -> bool {
+        req.headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|token| blake3::hash(token.as_bytes()) == self.token_hash)
+    }
+
+    fn handle<B>(&self, req: &Request<B>) -> Response<Full<Bytes>> {
+        if !self.is_authorized(req) {
+            warn!(path = %req.uri().path(), "unauthorized admin request");
+            let mut response = status_response(StatusCode::UNAUTHORIZED);
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            return response;
+        }
+
+        let segments: Vec<_> = req.uri().path().trim_matches('/').split('/').collect();
+        match (req.method(), segments.as_slice()) {
+            (&Method::GET, ["clients"]) => {
+                let connections: Vec<Connection> = self
+                    .clients
+                    .connections()
+                    .into_iter()
+                    .map(Connection::from)
+                    .collect();
+                json_response(&connections)
+            }
+            (&Method::POST, ["clients", endpoint_id, "disconnect"]) => {
+                with_endpoint_id(endpoint_id, |endpoint_id| {
+                    info!(endpoint_id = %endpoint_id.fmt_short(), "disconnecting endpoint");
+                    self.clients.disconnect(endpoint_id, None)
+                })
+            }
+            (&Method::GET, ["bans"]) => {
+                let banned: Vec<String> = self
+                    .clients
+                    .banned()
+                    .iter()
+                    .map(EndpointId::to_string)
+                    .collect();
+                json_response(&banned)
+            }
+            (&Method::PUT, ["bans", endpoint_id]) => with_endpoint_id(endpoint_id, |endpoint_id| {
+                info!(endpoint_id = %endpoint_id.fmt_short(), "banning endpoint");
+                self.clients.ban(endpoint_id);
+                true
+            }),
+            (&Method::DELETE, ["bans", endpoint_id]) => {
+                with_endpoint_id(endpoint_id, |endpoint_id| {
+                    info!(endpoint_id = %endpoint_id.fmt_short(), "unbanning endpoint");
+                    self.clients.unban(endpoint_id)
+                })
+            }
+            _ => status_response(StatusCode::NOT_FOUND),
+        }
+    }
+}
+
+impl hyper::service::Service<Request<Incoming>> for AdminService {
+    type Response = Response<Full<Bytes>>;
+    type Error = std::convert::Infallible;
+    type Future = std::future::Ready<Result<Self::Response, Self::Error>>;
+
+    fn call(&self, req: Request<Incoming>) -> Self::Future {
+        std::future::ready(Ok(self.handle(&req)))
+    }
+}
+
+/// Parses `endpoint_id` and applies `f` to it.
+///
+/// Responds with `204 No Content` if `f` returns `true`, and `404 Not Found` otherwise.
+fn with_endpoint_id(
+    endpoint_id: &str,
+    f: impl FnOnce(EndpointId) -> bool,
+) -> Response<Full<Bytes>> {
+    let Ok(endpoint_id) = endpoint_id.parse() else {
+        return status_response(StatusCode::BAD_REQUEST);
+    };
+    if f(endpoint_id) {
+        status_response(StatusCode::NO_CONTENT)
+    } else {
+        status_response(StatusCode::NOT_FOUND)
+    }
+}
+
+fn json_response(value: &impl Serialize) -> Response<Full<Bytes>> {
+    match serde_json::to_vec(value) {
+        Ok(body) => {
+            let mut response = Response::new(Full::from(body));
+            response.headers_mut().insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            );
+            response
+        }
+        Err(err) => {
+            error!("failed to serialize response: {err:#}");
+            status_response(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+fn status_response(status: StatusCode) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::default());
+    *response.status_mut() = status;
+    response
+}
@@ -1,6 +1,14 @@
 //! The server-side representation of an ongoing client relaying connection.
 
-use std::{collections::HashSet, sync::Arc, time::Duration};
+use std::{
+    collections::HashSet,
+    num::NonZeroU32,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime},
+};
 
 use iroh_base::EndpointId;
 use n0_error::{e, stack_error};
@@ -26,8 +34,9 @@ use crate::{
         streams::BytesStreamSink,
     },
     server::{
-        ConnectionId, OnDisconnectGuard,
+        ConnectionId, EndpointLimits, OnDisconnectGuard,
         clients::Clients,
+        fair_queue,
         metrics::Metrics,
         streams::{RecvError as RelayRecvError, RelayedStream, SendError as RelaySendError},
     },
@@ -37,9 +46,9 @@ use crate::{
 #[derive(Debug, Clone)]
 pub(super) struct Packet {
     /// The sender of the packet
-    src: EndpointId,
+    pub(super) src: EndpointId,
     /// The data packet bytes.
-    data: Datagrams,
+    pub(super) data: Datagrams,
 }
 
 /// Configuration for a client connection.
@@ -60,12 +69,17 @@ pub struct Config<S> {
     pub channel_capacity: usize,
     /// Protocol version negotiated for this client
     pub protocol_version: ProtocolVersion,
+    /// Quota and weight of the endpoint
+    pub limits: EndpointLimits,
+    /// Traffic statistics of the connection, shared with the rate limiter of the stream.
+    pub(super) stats: Arc<ConnectionStats>,
 }
 
 impl<S> Config<S> {
     /// Creates a new config with sensible default values for `write_timeout` and `channel_capacity`.
     ///
-    /// The endpoint and connection ids are taken from `guard`.
+    /// The endpoint and connection ids are taken from `guard`. The `limits` default to
+    /// [`EndpointLimits::default`].
     pub fn new(
         guard: OnDisconnectGuard,
         stream: RelayedStream<S>,
@@ -77,10 +91,23 @@ impl<S> Config<S> {
             protocol_version,
             write_timeout: SERVER_WRITE_TIMEOUT,
             channel_capacity: PER_CLIENT_SEND_QUEUE_DEPTH,
+            limits: EndpointLimits::default(),
+            stats: Default::default(),
         }
     }
 }
 
+/// Traffic statistics of a single client connection.
+#[derive(Debug, Default)]
+pub(super) struct ConnectionStats {
+    /// Bytes of datagrams received from the client.
+    pub(super) bytes_recv: AtomicU64,
+    /// Bytes of datagrams sent to the client.
+    pub(super) bytes_sent: AtomicU64,
+    /// Bytes read from the client while its receive rate limit was exceeded.
+    pub(super) bytes_rx_ratelimited: AtomicU64,
+}
+
 /// The [`Server`] side representation of a [`Client`]'s connection.
 ///
 /// [`Server`]: crate::server::Server
@@ -95,12 +122,20 @@ pub(super) struct Client {
     done: CancellationToken,
     /// Actor handle.
     handle: AbortOnDropHandle<()>,
-    /// Channel to send packets intended for the client.
-    packet_queue: mpsc::Sender<Packet>,
+    /// Queue of packets intended for the client, fair across the senders.
+    packet_queue: fair_queue::Sender,
     /// Channel to send non-packet messages to the client.
     message_queue: mpsc::Sender<RelayToClientMsg>,
     /// Relay protocol version negotiated for this client.
     protocol_version: ProtocolVersion,
+    /// Weight of the packets sent by this client in the queues of other clients.
+    weight: NonZeroU32,
+    /// The start of the quota period in which the client was told that its quota is exceeded.
+    quota_notified: Mutex<Option<Date>>,
+    /// When the client connected.
+    connected_at: SystemTime,
+    /// Traffic statistics of the connection.
+    stats: Arc<ConnectionStats>,
 }
 
 impl Client {
@@ -121,11 +156,14 @@ impl Client {
             write_timeout,
             channel_capacity,
             protocol_version,
+            limits,
+            stats,
         } = config;
         let endpoint_id = guard.endpoint_id;
         let connection_id = guard.connection_id;
 
-        let (packet_send_queue_s, packet_send_queue_r) = mpsc::channel(channel_capacity);
+        let (packet_send_queue_s, packet_send_queue_r) =
+            fair_queue::channel(channel_capacity, metrics.clone());
         let (message_send_queue_s, message_send_queue_r) = mpsc::channel(channel_capacity);
         let done = CancellationToken::new();
 
@@ -139,6 +177,7 @@ impl Client {
             client_counter: ClientCounter::default(),
             ping_tracker: PingTracker::default(),
             metrics,
+            stats: stats.clone(),
         };
 
         // start io loop
@@ -157,6 +196,10 @@ impl Client {
             packet_queue: packet_send_queue_s,
             message_queue: message_send_queue_s,
             protocol_version,
+            weight: limits.weight,
+            quota_notified: Mutex::new(None),
+            connected_at: SystemTime::now(),
+            stats,
         }
     }
 
@@ -164,6 +207,22 @@ impl Client {
         self.connection_id
     }
 
+    pub(super) fn endpoint_id(&self) -> EndpointId {
+        self.endpoint_id
+    }
+
+    pub(super) fn protocol_version(&self) -> ProtocolVersion {
+        self.protocol_version
+    }
+
+    pub(super) fn connected_at(&self) -> SystemTime {
+        self.connected_at
+    }
+
+    pub(super) fn stats(&self) -> &ConnectionStats {
+        &self.stats
+    }
+
     /// Shutdown the reader and writer loops and closes the connection.
     ///
     /// Any shutdown errors will be logged as warnings.
@@ -182,12 +241,19 @@ impl Client {
         self.done.cancel();
     }
 
+    /// Returns the weight of the packets sent by this client.
+    pub(super) fn weight(&self) -> NonZeroU32 {
+        self.weight
+    }
+
+    /// Queues a packet from `src`, served according to the `weight` of `src`.
     pub(super) fn try_send_packet(
         &self,
         src: EndpointId,
         data: Datagrams,
+        weight: NonZeroU32,
     ) -> Result<(), TrySendError<Packet>> {
-        self.packet_queue.try_send(Packet { src, data })
+        self.packet_queue.try_send(Packet { src, data }, weight)
     }
 
     pub(super) fn try_send_peer_gone(
@@ -210,6 +276,35 @@ impl Client {
         };
         self.message_queue.try_send(message)
     }
+
+    /// Tells the client that the relay is restarting.
+    ///
+    /// The client is asked to reconnect after a random delay of up to `reconnect_in`.
+    pub(super) fn try_send_restarting(
+        &self,
+        reconnect_in: Duration,
+        try_for: Duration,
+    ) -> Result<(), TrySendError<RelayToClientMsg>> {
+        let reconnect_in = reconnect_in.mul_f64(rand::rng().random::<f64>());
+        self.message_queue.try_send(RelayToClientMsg::Restarting {
+            reconnect_in,
+            try_for,
+        })
+    }
+
+    /// Tells the client that its quota is exceeded, once per quota period.
+    ///
+    /// `period_start` is the first day of the period in which the quota is exceeded.
+    pub(super) fn notify_quota_exceeded(&self, period_start: Date) {
+        let previous = self
+            .quota_notified
+            .lock()
+            .expect("poisoned")
+            .replace(period_start);
+        if previous != Some(period_start) {
+            self.try_send_health(Status::QuotaExceeded).ok();
+        }
+    }
 }
 
 /// Error when handling an incoming frame from a client.
@@ -292,7 +387,7 @@ struct Actor<S> {
     /// Maximum time we wait to complete a write to the client
     timeout: Duration,
     /// Receiver for packets to be sent to the client.
-    packet_send_queue: mpsc::Receiver<Packet>,
+    packet_send_queue: fair_queue::Receiver,
     /// Receiver for non-packet messages to be sent to the client.
     message_send_queue: mpsc::Receiver<RelayToClientMsg>,
     /// Reports the disconnect to access control when dropped.
@@ -305,6 +400,8 @@ struct Actor<S> {
     client_counter: ClientCounter,
     ping_tracker: PingTracker,
     metrics: Arc<Metrics>,
+    /// Traffic statistics of this connection.
+    stats: Arc<ConnectionStats>,
 }
 
 impl<S> Actor<S>
@@ -417,6 +514,7 @@ where
 
         if let Ok(len) = datagrams.contents.len().try_into() {
             self.metrics.bytes_sent.inc_by(len);
+            self.stats.bytes_sent.fetch_add(len, Ordering::Relaxed);
         }
         self.write_frame(RelayToClientMsg::Datagrams {
             remote_endpoint_id,
@@ -462,6 +560,9 @@ where
                     warn!("failed to handle send packet frame: {err:#}");
                 }
                 self.metrics.bytes_recv.inc_by(packet_len as u64);
+                self.stats
+                    .bytes_recv
+                    .fetch_add(packet_len as u64, Ordering::Relaxed);
             }
             ClientToRelayMsg::Ping(data) => {
                 self.metrics.got_ping.inc();
@@ -583,7 +684,8 @@ mod tests {
     async fn test_client_actor_basic() -> Result {
         let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0u64);
 
-        let (send_queue_s, send_queue_r) = mpsc::channel(10);
+        let metrics = Arc::new(Metrics::default());
+        let (send_queue_s, send_queue_r) = fair_queue::channel(10, metrics.clone());
         let (message_s, message_r) = mpsc::channel(10);
 
         let endpoint_id = SecretKey::from_bytes(&rng.random()).public();
@@ -592,7 +694,6 @@ mod tests {
         let stream = RelayedStream::test(io);
 
         let clients = Clients::default();
-        let metrics = Arc::new(Metrics::default());
         let actor = Actor {
             stream,
             timeout: Duration::from_secs(1),
@@ -603,6 +704,7 @@ mod tests {
             client_counter: ClientCounter::default(),
             ping_tracker: PingTracker::default(),
             metrics,
+            stats: Default::default(),
         };
 
         let done = CancellationToken::new();
@@ -620,8 +722,7 @@ mod tests {
             data: Datagrams::from(&data[..]),
         };
         send_queue_s
-            .send(packet.clone())
-            .await
+            .try_send(packet.clone(), NonZeroU32::MIN)
             .std_context("send")?;
         let frame = recv_frame(FrameType::RelayToClientDatagram, &mut io_rw)
             .await
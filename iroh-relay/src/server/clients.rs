@@ -1,19 +1,27 @@
 //! The "Server" side of the client. Uses the `ClientConnManager`.
 // Based on tailscale/derp/derp_server.go
 
-use std::{collections::HashSet, sync::Arc};
+use std::{
+    collections::HashSet,
+    num::NonZeroU32,
+    sync::{Arc, atomic::Ordering},
+    time::{Duration, SystemTime},
+};
 
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
 use iroh_base::EndpointId;
 use n0_future::IterExt;
-use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{Notify, mpsc::error::TrySendError};
 use tracing::{debug, trace};
 
 use super::{
     ConnectionId, OnDisconnectGuard,
     client::{Client, Config, ForwardPacketError},
+    mesh::Mesh,
+    quota::QuotaTracker,
 };
 use crate::{
+    http::ProtocolVersion,
     protos::{
         relay::{Datagrams, Status},
         streams::BytesStreamSink,
@@ -34,6 +42,67 @@ struct Inner {
     clients: DashMap<EndpointId, ClientState>,
     /// Map of which client has sent where
     sent_to: DashMap<EndpointId, HashSet<EndpointId>>,
+    /// The relay mesh, if datagrams to endpoints connected elsewhere are forwarded.
+    mesh: Option<Mesh>,
+    /// Usage of the endpoints with a quota, kept across reconnects.
+    quotas: QuotaTracker,
+    /// Notified when the last client unregistered.
+    on_empty: Notify,
+    /// Endpoints which are not allowed to connect.
+    banned: DashSet<EndpointId>,
+}
+
+/// Details about a connected relay client, see [`Clients::connections`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ConnectionInfo {
+    /// The endpoint that is connected.
+    pub endpoint_id: EndpointId,
+    /// The id of the connection.
+    pub connection_id: ConnectionId,
+    /// The relay protocol version negotiated for the connection.
+    pub protocol_version: ProtocolVersion,
+    /// When the connection was established.
+    pub connected_at: SystemTime,
+    /// Whether this is the connection datagrams for the endpoint are delivered on.
+    ///
+    /// When an endpoint connects several times only its latest connection is active.
+    pub active: bool,
+    /// Bytes of datagrams received from the endpoint on this connection.
+    pub bytes_recv: u64,
+    /// Bytes of datagrams sent to the endpoint on this connection.
+    pub bytes_sent: u64,
+    /// Bytes received on this connection while its receive rate limit was exceeded.
+    ///
+    /// This is non-zero if the connection was slowed down by [`Limits::client_rx`].
+    ///
+    /// [`Limits::client_rx`]: super::Limits::client_rx
+    pub bytes_rx_ratelimited: u64,
+    /// Bytes relayed from and to the endpoint in the current period of its quota.
+    ///
+    /// `None` if the endpoint has no quota, see [`Clients::quota_usage`].
+    pub quota_used: Option<u64>,
+    /// Whether the endpoint exceeded its quota.
+    pub quota_exceeded: bool,
+}
+
+impl ConnectionInfo {
+    fn new(client: &Client, active: bool, quotas: &QuotaTracker) -> Self {
+        let stats = client.stats();
+        let endpoint_id = client.endpoint_id();
+        Self {
+            endpoint_id,
+            connection_id: client.connection_id(),
+            protocol_version: client.protocol_version(),
+            connected_at: client.connected_at(),
+            active,
+            bytes_recv: stats.bytes_recv.load(Ordering::Relaxed),
+            bytes_sent: stats.bytes_sent.load(Ordering::Relaxed),
+            bytes_rx_ratelimited: stats.bytes_rx_ratelimited.load(Ordering::Relaxed),
+            quota_used: quotas.bytes_used(endpoint_id),
+            quota_exceeded: quotas.is_exceeded(endpoint_id),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -54,6 +123,15 @@ impl ClientState {
 }
 
 impl Clients {
+    /// Creates a registry that forwards datagrams for endpoints not connected locally
+    /// through `mesh`.
+    pub(super) fn with_mesh(mesh: Mesh) -> Self {
+        Self(Arc::new(Inner {
+            mesh: Some(mesh),
+            ..Default::default()
+        }))
+    }
+
     /// Shuts down all connected clients.
     ///
     /// This method gracefully disconnects all active client connections managed by
@@ -77,6 +155,19 @@ impl Clients {
         let endpoint_id = client_config.guard.endpoint_id;
         trace!(remote_endpoint = %endpoint_id.fmt_short(), "registering client");
 
+        if self.is_banned(endpoint_id) {
+            // Banned while the connection was being accepted.
+            debug!(remote_endpoint = %endpoint_id.fmt_short(), "endpoint is banned, dropping connection");
+            return;
+        }
+
+        self.0
+            .quotas
+            .prune(|endpoint_id| self.0.clients.contains_key(endpoint_id));
+        self.0
+            .quotas
+            .set_quota(endpoint_id, client_config.limits.quota);
+
         let client = Client::new(client_config, self, metrics.clone());
         match self.0.clients.entry(endpoint_id) {
             dashmap::Entry::Occupied(mut entry) => {
@@ -97,6 +188,9 @@ impl Clients {
                     active: client,
                     inactive: Vec::new(),
                 });
+                if let Some(mesh) = &self.0.mesh {
+                    mesh.endpoint_connected(endpoint_id);
+                }
             }
         }
     }
@@ -116,7 +210,7 @@ impl Clients {
 
         let mut notify_peers = None;
 
-        self.0.clients.remove_if_mut(&endpoint_id, |_id, state| {
+        let removed = self.0.clients.remove_if_mut(&endpoint_id, |_id, state| {
             if state.active.connection_id() == connection_id {
                 // The unregistering client is the currently active client
                 if let Some(last_inactive_client) = state.inactive.pop() {
@@ -144,6 +238,15 @@ impl Clients {
             }
         });
 
+        if removed.is_some() {
+            if let Some(mesh) = &self.0.mesh {
+                mesh.endpoint_gone(endpoint_id);
+            }
+            if self.0.clients.is_empty() {
+                self.0.on_empty.notify_waiters();
+            }
+        }
+
         // Inform peers that this endpoint is gone.
         // Done outside the remove_if_mut closure to avoid DashMap deadlocks.
         if let Some(peers) = notify_peers {
@@ -196,7 +299,107 @@ impl Clients {
         true
     }
 
+    /// Returns the number of bytes relayed from and to `endpoint_id` in the current period
+    /// of its quota.
+    ///
+    /// Returns `None` if the endpoint has no quota, see [`EndpointLimits::quota`].
+    ///
+    /// [`EndpointLimits::quota`]: super::EndpointLimits::quota
+    pub fn quota_usage(&self, endpoint_id: EndpointId) -> Option<u64> {
+        self.0.quotas.bytes_used(endpoint_id)
+    }
+
+    /// Tells all connected clients that the relay is restarting.
+    ///
+    /// Each client is asked to reconnect after a random delay of up to `reconnect_in`, to
+    /// smear out the reconnects, and to keep trying for `try_for`.
+    pub(super) fn send_restarting(&self, reconnect_in: Duration, try_for: Duration) {
+        for state in self.0.clients.iter() {
+            for client in state.inactive.iter().chain([&state.active]) {
+                if let Err(err) = client.try_send_restarting(reconnect_in, try_for) {
+                    debug!(
+                        dst = %state.key().fmt_short(),
+                        "failed to send restarting notification: {err:#}"
+                    );
+                }
+            }
+        }
+    }
+
+    /// Returns details about all client connections.
+    ///
+    /// Every connection of an endpoint is listed, including the inactive ones of an
+    /// endpoint that connected several times.
+    pub fn connections(&self) -> Vec<ConnectionInfo> {
+        let mut connections = Vec::with_capacity(self.0.clients.len());
+        for state in self.0.clients.iter() {
+            connections.push(ConnectionInfo::new(&state.active, true, &self.0.quotas));
+            for client in &state.inactive {
+                connections.push(ConnectionInfo::new(client, false, &self.0.quotas));
+            }
+        }
+        connections
+    }
+
+    /// Bans `endpoint_id` and disconnects all of its connections.
+    ///
+    /// Banned endpoints are denied when they try to connect again, until they are
+    /// unbanned with [`Self::unban`]. Bans are not persisted across restarts of the server.
+    ///
+    /// Returns `false` if the endpoint was already banned.
+    pub fn ban(&self, endpoint_id: EndpointId) -> bool {
+        let inserted = self.0.banned.insert(endpoint_id);
+        self.disconnect(endpoint_id, None);
+        inserted
+    }
+
+    /// Lifts the ban of `endpoint_id`.
+    ///
+    /// Returns `false` if the endpoint was not banned.
+    pub fn unban(&self, endpoint_id: EndpointId) -> bool {
+        self.0.banned.remove(&endpoint_id).is_some()
+    }
+
+    /// Returns `true` if `endpoint_id` is banned.
+    pub fn is_banned(&self, endpoint_id: EndpointId) -> bool {
+        self.0.banned.contains(&endpoint_id)
+    }
+
+    /// Returns all banned endpoints.
+    pub fn banned(&self) -> Vec<EndpointId> {
+        self.0.banned.iter().map(|id| *id).collect()
+    }
+
+    /// Returns the number of connected endpoints.
+    pub fn len(&self) -> usize {
+        self.0.clients.len()
+    }
+
+    /// Returns `true` if no endpoints are connected.
+    pub fn is_empty(&self) -> bool {
+        self.0.clients.is_empty()
+    }
+
+    /// Waits until no endpoints are connected.
+    pub(super) async fn wait_empty(&self) {
+        loop {
+            let notified = self.0.on_empty.notified();
+            if self.is_empty() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Returns the ids of all connected endpoints.
+    pub(super) fn endpoint_ids(&self) -> Vec<EndpointId> {
+        self.0.clients.iter().map(|x| *x.key()).collect()
+    }
+
     /// Attempt to send a packet to client with [`EndpointId`] `dst`.
+    ///
+    /// If no such client is connected, the packet is forwarded to the peer relay in the
+    /// mesh that `dst` is connected to, if any.
     pub(super) fn send_packet(
         &self,
         dst: EndpointId,
@@ -204,15 +407,74 @@ impl Clients {
         src: EndpointId,
         metrics: &Metrics,
     ) -> Result<(), ForwardPacketError> {
+        if let Some(period_start) = self.0.quotas.exceeded_period(src) {
+            debug!(src = %src.fmt_short(), "sender exceeded its quota, dropping packet");
+            metrics.send_packets_over_quota.inc();
+            if let Some(state) = self.0.clients.get(&src) {
+                state.active.notify_quota_exceeded(period_start);
+            }
+            return Ok(());
+        }
+        if let Some(mesh) = &self.0.mesh
+            && !self.0.clients.contains_key(&dst)
+        {
+            let len = data.contents.len() as u64;
+            mesh.forward(dst, data, src)?;
+            self.0.quotas.record(src, len);
+            return Ok(());
+        }
+        self.send_packet_inner(dst, data, src, metrics, true)
+    }
+
+    /// Attempt to send a packet received from a peer relay in the mesh to client `dst`.
+    ///
+    /// Unlike [`Self::send_packet`] this never forwards the packet back into the mesh.
+    pub(super) fn send_packet_from_mesh(
+        &self,
+        dst: EndpointId,
+        data: Datagrams,
+        src: EndpointId,
+        metrics: &Metrics,
+    ) -> Result<(), ForwardPacketError> {
+        // `src` is not connected here, so there is nobody to send a peer gone notification
+        // to and no point in recording where it has sent to.
+        self.send_packet_inner(dst, data, src, metrics, false)
+    }
+
+    fn send_packet_inner(
+        &self,
+        dst: EndpointId,
+        data: Datagrams,
+        src: EndpointId,
+        metrics: &Metrics,
+        record_sent_to: bool,
+    ) -> Result<(), ForwardPacketError> {
+        // Look up the weight first, holding two entries of the map can deadlock.
+        let weight = self
+            .0
+            .clients
+            .get(&src)
+            .map_or(NonZeroU32::MIN, |state| state.active.weight());
         let Some(client) = self.0.clients.get(&dst) else {
             debug!(dst = %dst.fmt_short(), "no connected client, dropped packet");
             metrics.send_packets_dropped.inc();
             return Ok(());
         };
-        match client.active.try_send_packet(src, data) {
+        if let Some(period_start) = self.0.quotas.exceeded_period(dst) {
+            debug!(dst = %dst.fmt_short(), "recipient exceeded its quota, dropping packet");
+            metrics.send_packets_over_quota.inc();
+            client.active.notify_quota_exceeded(period_start);
+            return Ok(());
+        }
+        let len = data.contents.len() as u64;
+        match client.active.try_send_packet(src, data, weight) {
             Ok(_) => {
+                self.0.quotas.record(src, len);
+                self.0.quotas.record(dst, len);
                 // Record sent_to relationship
-                self.0.sent_to.entry(src).or_default().insert(dst);
+                if record_sent_to {
+                    self.0.sent_to.entry(src).or_default().insert(dst);
+                }
                 Ok(())
             }
             Err(TrySendError::Full(_)) => {
@@ -257,7 +519,10 @@ mod tests {
         client::conn::Conn,
         http::ProtocolVersion,
         protos::{common::FrameType, relay::RelayToClientMsg, streams::WsBytesFramed},
-        server::streams::{MaybeTlsStream, RateLimited, ServerRelayedStream},
+        server::{
+            Quota,
+            streams::{MaybeTlsStream, RateLimited, ServerRelayedStream},
+        },
     };
 
     async fn recv_frame<
@@ -517,4 +782,71 @@ mod tests {
         clients.shutdown().await;
         Ok(())
     }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn test_clients_quota() -> Result {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0u64);
+        let a_key = SecretKey::from_bytes(&rng.random()).public();
+        let b_key = SecretKey::from_bytes(&rng.random()).public();
+        let quota = Quota::daily(20);
+
+        let clients = Clients::default();
+        let metrics = Arc::new(Metrics::default());
+        let (mut a1_builder, mut a1_rw) = test_client_builder(a_key);
+        a1_builder.limits.quota = Some(quota);
+        clients.register(a1_builder, metrics.clone());
+
+        // Two packets fit into the quota, the last one exceeds it.
+        let data = b"hello world!";
+        for _ in 0..2 {
+            clients.send_packet(a_key, Datagrams::from(&data[..]), b_key, &metrics)?;
+            recv_frame(FrameType::RelayToClientDatagram, &mut a1_rw).await?;
+        }
+        assert_eq!(clients.quota_usage(a_key), Some(24));
+        assert_eq!(clients.quota_usage(b_key), None);
+
+        clients.send_packet(a_key, Datagrams::from(&data[..]), b_key, &metrics)?;
+        let frame = recv_frame(FrameType::Status, &mut a1_rw).await?;
+        assert_eq!(frame, RelayToClientMsg::Status(Status::QuotaExceeded));
+        assert_eq!(metrics.send_packets_over_quota.get(), 1);
+
+        // Reconnecting does not reset the usage.
+        let a1_conn_id = clients.active_connection_id(a_key).unwrap();
+        assert!(clients.disconnect(a_key, Some(a1_conn_id)));
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while clients.active_connection_id(a_key).is_some() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .std_context("timeout")?;
+
+        let (mut a2_builder, mut a2_rw) = test_client_builder(a_key);
+        a2_builder.limits.quota = Some(quota);
+        clients.register(a2_builder, metrics.clone());
+        assert_eq!(clients.quota_usage(a_key), Some(24));
+
+        clients.send_packet(a_key, Datagrams::from(&data[..]), b_key, &metrics)?;
+        let frame = recv_frame(FrameType::Status, &mut a2_rw).await?;
+        assert_eq!(frame, RelayToClientMsg::Status(Status::QuotaExceeded));
+        assert_eq!(metrics.send_packets_over_quota.get(), 2);
+
+        // The client is told again once its quota is exceeded in a new period.
+        let period_start = clients.0.quotas.exceeded_period(a_key).unwrap();
+        {
+            let state = clients.0.clients.get(&a_key).unwrap();
+            state.active.notify_quota_exceeded(period_start);
+            state
+                .active
+                .notify_quota_exceeded(period_start.next_day().unwrap());
+        }
+        let frame = recv_frame(FrameType::Status, &mut a2_rw).await?;
+        assert_eq!(frame, RelayToClientMsg::Status(Status::QuotaExceeded));
+        let next = tokio::time::timeout(Duration::from_millis(100), a2_rw.next()).await;
+        assert!(next.is_err(), "unexpected frame {next:?}");
+
+        clients.shutdown().await;
+        Ok(())
+    }
 }
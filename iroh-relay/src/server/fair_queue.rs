@@ -0,0 +1,323 @@
+//! Weighted fair queuing of the datagrams sent to a client.
+//!
+//! Each client has a single queue of datagrams to send, which is filled by all the
+//! endpoints sending to it. To keep a single heavy sender from starving the others, the
+//! queue keeps a separate queue per sender and serves them with deficit round robin: every
+//! time a sender's turn comes up it may send up to [`QUANTUM`] bytes times its
+//! [`EndpointLimits::weight`].
+//!
+//! When the queue is full, a datagram from a sender with a shorter queue evicts the last
+//! datagram of the sender with the longest queue, relative to their weights.
+//!
+//! [`EndpointLimits::weight`]: super::EndpointLimits::weight
+
+use std::{
+    collections::{HashMap, VecDeque},
+    num::NonZeroU32,
+    sync::{Arc, Mutex},
+};
+
+use iroh_base::EndpointId;
+use tokio::sync::{Notify, mpsc::error::TrySendError};
+
+use super::{client::Packet, metrics::Metrics};
+
+/// Number of bytes a sender with weight `1` may send per round.
+const QUANTUM: usize = 8 * 1024;
+
+/// Creates a fair queue holding up to `capacity` datagrams.
+pub(super) fn channel(capacity: usize, metrics: Arc<Metrics>) -> (Sender, Receiver) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            capacity,
+            ..Default::default()
+        }),
+        notify: Notify::new(),
+        metrics,
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<State>,
+    /// Wakes up the receiver.
+    notify: Notify,
+    metrics: Arc<Metrics>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    capacity: usize,
+    /// Number of queued datagrams, across all senders.
+    len: usize,
+    queues: HashMap<EndpointId, SenderQueue>,
+    /// Senders with queued datagrams, in the order they are served.
+    active: VecDeque<EndpointId>,
+    sender_dropped: bool,
+    receiver_dropped: bool,
+}
+
+#[derive(Debug)]
+struct SenderQueue {
+    packets: VecDeque<Packet>,
+    weight: NonZeroU32,
+    /// Number of bytes the sender may still send in its current turn.
+    deficit: usize,
+}
+
+/// Returns a queue length relative to `weight`, comparable across weights.
+fn weighted_len(len: usize, weight: NonZeroU32) -> u64 {
+    (len as u64) * u64::from(u32::MAX) / u64::from(weight.get())
+}
+
+impl State {
+    /// Queues `packet`, returning it if the queue is full.
+    fn push(
+        &mut self,
+        packet: Packet,
+        weight: NonZeroU32,
+        metrics: &Metrics,
+    ) -> Result<(), Packet> {
+        let src = packet.src;
+        if self.len >= self.capacity {
+            let own_len = self.queues.get(&src).map_or(0, |queue| queue.packets.len()) + 1;
+            let own_len = weighted_len(own_len, weight);
+            let longest = self
+                .queues
+                .iter()
+                .map(|(id, queue)| (*id, weighted_len(queue.packets.len(), queue.weight)))
+                .filter(|(_, len)| *len > own_len)
+                .max_by_key(|(_, len)| *len);
+            let Some((longest, _)) = longest else {
+                return Err(packet);
+            };
+            self.pop_back(longest);
+            metrics.send_packets_evicted.inc();
+        }
+        let queue = self.queues.entry(src).or_insert_with(|| {
+            self.active.push_back(src);
+            SenderQueue {
+                packets: VecDeque::new(),
+                weight,
+                deficit: 0,
+            }
+        });
+        queue.weight = weight;
+        queue.packets.push_back(packet);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Drops the last queued datagram of `src`.
+    fn pop_back(&mut self, src: EndpointId) {
+        let Some(queue) = self.queues.get_mut(&src) else {
+            return;
+        };
+        if queue.packets.pop_back().is_some() {
+            self.len -= 1;
+        }
+        if queue.packets.is_empty() {
+            self.queues.remove(&src);
+            self.active.retain(|id| *id != src);
+        }
+    }
+
+    /// Returns the next datagram to send.
+    fn pop_front(&mut self) -> Option<Packet> {
+        loop {
+            let src = *self.active.front()?;
+            let queue = self
+                .queues
+                .get_mut(&src)
+                .expect("active senders have a queue");
+            let size = queue
+                .packets
+                .front()
+                .expect("queues of active senders are not empty")
+                .data
+                .contents
+                .len();
+            if queue.deficit < size {
+                // The sender used up its turn, it continues in the next round.
+                queue.deficit += QUANTUM * queue.weight.get() as usize;
+                self.active.rotate_left(1);
+                continue;
+            }
+            queue.deficit -= size;
+            let packet = queue.packets.pop_front();
+            self.len -= 1;
+            if queue.packets.is_empty() {
+                // Senders start each busy period with an empty deficit.
+                self.queues.remove(&src);
+                self.active.pop_front();
+            }
+            return packet;
+        }
+    }
+}
+
+/// The sending half of a fair queue, see [`channel`].
+#[derive(Debug)]
+pub(super) struct Sender {
+    shared: Arc<Shared>,
+}
+
+impl Sender {
+    /// Queues `packet`, served according to the `weight` of its sender.
+    pub(super) fn try_send(
+        &self,
+        packet: Packet,
+        weight: NonZeroU32,
+    ) -> Result<(), TrySendError<Packet>> {
+        let mut state = self.shared.state.lock().expect("poisoned");
+        if state.receiver_dropped {
+            return Err(TrySendError::Closed(packet));
+        }
+        state
+            .push(packet, weight, &self.shared.metrics)
+            .map_err(TrySendError::Full)?;
+        drop(state);
+        self.shared.notify.notify_one();
+        Ok(())
+    }
+}
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        self.shared.state.lock().expect("poisoned").sender_dropped = true;
+        self.shared.notify.notify_one();
+    }
+}
+
+/// The receiving half of a fair queue, see [`channel`].
+#[derive(Debug)]
+pub(super) struct Receiver {
+    shared: Arc<Shared>,
+}
+
+impl Receiver {
+    /// Receives the next datagram to send.
+    ///
+    /// Returns `None` once the [`Sender`] is dropped and the queue is empty. This method
+    /// is cancel safe.
+    pub(super) async fn recv(&mut self) -> Option<Packet> {
+        loop {
+            {
+                let mut state = self.shared.state.lock().expect("poisoned");
+                if let Some(packet) = state.pop_front() {
+                    return Some(packet);
+                }
+                if state.sender_dropped {
+                    return None;
+                }
+            }
+            // There is a single receiver, so a `notify_one` before we wait stores a permit
+            // and no wakeup is lost.
+            self.shared.notify.notified().await;
+        }
+    }
+}
+
+impl Drop for Receiver {
+    fn drop(&mut self) {
+        self.shared.state.lock().expect("poisoned").receiver_dropped = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh_base::SecretKey;
+
+    use super::*;
+    use crate::protos::relay::Datagrams;
+
+    fn key(n: u8) -> EndpointId {
+        SecretKey::from_bytes(&[n; 32]).public()
+    }
+
+    fn packet(src: EndpointId, size: usize) -> Packet {
+        Packet {
+            src,
+            data: Datagrams::from(vec![0u8; size]),
+        }
+    }
+
+    fn weight(n: u32) -> NonZeroU32 {
+        NonZeroU32::new(n).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_fair_queue_round_robin() {
+        let (a, b) = (key(1), key(2));
+        let (tx, mut rx) = channel(100, Default::default());
+        // `b` queues first, but `a` has twice the weight and gets twice the bandwidth.
+        for _ in 0..24 {
+            tx.try_send(packet(b, 1024), weight(1)).unwrap();
+        }
+        for _ in 0..24 {
+            tx.try_send(packet(a, 1024), weight(2)).unwrap();
+        }
+
+        let mut order = Vec::new();
+        for _ in 0..24 {
+            order.push(rx.recv().await.unwrap().src);
+        }
+        assert_eq!(order.iter().filter(|id| **id == a).count(), 16);
+        assert_eq!(order.iter().filter(|id| **id == b).count(), 8);
+        // Each turn lasts one quantum of 8 KiB per weight.
+        assert!(order[..8].iter().all(|id| *id == b));
+        assert!(order[8..].iter().all(|id| *id == a));
+    }
+
+    #[tokio::test]
+    async fn test_fair_queue_evicts_longest() {
+        let (a, b) = (key(1), key(2));
+        let metrics = Arc::new(Metrics::default());
+        let (tx, mut rx) = channel(4, metrics.clone());
+        for _ in 0..4 {
+            tx.try_send(packet(a, 10), weight(1)).unwrap();
+        }
+        // The queue is full, `a` can not push out its own datagrams.
+        assert!(matches!(
+            tx.try_send(packet(a, 10), weight(1)),
+            Err(TrySendError::Full(_))
+        ));
+        // `b` pushes out the datagrams of `a` until both have the same share.
+        tx.try_send(packet(b, 20), weight(1)).unwrap();
+        tx.try_send(packet(b, 20), weight(1)).unwrap();
+        assert!(matches!(
+            tx.try_send(packet(b, 20), weight(1)),
+            Err(TrySendError::Full(_))
+        ));
+        assert_eq!(metrics.send_packets_evicted.get(), 2);
+
+        drop(tx);
+        let mut sizes = Vec::new();
+        while let Some(packet) = rx.recv().await {
+            sizes.push(packet.data.contents.len());
+        }
+        assert_eq!(sizes, [10, 10, 20, 20]);
+    }
+
+    #[tokio::test]
+    async fn test_fair_queue_closed() {
+        let (tx, mut rx) = channel(4, Default::default());
+        let recv = tokio::spawn(async move { rx.recv().await.map(|packet| packet.src) });
+        tokio::task::yield_now().await;
+        tx.try_send(packet(key(1), 10), weight(1)).unwrap();
+        assert_eq!(recv.await.unwrap(), Some(key(1)));
+
+        let (tx, rx) = channel(4, Default::default());
+        drop(rx);
+        assert!(matches!(
+            tx.try_send(packet(key(1), 10), weight(1)),
+            Err(TrySendError::Closed(_))
+        ));
+    }
+}
@@ -6,12 +6,23 @@
 //!
 //! For a complete relay server implementation, see the parent [`server`](super) module.
 
-use std::{collections::HashMap, convert::Infallible, net::SocketAddr, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
 
 use bytes::Bytes;
 use derive_more::Debug;
 use http::{
-    header::{CONNECTION, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_PROTOCOL, SEC_WEBSOCKET_VERSION},
+    header::{
+        CONNECTION, RETRY_AFTER, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_PROTOCOL, SEC_WEBSOCKET_VERSION,
+    },
     response::Builder as ResponseBuilder,
 };
 use hyper::{
@@ -32,19 +43,23 @@ use tokio_util::{sync::CancellationToken, task::AbortOnDropHandle};
 use tracing::{Instrument, debug, error, info, info_span, trace, warn, warn_span};
 
 use super::{
-    AllowAll, ClientRequest, DynAccessControl, SpawnError, clients::Clients,
+    Access, AllowAll, ClientRequest, DrainConfig, DynAccessControl, ReloadConfig, ReloadError,
+    SpawnError,
+    accept_limit::AcceptLimiter,
+    clients::Clients,
+    mesh::{MeshConfig, MeshHandle},
     streams::InvalidBucketConfig,
 };
 use crate::{
     KeyCache,
     defaults::{DEFAULT_KEY_CACHE_CAPACITY, timeouts::SERVER_WRITE_TIMEOUT},
     http::{
-        CLIENT_AUTH_HEADER, ProtocolVersion, RELAY_PATH, SUPPORTED_WEBSOCKET_VERSION,
-        WEBSOCKET_UPGRADE_PROTOCOL,
+        CLIENT_AUTH_HEADER, ProtocolVersion, RELAY_MESH_PATH, RELAY_PATH,
+        SUPPORTED_WEBSOCKET_VERSION, WEBSOCKET_UPGRADE_PROTOCOL,
     },
-    protos::{handshake, relay::MAX_FRAME_SIZE, streams::WsBytesFramed},
+    protos::{handshake, mesh::MESH_PROTOCOL, relay::MAX_FRAME_SIZE, streams::WsBytesFramed},
     server::{
-        ClientRateLimit,
+        ClientRateLimit, EndpointLimits,
         client::Config,
         metrics::Metrics,
         streams::{MaybeTlsStream, RateLimited, RelayedStream},
@@ -309,6 +324,10 @@ pub enum AcceptError {
     Handshake { source: handshake::Error },
     #[error("rate limiting misconfigured")]
     RateLimitingMisconfigured { source: InvalidBucketConfig },
+    #[error("mesh forwarding is not enabled")]
+    MeshDisabled {},
+    #[error("endpoint is banned")]
+    Banned {},
 }
 
 /// Server connection errors, includes errors that can happen on `accept`.
@@ -348,12 +367,18 @@ pub(super) struct ServerBuilder {
     /// Rate-limiting is enforced on received traffic from individual clients.  This
     /// configuration applies to a single client connection.
     client_rx_ratelimit: Option<ClientRateLimit>,
+    /// Rate limits for accepting new relay connections.
+    accept_limiter: Option<AcceptLimiter>,
+    /// Limits for endpoints without limits decided by access control.
+    endpoint_limits: EndpointLimits,
     /// The capacity of the key cache.
     key_cache_capacity: usize,
     /// Access control for endpoints.
     access: Arc<dyn DynAccessControl>,
     metrics: Option<Arc<Metrics>>,
     establish_timeout: Duration,
+    /// Configuration for forwarding to peer relays, disabled if `None`.
+    mesh: Option<MeshConfig>,
 }
 
 impl ServerBuilder {
@@ -365,10 +390,13 @@ impl ServerBuilder {
             handlers: Default::default(),
             headers: HeaderMap::new(),
             client_rx_ratelimit: None,
+            accept_limiter: None,
+            endpoint_limits: EndpointLimits::default(),
             key_cache_capacity: DEFAULT_KEY_CACHE_CAPACITY,
             access: Arc::new(AllowAll),
             metrics: None,
             establish_timeout: ESTABLISH_TIMEOUT,
+            mesh: None,
         }
     }
 
@@ -384,6 +412,12 @@ impl ServerBuilder {
         self
     }
 
+    /// Forwards datagrams to and from the peer relays in a mesh.
+    pub(super) fn mesh(mut self, config: MeshConfig) -> Self {
+        self.mesh = Some(config);
+        self
+    }
+
     /// Serves all requests content using TLS.
     pub(super) fn tls_config(mut self, config: Option<TlsConfig>) -> Self {
         self.tls_config = config;
@@ -413,6 +447,23 @@ impl ServerBuilder {
         self
     }
 
+    /// Sets the rate limits for accepting new relay connections.
+    ///
+    /// Relay connection requests over the limits are rejected with `429 Too Many Requests`.
+    /// By default no limits are enforced.
+    pub(super) fn accept_limiter(mut self, limiter: AcceptLimiter) -> Self {
+        self.accept_limiter = Some(limiter);
+        self
+    }
+
+    /// Sets the limits for endpoints for which access control decides no limits.
+    ///
+    /// See [`AccessControl::endpoint_limits`](super::AccessControl::endpoint_limits).
+    pub(super) fn endpoint_limits(mut self, limits: EndpointLimits) -> Self {
+        self.endpoint_limits = limits;
+        self
+    }
+
     /// Adds a custom handler for a specific Method & URI.
     pub(super) fn request_handler(
         mut self,
@@ -442,14 +493,21 @@ impl ServerBuilder {
     pub(super) async fn spawn(self) -> Result<Server, SpawnError> {
         let cancel_token = CancellationToken::new();
 
-        let service = RelayService::new(
-            self.handlers,
-            self.headers,
-            self.client_rx_ratelimit,
-            KeyCache::new(self.key_cache_capacity),
-            self.access,
-            self.metrics.unwrap_or_default(),
-        );
+        let key_cache = KeyCache::new(self.key_cache_capacity);
+        let metrics = self.metrics.unwrap_or_default();
+        let mesh = self
+            .mesh
+            .map(|config| MeshHandle::spawn(config, key_cache.clone(), metrics.clone()))
+            .transpose()?;
+        let settings = Settings {
+            rate_limit: self.client_rx_ratelimit,
+            key_cache,
+            key_cache_capacity: Some(self.key_cache_capacity),
+            access: self.access,
+            accept_limiter: self.accept_limiter.map(Arc::new),
+            endpoint_limits: self.endpoint_limits,
+        };
+        let service = RelayService::with_mesh(self.handlers, self.headers, settings, metrics, mesh);
 
         let addr = self.addr;
         let tls_config = self.tls_config;
@@ -532,10 +590,26 @@ struct Inner {
     headers: HeaderMap,
     clients: Clients,
     write_timeout: Duration,
+    metrics: Arc<Metrics>,
+    mesh: Option<MeshHandle>,
+    /// Settings for new connections, replaced on [`RelayService::reload`].
+    settings: RwLock<Arc<Settings>>,
+    /// Whether the service is draining, new relay connections are rejected.
+    draining: AtomicBool,
+}
+
+/// The settings of a [`RelayService`] which can be changed while it is running.
+///
+/// Connections use the settings in place when they were accepted.
+#[derive(Debug)]
+struct Settings {
     rate_limit: Option<ClientRateLimit>,
     key_cache: KeyCache,
+    /// The capacity of the key cache, if known.
+    key_cache_capacity: Option<usize>,
     access: Arc<dyn DynAccessControl>,
-    metrics: Arc<Metrics>,
+    accept_limiter: Option<Arc<AcceptLimiter>>,
+    endpoint_limits: EndpointLimits,
 }
 
 #[stack_error(derive, add_meta)]
@@ -569,46 +643,45 @@ impl RelayServiceWithNotify {
         res
     }
 
+    /// Takes a token from the accept rate limiter, if configured.
+    fn check_accept_limit(&self) -> Result<(), Duration> {
+        match &self.service.0.settings().accept_limiter {
+            Some(limiter) => limiter.check(self.remote_ip),
+            None => Ok(()),
+        }
+    }
+
+    /// Builds the response for a relay connection request over the accept rate limits.
+    fn too_many_requests_response(
+        &self,
+        retry_after: Duration,
+    ) -> HyperResult<Response<BytesBody>> {
+        debug!(remote_ip = ?self.remote_ip, "rejecting relay connection: accept rate limit exceeded");
+        self.service.0.metrics.http_accepts_ratelimited.inc();
+        let retry_after = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+        self.build_response()
+            .status(StatusCode::TOO_MANY_REQUESTS)
+            .header(RETRY_AFTER, retry_after)
+            .body(body_full("Too many connection attempts"))
+            .map_err(Into::into)
+    }
+
+    /// Builds the response for a relay connection request while draining.
+    fn service_unavailable_response(&self) -> HyperResult<Response<BytesBody>> {
+        debug!(remote_ip = ?self.remote_ip, "rejecting relay connection: draining");
+        self.service.0.metrics.http_accepts_draining.inc();
+        self.build_response()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .body(body_full("Relay is draining"))
+            .map_err(Into::into)
+    }
+
     /// Upgrades the HTTP connection to the relay protocol, runs relay client.
     fn handle_relay_ws_upgrade(
         &self,
         mut req: Request<Incoming>,
     ) -> Result<Response<BytesBody>, RelayUpgradeReqError> {
-        fn expect_header(
-            req: &Request<Incoming>,
-            header: http::HeaderName,
-        ) -> Result<&HeaderValue, RelayUpgradeReqError> {
-            req.headers()
-                .get(&header)
-                .ok_or_else(|| e!(RelayUpgradeReqError::MissingHeader { header }))
-        }
-
-        let upgrade_header = expect_header(&req, UPGRADE)?;
-        ensure!(
-            upgrade_header == HeaderValue::from_static(WEBSOCKET_UPGRADE_PROTOCOL),
-            RelayUpgradeReqError::InvalidHeader {
-                header: UPGRADE,
-                details: format!("value must be {WEBSOCKET_UPGRADE_PROTOCOL}")
-            }
-        );
-
-        let key = expect_header(&req, SEC_WEBSOCKET_KEY)?.clone();
-        let version = expect_header(&req, SEC_WEBSOCKET_VERSION)?.clone();
-
-        ensure!(
-            version.as_bytes() == SUPPORTED_WEBSOCKET_VERSION.as_bytes(),
-            RelayUpgradeReqError::UnsupportedWebsocketVersion
-        );
-
-        let subprotocols = expect_header(&req, SEC_WEBSOCKET_PROTOCOL)?
-            .to_str()
-            .ok()
-            .ok_or_else(|| {
-                e!(RelayUpgradeReqError::InvalidHeader {
-                    header: SEC_WEBSOCKET_PROTOCOL,
-                    details: "header value is not ascii".to_string()
-                })
-            })?;
+        let (key, subprotocols) = expect_ws_upgrade(&req)?;
         let protocol_version = subprotocols
             .split(",")
             .map(|s| s.trim())
@@ -657,19 +730,112 @@ impl RelayServiceWithNotify {
 
         // Now return a 101 Response saying we agree to the upgrade to the
         // websocket upgrade protocol
-        Ok(self
-            .build_response()
+        Ok(self.switching_protocols_response(&key, protocol_version.to_header_value()))
+    }
+
+    /// Upgrades the HTTP connection to the mesh protocol, runs the mesh link.
+    fn handle_mesh_ws_upgrade(
+        &self,
+        mut req: Request<Incoming>,
+    ) -> Result<Response<BytesBody>, RelayUpgradeReqError> {
+        let (key, subprotocols) = expect_ws_upgrade(&req)?;
+        ensure!(
+            subprotocols.split(",").any(|s| s.trim() == MESH_PROTOCOL),
+            RelayUpgradeReqError::UnsupportedRelayVersion {
+                we_support: MESH_PROTOCOL.to_string(),
+                you_support: subprotocols.to_string()
+            }
+        );
+
+        tokio::task::spawn({
+            let this = self.clone();
+            async move {
+                match hyper::upgrade::on(&mut req).await {
+                    Ok(upgraded) => {
+                        let (parts, _) = req.into_parts();
+                        if let Err(err) = this
+                            .service
+                            .0
+                            .mesh_connection_handler(upgraded, parts)
+                            .await
+                        {
+                            warn!("error accepting upgraded mesh connection: {err:#}",);
+                        } else {
+                            this.on_establish.notify_waiters();
+                            debug!("upgraded mesh connection completed");
+                        };
+                    }
+                    Err(err) => warn!("upgrade error: {err:#}"),
+                }
+            }
+            .instrument(warn_span!("mesh-handler"))
+        });
+
+        Ok(self.switching_protocols_response(&key, HeaderValue::from_static(MESH_PROTOCOL)))
+    }
+
+    /// Builds the 101 response agreeing to upgrade to a websocket with `protocol`.
+    fn switching_protocols_response(
+        &self,
+        key: &HeaderValue,
+        protocol: HeaderValue,
+    ) -> Response<BytesBody> {
+        self.build_response()
             .status(StatusCode::SWITCHING_PROTOCOLS)
             .header(
                 UPGRADE,
                 HeaderValue::from_static(WEBSOCKET_UPGRADE_PROTOCOL),
             )
-            .header(SEC_WEBSOCKET_ACCEPT, derive_accept_key(&key))
-            .header(SEC_WEBSOCKET_PROTOCOL, protocol_version.to_header_value())
+            .header(SEC_WEBSOCKET_ACCEPT, derive_accept_key(key))
+            .header(SEC_WEBSOCKET_PROTOCOL, protocol)
             .header(CONNECTION, "upgrade")
             .body(body_full("switching to websocket protocol"))
-            .expect("valid body"))
+            .expect("valid body")
+    }
+}
+
+/// Validates the headers of a websocket upgrade request.
+///
+/// Returns the websocket key and the offered sub-protocols.
+fn expect_ws_upgrade(
+    req: &Request<Incoming>,
+) -> Result<(HeaderValue, String), RelayUpgradeReqError> {
+    fn expect_header(
+        req: &Request<Incoming>,
+        header: http::HeaderName,
+    ) -> Result<&HeaderValue, RelayUpgradeReqError> {
+        req.headers()
+            .get(&header)
+            .ok_or_else(|| e!(RelayUpgradeReqError::MissingHeader { header }))
     }
+
+    let upgrade_header = expect_header(req, UPGRADE)?;
+    ensure!(
+        upgrade_header == HeaderValue::from_static(WEBSOCKET_UPGRADE_PROTOCOL),
+        RelayUpgradeReqError::InvalidHeader {
+            header: UPGRADE,
+            details: format!("value must be {WEBSOCKET_UPGRADE_PROTOCOL}")
+        }
+    );
+
+    let key = expect_header(req, SEC_WEBSOCKET_KEY)?.clone();
+    let version = expect_header(req, SEC_WEBSOCKET_VERSION)?.clone();
+
+    ensure!(
+        version.as_bytes() == SUPPORTED_WEBSOCKET_VERSION.as_bytes(),
+        RelayUpgradeReqError::UnsupportedWebsocketVersion
+    );
+
+    let subprotocols = expect_header(req, SEC_WEBSOCKET_PROTOCOL)?
+        .to_str()
+        .ok()
+        .ok_or_else(|| {
+            e!(RelayUpgradeReqError::InvalidHeader {
+                header: SEC_WEBSOCKET_PROTOCOL,
+                details: "header value is not ascii".to_string()
+            })
+        })?;
+    Ok((key, subprotocols.to_string()))
 }
 
 /// Combines [`RelayService`] with a notification token.
@@ -724,6 +890,7 @@ impl RelayServiceWithNotify {
 pub struct RelayServiceWithNotify {
     service: RelayService,
     on_establish: Arc<Notify>,
+    remote_ip: Option<IpAddr>,
 }
 
 impl RelayServiceWithNotify {
@@ -735,8 +902,17 @@ impl RelayServiceWithNotify {
         Self {
             service,
             on_establish,
+            remote_ip: None,
         }
     }
+
+    /// Sets the IP address of the remote end of the connection.
+    ///
+    /// Used to apply per-IP limits. Without it, only the global limits apply.
+    pub fn with_remote_ip(mut self, remote_ip: IpAddr) -> Self {
+        self.remote_ip = Some(remote_ip);
+        self
+    }
 }
 
 impl Service<Request<Incoming>> for RelayServiceWithNotify {
@@ -746,11 +922,29 @@ impl Service<Request<Incoming>> for RelayServiceWithNotify {
 
     fn call(&self, req: Request<Incoming>) -> Self::Future {
         // Create a client if the request hits the relay endpoint.
-        if matches!(
-            (req.method(), req.uri().path()),
-            (&hyper::Method::GET, RELAY_PATH)
-        ) {
-            let response = match self.handle_relay_ws_upgrade(req) {
+        let is_mesh = self.service.0.mesh.is_some()
+            && matches!(
+                (req.method(), req.uri().path()),
+                (&hyper::Method::GET, RELAY_MESH_PATH)
+            );
+        if is_mesh
+            || matches!(
+                (req.method(), req.uri().path()),
+                (&hyper::Method::GET, RELAY_PATH)
+            )
+        {
+            if !is_mesh && self.service.is_draining() {
+                return std::future::ready(self.service_unavailable_response());
+            }
+            if !is_mesh && let Err(retry_after) = self.check_accept_limit() {
+                return std::future::ready(self.too_many_requests_response(retry_after));
+            }
+            let response = if is_mesh {
+                self.handle_mesh_ws_upgrade(req)
+            } else {
+                self.handle_relay_ws_upgrade(req)
+            };
+            let response = match response {
                 Ok(response) => Ok(response),
                 // It's convention to send back the version(s) we *do* support
                 Err(e @ RelayUpgradeReqError::UnsupportedWebsocketVersion { .. }) => self
@@ -790,6 +984,11 @@ impl Service<Request<Incoming>> for RelayServiceWithNotify {
 }
 
 impl Inner {
+    /// Returns the current settings for new connections.
+    fn settings(&self) -> Arc<Settings> {
+        self.settings.read().expect("poisoned").clone()
+    }
+
     fn default_response(&self) -> ResponseBuilder {
         let mut response = Response::builder();
         for (key, value) in self.headers.iter() {
@@ -853,8 +1052,10 @@ impl Inner {
         // Set the socket to NO_DELAY.
         io.disable_nagle();
 
-        let io = RateLimited::from_cfg(self.rate_limit, io, self.metrics.clone())
+        let settings = self.settings();
+        let io = RateLimited::from_cfg(settings.rate_limit, io, self.metrics.clone())
             .map_err(|err| e!(AcceptError::RateLimitingMisconfigured, err))?;
+        let stats = io.stats();
 
         // Create a server builder with default config
         let websocket = tokio_websockets::ServerBuilder::new()
@@ -872,21 +1073,37 @@ impl Inner {
         let request =
             ClientRequest::new(authentication.client_key, protocol_version, request_parts);
 
+        if self.clients.is_banned(request.endpoint_id()) {
+            let access = Access::Deny {
+                reason: Some("endpoint is banned".to_string()),
+            };
+            authentication.authorize_if(access, &mut io).await?;
+            return Err(e!(AcceptError::Banned));
+        }
+
         // Authorize the request against the configured `AccessControl`.
         let guard = authentication
-            .authorize_with(&request, &self.access, &mut io)
+            .authorize_with(&request, &settings.access, &mut io)
             .await?;
 
         trace!("accept: verified authorization");
 
+        let limits = settings
+            .access
+            .endpoint_limits(&request)
+            .await
+            .unwrap_or(settings.endpoint_limits);
+
         let io = RelayedStream {
             inner: io,
-            key_cache: self.key_cache.clone(),
+            key_cache: settings.key_cache.clone(),
         };
 
         trace!("accept: build client conn");
         let mut client_conn_builder = Config::new(guard, io, protocol_version);
         client_conn_builder.write_timeout = self.write_timeout;
+        client_conn_builder.limits = limits;
+        client_conn_builder.stats = stats;
         trace!(endpoint_id = %request.endpoint_id().fmt_short(), "create client");
 
         // build and register client, starting up read & write loops for the client
@@ -895,6 +1112,56 @@ impl Inner {
             .register(client_conn_builder, self.metrics.clone());
         Ok(())
     }
+
+    /// The server HTTP handler for mesh links from peer relays.
+    async fn mesh_connection_handler(
+        &self,
+        upgraded: Upgraded,
+        request_parts: http::request::Parts,
+    ) -> Result<(), ConnectionHandlerError> {
+        debug!("mesh connection upgraded");
+        let (io, read_buf) = downcast_upgrade(upgraded)?;
+        if !read_buf.is_empty() {
+            return Err(e!(ConnectionHandlerError::BufferNotEmpty { buf: read_buf }));
+        }
+
+        self.accept_mesh(io, request_parts).await?;
+        Ok(())
+    }
+
+    /// Authenticates a peer relay and starts serving its mesh link.
+    ///
+    /// Only relays listed as mesh peers are admitted.  Mesh links are not rate-limited.
+    async fn accept_mesh(
+        &self,
+        io: MaybeTlsStream,
+        request_parts: http::request::Parts,
+    ) -> Result<(), AcceptError> {
+        let Some(mesh) = self.mesh.as_ref().map(MeshHandle::mesh) else {
+            return Err(e!(AcceptError::MeshDisabled));
+        };
+
+        io.disable_nagle();
+        let websocket = tokio_websockets::ServerBuilder::new()
+            .limits(tokio_websockets::Limits::default().max_payload_len(Some(MAX_FRAME_SIZE)))
+            .serve(io);
+        let mut io = WsBytesFramed { io: websocket };
+
+        let client_auth_header = request_parts.headers.get(CLIENT_AUTH_HEADER).cloned();
+        let authentication = handshake::serverside(&mut io, client_auth_header).await?;
+        let access = if mesh.is_peer(&authentication.client_key) {
+            Access::Allow
+        } else {
+            Access::Deny {
+                reason: Some("not a mesh peer".to_string()),
+            }
+        };
+        let peer = authentication.authorize_if(access, &mut io).await?;
+
+        trace!(peer = %peer.fmt_short(), "accept: mesh peer authorized");
+        mesh.accept_link(peer, io, self.clients.clone());
+        Ok(())
+    }
 }
 
 /// TLS Certificate Authority acceptor.
@@ -919,23 +1186,118 @@ impl RelayService {
         access: Arc<dyn DynAccessControl>,
         metrics: Arc<Metrics>,
     ) -> Self {
+        let settings = Settings {
+            rate_limit,
+            key_cache,
+            key_cache_capacity: None,
+            access,
+            accept_limiter: None,
+            endpoint_limits: EndpointLimits::default(),
+        };
+        Self::with_mesh(handlers, headers, settings, metrics, None)
+    }
+
+    /// Creates a new RelayService that forwards datagrams through `mesh`, if set.
+    fn with_mesh(
+        handlers: Handlers,
+        headers: HeaderMap,
+        settings: Settings,
+        metrics: Arc<Metrics>,
+        mesh: Option<MeshHandle>,
+    ) -> Self {
+        let clients = match mesh {
+            Some(ref mesh) => Clients::with_mesh(mesh.mesh().clone()),
+            None => Clients::default(),
+        };
         Self(Arc::new(Inner {
             handlers,
             headers,
-            clients: Clients::default(),
+            clients,
             write_timeout: SERVER_WRITE_TIMEOUT,
-            rate_limit,
-            key_cache,
-            access,
             metrics,
+            mesh,
+            settings: RwLock::new(Arc::new(settings)),
+            draining: AtomicBool::new(false),
         }))
     }
 
-    /// Shuts down the relay service, disconnecting all clients.
+    /// Replaces the access control and limits for new connections.
+    ///
+    /// Established connections are not affected, they keep the access control and limits
+    /// they were accepted with.  The key cache is only replaced if its capacity changed,
+    /// and the accept rate limiter only if the accept limits changed, so that reloading
+    /// does not refill the token buckets.
+    ///
+    /// The mesh keeps the key cache it was spawned with, so a changed key cache capacity
+    /// only applies to client connections.
+    ///
+    /// If the new limits are invalid, an error is returned and the current settings are
+    /// kept.
+    pub fn reload(&self, config: ReloadConfig) -> Result<(), ReloadError> {
+        let ReloadConfig {
+            limits,
+            key_cache_capacity,
+            access,
+        } = config;
+        let accept_limiter =
+            AcceptLimiter::new(&limits).map_err(|err| e!(ReloadError::Limits, err))?;
+        let key_cache_capacity = key_cache_capacity.unwrap_or(DEFAULT_KEY_CACHE_CAPACITY);
+
+        let mut settings = self.0.settings.write().expect("poisoned");
+        let key_cache = if settings.key_cache_capacity == Some(key_cache_capacity) {
+            settings.key_cache.clone()
+        } else {
+            KeyCache::new(key_cache_capacity)
+        };
+        let accept_limiter = match (accept_limiter, &settings.accept_limiter) {
+            (Some(new), Some(current)) if new.has_same_rates(current) => Some(current.clone()),
+            (new, _) => new.map(Arc::new),
+        };
+        *settings = Arc::new(Settings {
+            rate_limit: limits.client_rx,
+            key_cache,
+            key_cache_capacity: Some(key_cache_capacity),
+            access,
+            accept_limiter,
+            endpoint_limits: limits.endpoint,
+        });
+        Ok(())
+    }
+
+    /// Shuts down the relay service, disconnecting all clients and mesh links.
     pub async fn shutdown(&self) {
+        if let Some(mesh) = &self.0.mesh {
+            mesh.mesh().shutdown();
+        }
         self.0.clients.shutdown().await;
     }
 
+    /// Starts draining the relay service.
+    ///
+    /// New relay connections are rejected with `503 Service Unavailable` from now on, and
+    /// all connected clients are told that the relay is restarting, see
+    /// [`DrainConfig`]. Use [`Self::drained`] to wait for the clients to leave.
+    pub fn start_drain(&self, config: &DrainConfig) {
+        self.0.draining.store(true, Ordering::Relaxed);
+        info!(
+            clients = self.0.clients.len(),
+            "draining relay, asking clients to reconnect elsewhere"
+        );
+        self.0
+            .clients
+            .send_restarting(config.reconnect_in, config.try_for);
+    }
+
+    /// Returns whether [`Self::start_drain`] was called.
+    pub fn is_draining(&self) -> bool {
+        self.0.draining.load(Ordering::Relaxed)
+    }
+
+    /// Waits until all clients disconnected.
+    pub async fn drained(&self) {
+        self.0.clients.wait_empty().await
+    }
+
     /// Returns a reference to the registry of currently connected clients.
     ///
     /// The returned [`Clients`] handle can be used at runtime to disconnect a
@@ -1013,7 +1375,10 @@ impl RelayService {
         // We create a notification token to be triggered once the connection is fully established
         // and passed to the relay server.
         let on_establish = Arc::new(Notify::new());
-        let service = RelayServiceWithNotify::new(self, on_establish.clone());
+        let mut service = RelayServiceWithNotify::new(self, on_establish.clone());
+        if let Ok(addr) = stream.peer_addr() {
+            service = service.with_remote_ip(addr.ip());
+        }
 
         // This is the main connection future, driving the connection to completion.
         let serve_fut = async move {
@@ -1798,4 +2163,41 @@ mod tests {
         server.shutdown();
         Ok(())
     }
+
+    #[test]
+    fn test_reload_keeps_accept_limiter() -> Result {
+        let service = RelayService::new(
+            Default::default(),
+            Default::default(),
+            None,
+            KeyCache::test(),
+            Arc::new(AllowAll),
+            Arc::new(Metrics::default()),
+        );
+        let limiter = || service.0.settings().accept_limiter.clone();
+        let reload = |limit: f64| {
+            let mut config = ReloadConfig::default();
+            config.limits.accept_conn_limit = Some(limit);
+            service.reload(config)
+        };
+
+        reload(1.0)?;
+        let first = limiter().expect("accept limiter");
+        // Exhaust the bucket, a reload with the same limits must not refill it.
+        first.check(None).expect("first token");
+        reload(1.0)?;
+        let second = limiter().expect("accept limiter");
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(second.check(None).is_err());
+
+        // Changed limits replace the limiter.
+        reload(2.0)?;
+        let third = limiter().expect("accept limiter");
+        assert!(!Arc::ptr_eq(&second, &third));
+
+        // Removing the limits removes the limiter.
+        service.reload(ReloadConfig::default())?;
+        assert!(limiter().is_none());
+        Ok(())
+    }
 }
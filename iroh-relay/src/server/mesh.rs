@@ -0,0 +1,736 @@
+//! Forwarding of datagrams between relay servers in a mesh.
+//!
+//! Several relay servers can be configured as a mesh, for example to serve a single relay
+//! hostname from multiple instances behind a load balancer.  Each relay dials a mesh link to
+//! every peer relay on [`RELAY_MESH_PATH`], authenticating with its [`MeshConfig::secret_key`].
+//! A relay only accepts mesh links from relays listed in its [`MeshConfig::peers`], and the
+//! dialing relay in turn makes the accepting relay prove that it owns the key listed for it,
+//! so neither side trusts routes or datagrams from relays outside the mesh.  Peer relays must
+//! be reachable over TLS, as the links are not protected otherwise.
+//!
+//! On every accepted link the relay announces which endpoints are connected to it.  The
+//! dialing relay records these as routes, and when one of its clients sends datagrams to an
+//! endpoint that is not connected locally it forwards them over the link to the relay the
+//! endpoint is connected to.  Datagrams received over a mesh link are only ever delivered to
+//! locally connected clients, never forwarded again.
+//!
+//! [`RELAY_MESH_PATH`]: crate::http::RELAY_MESH_PATH
+
+use std::{collections::HashSet, sync::Arc};
+
+use dashmap::DashMap;
+use iroh_base::{EndpointId, RelayUrl, SecretKey};
+use iroh_dns::dns::DnsResolver;
+use n0_error::{e, stack_error};
+use n0_future::{
+    SinkExt, StreamExt,
+    time::{self, Duration, MissedTickBehavior},
+};
+use rand::RngExt;
+use tokio::sync::{
+    broadcast,
+    mpsc::{self, error::TrySendError},
+};
+use tokio_util::{sync::CancellationToken, task::AbortOnDropHandle};
+use tracing::{Instrument, debug, info_span, trace, warn};
+
+use super::{
+    SpawnError,
+    client::{ForwardPacketError, SendError},
+    clients::Clients,
+    metrics::Metrics,
+};
+use crate::{
+    KeyCache, PingTracker,
+    client::ClientBuilder,
+    defaults::timeouts::SERVER_WRITE_TIMEOUT,
+    protos::{
+        common::FrameType,
+        mesh::{MeshMsg, verify_peer_auth},
+        relay::{Datagrams, Error as ProtoError, PER_CLIENT_SEND_QUEUE_DEPTH, PING_INTERVAL},
+        streams::{BytesStreamSink, StreamError},
+    },
+    tls::{CaTlsConfig, default_provider},
+};
+
+/// Delay before redialing a peer relay after the first failed attempt.
+const MIN_REDIAL_DELAY: Duration = Duration::from_millis(500);
+/// Maximum delay between attempts to dial a peer relay.
+const MAX_REDIAL_DELAY: Duration = Duration::from_secs(30);
+/// Time for a peer relay to answer the challenge on a new mesh link.
+const PEER_AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+/// Number of presence announcements buffered for each accepted mesh link.
+///
+/// A link that falls further behind is closed, the peer relay redials it and receives a
+/// fresh list of connected endpoints.
+const PRESENCE_QUEUE_DEPTH: usize = 1024;
+
+/// Configuration for forwarding datagrams between relays in a mesh.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct MeshConfig {
+    /// The secret key this relay authenticates with to its peer relays.
+    ///
+    /// The public key needs to be listed in [`Self::peers`] of every other relay in the mesh.
+    pub secret_key: SecretKey,
+    /// The relays in the mesh.
+    ///
+    /// An entry for this relay itself, identified by the public key of [`Self::secret_key`],
+    /// is ignored, so all relays in a mesh can share the same list.
+    pub peers: Vec<MeshPeer>,
+    /// Configures how the TLS certificates of peer relays are verified.
+    pub tls_config: CaTlsConfig,
+    /// INSECURE: Allows peer relays with `http://` URLs.
+    ///
+    /// Without TLS anyone on the network path can read and tamper with mesh links.
+    /// May only be used in tests or local development setups.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub insecure_allow_http: bool,
+}
+
+impl MeshConfig {
+    /// Creates a new [`MeshConfig`] with the given secret key and peers.
+    ///
+    /// Peer certificates are verified with the default [`CaTlsConfig`].
+    pub fn new(secret_key: SecretKey, peers: Vec<MeshPeer>) -> Self {
+        Self {
+            secret_key,
+            peers,
+            tls_config: CaTlsConfig::default(),
+            #[cfg(any(test, feature = "test-utils"))]
+            insecure_allow_http: false,
+        }
+    }
+}
+
+/// A peer relay in a mesh.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MeshPeer {
+    /// The id the peer relay authenticates with, i.e. the public key of its
+    /// [`MeshConfig::secret_key`].
+    pub endpoint_id: EndpointId,
+    /// The URL under which the peer relay is reachable.
+    ///
+    /// This must address the individual relay instance, not a load balancer in front of
+    /// the mesh, and use `https`.
+    pub url: RelayUrl,
+}
+
+impl MeshPeer {
+    /// Creates a new [`MeshPeer`].
+    pub fn new(endpoint_id: EndpointId, url: RelayUrl) -> Self {
+        Self { endpoint_id, url }
+    }
+}
+
+/// Errors on a mesh link.
+#[stack_error(derive, add_meta, from_sources)]
+#[allow(missing_docs)]
+#[non_exhaustive]
+pub(crate) enum LinkError {
+    #[error(transparent)]
+    Protocol { source: ProtoError },
+    #[error("Stream error")]
+    Stream { source: StreamError },
+    #[error("Write timed out")]
+    WriteTimeout {
+        #[error(std_err)]
+        source: time::Elapsed,
+    },
+    #[error("Unexpected frame: {frame_type:?}")]
+    UnexpectedFrame { frame_type: FrameType },
+    #[error("Ping timed out")]
+    PingTimeout {},
+    #[error("Lagged behind on presence announcements")]
+    Lagged {},
+    #[error("Peer relay did not answer the challenge in time")]
+    PeerAuthTimeout {},
+    #[error("Peer relay failed to prove its identity")]
+    PeerAuthFailed {},
+}
+
+/// Routing state shared between the mesh links and the client registry.
+#[derive(Debug, Clone)]
+pub(crate) struct Mesh(Arc<Inner>);
+
+#[derive(Debug)]
+struct Inner {
+    /// Relays allowed to dial a mesh link to us.
+    peers: HashSet<EndpointId>,
+    /// The peer relay each remote endpoint is connected to.
+    routes: DashMap<EndpointId, EndpointId>,
+    /// Send queues of the links we dialed, by peer relay.
+    links: DashMap<EndpointId, mpsc::Sender<MeshMsg>>,
+    /// Announcements of locally connected and disconnected endpoints.
+    presence: broadcast::Sender<MeshMsg>,
+    /// Cancelled once the relay shuts down.
+    shutdown: CancellationToken,
+    /// The key this relay proves its identity with on accepted links.
+    secret_key: SecretKey,
+    key_cache: KeyCache,
+    metrics: Arc<Metrics>,
+}
+
+/// Owns the tasks dialing the peer relays of a [`Mesh`].
+///
+/// Dropping this stops the dialers.
+#[derive(Debug)]
+pub(crate) struct MeshHandle {
+    mesh: Mesh,
+    _dialers: Vec<AbortOnDropHandle<()>>,
+}
+
+impl MeshHandle {
+    /// Starts dialing the peer relays in `config`.
+    pub(crate) fn spawn(
+        config: MeshConfig,
+        key_cache: KeyCache,
+        metrics: Arc<Metrics>,
+    ) -> Result<Self, SpawnError> {
+        let own_id = config.secret_key.public();
+        let peers: Vec<_> = config
+            .peers
+            .into_iter()
+            .filter(|peer| peer.endpoint_id != own_id)
+            .collect();
+        #[cfg(any(test, feature = "test-utils"))]
+        let allow_http = config.insecure_allow_http;
+        #[cfg(not(any(test, feature = "test-utils")))]
+        let allow_http = false;
+        if let Some(peer) = peers
+            .iter()
+            .find(|peer| peer.url.scheme() != "https" && !allow_http)
+        {
+            return Err(e!(SpawnError::InsecureMeshPeer {
+                url: peer.url.clone()
+            }));
+        }
+        let tls_client_config = config
+            .tls_config
+            .client_config(default_provider())
+            .map_err(|err| e!(SpawnError::MeshTlsConfig, err))?;
+        let (presence, _) = broadcast::channel(PRESENCE_QUEUE_DEPTH);
+        let mesh = Mesh(Arc::new(Inner {
+            peers: peers.iter().map(|peer| peer.endpoint_id).collect(),
+            routes: DashMap::new(),
+            links: DashMap::new(),
+            presence,
+            shutdown: CancellationToken::new(),
+            secret_key: config.secret_key.clone(),
+            key_cache,
+            metrics,
+        }));
+        let dialers = peers
+            .into_iter()
+            .map(|peer| {
+                let span = info_span!("mesh-dialer", peer = %peer.endpoint_id.fmt_short());
+                let client =
+                    ClientBuilder::new(peer.url, config.secret_key.clone(), DnsResolver::new())
+                        .tls_client_config(tls_client_config.clone());
+                let mesh = mesh.clone();
+                let task = tokio::spawn(
+                    async move {
+                        let shutdown = mesh.0.shutdown.clone();
+                        shutdown
+                            .run_until_cancelled(mesh.run_dialer(peer.endpoint_id, client))
+                            .await;
+                    }
+                    .instrument(span),
+                );
+                AbortOnDropHandle::new(task)
+            })
+            .collect();
+        Ok(Self {
+            mesh,
+            _dialers: dialers,
+        })
+    }
+
+    pub(crate) fn mesh(&self) -> &Mesh {
+        &self.mesh
+    }
+}
+
+impl Mesh {
+    /// Stops all mesh links.
+    pub(crate) fn shutdown(&self) {
+        self.0.shutdown.cancel();
+    }
+
+    /// Returns whether `endpoint_id` is a peer relay allowed to dial a mesh link to us.
+    pub(crate) fn is_peer(&self, endpoint_id: &EndpointId) -> bool {
+        self.0.peers.contains(endpoint_id)
+    }
+
+    /// Announces to all peer relays that `endpoint_id` connected to this relay.
+    pub(super) fn endpoint_connected(&self, endpoint_id: EndpointId) {
+        self.0
+            .presence
+            .send(MeshMsg::EndpointConnected(endpoint_id))
+            .ok();
+    }
+
+    /// Announces to all peer relays that `endpoint_id` disconnected from this relay.
+    pub(super) fn endpoint_gone(&self, endpoint_id: EndpointId) {
+        self.0
+            .presence
+            .send(MeshMsg::EndpointGone(endpoint_id))
+            .ok();
+    }
+
+    /// Forwards datagrams to the peer relay `dst` is connected to.
+    ///
+    /// Drops the datagrams if `dst` is not known to be connected to any peer relay.
+    pub(super) fn forward(
+        &self,
+        dst: EndpointId,
+        datagrams: Datagrams,
+        src: EndpointId,
+    ) -> Result<(), ForwardPacketError> {
+        let route = self.0.routes.get(&dst).map(|peer| *peer);
+        let Some((peer, link)) = route.and_then(|peer| Some((peer, self.0.links.get(&peer)?)))
+        else {
+            debug!(dst = %dst.fmt_short(), "no connected client or mesh route, dropped packet");
+            self.0.metrics.send_packets_dropped.inc();
+            return Ok(());
+        };
+        let msg = MeshMsg::Datagrams {
+            dst_endpoint_id: dst,
+            src_endpoint_id: src,
+            datagrams,
+        };
+        let res = match link.try_send(msg) {
+            Ok(()) => {
+                self.0.metrics.mesh_packets_forwarded.inc();
+                return Ok(());
+            }
+            Err(TrySendError::Full(_)) => {
+                debug!(
+                    dst = %dst.fmt_short(),
+                    peer = %peer.fmt_short(),
+                    "mesh link too busy, dropping packet"
+                );
+                ForwardPacketError::new(SendError::Full)
+            }
+            Err(TrySendError::Closed(_)) => {
+                debug!(
+                    dst = %dst.fmt_short(),
+                    peer = %peer.fmt_short(),
+                    "mesh link closed, dropping packet"
+                );
+                ForwardPacketError::new(SendError::Closed)
+            }
+        };
+        self.0.metrics.mesh_packets_dropped.inc();
+        Err(res)
+    }
+
+    /// Serves a mesh link dialed by the peer relay `peer`.
+    ///
+    /// Announces the endpoints connected to this relay and delivers the datagrams received
+    /// from the peer to them.
+    pub(crate) fn accept_link<S>(&self, peer: EndpointId, stream: S, clients: Clients)
+    where
+        S: BytesStreamSink + Send + 'static,
+    {
+        let mesh = self.clone();
+        tokio::spawn(
+            async move {
+                mesh.0.metrics.mesh_links.inc();
+                let shutdown = mesh.0.shutdown.clone();
+                match shutdown
+                    .run_until_cancelled(mesh.run_accepted_link(stream, &clients))
+                    .await
+                {
+                    Some(Err(err)) => debug!("mesh link closed: {err:#}"),
+                    Some(Ok(())) => debug!("mesh link closed by peer"),
+                    None => trace!("mesh link cancelled"),
+                }
+                mesh.0.metrics.mesh_links_closed.inc();
+            }
+            .instrument(info_span!("mesh-link", peer = %peer.fmt_short())),
+        );
+    }
+
+    async fn run_dialer(self, peer: EndpointId, client: ClientBuilder) {
+        let mut delay = MIN_REDIAL_DELAY;
+        loop {
+            match client.connect_mesh().await {
+                Ok(mut stream) => match self.authenticate_peer(peer, &mut stream).await {
+                    Ok(()) => {
+                        debug!("mesh link established");
+                        delay = MIN_REDIAL_DELAY;
+                        self.0.metrics.mesh_links.inc();
+                        match self.run_dialed_link(peer, stream).await {
+                            Err(err) => debug!("mesh link closed: {err:#}"),
+                            Ok(()) => debug!("mesh link closed by peer"),
+                        }
+                        self.0.metrics.mesh_links_closed.inc();
+                    }
+                    Err(err) => warn!("mesh peer failed to authenticate: {err:#}"),
+                },
+                Err(err) => warn!("failed to dial mesh peer: {err:#}"),
+            }
+            time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_REDIAL_DELAY);
+        }
+    }
+
+    /// Makes the relay at the other end of a mesh link we dialed prove that it is `peer`.
+    async fn authenticate_peer<S: BytesStreamSink>(
+        &self,
+        peer: EndpointId,
+        stream: &mut S,
+    ) -> Result<(), LinkError> {
+        let challenge: [u8; 16] = rand::rng().random();
+        write_msg(stream, MeshMsg::PeerChallenge(challenge)).await?;
+        let frame = time::timeout(PEER_AUTH_TIMEOUT, stream.next())
+            .await
+            .map_err(|_| e!(LinkError::PeerAuthTimeout))?
+            .ok_or_else(|| e!(LinkError::PeerAuthFailed))??;
+        match MeshMsg::from_bytes(frame, &self.0.key_cache)? {
+            MeshMsg::PeerAuth(signature) if verify_peer_auth(&peer, &challenge, &signature) => {
+                Ok(())
+            }
+            MeshMsg::PeerAuth(_) => Err(e!(LinkError::PeerAuthFailed)),
+            msg => Err(e!(LinkError::UnexpectedFrame {
+                frame_type: msg.typ()
+            })),
+        }
+    }
+
+    /// Runs a mesh link we dialed until it closes.
+    ///
+    /// Records the routes announced by the peer and sends the datagrams forwarded to it.
+    async fn run_dialed_link<S: BytesStreamSink>(
+        &self,
+        peer: EndpointId,
+        mut stream: S,
+    ) -> Result<(), LinkError> {
+        let (send_queue, mut packets) = mpsc::channel(PER_CLIENT_SEND_QUEUE_DEPTH);
+        self.0.links.insert(peer, send_queue);
+
+        let mut ping_tracker = PingTracker::default();
+        let mut ping_interval = time::interval(PING_INTERVAL);
+        ping_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // ticks immediately
+        ping_interval.tick().await;
+
+        let res = loop {
+            tokio::select! {
+                biased;
+                frame = stream.next() => {
+                    let frame = match frame {
+                        Some(Ok(frame)) => frame,
+                        Some(Err(err)) => break Err(err.into()),
+                        None => break Ok(()),
+                    };
+                    match MeshMsg::from_bytes(frame, &self.0.key_cache) {
+                        Ok(MeshMsg::EndpointConnected(endpoint_id)) => {
+                            trace!(endpoint = %endpoint_id.fmt_short(), "route added");
+                            self.0.routes.insert(endpoint_id, peer);
+                        }
+                        Ok(MeshMsg::EndpointGone(endpoint_id)) => {
+                            trace!(endpoint = %endpoint_id.fmt_short(), "route removed");
+                            self.0.routes.remove_if(&endpoint_id, |_, via| *via == peer);
+                        }
+                        Ok(MeshMsg::Pong(data)) => ping_tracker.pong_received(data),
+                        Ok(msg) => {
+                            break Err(e!(LinkError::UnexpectedFrame { frame_type: msg.typ() }));
+                        }
+                        Err(err) => break Err(err.into()),
+                    }
+                }
+                Some(msg) = packets.recv() => {
+                    if let Err(err) = write_msg(&mut stream, msg).await {
+                        break Err(err);
+                    }
+                }
+                _ = ping_tracker.timeout() => break Err(e!(LinkError::PingTimeout)),
+                _ = ping_interval.tick() => {
+                    let data = ping_tracker.new_ping();
+                    if let Err(err) = write_msg(&mut stream, MeshMsg::Ping(data)).await {
+                        break Err(err);
+                    }
+                }
+            }
+        };
+
+        self.0.links.remove(&peer);
+        self.0.routes.retain(|_, via| *via != peer);
+        res
+    }
+
+    /// Runs a mesh link dialed by a peer until it closes.
+    async fn run_accepted_link<S: BytesStreamSink>(
+        &self,
+        mut stream: S,
+        clients: &Clients,
+    ) -> Result<(), LinkError> {
+        // Prove our identity before announcing anything.
+        let challenge = time::timeout(PEER_AUTH_TIMEOUT, stream.next())
+            .await
+            .map_err(|_| e!(LinkError::PeerAuthTimeout))?;
+        let Some(challenge) = challenge else {
+            return Ok(());
+        };
+        match MeshMsg::from_bytes(challenge?, &self.0.key_cache)? {
+            MeshMsg::PeerChallenge(challenge) => {
+                let auth = MeshMsg::peer_auth(&self.0.secret_key, &challenge);
+                write_msg(&mut stream, auth).await?;
+            }
+            msg => {
+                return Err(e!(LinkError::UnexpectedFrame {
+                    frame_type: msg.typ()
+                }));
+            }
+        }
+
+        // Subscribe before listing the connected endpoints, so that no change is missed.
+        let mut presence = self.0.presence.subscribe();
+        for endpoint_id in clients.endpoint_ids() {
+            write_msg(&mut stream, MeshMsg::EndpointConnected(endpoint_id)).await?;
+        }
+
+        loop {
+            tokio::select! {
+                biased;
+                frame = stream.next() => {
+                    let Some(frame) = frame else {
+                        return Ok(());
+                    };
+                    match MeshMsg::from_bytes(frame?, &self.0.key_cache)? {
+                        MeshMsg::Datagrams {
+                            dst_endpoint_id,
+                            src_endpoint_id,
+                            datagrams,
+                        } => {
+                            self.0.metrics.mesh_packets_recv.inc();
+                            if let Err(err) = clients.send_packet_from_mesh(
+                                dst_endpoint_id,
+                                datagrams,
+                                src_endpoint_id,
+                                &self.0.metrics,
+                            ) {
+                                debug!("failed to deliver packet from mesh: {err:#}");
+                            }
+                        }
+                        MeshMsg::Ping(data) => write_msg(&mut stream, MeshMsg::Pong(data)).await?,
+                        msg => {
+                            return Err(e!(LinkError::UnexpectedFrame { frame_type: msg.typ() }));
+                        }
+                    }
+                }
+                msg = presence.recv() => match msg {
+                    Ok(msg) => write_msg(&mut stream, msg).await?,
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        return Err(e!(LinkError::Lagged));
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                },
+            }
+        }
+    }
+}
+
+async fn write_msg<S: BytesStreamSink>(stream: &mut S, msg: MeshMsg) -> Result<(), LinkError> {
+    time::timeout(SERVER_WRITE_TIMEOUT, stream.send(msg.to_bytes().freeze())).await??;
+    Ok(())
+}
+
+#[cfg(all(test, with_crypto_provider))]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use iroh_base::SecretKey;
+    use n0_error::{Result, StackResultExt, StdResultExt};
+    use n0_tracing_test::traced_test;
+    use rand::{RngExt, SeedableRng};
+
+    use super::*;
+    use crate::{
+        client::ClientBuilder,
+        protos::relay::{ClientToRelayMsg, RelayToClientMsg},
+        server::{RelayConfig, Server, ServerConfig},
+        tls::make_dangerous_client_config,
+    };
+
+    async fn spawn_relay(
+        secret_key: &SecretKey,
+        addr: SocketAddr,
+        peers: Vec<MeshPeer>,
+    ) -> Result<Server> {
+        let mut relay = RelayConfig::new(addr);
+        let mut mesh = MeshConfig::new(secret_key.clone(), peers);
+        mesh.tls_config = CaTlsConfig::insecure_skip_verify();
+        mesh.insecure_allow_http = true;
+        relay.mesh = Some(mesh);
+        let config = ServerConfig {
+            relay: Some(relay),
+            ..Default::default()
+        };
+        Ok(Server::spawn(config).await?)
+    }
+
+    fn relay_url(server: &Server) -> RelayUrl {
+        format!("http://{}", server.http_addr().unwrap())
+            .parse()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn test_mesh_forwarding() -> Result {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0u64);
+        let relay_a_key = SecretKey::from_bytes(&rng.random());
+        let relay_b_key = SecretKey::from_bytes(&rng.random());
+
+        // The ports are only known after binding, so spawn the relays without peers first
+        // and then restart them on the same addresses meshed with each other.
+        let relay_a = spawn_relay(&relay_a_key, (Ipv4Addr::LOCALHOST, 0).into(), vec![]).await?;
+        let relay_b = spawn_relay(&relay_b_key, (Ipv4Addr::LOCALHOST, 0).into(), vec![]).await?;
+        let peers = vec![
+            MeshPeer::new(relay_a_key.public(), relay_url(&relay_a)),
+            MeshPeer::new(relay_b_key.public(), relay_url(&relay_b)),
+        ];
+        let addr_a = relay_a.http_addr().context("no http addr")?;
+        let addr_b = relay_b.http_addr().context("no http addr")?;
+        relay_a.shutdown().await?;
+        relay_b.shutdown().await?;
+        let relay_a = spawn_relay(&relay_a_key, addr_a, peers.clone()).await?;
+        let relay_b = spawn_relay(&relay_b_key, addr_b, peers).await?;
+
+        let a_key = SecretKey::from_bytes(&rng.random());
+        let b_key = SecretKey::from_bytes(&rng.random());
+        let mut client_a =
+            ClientBuilder::new(relay_url(&relay_a), a_key.clone(), DnsResolver::new())
+                .tls_client_config(make_dangerous_client_config())
+                .connect()
+                .await?;
+        let mut client_b =
+            ClientBuilder::new(relay_url(&relay_b), b_key.clone(), DnsResolver::new())
+                .tls_client_config(make_dangerous_client_config())
+                .connect()
+                .await?;
+
+        // Send until the route to b has been announced to relay a.
+        let msg = b"hello from a";
+        let received = time::timeout(Duration::from_secs(10), async {
+            loop {
+                client_a
+                    .send(ClientToRelayMsg::Datagrams {
+                        dst_endpoint_id: b_key.public(),
+                        datagrams: msg.into(),
+                    })
+                    .await?;
+                if let Ok(frame) = time::timeout(Duration::from_millis(100), client_b.next()).await
+                {
+                    return frame.context("client b closed");
+                }
+            }
+        })
+        .await
+        .std_context("timeout")??;
+        assert_eq!(
+            received?,
+            RelayToClientMsg::Datagrams {
+                remote_endpoint_id: a_key.public(),
+                datagrams: msg.into(),
+            }
+        );
+
+        // And the reverse direction.
+        let msg = b"hello from b";
+        let received = time::timeout(Duration::from_secs(10), async {
+            loop {
+                client_b
+                    .send(ClientToRelayMsg::Datagrams {
+                        dst_endpoint_id: a_key.public(),
+                        datagrams: msg.into(),
+                    })
+                    .await?;
+                if let Ok(frame) = time::timeout(Duration::from_millis(100), client_a.next()).await
+                {
+                    return frame.context("client a closed");
+                }
+            }
+        })
+        .await
+        .std_context("timeout")??;
+        assert_eq!(
+            received?,
+            RelayToClientMsg::Datagrams {
+                remote_endpoint_id: b_key.public(),
+                datagrams: msg.into(),
+            }
+        );
+        assert!(relay_a.metrics().server.mesh_packets_forwarded.get() >= 1);
+        assert!(relay_a.metrics().server.mesh_packets_recv.get() >= 1);
+
+        relay_a.shutdown().await?;
+        relay_b.shutdown().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn test_mesh_rejects_unknown_peer() -> Result {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(1u64);
+        let relay_key = SecretKey::from_bytes(&rng.random());
+        let intruder_key = SecretKey::from_bytes(&rng.random());
+        let relay = spawn_relay(&relay_key, (Ipv4Addr::LOCALHOST, 0).into(), vec![]).await?;
+
+        let res = ClientBuilder::new(relay_url(&relay), intruder_key, DnsResolver::new())
+            .tls_client_config(make_dangerous_client_config())
+            .connect_mesh()
+            .await;
+        assert!(res.is_err());
+
+        relay.shutdown().await?;
+        Ok(())
+    }
+    #[tokio::test]
+    #[traced_test]
+    async fn test_mesh_authenticates_peer() -> Result {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(2u64);
+        let relay_key = SecretKey::from_bytes(&rng.random());
+        let dialer_key = SecretKey::from_bytes(&rng.random());
+        let impostor = SecretKey::from_bytes(&rng.random()).public();
+        let dialer = MeshPeer::new(dialer_key.public(), "https://dialer.test".parse()?);
+        let relay = spawn_relay(&relay_key, (Ipv4Addr::LOCALHOST, 0).into(), vec![dialer]).await?;
+        let handle = MeshHandle::spawn(
+            MeshConfig::new(dialer_key.clone(), vec![]),
+            KeyCache::test(),
+            Default::default(),
+        )?;
+        let client = ClientBuilder::new(relay_url(&relay), dialer_key, DnsResolver::new())
+            .tls_client_config(make_dangerous_client_config());
+
+        // The relay does not own the key we expect for this peer.
+        let mut stream = client.connect_mesh().await?;
+        let res = handle.mesh().authenticate_peer(impostor, &mut stream).await;
+        assert!(matches!(res, Err(LinkError::PeerAuthFailed { .. })));
+
+        let mut stream = client.connect_mesh().await?;
+        handle
+            .mesh()
+            .authenticate_peer(relay_key.public(), &mut stream)
+            .await?;
+
+        relay.shutdown().await?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_mesh_requires_tls() {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(3u64);
+        let peer = MeshPeer::new(
+            SecretKey::from_bytes(&rng.random()).public(),
+            "http://relay.test".parse().unwrap(),
+        );
+        let config = MeshConfig::new(SecretKey::from_bytes(&rng.random()), vec![peer]);
+        let res = MeshHandle::spawn(config, KeyCache::test(), Default::default());
+        assert!(matches!(res, Err(SpawnError::InsecureMeshPeer { .. })));
+    }
+}
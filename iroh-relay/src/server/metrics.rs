@@ -71,6 +71,13 @@ pub struct Metrics {
     pub bytes_rx_ratelimited_total: Counter,
     /// Number of client connections which have had any frames rate-limited.
     pub conns_rx_ratelimited_total: Counter,
+    /// Number of datagrams dropped because the sender or the recipient exhausted its quota.
+    pub send_packets_over_quota: Counter,
+    /// Number of queued datagrams dropped to make room for datagrams from senders with a
+    /// shorter queue, see [`EndpointLimits::weight`].
+    ///
+    /// [`EndpointLimits::weight`]: crate::server::EndpointLimits::weight
+    pub send_packets_evicted: Counter,
 
     /*
      * Metrics about peers
@@ -104,6 +111,23 @@ pub struct Metrics {
     /// See [`Self::clients_inactive_added`] for details on when a client becomes inactive.
     pub clients_inactive_removed: Counter,
 
+    /*
+     * Metrics about the relay mesh
+     */
+    /// Number of mesh links to peer relays that were established.
+    ///
+    /// Counts both links dialed by this relay and links accepted from peer relays.  The
+    /// number of open links is `mesh_links` - `mesh_links_closed`.
+    pub mesh_links: Counter,
+    /// Number of mesh links to peer relays that were closed.
+    pub mesh_links_closed: Counter,
+    /// Number of 'send' packets forwarded to a peer relay.
+    pub mesh_packets_forwarded: Counter,
+    /// Number of 'send' packets received from a peer relay.
+    pub mesh_packets_recv: Counter,
+    /// Number of 'send' packets dropped because the mesh link to the peer relay was busy or closed.
+    pub mesh_packets_dropped: Counter,
+
     // TODO: only important stat that we cannot track right now
     // pub average_queue_duration:
     //
@@ -114,6 +138,11 @@ pub struct Metrics {
     /// Thus the number of inflight incomings is `qad_incoming` - `qad_incoming_error` - `qad_connections`.
     pub qad_incoming: Counter,
 
+    /// Number of incoming QAD connections refused because of the accept rate limits.
+    ///
+    /// These are not counted in `qad_incoming`.
+    pub qad_incoming_ratelimited: Counter,
+
     /// Number of QAD QUIC connections that aborted before completing the handshake.
     pub qad_incoming_error: Counter,
 
@@ -135,6 +164,14 @@ pub struct Metrics {
     /// The number of active connections at any time is `http_connections` - `http_connections_closed`
     pub http_connections: Counter,
 
+    /// Number of relay connection requests rejected with `429 Too Many Requests` because of
+    /// the accept rate limits.
+    pub http_accepts_ratelimited: Counter,
+
+    /// Number of relay connection requests rejected with `503 Service Unavailable` because
+    /// the relay is draining.
+    pub http_accepts_draining: Counter,
+
     /// Number of terminated HTTP(S) connections.
     pub http_connections_closed: Counter,
 
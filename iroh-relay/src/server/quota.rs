@@ -0,0 +1,188 @@
+//! Tracking of the relay usage of endpoints against their [`Quota`].
+//!
+//! The usage is kept per [`EndpointId`] and outlives the connections of an endpoint, so
+//! reconnecting does not reset it. Usage of endpoints which are no longer connected is
+//! dropped once their period ends.
+
+use std::sync::Mutex;
+
+use dashmap::DashMap;
+use iroh_base::EndpointId;
+use time::{Date, OffsetDateTime};
+
+use super::{Quota, QuotaPeriod};
+
+/// The relay usage of all endpoints with a quota.
+#[derive(Debug)]
+pub(super) struct QuotaTracker {
+    usage: DashMap<EndpointId, Usage>,
+    /// The date on which usage from past periods was last pruned.
+    last_prune: Mutex<Date>,
+}
+
+impl Default for QuotaTracker {
+    fn default() -> Self {
+        Self {
+            usage: DashMap::new(),
+            last_prune: Mutex::new(today()),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Usage {
+    quota: Quota,
+    /// The first day of the period `bytes` were used in.
+    period_start: Date,
+    bytes: u64,
+}
+
+impl Usage {
+    /// Resets the usage if the period of the quota ended.
+    fn refresh(&mut self, today: Date) {
+        let period_start = self.quota.period.start(today);
+        if period_start != self.period_start {
+            self.period_start = period_start;
+            self.bytes = 0;
+        }
+    }
+}
+
+impl QuotaPeriod {
+    /// Returns the first day of the period containing `today`.
+    fn start(self, today: Date) -> Date {
+        match self {
+            QuotaPeriod::Daily => today,
+            QuotaPeriod::Monthly => today.replace_day(1).expect("all months have a first day"),
+        }
+    }
+}
+
+fn today() -> Date {
+    OffsetDateTime::now_utc().date()
+}
+
+impl QuotaTracker {
+    /// Sets the quota of `endpoint_id`, keeping its usage in the current period.
+    ///
+    /// With `None` the endpoint is no longer limited and its usage is dropped.
+    pub(super) fn set_quota(&self, endpoint_id: EndpointId, quota: Option<Quota>) {
+        let Some(quota) = quota else {
+            self.usage.remove(&endpoint_id);
+            return;
+        };
+        let today = today();
+        let mut usage = self.usage.entry(endpoint_id).or_insert_with(|| Usage {
+            quota,
+            period_start: quota.period.start(today),
+            bytes: 0,
+        });
+        usage.quota = quota;
+        usage.refresh(today);
+    }
+
+    /// Returns whether `endpoint_id` used up its quota.
+    pub(super) fn is_exceeded(&self, endpoint_id: EndpointId) -> bool {
+        self.exceeded_period(endpoint_id).is_some()
+    }
+
+    /// Returns the first day of the current period if `endpoint_id` used up its quota in it.
+    pub(super) fn exceeded_period(&self, endpoint_id: EndpointId) -> Option<Date> {
+        let mut usage = self.usage.get_mut(&endpoint_id)?;
+        usage.refresh(today());
+        (usage.bytes >= usage.quota.bytes).then_some(usage.period_start)
+    }
+
+    /// Adds `bytes` to the usage of `endpoint_id`, if it has a quota.
+    pub(super) fn record(&self, endpoint_id: EndpointId, bytes: u64) {
+        if let Some(mut usage) = self.usage.get_mut(&endpoint_id) {
+            usage.refresh(today());
+            usage.bytes = usage.bytes.saturating_add(bytes);
+        }
+    }
+
+    /// Returns the bytes used by `endpoint_id` in the current period, if it has a quota.
+    pub(super) fn bytes_used(&self, endpoint_id: EndpointId) -> Option<u64> {
+        let mut usage = self.usage.get_mut(&endpoint_id)?;
+        usage.refresh(today());
+        Some(usage.bytes)
+    }
+
+    /// Drops the usage from past periods of endpoints which are not connected.
+    ///
+    /// Only does work once per day.
+    pub(super) fn prune(&self, is_connected: impl Fn(&EndpointId) -> bool) {
+        let today = today();
+        {
+            let mut last_prune = self.last_prune.lock().expect("poisoned");
+            if *last_prune == today {
+                return;
+            }
+            *last_prune = today;
+        }
+        self.usage.retain(|endpoint_id, usage| {
+            usage.period_start == usage.quota.period.start(today) || is_connected(endpoint_id)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh_base::SecretKey;
+    use time::Month;
+
+    use super::*;
+
+    #[test]
+    fn test_quota_periods() {
+        let date = |month, day| Date::from_calendar_date(2026, month, day).unwrap();
+        assert_eq!(
+            QuotaPeriod::Daily.start(date(Month::March, 17)),
+            date(Month::March, 17)
+        );
+        assert_eq!(
+            QuotaPeriod::Monthly.start(date(Month::March, 17)),
+            date(Month::March, 1)
+        );
+
+        let mut usage = Usage {
+            quota: Quota::monthly(100),
+            period_start: date(Month::March, 1),
+            bytes: 80,
+        };
+        usage.refresh(date(Month::March, 31));
+        assert_eq!(usage.bytes, 80);
+        usage.refresh(date(Month::April, 1));
+        assert_eq!(usage.bytes, 0);
+        assert_eq!(usage.period_start, date(Month::April, 1));
+    }
+
+    #[test]
+    fn test_quota_tracker() {
+        let tracker = QuotaTracker::default();
+        let a = SecretKey::from_bytes(&[1u8; 32]).public();
+        let b = SecretKey::from_bytes(&[2u8; 32]).public();
+
+        // Endpoints without a quota are not tracked.
+        tracker.record(b, 1000);
+        assert!(!tracker.is_exceeded(b));
+        assert_eq!(tracker.bytes_used(b), None);
+
+        tracker.set_quota(a, Some(Quota::daily(100)));
+        tracker.record(a, 60);
+        assert!(!tracker.is_exceeded(a));
+        // Setting the quota again keeps the usage.
+        tracker.set_quota(a, Some(Quota::daily(100)));
+        tracker.record(a, 60);
+        assert!(tracker.is_exceeded(a));
+        assert_eq!(tracker.exceeded_period(a), Some(today()));
+        assert_eq!(tracker.bytes_used(a), Some(120));
+
+        // A larger quota applies to the usage so far.
+        tracker.set_quota(a, Some(Quota::daily(200)));
+        assert!(!tracker.is_exceeded(a));
+
+        tracker.set_quota(a, None);
+        assert_eq!(tracker.bytes_used(a), None);
+    }
+}
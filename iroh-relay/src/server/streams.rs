@@ -2,7 +2,10 @@
 
 use std::{
     pin::Pin,
-    sync::{Arc, atomic::AtomicBool},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
     task::{Context, Poll},
 };
 
@@ -11,7 +14,7 @@ use n0_future::{FutureExt, Sink, Stream, ready, time};
 use tokio::io::{AsyncRead, AsyncWrite};
 use tracing::{instrument, warn};
 
-use super::{ClientRateLimit, Metrics};
+use super::{ClientRateLimit, Metrics, client::ConnectionStats};
 use crate::{
     ExportKeyingMaterial, KeyCache, MAX_PACKET_SIZE,
     protos::{
@@ -334,6 +337,8 @@ pub(crate) struct RateLimited<S> {
     /// Keeps track if this stream was ever rate-limited.
     limited_once: bool,
     metrics: Arc<Metrics>,
+    /// Statistics of the connection, records how much was rate-limited.
+    stats: Arc<ConnectionStats>,
 }
 
 /// A token bucket used for rate-limiting.
@@ -477,6 +482,7 @@ impl<S> RateLimited<S> {
             bucket_refilled: None,
             limited_once: false,
             metrics,
+            stats: Default::default(),
         })
     }
 
@@ -487,13 +493,22 @@ impl<S> RateLimited<S> {
             bucket_refilled: None,
             limited_once: false,
             metrics,
+            stats: Default::default(),
         }
     }
 
+    /// Returns the statistics of the connection, to be passed on to the client.
+    pub(super) fn stats(&self) -> Arc<ConnectionStats> {
+        self.stats.clone()
+    }
+
     /// Records metrics about being rate-limited.
     fn record_rate_limited(&mut self, bytes: usize) {
         // TODO: add a label for the frame type.
         self.metrics.bytes_rx_ratelimited_total.inc_by(bytes as u64);
+        self.stats
+            .bytes_rx_ratelimited
+            .fetch_add(bytes as u64, Ordering::Relaxed);
         if !self.limited_once {
             self.metrics.conns_rx_ratelimited_total.inc();
             self.limited_once = true;
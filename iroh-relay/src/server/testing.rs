@@ -57,6 +57,7 @@ pub fn relay_config() -> RelayConfig {
         limits: Default::default(),
         key_cache_capacity: Some(1024),
         access: Arc::new(AllowAll),
+        mesh: None,
     }
 }
 
@@ -83,5 +84,6 @@ pub fn server_config() -> ServerConfig {
         quic: Some(quic_config()),
         #[cfg(feature = "metrics")]
         metrics_addr: None,
+        admin: None,
     }
 }
@@ -0,0 +1,197 @@
+//! Example of an endpoint which does not hold its secret key.
+//!
+//! The secret key is held by a separate signing service, which the endpoint asks for
+//! signatures over a Unix socket. In a real deployment the service would run as a
+//! different user, or be backed by a hardware security module.
+//!
+//! ## Usage
+//!
+//! Start the signing service in one terminal:
+//!
+//!     cargo run --example unix-socket-signer -- serve --socket /tmp/iroh-signer.sock
+//!
+//! And the endpoint in another:
+//!
+//!     cargo run --example unix-socket-signer -- listen --socket /tmp/iroh-signer.sock
+//!
+//! The listening endpoint echoes the first stream of every connection, you can connect to it
+//! with its endpoint id, e.g. using the `connect` example.
+
+#[cfg(unix)]
+#[tokio::main]
+async fn main() -> n0_error::Result<()> {
+    unix::main().await
+}
+
+#[cfg(not(unix))]
+fn main() {
+    println!("This example needs Unix sockets.");
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::{
+        io::{Read, Write},
+        os::unix::net::{UnixListener, UnixStream},
+        path::PathBuf,
+        str::FromStr,
+    };
+
+    use clap::Parser;
+    use iroh::{
+        Endpoint, PublicKey, SecretKey, SignError, Signature, Signer,
+        endpoint::{Connection, presets},
+        protocol::{AcceptError, ProtocolHandler, Router},
+    };
+    use n0_error::{AnyError, Result, StackResultExt, StdResultExt};
+
+    const ALPN: &[u8] = b"iroh-example/echo/0";
+
+    /// Request for the public key of the service.
+    const OP_PUBLIC_KEY: u8 = 0;
+    /// Request to sign a message, followed by the length of the message as a big endian `u32`
+    /// and the message.
+    const OP_SIGN: u8 = 1;
+
+    #[derive(Parser)]
+    enum Args {
+        /// Runs the signing service.
+        ///
+        /// Uses the secret key from the IROH_SECRET environment variable, or a new random key.
+        Serve {
+            #[clap(long)]
+            socket: PathBuf,
+        },
+        /// Runs an endpoint which signs with the signing service.
+        Listen {
+            #[clap(long)]
+            socket: PathBuf,
+        },
+    }
+
+    pub async fn main() -> Result<()> {
+        tracing_subscriber::fmt::init();
+        match Args::parse() {
+            Args::Serve { socket } => {
+                let secret_key = match std::env::var("IROH_SECRET") {
+                    Ok(secret) => {
+                        SecretKey::from_str(&secret).context("Invalid secret key format")?
+                    }
+                    Err(_) => SecretKey::generate(),
+                };
+                println!("serving signatures for {}", secret_key.public());
+                tokio::task::spawn_blocking(move || serve(&socket, &secret_key))
+                    .await
+                    .anyerr()?
+            }
+            Args::Listen { socket } => listen(socket).await,
+        }
+    }
+
+    /// Answers signing requests on the Unix socket at `path`.
+    fn serve(path: &PathBuf, secret_key: &SecretKey) -> Result<()> {
+        let _ = std::fs::remove_file(path);
+        let listener = UnixListener::bind(path).anyerr()?;
+        for stream in listener.incoming() {
+            let mut stream = stream.anyerr()?;
+            if let Err(err) = handle_request(&mut stream, secret_key) {
+                println!("failed to handle request: {err:#}");
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_request(stream: &mut UnixStream, secret_key: &SecretKey) -> std::io::Result<()> {
+        let mut op = [0u8; 1];
+        stream.read_exact(&mut op)?;
+        match op[0] {
+            OP_PUBLIC_KEY => stream.write_all(secret_key.public().as_bytes()),
+            OP_SIGN => {
+                let mut len = [0u8; 4];
+                stream.read_exact(&mut len)?;
+                let mut message = vec![0u8; u32::from_be_bytes(len) as usize];
+                stream.read_exact(&mut message)?;
+                println!("signing {} bytes", message.len());
+                stream.write_all(&secret_key.sign(&message).to_bytes())
+            }
+            op => Err(std::io::Error::other(format!("unknown operation {op}"))),
+        }
+    }
+
+    /// A [`Signer`] which asks the signing service for signatures.
+    #[derive(Debug)]
+    struct UnixSocketSigner {
+        path: PathBuf,
+        /// The public key of the service, queried once as it is needed often.
+        public_key: PublicKey,
+    }
+
+    impl UnixSocketSigner {
+        fn connect(path: PathBuf) -> Result<Self> {
+            let mut stream = UnixStream::connect(&path).anyerr()?;
+            stream.write_all(&[OP_PUBLIC_KEY]).anyerr()?;
+            let mut public_key = [0u8; 32];
+            stream.read_exact(&mut public_key).anyerr()?;
+            let public_key = PublicKey::from_bytes(&public_key)?;
+            Ok(Self { path, public_key })
+        }
+
+        fn request_signature(&self, message: &[u8]) -> std::io::Result<Signature> {
+            let len = u32::try_from(message.len()).map_err(std::io::Error::other)?;
+            let mut stream = UnixStream::connect(&self.path)?;
+            stream.write_all(&[OP_SIGN])?;
+            stream.write_all(&len.to_be_bytes())?;
+            stream.write_all(message)?;
+            let mut signature = [0u8; Signature::LENGTH];
+            stream.read_exact(&mut signature)?;
+            Ok(Signature::from_bytes(&signature))
+        }
+    }
+
+    impl Signer for UnixSocketSigner {
+        fn public_key(&self) -> PublicKey {
+            self.public_key
+        }
+
+        fn sign(&self, message: &[u8]) -> Result<Signature, SignError> {
+            let signature = self
+                .request_signature(message)
+                .map_err(|err| SignError::new(AnyError::from(err)))?;
+            // Do not trust the service blindly, a bad signature fails the handshake later on
+            // with a much less helpful error.
+            self.public_key
+                .verify(message, &signature)
+                .map_err(|err| SignError::new(AnyError::from_std(err)))?;
+            Ok(signature)
+        }
+    }
+
+    async fn listen(socket: PathBuf) -> Result<()> {
+        let signer = tokio::task::spawn_blocking(move || UnixSocketSigner::connect(socket))
+            .await
+            .anyerr()??;
+
+        // The endpoint is built from the signer alone, it never sees the secret key.
+        let endpoint = Endpoint::builder(presets::N0).signer(signer).bind().await?;
+        println!("endpoint id: {}", endpoint.id());
+
+        let router = Router::builder(endpoint).accept(ALPN, Echo).spawn();
+        tokio::signal::ctrl_c().await.anyerr()?;
+        router.shutdown().await.anyerr()?;
+        Ok(())
+    }
+
+    #[derive(Debug, Clone)]
+    struct Echo;
+
+    impl ProtocolHandler for Echo {
+        async fn accept(&self, connection: Connection) -> Result<(), AcceptError> {
+            println!("accepted connection from {}", connection.remote_id());
+            let (mut send, mut recv) = connection.accept_bi().await?;
+            tokio::io::copy(&mut recv, &mut send).await?;
+            send.finish()?;
+            connection.closed().await;
+            Ok(())
+        }
+    }
+}
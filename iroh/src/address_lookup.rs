@@ -35,6 +35,9 @@
 //! - [`MemoryLookup`] which allows application to add and remove out-of-band addressing
 //!   information.
 //!
+//! - [`FileLookup`] which remembers the addressing information of endpoints we connected
+//!   to in a file, so that it is available right away after a restart.
+//!
 //! - The [`address_lookup::DnsAddressLookup`] which performs lookups via the standard DNS systems.  To publish
 //!   to this DNS server a [`PkarrPublisher`] is needed.  [Number 0] runs a public instance
 //!   of a [`PkarrPublisher`] with attached DNS server which is globally available and a
@@ -43,6 +46,9 @@
 //! - The [`PkarrResolver`] which can perform lookups from designated [pkarr relay servers]
 //!   using HTTP.
 //!
+//! - The [`MulticastLookup`] which announces and resolves endpoints on the local network
+//!   using UDP multicast, without the need for any DNS or relay infrastructure.
+//!
 //! mDNS-based and Mainline-DHT-based Address Lookup services live in
 //! separate crates: [`iroh-mdns-address-lookup`] and
 //! [`iroh-mainline-address-lookup`].
@@ -100,9 +106,12 @@
 //! [`PkarrPublisherBuilder::addr_filter`]: pkarr::PkarrPublisherBuilder::addr_filter
 //! [pkarr relay servers]: https://pkarr.org/#servers
 //! [`MemoryLookup`]: memory::MemoryLookup
+//! [`FileLookup`]: file::FileLookup
+//! [`MulticastLookup`]: multicast::MulticastLookup
 
 use std::{
     borrow::{Borrow, Cow},
+    collections::BTreeSet,
     pin::Pin,
     sync::{Arc, RwLock},
     task::{Poll, ready},
@@ -111,20 +120,31 @@ use std::{
 use iroh_base::{EndpointAddr, EndpointId};
 pub use iroh_dns::{ParseError, endpoint_info::AddrFilter};
 use n0_error::{AnyError, e, stack_error};
-use n0_future::{MergeBounded, Stream, boxed::BoxStream};
-use tracing::debug;
+use n0_future::{MergeBounded, Stream, StreamExt, boxed::BoxStream};
+use tracing::{debug, warn};
 
 pub use crate::endpoint_info::{EndpointData, EndpointInfo, UserData};
 use crate::{Endpoint, endpoint::EndpointError};
 
 #[cfg(not(wasm_browser))]
 pub mod dns;
+#[cfg(not(wasm_browser))]
+pub mod file;
 pub mod memory;
+#[cfg(not(wasm_browser))]
+pub mod multicast;
 pub mod pkarr;
 
+/// Maximum number of key successions followed by [`AddressLookupServices::follow_succession`].
+const MAX_SUCCESSION_HOPS: usize = 16;
+
 #[cfg(not(wasm_browser))]
 pub use dns::*;
+#[cfg(not(wasm_browser))]
+pub use file::{FileLookup, FileLookupBuilder};
 pub use memory::*;
+#[cfg(not(wasm_browser))]
+pub use multicast::{MulticastEvent, MulticastLookup, MulticastLookupBuilder};
 pub use pkarr::*;
 
 /// Trait for structs that can be converted into [`AddressLookup`]s.
@@ -564,6 +584,64 @@ impl AddressLookupServices {
             AddressLookupStream::new(streams)
         }
     }
+
+    /// Follows the key successions published for an [`EndpointId`] to its current key.
+    ///
+    /// Endpoints which rotated their key publish a [`KeySuccession`], signed by the old key,
+    /// under the old key. This resolves `endpoint_id` and follows the successions from key
+    /// to key, returning the newest key found. If no succession is published for
+    /// `endpoint_id`, it is returned unchanged.
+    ///
+    /// At most 16 successions are followed. A succession is followed as soon as one service
+    /// returns it, otherwise all services are waited for before concluding a key is the
+    /// newest.
+    ///
+    /// Returns an error only if resolving `endpoint_id` itself fails.
+    ///
+    /// [`KeySuccession`]: iroh_base::KeySuccession
+    pub async fn follow_succession(
+        &self,
+        endpoint_id: EndpointId,
+    ) -> Result<EndpointId, AddressLookupFailed> {
+        let mut current = endpoint_id;
+        let mut seen = BTreeSet::from([endpoint_id]);
+        for hop in 0..MAX_SUCCESSION_HOPS {
+            let mut stream = std::pin::pin!(self.resolve(current));
+            let mut successor = None;
+            while let Some(res) = stream.next().await {
+                match res {
+                    Ok(Ok(item)) => {
+                        if let Some(succession) = item.endpoint_info().successor()
+                            && succession.predecessor() == current
+                        {
+                            successor = Some(succession.successor());
+                            break;
+                        }
+                    }
+                    Ok(Err(err)) => debug!("address lookup failed: {err:#}"),
+                    Err(err) if hop == 0 => return Err(err),
+                    Err(_) => break,
+                }
+            }
+            let Some(successor) = successor else {
+                break;
+            };
+            if !seen.insert(successor) {
+                warn!(
+                    endpoint_id = %endpoint_id.fmt_short(),
+                    "key succession contains a cycle"
+                );
+                break;
+            }
+            debug!(
+                from = %current.fmt_short(),
+                to = %successor.fmt_short(),
+                "following key succession"
+            );
+            current = successor;
+        }
+        Ok(current)
+    }
 }
 
 /// Stream returned by [`AddressLookupServices::resolve`].
@@ -657,7 +735,7 @@ mod tests {
         time::{Duration, SystemTime},
     };
 
-    use iroh_base::{EndpointAddr, SecretKey, TransportAddr};
+    use iroh_base::{EndpointAddr, KeySuccession, SecretKey, TransportAddr};
     use n0_error::{AnyError, Result, StackResultExt};
     use n0_future::{StreamExt, time};
     use n0_tracing_test::traced_test;
@@ -826,6 +904,51 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    #[traced_test]
+    async fn address_lookup_follow_succession() -> Result {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0u64);
+        let keys: Vec<_> = (0..3)
+            .map(|_| SecretKey::from_bytes(&rng.random()))
+            .collect();
+        let succession = |from: &SecretKey, to: &SecretKey| {
+            let succession = KeySuccession::new(from, to.public()).unwrap();
+            EndpointInfo::from_parts(
+                from.public(),
+                EndpointData::new(Vec::new()).with_successor(succession),
+            )
+        };
+
+        let memory = memory::MemoryLookup::new();
+        memory.add_endpoint_info(succession(&keys[0], &keys[1]));
+        memory.add_endpoint_info(succession(&keys[1], &keys[2]));
+        memory.add_endpoint_info(
+            EndpointInfo::new(keys[2].public()).with_relay_url("https://relay.example".parse()?),
+        );
+        let services = AddressLookupServices::default();
+        services.add(memory.clone());
+
+        assert_eq!(
+            services.follow_succession(keys[0].public()).await?,
+            keys[2].public()
+        );
+        assert_eq!(
+            services.follow_succession(keys[2].public()).await?,
+            keys[2].public()
+        );
+        // Nothing is known about an unrelated key.
+        let unknown = SecretKey::from_bytes(&rng.random()).public();
+        assert!(services.follow_succession(unknown).await.is_err());
+
+        // Cycles end at the last new key.
+        memory.add_endpoint_info(succession(&keys[2], &keys[0]));
+        assert_eq!(
+            services.follow_succession(keys[1].public()).await?,
+            keys[0].public()
+        );
+        Ok(())
+    }
+
     /// This is a smoke test to ensure a Address Lookup can be
     /// `Arc`-d, and Address Lookup will still work
     #[tokio::test]
@@ -1244,8 +1367,8 @@ mod test_dns_pkarr {
     use iroh_base::{EndpointAddr, SecretKey, TransportAddr};
     use iroh_dns::endpoint_info::UserData;
     use iroh_relay::tls::{CaTlsConfig, default_provider};
-    use n0_error::{Result, StackResultExt};
-    use n0_future::time::Duration;
+    use n0_error::{AnyError, Result, StackResultExt, StdResultExt};
+    use n0_future::time::{self, Duration};
     use n0_tracing_test::traced_test;
     use rand::{RngExt, SeedableRng};
 
@@ -1358,6 +1481,52 @@ mod test_dns_pkarr {
         Ok(())
     }
 
+    #[cfg(with_crypto_provider)]
+    #[tokio::test]
+    #[traced_test]
+    async fn pkarr_publish_key_succession() -> Result<()> {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0u64);
+
+        let dns_pkarr_server = DnsPkarrServer::run().await.context("DnsPkarrServer run")?;
+        let (relay_map, _relay_url, _relay_guard) = crate::test_utils::run_relay_server().await?;
+
+        let (ep1, _guard1) =
+            ep_with_address_lookup(&mut rng, &relay_map, &dns_pkarr_server).await?;
+        let (ep2, _guard2) =
+            ep_with_address_lookup(&mut rng, &relay_map, &dns_pkarr_server).await?;
+        let old_id = ep1.id();
+        dns_pkarr_server
+            .on_endpoint(&old_id, PUBLISH_TIMEOUT)
+            .await
+            .context("wait for on endpoint update")?;
+
+        let new_key = SecretKey::from_bytes(&rng.random());
+        let succession = ep1.rotate_secret_key(new_key.clone()).await?;
+        assert_eq!(succession.predecessor(), old_id);
+        assert_eq!(ep1.id(), new_key.public());
+
+        // The old key publishes the succession to the new key.
+        time::timeout(PUBLISH_TIMEOUT, async {
+            loop {
+                let current = ep2.address_lookup()?.follow_succession(old_id).await?;
+                if current == new_key.public() {
+                    return Ok::<_, AnyError>(());
+                }
+                time::sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .std_context("timeout following succession")??;
+
+        dns_pkarr_server
+            .on_endpoint(&new_key.public(), PUBLISH_TIMEOUT)
+            .await
+            .context("wait for on endpoint update")?;
+        let conn = ep2.connect(new_key.public(), TEST_ALPN).await?;
+        assert_eq!(conn.remote_id(), new_key.public());
+        Ok(())
+    }
+
     #[cfg(with_crypto_provider)]
     async fn ep_with_address_lookup<R: rand::CryptoRng + ?Sized>(
         rng: &mut R,
@@ -0,0 +1,684 @@
+//! A file-backed address lookup system which remembers endpoints across restarts.
+//!
+//! The [`MemoryLookup`] forgets all addressing information when the process exits, so after
+//! a restart every dial has to go through the network-based address lookup services again.
+//! The [`FileLookup`] instead keeps the addressing information of endpoints we successfully
+//! connected to in a file, and serves it immediately on the next run.
+//!
+//! When added to an [`Endpoint`] via [`Builder::address_lookup`], the [`FileLookup`] records
+//! every remote endpoint a connection is established with, whether we dialed it or it
+//! connected to us.  It stores the addresses from the endpoint's [`RemoteInfo`], which
+//! includes the direct addresses learned while connected.  Entries which have not been updated for longer than
+//! [`FileLookupBuilder::max_age`] are expired.
+//!
+//! The file is written periodically and once more when the endpoint closes.
+//!
+//! [`MemoryLookup`]: super::MemoryLookup
+//! [`Builder::address_lookup`]: crate::endpoint::Builder::address_lookup
+//! [`RemoteInfo`]: crate::endpoint::RemoteInfo
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use iroh_base::{EndpointId, TransportAddr};
+use n0_error::{e, stack_error};
+use n0_future::{
+    boxed::BoxStream,
+    stream::{self, StreamExt},
+    task,
+    time::{self, Duration, Instant, MissedTickBehavior, SystemTime},
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::{Instrument, debug, error_span, trace, warn};
+
+use super::{
+    AddressLookup, AddressLookupBuilder, AddressLookupBuilderError, EndpointData, EndpointInfo,
+    Error, Item, UserData,
+};
+use crate::{
+    Endpoint,
+    endpoint::{RemoteInfo, TransportAddrUsage, WeakEndpoint},
+};
+
+/// Duration after which entries which have not been updated are expired: 7 days.
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(60 * 60 * 24 * 7);
+
+/// Interval in which changes are written to the file: 30 seconds.
+pub const DEFAULT_SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Interval in which we check whether connected endpoints have addresses to record.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time after which we stop waiting for a connected endpoint to have addresses to record.
+const PENDING_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Version of the file format, written as the first byte of the file.
+const FILE_VERSION: u8 = 1;
+
+#[allow(missing_docs)]
+#[stack_error(derive, add_meta)]
+#[non_exhaustive]
+pub enum FileLookupError {
+    #[error("Failed to read address lookup file")]
+    Read { source: io::Error },
+    #[error("Failed to write address lookup file")]
+    Write { source: io::Error },
+}
+
+/// Builder for [`FileLookup`].
+///
+/// See [`FileLookup::builder`].
+#[derive(Debug)]
+pub struct FileLookupBuilder {
+    path: PathBuf,
+    max_age: Duration,
+    save_interval: Duration,
+}
+
+impl FileLookupBuilder {
+    /// Sets the duration after which entries which have not been updated are expired.
+    ///
+    /// Default is [`DEFAULT_MAX_AGE`].
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Sets the interval in which changes are written to the file.
+    ///
+    /// Default is [`DEFAULT_SAVE_INTERVAL`].
+    pub fn save_interval(mut self, save_interval: Duration) -> Self {
+        self.save_interval = save_interval;
+        self
+    }
+
+    /// Builds the [`FileLookup`], loading all entries from the file.
+    ///
+    /// A missing file is treated as empty.  A file which cannot be parsed is logged and
+    /// ignored, it will be overwritten on the next save.
+    ///
+    /// A [`FileLookup`] built this way does not record connected endpoints by itself and is
+    /// not written to the file automatically.  Use [`FileLookup::record_remote_info`] or
+    /// [`FileLookup::add_endpoint_info`] to add entries and [`FileLookup::save`] to persist
+    /// them.  When passing the builder to [`Builder::address_lookup`] instead, both happens
+    /// automatically.
+    ///
+    /// [`Builder::address_lookup`]: crate::endpoint::Builder::address_lookup
+    pub fn build(self) -> Result<FileLookup, FileLookupError> {
+        let entries = load(&self.path, self.max_age)?;
+        debug!(path = %self.path.display(), entries = entries.len(), "loaded address lookup file");
+        let inner = Arc::new(Inner {
+            path: self.path,
+            max_age: self.max_age,
+            entries: RwLock::new(entries),
+            dirty: AtomicBool::new(false),
+        });
+        Ok(FileLookup { inner })
+    }
+}
+
+impl AddressLookupBuilder for FileLookupBuilder {
+    fn into_address_lookup(
+        self,
+        endpoint: &Endpoint,
+    ) -> Result<impl AddressLookup, AddressLookupBuilderError> {
+        let save_interval = self.save_interval;
+        let lookup = self
+            .build()
+            .map_err(|err| AddressLookupBuilderError::from_err("file", err))?;
+        let recorder = Recorder {
+            inner: lookup.inner.clone(),
+            endpoint: endpoint.downgrade(),
+            connected: endpoint.subscribe_connected(),
+            pending: HashMap::new(),
+            save_interval,
+        };
+        let inner = lookup.inner.clone();
+        let closed = endpoint.closed();
+        // The recorder stops once the endpoint closes.
+        task::spawn(
+            async move {
+                tokio::select! {
+                    _ = recorder.run() => {}
+                    _ = closed => {}
+                }
+                // Persist any changes not yet written.
+                inner.save_if_dirty().await;
+            }
+            .instrument(error_span!("file_lookup")),
+        );
+        Ok(lookup)
+    }
+}
+
+/// A file-backed address lookup system which remembers endpoints across restarts.
+///
+/// See the [module docs] for details.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[cfg(with_crypto_provider)] // Endpoint::bind needs a crypto provider
+/// # {
+/// use iroh::{Endpoint, address_lookup::file::FileLookup, endpoint::presets};
+///
+/// # async fn wrapper() -> n0_error::Result<()> {
+/// let endpoint = Endpoint::builder(presets::N0)
+///     .address_lookup(FileLookup::builder("address-cache.bin"))
+///     .bind()
+///     .await?;
+/// # Ok(())
+/// # }
+/// # }
+/// ```
+///
+/// [module docs]: crate::address_lookup::file
+#[derive(Debug, Clone)]
+pub struct FileLookup {
+    inner: Arc<Inner>,
+}
+
+impl FileLookup {
+    /// The provenance string for this Address Lookup implementation.
+    ///
+    /// This is mostly used for debugging information and allows understanding the origin of
+    /// addressing information used by an iroh [`Endpoint`].
+    pub const PROVENANCE: &'static str = "file_lookup";
+
+    /// Returns a [`FileLookupBuilder`] storing the addressing information at `path`.
+    ///
+    /// [`FileLookupBuilder`] implements [`AddressLookupBuilder`], so it can be passed to
+    /// [`address_lookup`].
+    ///
+    /// [`address_lookup`]: crate::endpoint::Builder::address_lookup
+    pub fn builder(path: impl Into<PathBuf>) -> FileLookupBuilder {
+        FileLookupBuilder {
+            path: path.into(),
+            max_age: DEFAULT_MAX_AGE,
+            save_interval: DEFAULT_SAVE_INTERVAL,
+        }
+    }
+
+    /// Returns the path of the file the addressing information is stored in.
+    pub fn path(&self) -> &Path {
+        &self.inner.path
+    }
+
+    /// Sets endpoint addressing information for the given endpoint ID.
+    ///
+    /// This overwrites any existing info for the endpoint and marks it as updated now.
+    pub fn add_endpoint_info(&self, endpoint_info: impl Into<EndpointInfo>) {
+        let EndpointInfo { endpoint_id, data } = endpoint_info.into();
+        self.inner.insert(endpoint_id, data);
+    }
+
+    /// Records the addressing information of a remote endpoint we are connected to.
+    ///
+    /// The addresses which are in active use are stored, together with all known relay
+    /// addresses.  If no address of the remote is in active use, nothing is recorded and
+    /// `false` is returned.
+    pub fn record_remote_info(&self, remote_info: &RemoteInfo) -> bool {
+        self.inner.record_remote_info(remote_info)
+    }
+
+    /// Returns endpoint addressing information for the given endpoint ID.
+    ///
+    /// Returns `None` for unknown and expired entries.
+    pub fn get_endpoint_info(&self, endpoint_id: EndpointId) -> Option<EndpointInfo> {
+        let entry = self.inner.get(&endpoint_id)?;
+        Some(EndpointInfo::from_parts(endpoint_id, entry.data))
+    }
+
+    /// Removes all endpoint addressing information for the given endpoint ID.
+    ///
+    /// Any removed information is returned.
+    pub fn remove_endpoint_info(&self, endpoint_id: EndpointId) -> Option<EndpointInfo> {
+        let entry = self
+            .inner
+            .entries
+            .write()
+            .expect("poisoned")
+            .remove(&endpoint_id)?;
+        self.inner.dirty.store(true, Ordering::Relaxed);
+        Some(EndpointInfo::from_parts(endpoint_id, entry.data))
+    }
+
+    /// Writes all current, non-expired entries to the file.
+    pub async fn save(&self) -> Result<(), FileLookupError> {
+        self.inner.save().await
+    }
+}
+
+impl AddressLookup for FileLookup {
+    fn resolve(&self, endpoint_id: EndpointId) -> Option<BoxStream<Result<Item, Error>>> {
+        let entry = self.inner.get(&endpoint_id)?;
+        let item = Item::new(
+            EndpointInfo::from_parts(endpoint_id, entry.data),
+            Self::PROVENANCE,
+            Some(entry.last_updated.to_micros()),
+        );
+        Some(stream::iter(Some(Ok(item))).boxed())
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    path: PathBuf,
+    max_age: Duration,
+    entries: RwLock<BTreeMap<EndpointId, StoredEndpointInfo>>,
+    /// Whether entries changed since the last save.
+    dirty: AtomicBool,
+}
+
+impl Inner {
+    /// Returns the entry for the endpoint, unless it is expired.
+    fn get(&self, endpoint_id: &EndpointId) -> Option<StoredEndpointInfo> {
+        let guard = self.entries.read().expect("poisoned");
+        let entry = guard.get(endpoint_id)?;
+        (!entry.is_expired(self.max_age)).then(|| entry.clone())
+    }
+
+    fn insert(&self, endpoint_id: EndpointId, data: EndpointData) {
+        let entry = StoredEndpointInfo {
+            data,
+            last_updated: Timestamp::now(),
+        };
+        self.entries
+            .write()
+            .expect("poisoned")
+            .insert(endpoint_id, entry);
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    fn record_remote_info(&self, remote_info: &RemoteInfo) -> bool {
+        let mut active = Vec::new();
+        let mut relays = Vec::new();
+        for addr in remote_info.addrs() {
+            match addr.usage() {
+                TransportAddrUsage::Active => active.push(addr.addr().clone()),
+                _ if addr.addr().is_relay() => relays.push(addr.addr().clone()),
+                _ => {}
+            }
+        }
+        if active.is_empty() {
+            return false;
+        }
+        active.extend(relays);
+        let endpoint_id = remote_info.id();
+        let mut data = EndpointData::new(active);
+        if let Some(existing) = self.get(&endpoint_id) {
+            data.set_user_data(existing.data.user_data().cloned());
+        }
+        trace!(endpoint_id = %endpoint_id.fmt_short(), ?data, "recording remote info");
+        self.insert(endpoint_id, data);
+        true
+    }
+
+    async fn save(&self) -> Result<(), FileLookupError> {
+        self.dirty.store(false, Ordering::Relaxed);
+        let bytes = {
+            let mut guard = self.entries.write().expect("poisoned");
+            guard.retain(|_, entry| !entry.is_expired(self.max_age));
+            encode(&guard)
+        };
+        // Write to a temporary file first, so that a crash never leaves a truncated file.
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let res = async {
+            tokio::fs::write(&tmp_path, &bytes).await?;
+            tokio::fs::rename(&tmp_path, &self.path).await
+        }
+        .await;
+        if let Err(err) = res {
+            self.dirty.store(true, Ordering::Relaxed);
+            return Err(e!(FileLookupError::Write, err));
+        }
+        trace!(path = %self.path.display(), "saved address lookup file");
+        Ok(())
+    }
+
+    async fn save_if_dirty(&self) {
+        if !self.dirty.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(err) = self.save().await {
+            warn!(path = %self.path.display(), "failed to save address lookup file: {err:#}");
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct StoredEndpointInfo {
+    data: EndpointData,
+    last_updated: Timestamp,
+}
+
+impl StoredEndpointInfo {
+    fn is_expired(&self, max_age: Duration) -> bool {
+        SystemTime::from(self.last_updated)
+            .elapsed()
+            .is_ok_and(|age| age > max_age)
+    }
+}
+
+/// Microseconds since the unix epoch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct Timestamp(u64);
+
+impl Timestamp {
+    fn now() -> Self {
+        let micros = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("time drift")
+            .as_micros() as u64;
+        Self(micros)
+    }
+
+    fn to_micros(self) -> u64 {
+        self.0
+    }
+}
+
+impl From<Timestamp> for SystemTime {
+    fn from(value: Timestamp) -> Self {
+        SystemTime::UNIX_EPOCH + Duration::from_micros(value.0)
+    }
+}
+
+/// An entry as stored in the file.
+#[derive(Debug, Serialize, Deserialize)]
+struct FileEntry {
+    endpoint_id: EndpointId,
+    addrs: Vec<TransportAddr>,
+    user_data: Option<String>,
+    last_updated: Timestamp,
+}
+
+fn encode(entries: &BTreeMap<EndpointId, StoredEndpointInfo>) -> Vec<u8> {
+    let entries: Vec<_> = entries
+        .iter()
+        .map(|(endpoint_id, entry)| FileEntry {
+            endpoint_id: *endpoint_id,
+            addrs: entry.data.addrs().cloned().collect(),
+            user_data: entry.data.user_data().map(ToString::to_string),
+            last_updated: entry.last_updated,
+        })
+        .collect();
+    let mut out = vec![FILE_VERSION];
+    postcard::to_io(&entries, &mut out).expect("writing to a vec is infallible");
+    out
+}
+
+fn decode(bytes: &[u8]) -> Result<Vec<FileEntry>, &'static str> {
+    match bytes.split_first() {
+        Some((&FILE_VERSION, rest)) => postcard::from_bytes(rest).map_err(|_| "invalid encoding"),
+        Some(_) => Err("unsupported version"),
+        None => Err("empty file"),
+    }
+}
+
+/// Loads all non-expired entries from the file at `path`.
+fn load(
+    path: &Path,
+    max_age: Duration,
+) -> Result<BTreeMap<EndpointId, StoredEndpointInfo>, FileLookupError> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Default::default()),
+        Err(err) => return Err(e!(FileLookupError::Read, err)),
+    };
+    let entries = match decode(&bytes) {
+        Ok(entries) => entries,
+        Err(reason) => {
+            warn!(path = %path.display(), "ignoring invalid address lookup file: {reason}");
+            return Ok(Default::default());
+        }
+    };
+    let entries = entries
+        .into_iter()
+        .map(|entry| {
+            let mut data = EndpointData::new(entry.addrs);
+            data.set_user_data(entry.user_data.and_then(|s| UserData::try_from(s).ok()));
+            let stored = StoredEndpointInfo {
+                data,
+                last_updated: entry.last_updated,
+            };
+            (entry.endpoint_id, stored)
+        })
+        .filter(|(_, entry)| !entry.is_expired(max_age))
+        .collect();
+    Ok(entries)
+}
+
+/// Records the endpoints connections are established with and periodically saves the file.
+#[derive(Debug)]
+struct Recorder {
+    inner: Arc<Inner>,
+    endpoint: WeakEndpoint,
+    connected: broadcast::Receiver<EndpointId>,
+    /// Connected endpoints not recorded yet, with the time they connected.
+    pending: HashMap<EndpointId, Instant>,
+    save_interval: Duration,
+}
+
+impl Recorder {
+    async fn run(mut self) {
+        let mut check = time::interval(CHECK_INTERVAL);
+        check.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut save = time::interval(self.save_interval);
+        save.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                endpoint_id = self.connected.recv() => match endpoint_id {
+                    Ok(endpoint_id) => {
+                        self.pending.entry(endpoint_id).or_insert_with(Instant::now);
+                    }
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        debug!("missed {n} connections, not recording them");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = check.tick(), if !self.pending.is_empty() => {
+                    if !self.check_pending().await {
+                        break;
+                    }
+                }
+                _ = save.tick() => self.inner.save_if_dirty().await,
+            }
+        }
+    }
+
+    /// Records all pending endpoints for which addresses are known by now.
+    ///
+    /// Returns `false` if the endpoint is gone.
+    async fn check_pending(&mut self) -> bool {
+        let Some(endpoint) = self.endpoint.upgrade() else {
+            return false;
+        };
+        let mut done = Vec::new();
+        for (endpoint_id, started) in &self.pending {
+            let recorded = match endpoint.remote_info(*endpoint_id).await {
+                Some(info) => self.inner.record_remote_info(&info),
+                None => false,
+            };
+            if recorded || started.elapsed() > PENDING_TIMEOUT {
+                done.push(*endpoint_id);
+            }
+        }
+        for endpoint_id in done {
+            self.pending.remove(&endpoint_id);
+        }
+        true
+    }
+}
+
+#[cfg(all(test, with_crypto_provider))]
+mod tests {
+    use iroh_base::{EndpointAddr, SecretKey};
+    use n0_error::{Result, StackResultExt, StdResultExt};
+    use n0_tracing_test::traced_test;
+    use rand::{RngExt, SeedableRng};
+
+    use super::*;
+    use crate::{address_lookup::MemoryLookup, endpoint::presets};
+
+    const TEST_ALPN: &[u8] = b"n0/iroh/test";
+
+    /// Returns a path to a lookup file in a new temporary directory.
+    ///
+    /// The directory is removed when the returned [`tempfile::TempDir`] is dropped.
+    fn test_path() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lookup.bin");
+        (dir, path)
+    }
+
+    #[tokio::test]
+    async fn test_save_and_load() -> Result {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0);
+        let (_dir, path) = test_path();
+        let id = SecretKey::from_bytes(&rng.random()).public();
+        let addr = EndpointAddr::from_parts(
+            id,
+            [
+                TransportAddr::Relay("https://relay.example.com".parse()?),
+                TransportAddr::Ip("192.168.1.10:1234".parse().unwrap()),
+            ],
+        );
+        let info = EndpointInfo::from(addr).with_user_data(Some("foo".parse().unwrap()));
+
+        let lookup = FileLookup::builder(&path).build()?;
+        assert!(lookup.get_endpoint_info(id).is_none());
+        lookup.add_endpoint_info(info.clone());
+        lookup.save().await?;
+
+        let lookup = FileLookup::builder(&path).build()?;
+        assert_eq!(lookup.get_endpoint_info(id), Some(info.clone()));
+        let item = lookup
+            .resolve(id)
+            .context("no stream")?
+            .next()
+            .await
+            .context("no item")??;
+        assert_eq!(item.provenance(), FileLookup::PROVENANCE);
+        assert_eq!(item.endpoint_info(), &info);
+
+        assert_eq!(lookup.remove_endpoint_info(id), Some(info));
+        lookup.save().await?;
+        let lookup = FileLookup::builder(&path).build()?;
+        assert!(lookup.get_endpoint_info(id).is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_expiry() -> Result {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(1);
+        let (_dir, path) = test_path();
+        let fresh = SecretKey::from_bytes(&rng.random()).public();
+        let stale = SecretKey::from_bytes(&rng.random()).public();
+
+        let lookup = FileLookup::builder(&path).build()?;
+        lookup.add_endpoint_info(EndpointAddr::new(fresh));
+        lookup.add_endpoint_info(EndpointAddr::new(stale));
+        // Backdate one of the entries.
+        lookup
+            .inner
+            .entries
+            .write()
+            .unwrap()
+            .get_mut(&stale)
+            .unwrap()
+            .last_updated = Timestamp(Timestamp::now().0 - 3600 * 1_000_000);
+        lookup.save().await?;
+
+        let lookup = FileLookup::builder(&path)
+            .max_age(Duration::from_secs(60))
+            .build()?;
+        assert!(lookup.get_endpoint_info(fresh).is_some());
+        assert!(lookup.get_endpoint_info(stale).is_none());
+        assert!(lookup.resolve(stale).is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invalid_file() -> Result {
+        let (_dir, path) = test_path();
+        std::fs::write(&path, b"garbage").unwrap();
+        let lookup = FileLookup::builder(&path).build()?;
+        assert!(lookup.inner.entries.read().unwrap().is_empty());
+        Ok(())
+    }
+
+    /// Waits until the file at `path` holds an entry for `endpoint_id`.
+    async fn wait_recorded(path: &Path, endpoint_id: EndpointId) -> Result<EndpointInfo> {
+        time::timeout(Duration::from_secs(5), async {
+            loop {
+                let lookup = FileLookup::builder(path).build()?;
+                if let Some(info) = lookup.get_endpoint_info(endpoint_id) {
+                    return Ok::<_, FileLookupError>(info);
+                }
+                time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .std_context("timeout")?
+        .anyerr()
+    }
+
+    /// Both ends of a connection record the remote, and the record is served after a
+    /// restart.
+    #[tokio::test]
+    #[traced_test]
+    async fn test_records_connected_endpoints() -> Result {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(2);
+        let (_dir, path) = test_path();
+        let (_server_dir, server_path) = test_path();
+
+        let server = Endpoint::builder(presets::Minimal)
+            .secret_key(SecretKey::from_bytes(&rng.random()))
+            .alpns(vec![TEST_ALPN.to_vec()])
+            .address_lookup(FileLookup::builder(&server_path))
+            .bind()
+            .await?;
+        let server_task = tokio::spawn({
+            let server = server.clone();
+            async move {
+                let conn = server.accept().await?.await.ok()?;
+                conn.closed().await;
+                Some(())
+            }
+        });
+
+        let memory = MemoryLookup::new();
+        memory.add_endpoint_info(server.addr());
+        let client = Endpoint::builder(presets::Minimal)
+            .secret_key(SecretKey::from_bytes(&rng.random()))
+            .address_lookup(memory)
+            .address_lookup(FileLookup::builder(&path))
+            .bind()
+            .await?;
+        let conn = client.connect(server.id(), TEST_ALPN).await?;
+        // Give the recorder time to notice the connection.
+        time::sleep(CHECK_INTERVAL * 2).await;
+        conn.close(0u32.into(), b"bye");
+        client.close().await;
+
+        let info = wait_recorded(&path, server.id()).await?;
+        assert!(info.ip_addrs().next().is_some());
+
+        // The server recorded the incoming connection.
+        server.close().await;
+        server_task.abort();
+        let info = wait_recorded(&server_path, client.id()).await?;
+        assert!(info.ip_addrs().next().is_some());
+        Ok(())
+    }
+}
@@ -179,8 +179,8 @@ impl MemoryLookup {
     /// Augments endpoint addressing information for the given endpoint ID.
     ///
     /// The provided addressing information is combined with the existing info in the memory
-    /// lookup.  Any new direct addresses are added to those already present while the
-    /// relay URL is overwritten.
+    /// lookup.  Any new direct addresses and services are added to those already present
+    /// while the relay URL is overwritten.
     pub fn add_endpoint_info(&self, endpoint_info: impl Into<EndpointInfo>) {
         let last_updated = SystemTime::now();
         let EndpointInfo { endpoint_id, data } = endpoint_info.into();
@@ -190,6 +190,10 @@ impl MemoryLookup {
                 let existing = entry.get_mut();
                 existing.data.add_addrs(data.addrs().cloned());
                 existing.data.set_user_data(data.user_data().cloned());
+                existing.data.set_successor(data.successor().copied());
+                for service in data.services() {
+                    existing.data.add_service(service.clone());
+                }
                 existing.last_updated = last_updated;
             }
             Entry::Vacant(entry) => {
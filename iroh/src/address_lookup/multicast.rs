@@ -0,0 +1,844 @@
+//! An address lookup service which announces and resolves endpoints on the local network
+//! using UDP multicast.
+//!
+//! Every [`MulticastLookup`] joins a multicast group on the local link and periodically
+//! announces the [`EndpointData`] of its own endpoint to that group.  The announcement
+//! is a pkarr [`SignedPacket`], the same signed encoding used by the [`PkarrPublisher`], so
+//! receivers can verify that the addressing information was produced by the owner of the
+//! announced [`EndpointId`].
+//!
+//! All announcements heard on the group are kept in memory and used to answer
+//! [`AddressLookup::resolve`].  When asked for an endpoint that has not been heard from yet,
+//! a query is sent to the group, which the endpoint answers with an immediate announcement.
+//! Use [`MulticastLookup::subscribe`] to be notified about endpoints as they appear on, and
+//! disappear from, the local network.
+//!
+//! This requires no DNS server, relay or any other infrastructure, which makes it useful for
+//! deployments on isolated networks.  Announcements are only sent on the local link, they are
+//! not routed across networks.
+//!
+//! [`PkarrPublisher`]: crate::address_lookup::PkarrPublisher
+
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    sync::{Arc, RwLock},
+};
+
+use iroh_base::{EndpointId, PublicKey, Signer};
+use iroh_dns::{
+    endpoint_info::{AddrFilter, EndpointInfo},
+    pkarr::SignedPacket,
+};
+use n0_error::{e, stack_error};
+use n0_future::{
+    Stream, StreamExt,
+    boxed::BoxStream,
+    task::{self, AbortOnDropHandle},
+    time::{self, Duration, Instant, SystemTime},
+};
+use n0_watcher::{Disconnected, Watchable, Watcher as _};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::{
+    net::UdpSocket,
+    sync::{broadcast, mpsc},
+};
+use tokio_stream::wrappers::{BroadcastStream, errors::BroadcastStreamRecvError};
+use tracing::{Instrument, debug, error_span, trace, warn};
+
+use crate::{
+    Endpoint,
+    address_lookup::{
+        AddressLookup, AddressLookupBuilder, AddressLookupBuilderError, EndpointData,
+        Error as AddressLookupError, Item as AddressLookupItem,
+    },
+};
+
+/// The default IPv4 multicast group announcements are sent to.
+///
+/// This is an address from the organization-local scope (`239.255.0.0/16`), which is not
+/// routed beyond the local network.
+pub const DEFAULT_MULTICAST_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 42, 98);
+
+/// The default UDP port announcements are sent to.
+pub const DEFAULT_MULTICAST_PORT: u16 = 53798;
+
+/// Interval in which our own endpoint data is announced even if unchanged: 10 seconds.
+pub const DEFAULT_ANNOUNCE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Duration after which an endpoint which has not been announced anymore is forgotten: 45 seconds.
+pub const DEFAULT_EXPIRY: Duration = Duration::from_secs(45);
+
+/// Time to wait for an answer to a query for an endpoint not heard from yet: 2 seconds.
+pub const DEFAULT_RESOLVE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The TTL of the records in announced signed packets.
+///
+/// Receivers expire entries based on [`MulticastLookupBuilder::expiry`], so this is
+/// informational only.
+const ANNOUNCE_TTL: u32 = 30;
+
+/// Magic prefix of all messages sent to the multicast group.
+const MAGIC: &[u8; 8] = b"iroh-lan";
+
+/// Version of the message format.
+const VERSION: u8 = 1;
+
+/// Minimum time between two announcements triggered by queries.
+const MIN_QUERY_ANSWER_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Capacity of the broadcast channel used for [`MulticastLookup::subscribe`].
+const EVENTS_CAPACITY: usize = 64;
+
+/// Maximum size of a message we are willing to receive.
+///
+/// A pkarr signed packet is at most 1104 bytes, plus our header.
+const MAX_MESSAGE_SIZE: usize = 2048;
+
+/// The provenance string for [`AddressLookupItem`]s produced by [`MulticastLookup`].
+const PROVENANCE: &str = "multicast";
+
+#[allow(missing_docs)]
+#[stack_error(derive, add_meta)]
+#[non_exhaustive]
+pub enum MulticastError {
+    #[error("Failed to bind multicast socket")]
+    Bind { source: std::io::Error },
+    #[error("Failed to join multicast group {group}")]
+    JoinGroup {
+        group: Ipv4Addr,
+        source: std::io::Error,
+    },
+}
+
+/// An event emitted by [`MulticastLookup::subscribe`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MulticastEvent {
+    /// An endpoint was heard on the local network for the first time, or announced changed data.
+    Discovered(AddressLookupItem),
+    /// An endpoint has not been heard from within the expiry duration and was forgotten.
+    Expired(EndpointId),
+    /// The subscriber fell behind and missed this number of events.
+    Lagged {
+        /// The number of missed events.
+        missed: u64,
+    },
+}
+
+/// Builder for [`MulticastLookup`].
+///
+/// See [`MulticastLookup::builder`].
+#[derive(Debug)]
+pub struct MulticastLookupBuilder {
+    group: SocketAddrV4,
+    interface: Ipv4Addr,
+    announce_interval: Duration,
+    expiry: Duration,
+    resolve_timeout: Duration,
+    advertise: bool,
+    filter: AddrFilter,
+}
+
+impl Default for MulticastLookupBuilder {
+    fn default() -> Self {
+        Self {
+            group: SocketAddrV4::new(DEFAULT_MULTICAST_GROUP, DEFAULT_MULTICAST_PORT),
+            interface: Ipv4Addr::UNSPECIFIED,
+            announce_interval: DEFAULT_ANNOUNCE_INTERVAL,
+            expiry: DEFAULT_EXPIRY,
+            resolve_timeout: DEFAULT_RESOLVE_TIMEOUT,
+            advertise: true,
+            filter: AddrFilter::unfiltered(),
+        }
+    }
+}
+
+impl MulticastLookupBuilder {
+    /// Sets the multicast group and port to announce to and listen on.
+    ///
+    /// All endpoints which should find each other need to use the same group.
+    /// Default is [`DEFAULT_MULTICAST_GROUP`] on port [`DEFAULT_MULTICAST_PORT`].
+    pub fn group(mut self, group: SocketAddrV4) -> Self {
+        self.group = group;
+        self
+    }
+
+    /// Sets the address of the local interface used to join the group and send announcements.
+    ///
+    /// Default is [`Ipv4Addr::UNSPECIFIED`], which lets the operating system choose.
+    pub fn interface(mut self, interface: Ipv4Addr) -> Self {
+        self.interface = interface;
+        self
+    }
+
+    /// Sets the interval after which our endpoint data is announced even if it did not change.
+    ///
+    /// Default is [`DEFAULT_ANNOUNCE_INTERVAL`].
+    pub fn announce_interval(mut self, announce_interval: Duration) -> Self {
+        self.announce_interval = announce_interval;
+        self
+    }
+
+    /// Sets the duration after which endpoints which are not announced anymore are forgotten.
+    ///
+    /// This should be a multiple of the announce interval used on the network.
+    /// Default is [`DEFAULT_EXPIRY`].
+    pub fn expiry(mut self, expiry: Duration) -> Self {
+        self.expiry = expiry;
+        self
+    }
+
+    /// Sets how long [`AddressLookup::resolve`] waits for endpoints not heard from yet.
+    ///
+    /// Default is [`DEFAULT_RESOLVE_TIMEOUT`].
+    pub fn resolve_timeout(mut self, resolve_timeout: Duration) -> Self {
+        self.resolve_timeout = resolve_timeout;
+        self
+    }
+
+    /// Sets whether our own endpoint data is announced to the group.
+    ///
+    /// When disabled the service only listens and resolves, it neither announces our endpoint
+    /// nor answers queries for it.  Default is `true`.
+    pub fn advertise(mut self, advertise: bool) -> Self {
+        self.advertise = advertise;
+        self
+    }
+
+    /// Sets the address filter to control which addresses are announced.
+    ///
+    /// By default [`AddrFilter::unfiltered`] is used, since announcements do not leave the
+    /// local network and the direct addresses are the most useful information there.
+    pub fn addr_filter(mut self, filter: AddrFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Builds the [`MulticastLookup`] with the passed signer for signing announcements.
+    ///
+    /// The `signer` can be a [`SecretKey`]. This binds the multicast socket and must be
+    /// called from within a tokio runtime.
+    ///
+    /// [`SecretKey`]: crate::SecretKey
+    pub fn build(self, signer: impl Signer) -> Result<MulticastLookup, MulticastError> {
+        self.build_with_signer(Arc::new(signer))
+    }
+
+    fn build_with_signer(self, signer: Arc<dyn Signer>) -> Result<MulticastLookup, MulticastError> {
+        let socket = bind_socket(self.group, self.interface)?;
+        let endpoint_id = signer.public_key();
+        debug!(group = %self.group, "creating multicast address lookup");
+
+        let (events, _) = broadcast::channel(EVENTS_CAPACITY);
+        let shared = Arc::new(Shared {
+            endpoints: Default::default(),
+            events,
+        });
+        let (query_tx, query_rx) = mpsc::channel(16);
+        let watchable = Watchable::default();
+        let actor = Actor {
+            signer: signer.clone(),
+            socket,
+            group: self.group,
+            announce_interval: self.announce_interval,
+            expiry: self.expiry,
+            advertise: self.advertise,
+            watcher: watchable.watch(),
+            shared: shared.clone(),
+            query_rx,
+            signed_packet: None,
+            last_query_answer: None,
+        };
+        let join_handle = task::spawn(
+            actor
+                .run()
+                .instrument(error_span!("multicast", me = %endpoint_id.fmt_short())),
+        );
+        Ok(MulticastLookup {
+            signer,
+            shared,
+            watchable,
+            query_tx,
+            addr_filter: self.filter,
+            resolve_timeout: self.resolve_timeout,
+            _drop_guard: Arc::new(AbortOnDropHandle::new(join_handle)),
+        })
+    }
+}
+
+impl AddressLookupBuilder for MulticastLookupBuilder {
+    fn into_address_lookup(
+        self,
+        endpoint: &Endpoint,
+    ) -> Result<impl AddressLookup, AddressLookupBuilderError> {
+        self.build_with_signer(endpoint.signer().clone())
+            .map_err(|err| AddressLookupBuilderError::from_err("multicast", err))
+    }
+}
+
+/// Address lookup on the local network using UDP multicast.
+///
+/// This announces our own endpoint data to a multicast group and resolves endpoints from
+/// the announcements heard on that group, see the [module docs] for details.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[cfg(with_crypto_provider)] // Endpoint::bind needs a crypto provider
+/// # {
+/// use iroh::{
+///     Endpoint,
+///     address_lookup::multicast::{MulticastEvent, MulticastLookup},
+///     endpoint::presets,
+/// };
+/// use n0_future::StreamExt;
+///
+/// # async fn wrapper() -> n0_error::Result<()> {
+/// let endpoint = Endpoint::builder(presets::Minimal).bind().await?;
+/// let lookup = MulticastLookup::builder().build(endpoint.signer().clone())?;
+/// endpoint.address_lookup()?.add(lookup.clone());
+///
+/// let mut events = lookup.subscribe();
+/// while let Some(event) = events.next().await {
+///     if let MulticastEvent::Discovered(item) = event {
+///         println!("found {} on the local network", item.endpoint_id());
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// # }
+/// ```
+///
+/// [module docs]: crate::address_lookup::multicast
+#[derive(Debug, Clone)]
+pub struct MulticastLookup {
+    signer: Arc<dyn Signer>,
+    shared: Arc<Shared>,
+    watchable: Watchable<Option<EndpointInfo>>,
+    query_tx: mpsc::Sender<EndpointId>,
+    addr_filter: AddrFilter,
+    resolve_timeout: Duration,
+    _drop_guard: Arc<AbortOnDropHandle<()>>,
+}
+
+impl MulticastLookup {
+    /// The provenance string for this Address Lookup implementation.
+    pub const PROVENANCE: &'static str = PROVENANCE;
+
+    /// Returns a [`MulticastLookupBuilder`] with the default settings.
+    ///
+    /// [`MulticastLookupBuilder`] implements [`AddressLookupBuilder`], so it can be passed to
+    /// [`address_lookup`].  It will then use the endpoint's secret key to sign announcements.
+    ///
+    /// [`address_lookup`]: crate::endpoint::Builder::address_lookup
+    pub fn builder() -> MulticastLookupBuilder {
+        MulticastLookupBuilder::default()
+    }
+
+    /// Returns a stream of [`MulticastEvent`]s for endpoints seen on the local network.
+    ///
+    /// Only events which happen after subscribing are emitted.  Use [`Self::endpoints`] to
+    /// get the endpoints which are currently known.
+    pub fn subscribe(&self) -> impl Stream<Item = MulticastEvent> + Send + Unpin + 'static {
+        BroadcastStream::new(self.shared.events.subscribe()).map(|event| match event {
+            Ok(event) => event,
+            Err(BroadcastStreamRecvError::Lagged(missed)) => MulticastEvent::Lagged { missed },
+        })
+    }
+
+    /// Returns the endpoints currently known on the local network.
+    pub fn endpoints(&self) -> Vec<AddressLookupItem> {
+        let guard = self.shared.endpoints.read().expect("poisoned");
+        guard.values().map(Seen::to_item).collect()
+    }
+
+    /// Announces the addressing information about this endpoint on the local network.
+    ///
+    /// This is a nonblocking function, the actual announcement is performed in the background.
+    pub fn update_endpoint_data(&self, data: &EndpointData) {
+        let data = data.apply_filter(&self.addr_filter).into_owned();
+        let info = EndpointInfo::from_parts(self.signer.public_key(), data);
+        self.watchable.set(Some(info)).ok();
+    }
+}
+
+impl AddressLookup for MulticastLookup {
+    fn publish(&self, data: &EndpointData) {
+        self.update_endpoint_data(data);
+    }
+
+    fn resolve(
+        &self,
+        endpoint_id: EndpointId,
+    ) -> Option<BoxStream<Result<AddressLookupItem, AddressLookupError>>> {
+        if let Some(seen) = self.shared.get(&endpoint_id) {
+            let item = seen.to_item();
+            return Some(n0_future::stream::once(Ok(item)).boxed());
+        }
+
+        // Not heard from yet: subscribe before sending the query, to not miss the answer.
+        let events = self.subscribe();
+        self.query_tx.try_send(endpoint_id).ok();
+        let timeout = self.resolve_timeout;
+        let fut = async move {
+            let found = events.filter_map(|event| match event {
+                MulticastEvent::Discovered(item) if item.endpoint_id() == endpoint_id => Some(item),
+                _ => None,
+            });
+            tokio::pin!(found);
+            time::timeout(timeout, found.next()).await.ok().flatten()
+        };
+        let stream = n0_future::stream::once_future(fut).filter_map(|item| item.map(Ok));
+        Some(Box::pin(stream))
+    }
+}
+
+/// State shared between the [`MulticastLookup`] handles and its actor.
+#[derive(Debug)]
+struct Shared {
+    endpoints: RwLock<HashMap<EndpointId, Seen>>,
+    events: broadcast::Sender<MulticastEvent>,
+}
+
+impl Shared {
+    fn get(&self, endpoint_id: &EndpointId) -> Option<Seen> {
+        self.endpoints
+            .read()
+            .expect("poisoned")
+            .get(endpoint_id)
+            .cloned()
+    }
+}
+
+/// An endpoint heard on the multicast group.
+#[derive(Debug, Clone)]
+struct Seen {
+    info: EndpointInfo,
+    /// Timestamp of the signed packet, used to ignore outdated announcements.
+    packet_timestamp: u64,
+    /// When we last heard an announcement of this endpoint.
+    last_seen: Instant,
+    /// Same as `last_seen`, but as system time for [`AddressLookupItem::last_updated`].
+    last_updated: SystemTime,
+}
+
+impl Seen {
+    fn to_item(&self) -> AddressLookupItem {
+        let last_updated = self
+            .last_updated
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("time drift")
+            .as_micros() as u64;
+        AddressLookupItem::new(self.info.clone(), PROVENANCE, Some(last_updated))
+    }
+}
+
+/// A message sent to the multicast group.
+#[derive(Debug, PartialEq, Eq)]
+enum Message {
+    /// Announces the signed endpoint data of the sender.
+    Announce(SignedPacket),
+    /// Asks the endpoint with this id to announce itself.
+    Query(EndpointId),
+}
+
+impl Message {
+    const ANNOUNCE: u8 = 0;
+    const QUERY: u8 = 1;
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(MAX_MESSAGE_SIZE);
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        match self {
+            Message::Announce(packet) => {
+                out.push(Self::ANNOUNCE);
+                out.extend_from_slice(packet.as_bytes());
+            }
+            Message::Query(endpoint_id) => {
+                out.push(Self::QUERY);
+                out.extend_from_slice(endpoint_id.as_bytes());
+            }
+        }
+        out
+    }
+
+    /// Parses and verifies a message.
+    ///
+    /// Returns `None` for messages not sent by this protocol version or failing to verify.
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let rest = bytes.strip_prefix(MAGIC.as_slice())?;
+        let (&[version, kind], payload) = rest.split_first_chunk::<2>()?;
+        if version != VERSION {
+            return None;
+        }
+        match kind {
+            Self::ANNOUNCE => SignedPacket::from_bytes(payload)
+                .ok()
+                .map(Message::Announce),
+            Self::QUERY => {
+                let bytes: &[u8; 32] = payload.try_into().ok()?;
+                PublicKey::from_bytes(bytes).ok().map(Message::Query)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Owns the multicast socket, announces our endpoint and processes received messages.
+#[derive(derive_more::Debug)]
+struct Actor {
+    /// Signs for our endpoint id, which changes if the endpoint key is rotated.
+    signer: Arc<dyn Signer>,
+    socket: UdpSocket,
+    group: SocketAddrV4,
+    announce_interval: Duration,
+    expiry: Duration,
+    advertise: bool,
+    watcher: n0_watcher::Direct<Option<EndpointInfo>>,
+    shared: Arc<Shared>,
+    query_rx: mpsc::Receiver<EndpointId>,
+    /// The signed packet of our current endpoint data.
+    signed_packet: Option<SignedPacket>,
+    last_query_answer: Option<Instant>,
+}
+
+impl Actor {
+    async fn run(mut self) {
+        let mut buf = vec![0u8; MAX_MESSAGE_SIZE];
+        let announce = time::sleep(Duration::ZERO);
+        tokio::pin!(announce);
+        let mut expire = time::interval(self.expiry / 3);
+        loop {
+            tokio::select! {
+                res = self.socket.recv_from(&mut buf) => match res {
+                    Ok((len, from)) => self.handle_message(&buf[..len], from).await,
+                    Err(err) => {
+                        warn!("failed to receive on multicast socket: {err:#}");
+                        time::sleep(Duration::from_millis(100)).await;
+                    }
+                },
+                res = self.watcher.updated() => match res {
+                    Ok(_) => {
+                        debug!("endpoint data changed, announcing");
+                        self.signed_packet = None;
+                        announce.as_mut().reset(Instant::now());
+                    }
+                    Err(Disconnected { .. }) => break,
+                },
+                _ = &mut announce => {
+                    self.announce().await;
+                    announce.as_mut().reset(Instant::now() + self.announce_interval);
+                }
+                Some(endpoint_id) = self.query_rx.recv() => {
+                    trace!(endpoint_id = %endpoint_id.fmt_short(), "sending query");
+                    self.send(Message::Query(endpoint_id)).await;
+                }
+                _ = expire.tick() => self.expire(),
+            }
+        }
+    }
+
+    async fn handle_message(&mut self, bytes: &[u8], from: SocketAddr) {
+        let Some(message) = Message::from_bytes(bytes) else {
+            trace!(%from, "ignoring invalid message");
+            return;
+        };
+        match message {
+            Message::Announce(packet) => self.handle_announce(packet, from),
+            Message::Query(endpoint_id) => {
+                if !self.advertise || endpoint_id != self.signer.public_key() {
+                    return;
+                }
+                let now = Instant::now();
+                if self
+                    .last_query_answer
+                    .is_some_and(|last| now.duration_since(last) < MIN_QUERY_ANSWER_INTERVAL)
+                {
+                    return;
+                }
+                trace!(%from, "answering query");
+                self.last_query_answer = Some(now);
+                self.announce().await;
+            }
+        }
+    }
+
+    fn handle_announce(&mut self, packet: SignedPacket, from: SocketAddr) {
+        let endpoint_id = packet.public_key();
+        if endpoint_id == self.signer.public_key() {
+            return;
+        }
+        let info = match EndpointInfo::from_pkarr_signed_packet(&packet) {
+            Ok(info) => info,
+            Err(err) => {
+                debug!(%from, "ignoring invalid announcement: {err:#}");
+                return;
+            }
+        };
+        let packet_timestamp = packet.timestamp().as_micros();
+        let seen = Seen {
+            info,
+            packet_timestamp,
+            last_seen: Instant::now(),
+            last_updated: SystemTime::now(),
+        };
+        let mut guard = self.shared.endpoints.write().expect("poisoned");
+        let changed = match guard.entry(endpoint_id) {
+            Entry::Occupied(mut entry) => {
+                let existing = entry.get_mut();
+                if packet_timestamp < existing.packet_timestamp {
+                    trace!(%from, "ignoring outdated announcement");
+                    return;
+                }
+                let changed = existing.info != seen.info;
+                *existing = seen.clone();
+                changed
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(seen.clone());
+                true
+            }
+        };
+        drop(guard);
+        if changed {
+            debug!(endpoint_id = %endpoint_id.fmt_short(), %from, "discovered endpoint");
+            self.shared
+                .events
+                .send(MulticastEvent::Discovered(seen.to_item()))
+                .ok();
+        }
+    }
+
+    fn expire(&mut self) {
+        let now = Instant::now();
+        let mut expired = Vec::new();
+        self.shared
+            .endpoints
+            .write()
+            .expect("poisoned")
+            .retain(|endpoint_id, seen| {
+                let keep = now.duration_since(seen.last_seen) < self.expiry;
+                if !keep {
+                    expired.push(*endpoint_id);
+                }
+                keep
+            });
+        for endpoint_id in expired {
+            debug!(endpoint_id = %endpoint_id.fmt_short(), "endpoint expired");
+            self.shared
+                .events
+                .send(MulticastEvent::Expired(endpoint_id))
+                .ok();
+        }
+    }
+
+    async fn announce(&mut self) {
+        if !self.advertise {
+            return;
+        }
+        if self.signed_packet.is_none() {
+            let Some(info) = self.watcher.get() else {
+                return;
+            };
+            match info.to_pkarr_signed_packet(&*self.signer, ANNOUNCE_TTL) {
+                Ok(packet) => self.signed_packet = Some(packet),
+                Err(err) => {
+                    warn!("failed to sign endpoint data: {err:#}");
+                    return;
+                }
+            }
+        }
+        if let Some(packet) = self.signed_packet.clone() {
+            trace!("announcing endpoint data");
+            self.send(Message::Announce(packet)).await;
+        }
+    }
+
+    async fn send(&self, message: Message) {
+        if let Err(err) = self
+            .socket
+            .send_to(&message.to_bytes(), SocketAddr::V4(self.group))
+            .await
+        {
+            debug!(group = %self.group, "failed to send to multicast group: {err:#}");
+        }
+    }
+}
+
+/// Binds a UDP socket to the group's port and joins the multicast group.
+///
+/// The socket allows address reuse, so that multiple endpoints on the same host can listen
+/// on the group.
+fn bind_socket(group: SocketAddrV4, interface: Ipv4Addr) -> Result<UdpSocket, MulticastError> {
+    let bind = |group: SocketAddrV4| -> std::io::Result<Socket> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+        socket.set_reuse_port(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, group.port())).into())?;
+        socket.set_multicast_loop_v4(true)?;
+        if !interface.is_unspecified() {
+            socket.set_multicast_if_v4(&interface)?;
+        }
+        Ok(socket)
+    };
+    let socket = bind(group).map_err(|err| e!(MulticastError::Bind, err))?;
+    socket
+        .join_multicast_v4(group.ip(), &interface)
+        .map_err(|err| {
+            e!(MulticastError::JoinGroup {
+                group: *group.ip(),
+                source: err
+            })
+        })?;
+    UdpSocket::from_std(socket.into()).map_err(|err| e!(MulticastError::Bind, err))
+}
+
+#[cfg(all(test, with_crypto_provider))]
+mod tests {
+    use iroh_base::{RelayUrl, SecretKey, TransportAddr};
+    use n0_error::{Result, StackResultExt, StdResultExt};
+    use n0_tracing_test::traced_test;
+    use rand::{RngExt, SeedableRng};
+
+    use super::*;
+
+    /// Returns a builder on a random port, so that tests don't interfere with each other.
+    fn test_builder(port: u16) -> MulticastLookupBuilder {
+        MulticastLookup::builder()
+            .group(SocketAddrV4::new(DEFAULT_MULTICAST_GROUP, port))
+            .interface(Ipv4Addr::LOCALHOST)
+            .announce_interval(Duration::from_millis(200))
+            .expiry(Duration::from_millis(900))
+    }
+
+    fn test_port(rng: &mut rand_chacha::ChaCha8Rng) -> u16 {
+        rng.random_range(40000..50000)
+    }
+
+    fn test_data() -> Result<EndpointData> {
+        let relay: RelayUrl = "https://relay.example.com".parse()?;
+        Ok(EndpointData::new(vec![
+            TransportAddr::Relay(relay),
+            TransportAddr::Ip("192.168.1.10:1234".parse().unwrap()),
+        ]))
+    }
+
+    #[test]
+    fn test_message_roundtrip() -> Result {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0);
+        let secret_key = SecretKey::from_bytes(&rng.random());
+        let info = EndpointInfo::from_parts(secret_key.public(), test_data()?);
+        let packet = info.to_pkarr_signed_packet(&secret_key, ANNOUNCE_TTL)?;
+
+        let announce = Message::Announce(packet);
+        assert_eq!(Message::from_bytes(&announce.to_bytes()), Some(announce));
+        let query = Message::Query(secret_key.public());
+        assert_eq!(Message::from_bytes(&query.to_bytes()), Some(query));
+
+        // Tampering with the signed packet fails verification.
+        let mut bytes =
+            Message::Announce(info.to_pkarr_signed_packet(&secret_key, ANNOUNCE_TTL)?).to_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        assert_eq!(Message::from_bytes(&bytes), None);
+        assert_eq!(Message::from_bytes(b"not-iroh"), None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn test_announce_and_resolve() -> Result {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(1);
+        let port = test_port(&mut rng);
+        let key_a = SecretKey::from_bytes(&rng.random());
+        let key_b = SecretKey::from_bytes(&rng.random());
+        let lookup_a = test_builder(port).build(key_a.clone())?;
+        let lookup_b = test_builder(port).build(key_b.clone())?;
+
+        let mut events = lookup_b.subscribe();
+        let data = test_data()?;
+        lookup_a.publish(&data);
+
+        let event = time::timeout(Duration::from_secs(5), events.next())
+            .await
+            .std_context("timeout")?
+            .context("stream ended")?;
+        let MulticastEvent::Discovered(item) = event else {
+            panic!("unexpected event {event:?}");
+        };
+        assert_eq!(item.endpoint_id(), key_a.public());
+        assert_eq!(item.provenance(), MulticastLookup::PROVENANCE);
+        assert_eq!(item.endpoint_info().data, data);
+
+        let item = lookup_b
+            .resolve(key_a.public())
+            .context("no stream")?
+            .next()
+            .await
+            .context("no item")??;
+        assert_eq!(item.endpoint_info().data, data);
+
+        // We never learn about ourselves.
+        assert!(lookup_a.endpoints().is_empty());
+
+        // Once a stops announcing, it expires.
+        drop(lookup_a);
+        let event = time::timeout(Duration::from_secs(5), events.next())
+            .await
+            .std_context("timeout")?
+            .context("stream ended")?;
+        assert_eq!(event, MulticastEvent::Expired(key_a.public()));
+        assert!(lookup_b.endpoints().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn test_resolve_sends_query() -> Result {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(2);
+        let port = test_port(&mut rng);
+        let key_a = SecretKey::from_bytes(&rng.random());
+        let key_b = SecretKey::from_bytes(&rng.random());
+        // a only announces very rarely, so b must query for it.
+        let lookup_a = test_builder(port)
+            .announce_interval(Duration::from_secs(3600))
+            .build(key_a.clone())?;
+        let lookup_b = test_builder(port)
+            .resolve_timeout(Duration::from_secs(5))
+            .build(key_b.clone())?;
+        lookup_a.publish(&test_data()?);
+        // Let the initial announcement pass.
+        time::sleep(Duration::from_millis(100)).await;
+        lookup_b.shared.endpoints.write().expect("poisoned").clear();
+
+        let item = lookup_b
+            .resolve(key_a.public())
+            .context("no stream")?
+            .next()
+            .await
+            .context("no item")??;
+        assert_eq!(item.endpoint_id(), key_a.public());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_resolve_timeout() -> Result {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(3);
+        let port = test_port(&mut rng);
+        let lookup = test_builder(port)
+            .resolve_timeout(Duration::from_millis(100))
+            .build(SecretKey::from_bytes(&rng.random()))?;
+        let unknown = SecretKey::from_bytes(&rng.random()).public();
+        let items: Vec<_> = lookup
+            .resolve(unknown)
+            .context("no stream")?
+            .collect()
+            .await;
+        assert!(items.is_empty());
+        Ok(())
+    }
+}
@@ -56,7 +56,7 @@
 
 use std::sync::Arc;
 
-use iroh_base::{EndpointId, RelayUrl, SecretKey};
+use iroh_base::{EndpointId, RelayUrl, Signer};
 use iroh_dns::{
     EncodingError,
     endpoint_info::{AddrFilter, EndpointInfo},
@@ -80,7 +80,7 @@ use crate::{
         AddressLookup, AddressLookupBuilder, AddressLookupBuilderError, EndpointData,
         Error as AddressLookupError, Item as AddressLookupItem,
     },
-    endpoint::force_staging_infra,
+    endpoint::{Identity, force_staging_infra},
     util::reqwest_client_builder,
 };
 
@@ -219,12 +219,25 @@ impl PkarrPublisherBuilder {
         self
     }
 
-    /// Builds the [`PkarrPublisher`] with the passed secret key for signing packets.
+    /// Builds the [`PkarrPublisher`] with the passed signer for signing packets.
     ///
-    /// This publisher will be able to publish [pkarr](https://pkarr.org) records for [`SecretKey`].
-    pub fn build(self, secret_key: SecretKey, tls_config: rustls::ClientConfig) -> PkarrPublisher {
+    /// This publisher will be able to publish [pkarr](https://pkarr.org) records for the public
+    /// key of the `signer`, which can be a [`SecretKey`].
+    ///
+    /// [`SecretKey`]: crate::SecretKey
+    pub fn build(self, signer: impl Signer, tls_config: rustls::ClientConfig) -> PkarrPublisher {
+        self.build_with_signer(Arc::new(signer), None, tls_config)
+    }
+
+    fn build_with_signer(
+        self,
+        signer: Arc<dyn Signer>,
+        identity: Option<Arc<Identity>>,
+        tls_config: rustls::ClientConfig,
+    ) -> PkarrPublisher {
         PkarrPublisher::new(
-            secret_key,
+            signer,
+            identity,
             self.pkarr_relay,
             self.ttl,
             self.republish_interval,
@@ -246,7 +259,11 @@ impl AddressLookupBuilder for PkarrPublisherBuilder {
             self.dns_resolver = Some(endpoint.dns_resolver()?.clone());
         }
         let tls_config = endpoint.tls_config().clone();
-        Ok(self.build(endpoint.secret_key().clone(), tls_config))
+        Ok(self.build_with_signer(
+            endpoint.signer().clone(),
+            Some(endpoint.identity().clone()),
+            tls_config,
+        ))
     }
 }
 
@@ -263,13 +280,17 @@ impl AddressLookupBuilder for PkarrPublisherBuilder {
 /// the public pkarr server.  Which addresses are published is controlled by the [`AddrFilter`]
 /// set via [`PkarrPublisherBuilder::addr_filter`].
 ///
+/// When used as address lookup service of an [`Endpoint`] which rotated its key, the
+/// publisher also publishes a record for each retired key which only contains the signed
+/// succession to the next key, see [`Endpoint::rotate_secret_key`].
+///
 /// [pkarr]: https://pkarr.org
 /// [module docs]: crate::address_lookup::pkarr
 /// [`RelayUrl`]: crate::RelayUrl
 /// [`AddressLookupServices`]: super::AddressLookupServices
 #[derive(derive_more::Debug, Clone)]
 pub struct PkarrPublisher {
-    endpoint_id: EndpointId,
+    signer: Arc<dyn Signer>,
     watchable: Watchable<Option<EndpointInfo>>,
     addr_filter: AddrFilter,
     _drop_guard: Arc<AbortOnDropHandle<()>>,
@@ -283,7 +304,7 @@ impl PkarrPublisher {
     /// every [`DEFAULT_REPUBLISH_INTERVAL`], even if the information is unchanged.
     ///
     /// [`PkarrPublisherBuilder`] implements [`AddressLookupBuilder`], so it can be passed to [`address_lookup`].
-    /// It will then use the endpoint's secret key to sign published packets.
+    /// It will then use the endpoint's [`Signer`] to sign published packets.
     ///
     /// [`address_lookup`]:  crate::endpoint::Builder::address_lookup
     /// [pkarr]: https://pkarr.org
@@ -295,8 +316,10 @@ impl PkarrPublisher {
     ///
     /// This allows creating the publisher with custom time-to-live values of the
     /// [`SignedPacket`]s as well as a custom republish interval.
+    #[allow(clippy::too_many_arguments)]
     fn new(
-        secret_key: SecretKey,
+        signer: Arc<dyn Signer>,
+        identity: Option<Arc<Identity>>,
         pkarr_relay: Url,
         ttl: u32,
         republish_interval: Duration,
@@ -305,7 +328,6 @@ impl PkarrPublisher {
         addr_filter: AddrFilter,
     ) -> Self {
         debug!("creating pkarr publisher that publishes to {pkarr_relay}");
-        let endpoint_id = secret_key.public();
 
         #[cfg(wasm_browser)]
         let pkarr_client = PkarrRelayClient::new(pkarr_relay);
@@ -317,14 +339,15 @@ impl PkarrPublisher {
         let service = PublisherService {
             ttl,
             watcher: watchable.watch(),
-            secret_key,
+            signer: signer.clone(),
+            identity,
             pkarr_client,
             republish_interval,
         };
         let join_handle = task::spawn(service.run().instrument(error_span!("pkarr_publish")));
         Self {
             watchable,
-            endpoint_id,
+            signer,
             addr_filter,
             _drop_guard: Arc::new(AbortOnDropHandle::new(join_handle)),
         }
@@ -349,7 +372,7 @@ impl PkarrPublisher {
     /// This is a nonblocking function, the actual update is performed in the background.
     pub fn update_endpoint_data(&self, data: &EndpointData) {
         let data = data.apply_filter(&self.addr_filter).into_owned();
-        let info = EndpointInfo::from_parts(self.endpoint_id, data);
+        let info = EndpointInfo::from_parts(self.signer.public_key(), data);
         self.watchable.set(Some(info)).ok();
     }
 }
@@ -363,8 +386,9 @@ impl AddressLookup for PkarrPublisher {
 /// Publish endpoint info to a pkarr relay.
 #[derive(derive_more::Debug, Clone)]
 struct PublisherService {
-    #[debug("SecretKey")]
-    secret_key: SecretKey,
+    signer: Arc<dyn Signer>,
+    /// The identity of the endpoint, to also publish the successions of its retired keys.
+    identity: Option<Arc<Identity>>,
     #[debug("PkarrClient")]
     pkarr_client: PkarrRelayClient,
     watcher: n0_watcher::Direct<Option<EndpointInfo>>,
@@ -423,7 +447,7 @@ impl PublisherService {
             "Publishing endpoint info to pkarr"
         );
         let signed_packet = info
-            .to_pkarr_signed_packet(&self.secret_key, self.ttl)
+            .to_pkarr_signed_packet(&*self.signer, self.ttl)
             .map_err(|err| e!(PkarrError::Encoding, err))?;
         self.pkarr_client.publish(&signed_packet).await?;
         trace!(
@@ -431,8 +455,41 @@ impl PublisherService {
             pkarr_relay = %self.pkarr_client.pkarr_relay_url,
             "Published endpoint info to pkarr"
         );
+        self.publish_retired().await;
         Ok(())
     }
+
+    /// Publishes the successions of retired keys.
+    ///
+    /// The records of retired keys only point to the next key, connecting to a retired key
+    /// is not possible anymore.
+    async fn publish_retired(&self) {
+        let Some(identity) = &self.identity else {
+            return;
+        };
+        for retired in identity.retired() {
+            let succession = retired.succession;
+            let info = EndpointInfo::from_parts(
+                succession.predecessor(),
+                EndpointData::new(Vec::new()).with_successor(succession),
+            );
+            let res = match info.to_pkarr_signed_packet(&*retired.signer, self.ttl) {
+                Ok(signed_packet) => self.pkarr_client.publish(&signed_packet).await,
+                Err(err) => Err(e!(PkarrError::Encoding, err)),
+            };
+            match res {
+                Ok(()) => trace!(
+                    retired = %succession.predecessor().fmt_short(),
+                    "Published key succession to pkarr"
+                ),
+                Err(err) => warn!(
+                    err = %format!("{err:#}"),
+                    retired = %succession.predecessor().fmt_short(),
+                    "Failed to publish key succession to pkarr"
+                ),
+            }
+        }
+    }
 }
 
 /// Builder for [`PkarrResolver`].
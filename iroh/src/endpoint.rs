@@ -15,7 +15,9 @@ use std::{collections::BTreeSet, net::SocketAddr, pin::Pin, sync::Arc};
 
 #[cfg(not(wasm_browser))]
 use ipnet::{Ipv4Net, Ipv6Net};
-use iroh_base::{EndpointAddr, EndpointId, RelayUrl, SecretKey, TransportAddr};
+use iroh_base::{
+    EndpointAddr, EndpointId, KeySuccession, RelayUrl, SecretKey, Signer, TransportAddr,
+};
 use iroh_relay::{RelayConfig, RelayMap, tls::CaTlsConfig};
 #[cfg(not(wasm_browser))]
 use n0_error::bail;
@@ -23,7 +25,7 @@ use n0_error::{AnyError, e, ensure, stack_error};
 use n0_watcher::Watcher;
 use pin_project::pin_project;
 use tokio_util::sync::WaitForCancellationFutureOwned;
-use tracing::{Instrument, Span, debug, event, info_span, instrument, warn};
+use tracing::{Instrument, Span, debug, event, info, info_span, instrument, warn};
 use url::Url;
 
 #[cfg(feature = "unstable-custom-transports")]
@@ -84,12 +86,18 @@ use crate::{
 mod bind;
 mod connection;
 pub(crate) mod hooks;
+mod identity;
+mod pool;
 pub mod presets;
 pub(crate) mod quic;
+mod relay_manifest;
 
 #[cfg(not(wasm_browser))]
 pub use bind::{BindOpts, InvalidSocketAddr, ToSocketAddr};
 pub use hooks::{AfterHandshakeOutcome, BeforeConnectOutcome, EndpointHooks};
+pub(crate) use identity::Identity;
+pub use identity::RotateKeyError;
+pub use relay_manifest::{DEFAULT_RELAY_MANIFEST_REFRESH_INTERVAL, RelayManifestSource};
 
 #[cfg(feature = "qlog")]
 pub use self::quic::{QlogConfig, QlogFactory, QlogFileFactory};
@@ -100,6 +108,7 @@ pub use self::{
         IncomingZeroRttConnection, OutgoingZeroRtt, OutgoingZeroRttConnection,
         RemoteEndpointIdError, RetryError, WeakConnectionHandle, ZeroRttStatus,
     },
+    pool::{ConnectionPool, PoolConnectError, PoolOptions},
     quic::{
         AcceptBi, AcceptUni, AckFrequencyConfig, ApplicationClose, Chunk, Closed, ClosedStream,
         ConnectionClose, ConnectionError, ConnectionStats, Controller, ControllerFactory,
@@ -122,12 +131,14 @@ pub use crate::{net_report::NetReportConfig, portmapper::PortmapperConfig};
 /// Builder for [`Endpoint`].
 ///
 /// By default the endpoint will generate a new random [`SecretKey`], which will result in a
-/// new [`EndpointId`].
+/// new [`EndpointId`]. Use [`Builder::secret_key`] or [`Builder::signer`] to set the identity
+/// of the endpoint instead.
 ///
 /// To create the [`Endpoint`] call [`Builder::bind`].
 #[derive(Debug)]
 pub struct Builder {
     secret_key: Option<SecretKey>,
+    signer: Option<Arc<dyn Signer>>,
     alpn_protocols: Vec<Vec<u8>>,
     transport_config: QuicTransportConfig,
     keylog: bool,
@@ -141,9 +152,11 @@ pub struct Builder {
     #[cfg(not(wasm_browser))]
     dns_resolver: Option<DnsResolver>,
     transports: Vec<TransportConfig>,
+    relay_manifest: Option<RelayManifestSource>,
     max_tls_tickets: usize,
     hooks: EndpointHooksList,
     path_selector: Arc<dyn PathSelector>,
+    per_remote_metrics: usize,
     portmapper_config: PortmapperConfig,
     net_report_config: NetReportConfig,
     crypto_provider: Option<Arc<rustls::crypto::CryptoProvider>>,
@@ -198,6 +211,7 @@ impl Builder {
 
         Self {
             secret_key: Default::default(),
+            signer: None,
             alpn_protocols: Default::default(),
             transport_config: QuicTransportConfig::default(),
             keylog: Default::default(),
@@ -210,8 +224,10 @@ impl Builder {
             dns_resolver: None,
             max_tls_tickets: DEFAULT_MAX_TLS_TICKETS,
             transports,
+            relay_manifest: None,
             hooks: Default::default(),
             path_selector: Arc::new(BiasedRttPathSelector::default()),
+            per_remote_metrics: 0,
             portmapper_config: Default::default(),
             net_report_config: Default::default(),
             crypto_provider: None,
@@ -223,8 +239,6 @@ impl Builder {
 
     /// Binds the endpoint.
     pub async fn bind(self) -> Result<Endpoint, BindError> {
-        let secret_key = self.secret_key.unwrap_or_else(SecretKey::generate);
-
         let crypto_provider = self
             .crypto_provider
             .ok_or_else(|| e!(BindError::InvalidCryptoProvider))?;
@@ -234,14 +248,21 @@ impl Builder {
                 .ok_or_else(|| e!(BindError::InvalidCryptoProvider))?,
         );
 
-        let span = info_span!("endpoint", id = %secret_key.public().fmt_short());
+        let tls_config = match (self.signer, self.secret_key) {
+            (Some(signer), _) => {
+                tls::TlsConfig::with_signer(signer, self.max_tls_tickets, crypto_provider.clone())
+            }
+            (None, secret_key) => tls::TlsConfig::new(
+                secret_key.unwrap_or_else(SecretKey::generate),
+                self.max_tls_tickets,
+                crypto_provider.clone(),
+            ),
+        };
+        let signer = tls_config.signer.clone();
+
+        let span = info_span!("endpoint", id = %tls_config.endpoint_id().fmt_short());
         let _guard = span.enter();
 
-        let tls_config = tls::TlsConfig::new(
-            secret_key.clone(),
-            self.max_tls_tickets,
-            crypto_provider.clone(),
-        );
         let static_config = StaticConfig {
             server_config: tls_config.make_server_config(self.keylog)?,
             client_config: tls_config.make_client_config(self.keylog)?,
@@ -265,7 +286,7 @@ impl Builder {
 
         let sock_opts = socket::Options {
             transports: self.transports,
-            secret_key,
+            signer,
             address_lookup_user_data: self.address_lookup_user_data,
             proxy_url: self.proxy_url,
             #[cfg(not(wasm_browser))]
@@ -273,6 +294,7 @@ impl Builder {
             server_config,
             tls_config,
             metrics,
+            per_remote_metrics: self.per_remote_metrics,
             hooks: self.hooks,
             path_selector: self.path_selector,
             portmapper_config: self.portmapper_config,
@@ -285,7 +307,7 @@ impl Builder {
             .instrument(Span::current())
             .await?;
         debug!(
-            id = %inner.static_config.tls_config.secret_key.public(),
+            id = %inner.static_config.tls_config.endpoint_id(),
             iroh_version = %env!("CARGO_PKG_VERSION"),
             "iroh endpoint bound"
         );
@@ -304,6 +326,10 @@ impl Builder {
             address_lookup.add_boxed(service);
         }
 
+        if let Some(source) = self.relay_manifest {
+            source.spawn(&ep);
+        }
+
         Ok(ep)
     }
 
@@ -523,6 +549,23 @@ impl Builder {
     /// [`PublicKey`]: iroh_base::PublicKey
     pub fn secret_key(mut self, secret_key: SecretKey) -> Self {
         self.secret_key = Some(secret_key);
+        self.signer = None;
+        self
+    }
+
+    /// Sets a [`Signer`] to authenticate with other peers, instead of a secret key.
+    ///
+    /// This allows keeping the secret key outside of the process, e.g. in a hardware
+    /// security module or a separate signing service. The public key of the signer will be
+    /// the [`EndpointId`] of this endpoint, and all signatures the endpoint needs (the TLS
+    /// handshake, relay authentication and address lookup publishing) are made by the
+    /// signer.
+    ///
+    /// This replaces any secret key set by [`Builder::secret_key`]. The endpoint has no
+    /// secret key then, [`Endpoint::try_secret_key`] returns `None`.
+    pub fn signer(mut self, signer: impl Signer) -> Self {
+        self.signer = Some(Arc::new(signer));
+        self.secret_key = None;
         self
     }
 
@@ -555,6 +598,7 @@ impl Builder {
     /// [crate docs]: crate
     /// [number 0]: https://n0.computer
     pub fn relay_mode(mut self, relay_mode: RelayMode) -> Self {
+        self.relay_manifest = None;
         let transport: Option<_> = relay_mode.into();
         match transport {
             Some(transport) => {
@@ -576,6 +620,22 @@ impl Builder {
         self
     }
 
+    /// Uses the relays of a signed relay manifest, which is fetched and applied periodically.
+    ///
+    /// Until the first manifest was fetched, the [initial relay map] of the `source` is used.
+    /// This replaces the relays configured with [`Builder::relay_mode`], and a later call to
+    /// [`Builder::relay_mode`] stops using the manifest.
+    ///
+    /// There is no [`RelayMode`] variant for manifests, as adding one would be a breaking
+    /// change of that exhaustive enum.
+    ///
+    /// [initial relay map]: RelayManifestSource::initial_relay_map
+    pub fn relay_manifest(self, source: RelayManifestSource) -> Self {
+        let mut this = self.relay_mode(RelayMode::Custom(source.relay_map()));
+        this.relay_manifest = Some(source);
+        this
+    }
+
     /// Removes all Address Lookup services from the builder.
     ///
     /// If no Address Lookup is set, connecting to an endpoint without providing its
@@ -744,6 +804,21 @@ impl Builder {
         self
     }
 
+    /// Labels the per-remote byte metrics of up to `max_remotes` remotes with their endpoint id.
+    ///
+    /// Every labelled remote adds its own series to
+    /// [`SocketMetrics::remote_bytes_sent`](crate::metrics::SocketMetrics::remote_bytes_sent) and
+    /// [`SocketMetrics::remote_bytes_recv`](crate::metrics::SocketMetrics::remote_bytes_recv).  Once
+    /// `max_remotes` remotes are labelled, the bytes of further remotes are counted under
+    /// [`RemoteLabels::OTHER`](crate::metrics::RemoteLabels::OTHER) until a labelled remote
+    /// has been idle long enough for its series to be removed.
+    ///
+    /// The default is 0, which counts all remotes under `other`.
+    pub fn per_remote_metrics(mut self, max_remotes: usize) -> Self {
+        self.per_remote_metrics = max_remotes;
+        self
+    }
+
     /// Specify the rustls cryptography to use for all TLS operations.
     ///
     /// This includes
@@ -889,6 +964,22 @@ pub struct Endpoint {
     inner: Arc<EndpointInner>,
 }
 
+/// A weak reference to an [`Endpoint`].
+///
+/// Unlike an [`Endpoint`] clone this does not keep the endpoint alive, which allows
+/// services owned by the endpoint to refer back to it without creating a reference cycle.
+#[derive(Clone, Debug)]
+pub(crate) struct WeakEndpoint {
+    inner: std::sync::Weak<EndpointInner>,
+}
+
+impl WeakEndpoint {
+    /// Returns the [`Endpoint`] if it is still alive.
+    pub(crate) fn upgrade(&self) -> Option<Endpoint> {
+        self.inner.upgrade().map(|inner| Endpoint { inner })
+    }
+}
+
 #[allow(missing_docs)]
 #[stack_error(derive, add_meta, from_sources)]
 #[non_exhaustive]
@@ -964,6 +1055,14 @@ impl Endpoint {
             .set_server_config(Some(server_config));
     }
 
+    /// Returns a copy of the [`RelayMap`] currently used by the endpoint.
+    ///
+    /// With [`Builder::relay_manifest`] this reflects the last applied manifest, and can be
+    /// persisted to be used as [`RelayManifestSource::initial_relay_map`] on the next start.
+    pub fn relay_map(&self) -> RelayMap {
+        self.inner.relay_map()
+    }
+
     /// Adds the provided configuration to the [`RelayMap`].
     ///
     /// Replacing and returning any existing configuration for [`RelayUrl`].
@@ -1158,17 +1257,122 @@ impl Endpoint {
 
     // # Getter methods for properties of this Endpoint itself.
 
+    /// Returns a [`WeakEndpoint`] which does not keep this endpoint alive.
+    pub(crate) fn downgrade(&self) -> WeakEndpoint {
+        WeakEndpoint {
+            inner: Arc::downgrade(&self.inner),
+        }
+    }
+
+    /// Subscribes to the remotes of newly established connections, both incoming and
+    /// outgoing.
+    pub(crate) fn subscribe_connected(&self) -> tokio::sync::broadcast::Receiver<EndpointId> {
+        self.inner.connected.subscribe()
+    }
+
     /// Returns the secret_key of this endpoint.
+    ///
+    /// This is the key the endpoint was built with, use [`Endpoint::current_secret_key`]
+    /// for the key after rotations.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the endpoint was built with a [`Builder::signer`] instead of a secret key.
+    /// Use [`Endpoint::try_secret_key`] for endpoints which might use an external signer.
     pub fn secret_key(&self) -> &SecretKey {
-        &self.inner.static_config.tls_config.secret_key
+        self.try_secret_key()
+            .expect("endpoint was built with a signer instead of a secret key")
+    }
+
+    /// Returns the secret_key of this endpoint, if it was built with one.
+    ///
+    /// Returns `None` if the endpoint was built with a [`Builder::signer`].
+    pub fn try_secret_key(&self) -> Option<&SecretKey> {
+        self.inner.static_config.tls_config.secret_key.as_deref()
+    }
+
+    /// Returns the secret key the endpoint currently signs with.
+    ///
+    /// This follows key rotations with [`Endpoint::rotate_secret_key`]. Returns `None` if
+    /// the endpoint was built with a [`Builder::signer`], or the key was last rotated with
+    /// [`Endpoint::rotate_signer`].
+    pub fn current_secret_key(&self) -> Option<Arc<SecretKey>> {
+        self.identity().secret_key()
+    }
+
+    /// Returns the [`Signer`] which signs for the [`EndpointId`] of this endpoint.
+    ///
+    /// If the endpoint was built with a secret key, this signs with that key. The signer
+    /// follows key rotations, it always signs with the current key of the endpoint.
+    pub fn signer(&self) -> &Arc<dyn Signer> {
+        &self.inner.static_config.tls_config.signer
+    }
+
+    /// Returns the rotatable identity of this endpoint.
+    pub(crate) fn identity(&self) -> &Arc<Identity> {
+        &self.inner.static_config.tls_config.identity
     }
 
     /// Returns the endpoint id of this endpoint.
     ///
     /// This ID is the unique addressing information of this endpoint and other peers must know
     /// it to be able to connect to this endpoint.
+    ///
+    /// The endpoint id changes when the key is rotated using [`Endpoint::rotate_secret_key`]
+    /// or [`Endpoint::rotate_signer`].
     pub fn id(&self) -> EndpointId {
-        self.inner.static_config.tls_config.secret_key.public()
+        self.inner.static_config.tls_config.endpoint_id()
+    }
+
+    /// Rotates the key of this endpoint to `secret_key`.
+    ///
+    /// The current key signs a [`KeySuccession`] to the new key, which is published along
+    /// with the addressing information of the retired key. Peers which still know this
+    /// endpoint by the retired key can follow the succession to the new key using
+    /// [`AddressLookupServices::follow_succession`].
+    ///
+    /// Existing connections, the bound sockets and all other state of the endpoint are kept.
+    /// Connections to relay servers are re-established to authenticate with the new key, so
+    /// connections which only use a relay path might need to wait for a direct path or
+    /// break. New connections are authenticated with the new key.
+    ///
+    /// [`AddressLookupServices::follow_succession`]: crate::address_lookup::AddressLookupServices::follow_succession
+    pub async fn rotate_secret_key(
+        &self,
+        secret_key: SecretKey,
+    ) -> Result<KeySuccession, RotateKeyError> {
+        let secret_key = Arc::new(secret_key);
+        self.rotate(secret_key.clone(), Some(secret_key)).await
+    }
+
+    /// Rotates the key of this endpoint to the key of `signer`.
+    ///
+    /// See [`Endpoint::rotate_secret_key`] for details. After this
+    /// [`Endpoint::current_secret_key`] returns `None`.
+    pub async fn rotate_signer(
+        &self,
+        signer: impl Signer,
+    ) -> Result<KeySuccession, RotateKeyError> {
+        self.rotate(Arc::new(signer), None).await
+    }
+
+    async fn rotate(
+        &self,
+        signer: Arc<dyn Signer>,
+        secret_key: Option<Arc<SecretKey>>,
+    ) -> Result<KeySuccession, RotateKeyError> {
+        let succession = self
+            .inner
+            .static_config
+            .tls_config
+            .rotate(signer, secret_key)?;
+        info!(
+            old = %succession.predecessor().fmt_short(),
+            new = %succession.successor().fmt_short(),
+            "rotated endpoint key"
+        );
+        self.inner.identity_changed().await;
+        Ok(succession)
     }
 
     /// Returns the current [`EndpointAddr`].
@@ -1549,7 +1753,9 @@ impl Endpoint {
     /// [`Registry::sub_registry_with_label`] or [`Registry::sub_registry_with_prefix`].
     /// Furthermore, [`iroh_metrics::service`] provides functions to easily start services
     /// to serve the metrics with a HTTP server, dump them to a file, or push them
-    /// to a Prometheus gateway.
+    /// to a Prometheus gateway. With the `metrics-server` feature enabled,
+    /// `iroh::metrics::MetricsServer` serves the metrics of a single endpoint without further
+    /// setup.
     ///
     /// For example, the following snippet launches an HTTP server that serves the metrics in the
     /// OpenMetrics text format:
@@ -1988,11 +2194,16 @@ mod tests {
         io,
         net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4},
         str::FromStr,
-        sync::Arc,
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
         time::{Duration, Instant},
     };
 
-    use iroh_base::{EndpointAddr, EndpointId, RelayUrl, SecretKey, TransportAddr};
+    use iroh_base::{
+        EndpointAddr, EndpointId, RelayUrl, SecretKey, SignError, Signature, Signer, TransportAddr,
+    };
     use iroh_dns::endpoint_info::UserData;
     use iroh_relay::{RelayConfig, server::Access, tls::CaTlsConfig};
     use n0_error::{AnyError as Error, Result, StdResultExt};
@@ -3019,6 +3230,138 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    #[traced_test]
+    async fn endpoint_external_signer() -> Result {
+        /// Signs with a secret key the endpoint has no access to, counting signatures.
+        #[derive(Debug)]
+        struct CountingSigner {
+            key: SecretKey,
+            count: AtomicUsize,
+        }
+
+        impl Signer for CountingSigner {
+            fn public_key(&self) -> EndpointId {
+                self.key.public()
+            }
+
+            fn sign(&self, message: &[u8]) -> Result<Signature, SignError> {
+                self.count.fetch_add(1, Ordering::Relaxed);
+                Signer::sign(&self.key, message)
+            }
+        }
+
+        let key = SecretKey::from_bytes(&[7u8; 32]);
+        let signer = Arc::new(CountingSigner {
+            key: key.clone(),
+            count: Default::default(),
+        });
+        let server = Endpoint::builder(presets::Minimal)
+            .signer(signer.clone())
+            .alpns(vec![TEST_ALPN.to_vec()])
+            .bind()
+            .await?;
+        assert_eq!(server.id(), key.public());
+        assert!(server.try_secret_key().is_none());
+        assert_eq!(server.signer().public_key(), key.public());
+
+        let server_addr = server.addr();
+        let server_task = tokio::spawn(async move {
+            let conn = server.accept().await.anyerr()?.await.anyerr()?;
+            let remote_id = conn.remote_id();
+            conn.closed().await;
+            server.close().await;
+            Ok::<_, Error>(remote_id)
+        });
+
+        let client = Endpoint::builder(presets::Minimal)
+            .signer(SecretKey::from_bytes(&[8u8; 32]))
+            // A secret key set later replaces the signer.
+            .secret_key(SecretKey::from_bytes(&[9u8; 32]))
+            .bind()
+            .await?;
+        assert_eq!(
+            client.secret_key().public(),
+            SecretKey::from_bytes(&[9u8; 32]).public()
+        );
+        let conn = client.connect(server_addr, TEST_ALPN).await?;
+        // The server authenticated with the signer in the handshake.
+        assert_eq!(conn.remote_id(), key.public());
+        assert!(signer.count.load(Ordering::Relaxed) > 0);
+        conn.close(0u32.into(), b"bye");
+
+        assert_eq!(server_task.await.anyerr()??, client.id());
+        client.close().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn endpoint_rotate_secret_key() -> Result {
+        let old_key = SecretKey::from_bytes(&[10u8; 32]);
+        let new_key = SecretKey::from_bytes(&[11u8; 32]);
+        let server = Endpoint::builder(presets::Minimal)
+            .secret_key(old_key.clone())
+            .alpns(vec![TEST_ALPN.to_vec()])
+            .bind()
+            .await?;
+        let server_task = tokio::spawn({
+            let server = server.clone();
+            async move {
+                let mut remotes = Vec::new();
+                while let Some(incoming) = server.accept().await {
+                    // The client aborts the handshake to the old key.
+                    let Ok(conn) = incoming.await else {
+                        continue;
+                    };
+                    remotes.push(conn.remote_id());
+                    conn.closed().await;
+                }
+                remotes
+            }
+        });
+
+        let client = Endpoint::builder(presets::Minimal).bind().await?;
+        let conn = client.connect(server.addr(), TEST_ALPN).await?;
+        assert_eq!(conn.remote_id(), old_key.public());
+
+        assert!(server.rotate_secret_key(old_key.clone()).await.is_err());
+        let succession = server.rotate_secret_key(new_key.clone()).await?;
+        assert_eq!(succession.predecessor(), old_key.public());
+        assert_eq!(succession.successor(), new_key.public());
+        assert_eq!(server.id(), new_key.public());
+        assert_eq!(server.signer().public_key(), new_key.public());
+        assert_eq!(
+            server.current_secret_key().map(|key| key.public()),
+            Some(new_key.public())
+        );
+        // The key the endpoint was built with does not change.
+        assert_eq!(server.secret_key().public(), old_key.public());
+
+        // The existing connection survives the rotation.
+        assert!(conn.close_reason().is_none());
+        conn.close(0u32.into(), b"bye");
+
+        // New connections authenticate with the new key, the old key is not accepted anymore.
+        let old_addr = EndpointAddr::from_parts(old_key.public(), server.addr().addrs);
+        assert!(client.connect(old_addr, TEST_ALPN).await.is_err());
+        let conn = client.connect(server.addr(), TEST_ALPN).await?;
+        assert_eq!(conn.remote_id(), new_key.public());
+        conn.close(0u32.into(), b"bye");
+
+        // An external signer can replace the secret key.
+        let signer_key = SecretKey::from_bytes(&[12u8; 32]);
+        server.rotate_signer(signer_key.clone()).await?;
+        assert_eq!(server.id(), signer_key.public());
+        assert!(server.current_secret_key().is_none());
+
+        client.close().await;
+        server.close().await;
+        let remotes = server_task.await.anyerr()?;
+        assert_eq!(remotes, vec![client.id(), client.id()]);
+        Ok(())
+    }
+
     #[cfg(feature = "metrics")]
     #[tokio::test]
     #[traced_test]
@@ -355,6 +355,7 @@ fn conn_from_noq_conn(
             conn.close(error_code, &reason);
             return Err(e!(ConnectingError::LocallyRejected));
         }
+        inner.connected.send(conn.remote_id()).ok();
 
         Ok(conn)
     })
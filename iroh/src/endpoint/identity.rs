@@ -0,0 +1,230 @@
+//! The rotatable identity of an endpoint.
+
+use std::sync::{Arc, RwLock};
+
+use iroh_base::{KeySuccession, PublicKey, SecretKey, SignError, Signature, Signer};
+use n0_error::{e, stack_error};
+
+/// Maximum number of retired keys for which succession records are kept.
+///
+/// Older retired keys are forgotten, peers which still know this endpoint by such an old
+/// key can no longer follow the succession chain to the current key.
+const MAX_RETIRED_KEYS: usize = 8;
+
+/// Error when rotating the key of an endpoint.
+#[allow(missing_docs)]
+#[stack_error(derive, add_meta)]
+#[non_exhaustive]
+pub enum RotateKeyError {
+    #[error("Failed to sign the key succession with the current key")]
+    Sign {
+        #[error(std_err)]
+        source: SignError,
+    },
+    #[error("The new key is the same as the current key")]
+    SameKey {},
+}
+
+/// The current key of an endpoint, along with the keys it has rotated away from.
+///
+/// This is a [`Signer`] itself, which always signs with the current key. Code which needs
+/// a consistent view of the key and its signer over several operations should use
+/// [`Identity::current`].
+#[derive(Debug)]
+pub(crate) struct Identity {
+    current: RwLock<Current>,
+    /// Retired keys, oldest first.
+    retired: RwLock<Vec<Retired>>,
+}
+
+#[derive(Debug, Clone)]
+struct Current {
+    signer: Arc<dyn Signer>,
+    /// The public key of `signer`, cached to not ask the signer every time.
+    public_key: PublicKey,
+    /// The secret key, unless the key is only available through the `signer`.
+    secret_key: Option<Arc<SecretKey>>,
+}
+
+/// A key this endpoint rotated away from.
+#[derive(Debug, Clone)]
+pub(crate) struct Retired {
+    /// Signs for the retired key, used to keep publishing the succession record.
+    pub(crate) signer: Arc<dyn Signer>,
+    /// The succession from the retired key to the key that replaced it.
+    pub(crate) succession: KeySuccession,
+}
+
+impl Identity {
+    pub(crate) fn new(signer: Arc<dyn Signer>, secret_key: Option<Arc<SecretKey>>) -> Self {
+        Self {
+            current: RwLock::new(Current {
+                public_key: signer.public_key(),
+                signer,
+                secret_key,
+            }),
+            retired: Default::default(),
+        }
+    }
+
+    /// Returns the current public key and the signer for it.
+    pub(crate) fn current(&self) -> (PublicKey, Arc<dyn Signer>) {
+        let current = self.current.read().expect("poisoned");
+        (current.public_key, current.signer.clone())
+    }
+
+    /// Returns the current secret key, if it is known.
+    pub(crate) fn secret_key(&self) -> Option<Arc<SecretKey>> {
+        self.current.read().expect("poisoned").secret_key.clone()
+    }
+
+    /// Returns the retired keys, oldest first.
+    pub(crate) fn retired(&self) -> Vec<Retired> {
+        self.retired.read().expect("poisoned").clone()
+    }
+
+    /// Replaces the current key with the key of `signer`.
+    ///
+    /// The current key signs the succession to the new key and is retired.  The signing
+    /// happens without holding the lock, if another rotation finishes in the meantime the
+    /// succession is signed again by the key that rotation installed.
+    pub(crate) fn rotate(
+        &self,
+        signer: Arc<dyn Signer>,
+        secret_key: Option<Arc<SecretKey>>,
+    ) -> Result<KeySuccession, RotateKeyError> {
+        let public_key = signer.public_key();
+        let (mut current, succession) = loop {
+            let (current_key, current_signer) = self.current();
+            if current_key == public_key {
+                return Err(e!(RotateKeyError::SameKey));
+            }
+            let succession = KeySuccession::new(&*current_signer, public_key)
+                .map_err(|err| e!(RotateKeyError::Sign, err))?;
+            let current = self.current.write().expect("poisoned");
+            if current.public_key == current_key {
+                break (current, succession);
+            }
+        };
+        let previous = std::mem::replace(
+            &mut *current,
+            Current {
+                signer,
+                public_key,
+                secret_key,
+            },
+        );
+        let mut retired = self.retired.write().expect("poisoned");
+        retired.push(Retired {
+            signer: previous.signer,
+            succession,
+        });
+        if retired.len() > MAX_RETIRED_KEYS {
+            retired.remove(0);
+        }
+        Ok(succession)
+    }
+}
+
+impl Signer for Identity {
+    fn public_key(&self) -> PublicKey {
+        self.current.read().expect("poisoned").public_key
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Signature, SignError> {
+        // Do not hold the lock while signing, external signers can be slow.
+        let (_, signer) = self.current();
+        signer.sign(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// A signer which rotates an identity to another key the first time it signs.
+    #[derive(Debug)]
+    struct RotatingSigner {
+        key: SecretKey,
+        rotate: Mutex<Option<(Arc<Identity>, Arc<SecretKey>)>>,
+    }
+
+    impl Signer for RotatingSigner {
+        fn public_key(&self) -> PublicKey {
+            self.key.public()
+        }
+
+        fn sign(&self, message: &[u8]) -> Result<Signature, SignError> {
+            let rotate = self.rotate.lock().expect("poisoned").take();
+            if let Some((identity, next)) = rotate {
+                identity.rotate(next, None).unwrap();
+            }
+            Ok(self.key.sign(message))
+        }
+    }
+
+    #[test]
+    fn test_rotate() {
+        let first = Arc::new(SecretKey::generate());
+        let identity = Identity::new(first.clone(), Some(first.clone()));
+        assert_eq!(identity.public_key(), first.public());
+
+        let second = Arc::new(SecretKey::generate());
+        let succession = identity
+            .rotate(second.clone(), Some(second.clone()))
+            .unwrap();
+        assert_eq!(succession.predecessor(), first.public());
+        assert_eq!(succession.successor(), second.public());
+        assert_eq!(identity.public_key(), second.public());
+        assert_eq!(
+            identity.secret_key().map(|key| key.public()),
+            Some(second.public())
+        );
+        let signature = identity.sign(b"hello").unwrap();
+        second.public().verify(b"hello", &signature).unwrap();
+
+        let retired = identity.retired();
+        assert_eq!(retired.len(), 1);
+        assert_eq!(retired[0].signer.public_key(), first.public());
+        assert_eq!(retired[0].succession, succession);
+
+        assert!(identity.rotate(second.clone(), None).is_err());
+
+        for _ in 0..MAX_RETIRED_KEYS {
+            identity
+                .rotate(Arc::new(SecretKey::generate()), None)
+                .unwrap();
+        }
+        let retired = identity.retired();
+        assert_eq!(retired.len(), MAX_RETIRED_KEYS);
+        assert_eq!(retired[0].succession.predecessor(), second.public());
+        assert!(identity.secret_key().is_none());
+    }
+
+    #[test]
+    fn test_rotate_concurrent() {
+        let first = Arc::new(RotatingSigner {
+            key: SecretKey::generate(),
+            rotate: Default::default(),
+        });
+        let identity = Arc::new(Identity::new(first.clone(), None));
+        let second = Arc::new(SecretKey::generate());
+        *first.rotate.lock().unwrap() = Some((identity.clone(), second.clone()));
+
+        // The rotation to `second` finishes while the first key signs the succession to
+        // `third`, which is then signed again by `second`.
+        let third = Arc::new(SecretKey::generate());
+        let succession = identity.rotate(third.clone(), None).unwrap();
+        assert_eq!(succession.predecessor(), second.public());
+        assert_eq!(succession.successor(), third.public());
+        assert_eq!(identity.public_key(), third.public());
+
+        let retired = identity.retired();
+        assert_eq!(retired.len(), 2);
+        assert_eq!(retired[0].signer.public_key(), first.public_key());
+        assert_eq!(retired[1].signer.public_key(), second.public());
+        assert_eq!(retired[1].succession, succession);
+    }
+}
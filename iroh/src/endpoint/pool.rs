@@ -0,0 +1,477 @@
+//! A pool of connections, keyed by remote [`EndpointId`] and ALPN.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, Weak},
+};
+
+use iroh_base::{EndpointAddr, EndpointId};
+use n0_error::{e, stack_error};
+use n0_future::{
+    task::{self, AbortOnDropHandle},
+    time::{self, Duration, Instant},
+};
+use tracing::{Instrument, debug, info_span, trace};
+
+use super::{ConnectError, ConnectOptions, Connection, Endpoint};
+
+/// The default for [`PoolOptions::with_idle_timeout`].
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+/// The default for [`PoolOptions::with_max_connections`].
+const DEFAULT_MAX_CONNECTIONS: usize = 1024;
+/// The default for [`PoolOptions::with_connect_timeout`].
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Minimum pause between runs of the idle eviction task.
+const MIN_EVICT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Options for a [`ConnectionPool`].
+#[derive(Debug, Clone)]
+pub struct PoolOptions {
+    connect_options: ConnectOptions,
+    idle_timeout: Duration,
+    max_connections: usize,
+    connect_timeout: Duration,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        Self {
+            connect_options: ConnectOptions::default(),
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+        }
+    }
+}
+
+impl PoolOptions {
+    /// Sets the options used for every new connection.
+    pub fn with_connect_options(mut self, connect_options: ConnectOptions) -> Self {
+        self.connect_options = connect_options;
+        self
+    }
+
+    /// Sets how long a connection stays in the pool after it was last handed out.
+    ///
+    /// Idle connections are evicted by a task which runs every half idle timeout, but at most
+    /// every 50 milliseconds. A zero timeout evicts connections on the next run after they
+    /// were handed out.
+    ///
+    /// Defaults to 30 seconds.
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Sets the maximum number of connections in the pool.
+    ///
+    /// Defaults to 1024.
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+
+    /// Sets how long a connection attempt, including the handshake, may take.
+    ///
+    /// Defaults to 10 seconds.
+    pub fn with_connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+}
+
+/// Error returned by [`ConnectionPool::get_or_connect`].
+#[allow(missing_docs)]
+#[stack_error(derive, add_meta, from_sources)]
+#[non_exhaustive]
+pub enum PoolConnectError {
+    #[error("Connection pool is full")]
+    PoolFull,
+    #[error("Timed out connecting to remote")]
+    Timeout,
+    #[error(transparent)]
+    Connect { source: ConnectError },
+}
+
+/// A pool of connections, keyed by remote [`EndpointId`] and ALPN.
+///
+/// [`Self::get_or_connect`] hands out a pooled connection, and only dials the remote if
+/// there is no open connection for the key yet. Concurrent calls for the same key share
+/// a single connection attempt.
+///
+/// Connections are removed from the pool when they close, so that the next call
+/// transparently reconnects, and when they have not been handed out for the
+/// [idle timeout]. If the pool is full, the least recently used connection is removed.
+///
+/// Removing a connection only drops the pool's handle to it: the connection stays open as
+/// long as other [`Connection`] handles to it are alive.
+///
+/// The pool is cheap to clone. Idle connections are evicted by a background task, which
+/// stops when the last clone is dropped.
+///
+/// [idle timeout]: PoolOptions::with_idle_timeout
+#[derive(Debug, Clone)]
+pub struct ConnectionPool {
+    inner: Arc<Inner>,
+    _evict_task: Arc<AbortOnDropHandle<()>>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    endpoint: Endpoint,
+    options: PoolOptions,
+    slots: Mutex<HashMap<Key, Arc<Slot>>>,
+}
+
+type Key = (EndpointId, Vec<u8>);
+
+/// The pool entry for a single key.
+#[derive(Debug)]
+struct Slot {
+    /// The pooled connection.
+    ///
+    /// Locked for the duration of a connection attempt, so that concurrent callers wait
+    /// for the attempt instead of dialing themselves.
+    conn: tokio::sync::Mutex<Option<Connection>>,
+    last_used: Mutex<Instant>,
+}
+
+impl Slot {
+    fn touch(&self) {
+        *self.last_used.lock().expect("poisoned") = Instant::now();
+    }
+
+    fn last_used(&self) -> Instant {
+        *self.last_used.lock().expect("poisoned")
+    }
+
+    /// Returns whether the slot is not in use and has no open connection.
+    fn is_vacant(&self) -> bool {
+        match self.conn.try_lock() {
+            Ok(conn) => conn.as_ref().is_none_or(is_closed),
+            Err(_) => false,
+        }
+    }
+}
+
+impl ConnectionPool {
+    /// Creates a new pool that dials connections from `endpoint`.
+    pub fn new(endpoint: Endpoint, options: PoolOptions) -> Self {
+        let idle_timeout = options.idle_timeout;
+        let inner = Arc::new(Inner {
+            endpoint,
+            options,
+            slots: Default::default(),
+        });
+        let evict_task = task::spawn(
+            evict_idle(Arc::downgrade(&inner), idle_timeout)
+                .instrument(info_span!("ConnectionPool")),
+        );
+        Self {
+            inner,
+            _evict_task: Arc::new(AbortOnDropHandle::new(evict_task)),
+        }
+    }
+
+    /// Returns an open connection to `endpoint_addr` for `alpn`, dialing if needed.
+    ///
+    /// The [`EndpointAddr`] is only used when a new connection is dialed.
+    pub async fn get_or_connect(
+        &self,
+        endpoint_addr: impl Into<EndpointAddr>,
+        alpn: &[u8],
+    ) -> Result<Connection, PoolConnectError> {
+        let endpoint_addr: EndpointAddr = endpoint_addr.into();
+        let key = (endpoint_addr.id, alpn.to_vec());
+        let slot = self.inner.slot(&key)?;
+        let mut conn = slot.conn.lock().await;
+        slot.touch();
+        if let Some(conn) = conn.as_ref()
+            && !is_closed(conn)
+        {
+            trace!(remote = %key.0.fmt_short(), "reusing pooled connection");
+            return Ok(conn.clone());
+        }
+
+        debug!(remote = %key.0.fmt_short(), "dialing new pooled connection");
+        let inner = &self.inner;
+        let connect = async {
+            let connecting = inner
+                .endpoint
+                .connect_with_opts(endpoint_addr, alpn, inner.options.connect_options.clone())
+                .await
+                .map_err(ConnectError::from)?;
+            connecting.await.map_err(ConnectError::from)
+        };
+        let new_conn = time::timeout(inner.options.connect_timeout, connect)
+            .await
+            .map_err(|_| e!(PoolConnectError::Timeout))??;
+        *conn = Some(new_conn.clone());
+        drop(conn);
+
+        // Remove the connection from the pool once it closes, unless it was replaced.
+        let weak = Arc::downgrade(inner);
+        let closed = new_conn.weak_handle().closed();
+        let stable_id = new_conn.stable_id();
+        task::spawn(async move {
+            closed.await;
+            if let Some(inner) = weak.upgrade() {
+                inner.remove_closed(&key, stable_id);
+            }
+        });
+        Ok(new_conn)
+    }
+
+    /// Removes the pooled connection to `endpoint_id` for `alpn`.
+    ///
+    /// Returns the connection if there was an open connection that was not in use.
+    pub fn remove(&self, endpoint_id: EndpointId, alpn: &[u8]) -> Option<Connection> {
+        let key = (endpoint_id, alpn.to_vec());
+        let slot = self.inner.slots.lock().expect("poisoned").remove(&key)?;
+        let conn = slot.conn.try_lock().ok()?.take()?;
+        (!is_closed(&conn)).then_some(conn)
+    }
+
+    /// Returns the number of connections in the pool, including connection attempts.
+    pub fn len(&self) -> usize {
+        self.inner.slots.lock().expect("poisoned").len()
+    }
+
+    /// Returns whether the pool holds no connections.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Inner {
+    /// Returns the slot for `key`, creating it if needed.
+    fn slot(&self, key: &Key) -> Result<Arc<Slot>, PoolConnectError> {
+        let mut slots = self.slots.lock().expect("poisoned");
+        if let Some(slot) = slots.get(key) {
+            return Ok(slot.clone());
+        }
+        if slots.len() >= self.options.max_connections {
+            slots.retain(|_, slot| !slot.is_vacant());
+        }
+        if slots.len() >= self.options.max_connections {
+            // Evict the least recently used connection that is not currently being dialed.
+            let lru = slots
+                .iter()
+                .filter(|(_, slot)| slot.conn.try_lock().is_ok())
+                .min_by_key(|(_, slot)| slot.last_used())
+                .map(|(key, _)| key.clone());
+            let Some(lru) = lru else {
+                return Err(e!(PoolConnectError::PoolFull));
+            };
+            debug!(remote = %lru.0.fmt_short(), "pool full, evicting connection");
+            slots.remove(&lru);
+        }
+        let slot = Arc::new(Slot {
+            conn: Default::default(),
+            last_used: Mutex::new(Instant::now()),
+        });
+        slots.insert(key.clone(), slot.clone());
+        Ok(slot)
+    }
+
+    /// Removes the slot for `key` if it still holds the connection with `stable_id`.
+    fn remove_closed(&self, key: &Key, stable_id: usize) {
+        let mut slots = self.slots.lock().expect("poisoned");
+        let Some(slot) = slots.get(key) else {
+            return;
+        };
+        let Ok(conn) = slot.conn.try_lock() else {
+            return;
+        };
+        if conn
+            .as_ref()
+            .is_none_or(|conn| conn.stable_id() == stable_id)
+        {
+            drop(conn);
+            trace!(remote = %key.0.fmt_short(), "pooled connection closed");
+            slots.remove(key);
+        }
+    }
+
+    /// Removes all connections that have not been used for `idle_timeout`.
+    fn evict_idle(&self, idle_timeout: Duration) {
+        let now = Instant::now();
+        let mut slots = self.slots.lock().expect("poisoned");
+        slots.retain(|key, slot| {
+            let keep = slot.conn.try_lock().is_err()
+                || now.duration_since(slot.last_used()) < idle_timeout;
+            if !keep {
+                trace!(remote = %key.0.fmt_short(), "evicting idle connection");
+            }
+            keep
+        });
+    }
+}
+
+fn is_closed(conn: &Connection) -> bool {
+    conn.close_reason().is_some()
+}
+
+/// Periodically removes idle connections from the pool.
+async fn evict_idle(inner: Weak<Inner>, idle_timeout: Duration) {
+    // `interval` panics on a zero period.
+    let mut interval = time::interval((idle_timeout / 2).max(MIN_EVICT_INTERVAL));
+    loop {
+        interval.tick().await;
+        let Some(inner) = inner.upgrade() else {
+            break;
+        };
+        inner.evict_idle(idle_timeout);
+    }
+}
+
+#[cfg(all(test, with_crypto_provider))]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use n0_error::{Result, StdResultExt};
+    use n0_future::task::AbortOnDropHandle;
+    use n0_tracing_test::traced_test;
+
+    use super::*;
+    use crate::endpoint::presets;
+
+    const TEST_ALPN: &[u8] = b"n0/iroh/test";
+
+    /// Binds an endpoint that accepts connections and counts them.
+    ///
+    /// Each accepted connection is held open until the remote closes it, or closed right
+    /// away if `close` is set.
+    async fn counting_server(
+        close: bool,
+    ) -> Result<(Endpoint, Arc<AtomicUsize>, AbortOnDropHandle<()>)> {
+        let ep = Endpoint::builder(presets::Minimal)
+            .alpns(vec![TEST_ALPN.to_vec()])
+            .bind()
+            .await?;
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let task = task::spawn({
+            let ep = ep.clone();
+            let accepted = accepted.clone();
+            async move {
+                while let Some(incoming) = ep.accept().await {
+                    let Ok(conn) = incoming.await else {
+                        continue;
+                    };
+                    accepted.fetch_add(1, Ordering::SeqCst);
+                    if close {
+                        conn.close(0u32.into(), b"bye");
+                    } else {
+                        task::spawn(async move {
+                            conn.closed().await;
+                        });
+                    }
+                }
+            }
+        });
+        Ok((ep, accepted, AbortOnDropHandle::new(task)))
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn pool_dedupes_dials() -> Result {
+        let (server, accepted, _task) = counting_server(false).await?;
+        let client = Endpoint::bind(presets::Minimal).await?;
+        let pool = ConnectionPool::new(client.clone(), PoolOptions::default());
+
+        let addr = server.addr();
+        let conns =
+            n0_future::join_all((0..8).map(|_| pool.get_or_connect(addr.clone(), TEST_ALPN))).await;
+        let conns = conns.into_iter().collect::<Result<Vec<_>, _>>().anyerr()?;
+        let stable_id = conns[0].stable_id();
+        assert!(conns.iter().all(|conn| conn.stable_id() == stable_id));
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+        assert_eq!(pool.len(), 1);
+
+        let conn = pool.get_or_connect(server.id(), TEST_ALPN).await.anyerr()?;
+        assert_eq!(conn.stable_id(), stable_id);
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+
+        assert!(pool.remove(server.id(), TEST_ALPN).is_some());
+        assert!(pool.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn pool_reconnects_closed() -> Result {
+        let (server, accepted, _task) = counting_server(true).await?;
+        let client = Endpoint::bind(presets::Minimal).await?;
+        let pool = ConnectionPool::new(client.clone(), PoolOptions::default());
+
+        let conn = pool
+            .get_or_connect(server.addr(), TEST_ALPN)
+            .await
+            .anyerr()?;
+        conn.closed().await;
+        // The closed connection is removed from the pool in the background.
+        time::timeout(Duration::from_secs(5), async {
+            while !pool.is_empty() {
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .anyerr()?;
+
+        let new_conn = pool
+            .get_or_connect(server.addr(), TEST_ALPN)
+            .await
+            .anyerr()?;
+        assert_ne!(conn.stable_id(), new_conn.stable_id());
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn pool_evicts_idle_and_lru() -> Result {
+        let (server1, _, _task1) = counting_server(false).await?;
+        let (server2, _, _task2) = counting_server(false).await?;
+        let client = Endpoint::bind(presets::Minimal).await?;
+        let options = PoolOptions::default()
+            .with_idle_timeout(Duration::from_millis(200))
+            .with_max_connections(1);
+        let pool = ConnectionPool::new(client.clone(), options);
+
+        pool.get_or_connect(server1.addr(), TEST_ALPN)
+            .await
+            .anyerr()?;
+        pool.get_or_connect(server2.addr(), TEST_ALPN)
+            .await
+            .anyerr()?;
+        assert_eq!(pool.len(), 1);
+        assert!(pool.remove(server1.id(), TEST_ALPN).is_none());
+
+        time::sleep(Duration::from_millis(500)).await;
+        assert!(pool.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn pool_zero_idle_timeout() -> Result {
+        let (server, accepted, _task) = counting_server(false).await?;
+        let client = Endpoint::bind(presets::Minimal).await?;
+        let options = PoolOptions::default().with_idle_timeout(Duration::ZERO);
+        let pool = ConnectionPool::new(client.clone(), options);
+
+        let conn = pool
+            .get_or_connect(server.addr(), TEST_ALPN)
+            .await
+            .anyerr()?;
+        time::sleep(MIN_EVICT_INTERVAL * 4).await;
+        assert!(pool.is_empty());
+        // The eviction task is still running, and the pool reconnects.
+        pool.get_or_connect(server.addr(), TEST_ALPN)
+            .await
+            .anyerr()?;
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+        drop(conn);
+        Ok(())
+    }
+}
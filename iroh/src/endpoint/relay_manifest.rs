@@ -0,0 +1,322 @@
+//! Keeps the [`RelayMap`] of an endpoint in sync with a signed relay manifest.
+
+use iroh_base::PublicKey;
+use iroh_relay::{RelayManifestError, RelayMap, SignedRelayManifest};
+use n0_error::{e, stack_error};
+use n0_future::{
+    task,
+    time::{self, Duration},
+};
+use tracing::{Instrument, debug, info, info_span, warn};
+use url::Url;
+
+use super::{Endpoint, WeakEndpoint};
+use crate::util::reqwest_client_builder;
+
+/// Default interval between fetches of the relay manifest.
+pub const DEFAULT_RELAY_MANIFEST_REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 10);
+
+/// Delay before retrying a failed fetch of the relay manifest, doubled on every failure.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Where to fetch a signed relay manifest from, see [`Builder::relay_manifest`].
+///
+/// This is configured on the builder rather than as a [`RelayMode`] variant, because
+/// [`RelayMode`] is an exhaustive public enum and a new variant would break downstream
+/// `match`es.
+///
+/// The manifest is an encoded [`SignedRelayManifest`], served over HTTPS at [`Self::url`].
+/// Only manifests signed by the pinned [`Self::public_key`] are applied.
+///
+/// Manifests with a lower serial than the last applied manifest are ignored. The serial is
+/// not persisted though: after a restart the first validly signed manifest is applied, so
+/// whoever can serve content at [`Self::url`] can replay an older manifest until the
+/// current one is fetched again.
+///
+/// [`Builder::relay_manifest`]: super::Builder::relay_manifest
+/// [`RelayMode`]: super::RelayMode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayManifestSource {
+    url: Url,
+    public_key: PublicKey,
+    refresh_interval: Duration,
+    initial_relay_map: RelayMap,
+}
+
+impl RelayManifestSource {
+    /// Creates a source for the manifest at `url`, which must be signed by `public_key`.
+    ///
+    /// The manifest is fetched every [`DEFAULT_RELAY_MANIFEST_REFRESH_INTERVAL`], and the
+    /// endpoint starts without relays until the first manifest was fetched.
+    pub fn new(url: Url, public_key: PublicKey) -> Self {
+        Self {
+            url,
+            public_key,
+            refresh_interval: DEFAULT_RELAY_MANIFEST_REFRESH_INTERVAL,
+            initial_relay_map: RelayMap::empty(),
+        }
+    }
+
+    /// Sets the interval between fetches of the manifest.
+    pub fn refresh_interval(mut self, refresh_interval: Duration) -> Self {
+        self.refresh_interval = refresh_interval;
+        self
+    }
+
+    /// Sets the relays to use until the first manifest was fetched.
+    ///
+    /// This can be the relays of a manifest which was persisted from a previous run, so the
+    /// endpoint has relays even if the manifest server is unreachable.
+    pub fn initial_relay_map(mut self, relay_map: RelayMap) -> Self {
+        self.initial_relay_map = relay_map;
+        self
+    }
+
+    /// Returns the URL of the manifest.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Returns the public key which must have signed the manifest.
+    pub fn public_key(&self) -> PublicKey {
+        self.public_key
+    }
+
+    /// Returns a copy of the relays used until the first manifest was fetched.
+    ///
+    /// This is a copy, so that updates from the manifest do not change the map of this
+    /// source.
+    pub(super) fn relay_map(&self) -> RelayMap {
+        self.initial_relay_map
+            .relays::<Vec<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Starts keeping the relay map of `endpoint` in sync with the manifest.
+    ///
+    /// The task stops once the endpoint is closed.
+    pub(super) fn spawn(self, endpoint: &Endpoint) {
+        #[cfg(not(wasm_browser))]
+        let http_client = {
+            let dns_resolver = match endpoint.dns_resolver() {
+                Ok(dns_resolver) => dns_resolver,
+                Err(err) => {
+                    warn!(url = %self.url, "not refreshing relay manifest: {err:#}");
+                    return;
+                }
+            };
+            reqwest_client_builder(endpoint.tls_config().clone(), dns_resolver.clone())
+        };
+        #[cfg(wasm_browser)]
+        let http_client = reqwest_client_builder();
+        let http_client = http_client
+            .build()
+            .expect("failed to create reqwest client");
+        let refresher = Refresher {
+            source: self,
+            http_client,
+            endpoint: endpoint.downgrade(),
+            serial: None,
+        };
+        let closed = endpoint.inner.closed();
+        let span = info_span!("relay-manifest", url = %refresher.source.url);
+        task::spawn(
+            async move {
+                tokio::select! {
+                    _ = closed => {}
+                    _ = refresher.run() => {}
+                }
+            }
+            .instrument(span),
+        );
+    }
+}
+
+#[allow(missing_docs)]
+#[stack_error(derive, add_meta)]
+enum FetchError {
+    #[error("Failed to fetch relay manifest")]
+    Http {
+        #[error(std_err)]
+        source: reqwest::Error,
+    },
+    #[error("Invalid relay manifest")]
+    Manifest { source: RelayManifestError },
+}
+
+#[derive(derive_more::Debug)]
+struct Refresher {
+    source: RelayManifestSource,
+    #[debug("reqwest::Client")]
+    http_client: reqwest::Client,
+    endpoint: WeakEndpoint,
+    /// The serial of the last applied manifest.
+    serial: Option<u64>,
+}
+
+impl Refresher {
+    async fn run(mut self) {
+        let mut retry_delay = INITIAL_RETRY_DELAY;
+        loop {
+            let delay = match self.refresh().await {
+                Ok(()) => {
+                    retry_delay = INITIAL_RETRY_DELAY;
+                    self.source.refresh_interval
+                }
+                Err(err) => {
+                    warn!("failed to refresh relay manifest: {err:#}");
+                    let delay = retry_delay.min(self.source.refresh_interval);
+                    retry_delay = retry_delay
+                        .saturating_mul(2)
+                        .min(self.source.refresh_interval);
+                    delay
+                }
+            };
+            time::sleep(delay).await;
+            if self.endpoint.upgrade().is_none() {
+                break;
+            }
+        }
+    }
+
+    async fn refresh(&mut self) -> Result<(), FetchError> {
+        let response = self
+            .http_client
+            .get(self.source.url.clone())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| e!(FetchError::Http, err))?;
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|err| e!(FetchError::Http, err))?;
+        let manifest = SignedRelayManifest::from_bytes(&bytes)
+            .and_then(|signed| signed.verify(&self.source.public_key))
+            .map_err(|err| e!(FetchError::Manifest, err))?;
+
+        if self.serial.is_some_and(|serial| manifest.serial < serial) {
+            debug!(serial = manifest.serial, "ignoring outdated relay manifest");
+            return Ok(());
+        }
+        if manifest.relays.is_empty() {
+            // Most likely a mistake of the operator, keep using the current relays.
+            warn!(
+                serial = manifest.serial,
+                "ignoring relay manifest without relays"
+            );
+            return Ok(());
+        }
+        self.serial = Some(manifest.serial);
+        let Some(endpoint) = self.endpoint.upgrade() else {
+            return Ok(());
+        };
+        if endpoint.inner.set_relays(&manifest.relay_map()).await {
+            info!(
+                serial = manifest.serial,
+                relays = manifest.relays.len(),
+                "applied relay manifest"
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, not(wasm_browser)))]
+mod tests {
+    use std::{
+        future::IntoFuture,
+        net::{Ipv4Addr, SocketAddr},
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{Router, extract::State, routing::get};
+    use iroh_base::{RelayUrl, SecretKey};
+    use iroh_relay::{RelayManifest, RelayManifestEntry};
+    use n0_error::{Result, StdResultExt};
+    use n0_tracing_test::traced_test;
+
+    use super::*;
+    use crate::endpoint::presets;
+
+    type Served = Arc<Mutex<Vec<u8>>>;
+
+    async fn serve_manifest(served: Served) -> Result<Url> {
+        let app = Router::new()
+            .route(
+                "/manifest",
+                get(|State(served): State<Served>| async move {
+                    served.lock().expect("poisoned").clone()
+                }),
+            )
+            .with_state(served);
+        let listener = tokio::net::TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)))
+            .await
+            .anyerr()?;
+        let url = format!("http://{}/manifest", listener.local_addr().anyerr()?)
+            .parse()
+            .anyerr()?;
+        tokio::spawn(axum::serve(listener, app).into_future());
+        Ok(url)
+    }
+
+    fn relay_url(name: &str) -> RelayUrl {
+        format!("https://{name}.example.org").parse().unwrap()
+    }
+
+    fn urls(endpoint: &Endpoint) -> Vec<RelayUrl> {
+        let mut urls: Vec<_> = endpoint.relay_map().urls();
+        urls.sort();
+        urls
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn relay_manifest_refresh() -> Result {
+        let operator = SecretKey::generate();
+        let manifest = |serial, names: &[&str]| {
+            RelayManifest::new(
+                serial,
+                names
+                    .iter()
+                    .map(|name| RelayManifestEntry::new(relay_url(name)).with_quic_port(None)),
+            )
+        };
+        let served = Served::default();
+        *served.lock().unwrap() = manifest(2, &["b", "c"]).sign(&operator)?.to_bytes();
+        let url = serve_manifest(served.clone()).await?;
+
+        let source = RelayManifestSource::new(url, operator.public())
+            .refresh_interval(Duration::from_millis(50))
+            .initial_relay_map(RelayMap::from_iter([relay_url("a")]));
+        let ep = Endpoint::builder(presets::Minimal)
+            .relay_manifest(source)
+            .bind()
+            .await?;
+
+        let wait_for = async |expected: Vec<RelayUrl>| {
+            time::timeout(Duration::from_secs(10), async {
+                while urls(&ep) != expected {
+                    time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .std_context("manifest not applied")
+        };
+        wait_for(vec![relay_url("b"), relay_url("c")]).await?;
+
+        // Neither an older manifest, nor one signed by another key are applied.
+        *served.lock().unwrap() = manifest(1, &["d"]).sign(&operator)?.to_bytes();
+        time::sleep(Duration::from_millis(200)).await;
+        *served.lock().unwrap() = manifest(3, &["d"]).sign(&SecretKey::generate())?.to_bytes();
+        time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(urls(&ep), vec![relay_url("b"), relay_url("c")]);
+
+        *served.lock().unwrap() = manifest(3, &["c", "d"]).sign(&operator)?.to_bytes();
+        wait_for(vec![relay_url("c"), relay_url("d")]).await?;
+
+        ep.close().await;
+        Ok(())
+    }
+}
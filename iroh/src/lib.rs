@@ -282,7 +282,7 @@ pub mod protocol;
 pub use endpoint::{Endpoint, RelayMode};
 pub use iroh_base::{
     EndpointAddr, EndpointId, KeyParsingError, PublicKey, RelayUrl, RelayUrlParseError, SecretKey,
-    Signature, SignatureError, TransportAddr,
+    SignError, Signature, SignatureError, Signer, TransportAddr,
 };
 #[cfg(not(wasm_browser))]
 pub use iroh_dns::dns;
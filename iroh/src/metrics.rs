@@ -6,7 +6,17 @@ use iroh_metrics::MetricsGroupSet;
 pub use iroh_relay::server::Metrics as RelayMetrics;
 use serde::{Deserialize, Serialize};
 
-pub use crate::{net_report::Metrics as NetReportMetrics, socket::Metrics as SocketMetrics};
+#[cfg(all(feature = "metrics-server", not(wasm_browser)))]
+pub use self::server::MetricsServer;
+
+pub use crate::{
+    net_report::Metrics as NetReportMetrics,
+    portmapper::Metrics as PortmapperMetrics,
+    socket::{Metrics as SocketMetrics, PathTransport, RemoteLabels},
+};
+
+#[cfg(all(feature = "metrics-server", not(wasm_browser)))]
+mod server;
 
 /// Metrics collected by an [`crate::endpoint::Endpoint`].
 ///
@@ -19,6 +29,8 @@ pub struct EndpointMetrics {
     pub socket: Arc<SocketMetrics>,
     /// Metrics collected by net reports.
     pub net_report: Arc<NetReportMetrics>,
+    /// Metrics collected by the portmapper.
+    pub portmapper: Arc<PortmapperMetrics>,
 }
 
 #[cfg(test)]
@@ -0,0 +1,135 @@
+//! HTTP server for the metrics of an [`Endpoint`].
+
+use std::{io, net::SocketAddr, sync::Arc};
+
+use iroh_metrics::Registry;
+
+use crate::Endpoint;
+
+/// HTTP server that serves the metrics of an [`Endpoint`] in the Prometheus text format.
+///
+/// Serves the socket, net report, portmapper and per-remote metrics from
+/// [`Endpoint::metrics`] on every path. The per-remote metrics carry a `remote` and a
+/// `transport` label, which separate the bytes sent over relays from the bytes sent
+/// over direct paths.
+///
+/// The server stops when this handle is dropped. Use [`Self::shutdown`] to let in-flight
+/// requests finish.
+///
+/// [`Endpoint::metrics`]: crate::Endpoint::metrics
+#[derive(Debug)]
+pub struct MetricsServer {
+    inner: iroh_metrics::service::MetricsServer,
+}
+
+impl MetricsServer {
+    /// Binds to `addr` and serves the metrics of `endpoint`.
+    ///
+    /// Use port `0` to bind to a random port, and [`Self::local_addr`] to read it back.
+    pub async fn spawn(endpoint: &Endpoint, addr: SocketAddr) -> io::Result<Self> {
+        let mut registry = Registry::default();
+        registry.register_all(endpoint.metrics());
+        let inner = iroh_metrics::service::MetricsServer::spawn(addr, Arc::new(registry)).await?;
+        Ok(Self { inner })
+    }
+
+    /// Returns the local address the server is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.inner.local_addr()
+    }
+
+    /// Stops accepting connections and waits for in-flight requests to finish.
+    pub async fn shutdown(self) {
+        self.inner.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::Ipv4Addr, time::Duration};
+
+    use n0_error::{Result, StdResultExt};
+    use n0_tracing_test::traced_test;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+
+    use super::*;
+    use crate::endpoint::presets;
+
+    const TEST_ALPN: &[u8] = b"n0/iroh/test";
+
+    async fn scrape(server: &MetricsServer) -> Result<String> {
+        let mut stream = TcpStream::connect(server.local_addr()).await.anyerr()?;
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .anyerr()?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.anyerr()?;
+        Ok(response)
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn metrics_server() -> Result {
+        let client = Endpoint::builder(presets::Minimal)
+            .per_remote_metrics(8)
+            .bind()
+            .await?;
+        let server = Endpoint::builder(presets::Minimal)
+            .alpns(vec![TEST_ALPN.to_vec()])
+            .bind()
+            .await?;
+        let metrics_server = MetricsServer::spawn(&client, (Ipv4Addr::LOCALHOST, 0).into()).await?;
+
+        let body = scrape(&metrics_server).await?;
+        assert!(body.contains("socket_recv_datagrams_total 0"));
+        assert!(body.contains("portmap_probes_started_total"));
+        assert!(!body.contains("socket_remote_bytes_sent_total{"));
+
+        let server_addr = server.addr();
+        let server_task = tokio::task::spawn(async move {
+            let conn = server.accept().await.anyerr()?.await.anyerr()?;
+            let mut uni = conn.accept_uni().await.anyerr()?;
+            uni.read_to_end(1024).await.anyerr()?;
+            conn.close(0u32.into(), b"done");
+            Ok::<_, n0_error::AnyError>(server)
+        });
+        let conn = client.connect(server_addr, TEST_ALPN).await?;
+        let mut uni = conn.open_uni().await.anyerr()?;
+        uni.write_all(&[0u8; 512]).await.anyerr()?;
+        uni.finish().anyerr()?;
+        conn.closed().await;
+        let server = server_task.await.anyerr()??;
+
+        // The bytes of a closed connection are reported without waiting for the next sample.
+        let expected = format!(
+            r#"socket_remote_bytes_sent_total{{remote="{}",transport="direct"}}"#,
+            server.id()
+        );
+        let body = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let body = scrape(&metrics_server).await?;
+                if body.contains(&expected) {
+                    return Ok::<_, n0_error::AnyError>(body);
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .anyerr()??;
+        let sent: u64 = body
+            .lines()
+            .find_map(|line| line.strip_prefix(&expected))
+            .and_then(|value| value.trim().parse().ok())
+            .expect("valid counter");
+        assert!(sent > 512);
+
+        metrics_server.shutdown().await;
+        client.close().await;
+        server.close().await;
+        Ok(())
+    }
+}
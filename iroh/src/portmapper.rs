@@ -3,10 +3,25 @@
 //! Wraps the real [`portmapper`] crate when the `portmapper` feature is enabled,
 //! or provides a no-op stub otherwise.
 
-use std::net::SocketAddrV4;
+use std::{net::SocketAddrV4, sync::Arc};
 
+#[cfg(not(all(not(wasm_browser), feature = "portmapper")))]
+use iroh_metrics::MetricsGroup;
+#[cfg(all(not(wasm_browser), feature = "portmapper"))]
+pub use portmapper::Metrics;
+#[cfg(not(all(not(wasm_browser), feature = "portmapper")))]
+use serde::{Deserialize, Serialize};
 use tokio::sync::watch;
 
+/// Metrics collected by the portmapper.
+///
+/// Empty because the `portmapper` feature is disabled.
+#[cfg(not(all(not(wasm_browser), feature = "portmapper")))]
+#[derive(Debug, Default, Serialize, Deserialize, MetricsGroup)]
+#[metrics(name = "portmap")]
+#[non_exhaustive]
+pub struct Metrics {}
+
 /// Configuration for the portmapper service (UPnP, PCP, NAT-PMP).
 ///
 /// Used with [`crate::endpoint::Builder::portmapper_config`].
@@ -28,10 +43,17 @@ impl Default for PortmapperConfig {
     }
 }
 
-pub(crate) fn create_client(config: &PortmapperConfig) -> Client {
+#[cfg_attr(
+    any(wasm_browser, not(feature = "portmapper")),
+    allow(unused_variables)
+)]
+pub(crate) fn create_client(config: &PortmapperConfig, metrics: Arc<Metrics>) -> Client {
     match config {
         #[cfg(all(not(wasm_browser), feature = "portmapper"))]
-        PortmapperConfig::Enabled {} => Client::Enabled(::portmapper::Client::default()),
+        PortmapperConfig::Enabled {} => Client::Enabled(::portmapper::Client::with_metrics(
+            Default::default(),
+            metrics,
+        )),
         _ => {
             let (tx, rx) = watch::channel(None);
             Client::Disabled { _tx: tx, rx }
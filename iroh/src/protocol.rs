@@ -60,6 +60,8 @@ use crate::{
     endpoint::{Accepting, Connection, RemoteEndpointIdError, quic},
 };
 
+pub mod rpc;
+
 /// The built router.
 ///
 /// Construct this using [`Router::builder`].
@@ -0,0 +1,533 @@
+//! A simple request/response protocol on top of [`Router`].
+//!
+//! Every call opens a new bidirectional stream. The client sends a single request frame
+//! and finishes its side of the stream, the server answers with a single response frame.
+//! A frame is the message length as a big-endian `u32`, followed by the message bytes.
+//!
+//! Errors are signalled by resetting or stopping the stream with an application error code:
+//! a message that exceeds the maximum message size of the receiving side, a failing
+//! [`RpcHandler`] and a cancelled call each use a dedicated code, which the client reports
+//! as an [`RpcError`].
+//!
+//! ## Example
+//!
+//! ```no_run
+//! # #[cfg(with_crypto_provider)]
+//! # {
+//! use bytes::Bytes;
+//! use iroh::{
+//!     Endpoint,
+//!     endpoint::{Connection, presets},
+//!     protocol::{
+//!         Router,
+//!         rpc::{RpcClient, RpcHandler, RpcServer},
+//!     },
+//! };
+//! use n0_error::AnyError;
+//!
+//! const ALPN: &[u8] = b"/my/rpc/1";
+//!
+//! #[derive(Debug)]
+//! struct Reverse;
+//!
+//! impl RpcHandler for Reverse {
+//!     async fn handle(&self, _connection: &Connection, request: Bytes) -> Result<Bytes, AnyError> {
+//!         Ok(request.iter().rev().copied().collect())
+//!     }
+//! }
+//!
+//! # async fn test_compile() -> n0_error::Result<()> {
+//! let endpoint = Endpoint::bind(presets::N0).await?;
+//! let router = Router::builder(endpoint)
+//!     .accept(ALPN, RpcServer::new(Reverse))
+//!     .spawn();
+//!
+//! let client_endpoint = Endpoint::bind(presets::N0).await?;
+//! let connection = client_endpoint.connect(router.endpoint().addr(), ALPN).await?;
+//! let client = RpcClient::new(connection);
+//! let response = client.call(&b"hello"[..]).await?;
+//! assert_eq!(&response[..], b"olleh");
+//! # Ok(())
+//! # }
+//! # }
+//! ```
+//!
+//! [`Router`]: super::Router
+
+use std::{future::Future, sync::Arc};
+
+use bytes::Bytes;
+use n0_error::{AnyError, e, stack_error};
+use n0_future::{
+    task::JoinSet,
+    time::{self, Duration},
+};
+use tracing::{Instrument, debug, debug_span, warn};
+
+use super::{AcceptError, ProtocolHandler};
+use crate::endpoint::{
+    Connection, ConnectionError, ReadError, ReadExactError, RecvStream, SendStream, VarInt,
+    WriteError,
+};
+
+/// The default for [`RpcServer::with_max_message_size`] and [`RpcClient::with_max_message_size`].
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Error code for a message that exceeds the maximum message size of the receiver.
+const MESSAGE_TOO_LARGE: VarInt = VarInt::from_u32(1);
+/// Error code for a request the [`RpcHandler`] failed to handle.
+const HANDLER_FAILED: VarInt = VarInt::from_u32(2);
+/// Error code for a call that was cancelled by the client.
+const CANCELLED: VarInt = VarInt::from_u32(3);
+
+/// Handles requests received by an [`RpcServer`].
+pub trait RpcHandler: Send + Sync + std::fmt::Debug + 'static {
+    /// Handles a single request and returns the response.
+    ///
+    /// Can be implemented as `async fn handle(&self, connection: &Connection, request: Bytes)
+    /// -> Result<Bytes, AnyError>`.
+    ///
+    /// Requests on the same connection are handled concurrently. The returned future is
+    /// dropped if the client cancels the call. Returning an error resets the stream, which
+    /// the client sees as [`RpcError::RemoteFailed`].
+    fn handle(
+        &self,
+        connection: &Connection,
+        request: Bytes,
+    ) -> impl Future<Output = Result<Bytes, AnyError>> + Send;
+}
+
+/// A [`ProtocolHandler`] that dispatches each incoming stream to an [`RpcHandler`].
+///
+/// See the [module documentation](self) for the wire format.
+#[derive(Debug)]
+pub struct RpcServer<H> {
+    handler: Arc<H>,
+    max_message_size: usize,
+}
+
+impl<H: RpcHandler> RpcServer<H> {
+    /// Creates a new server that handles requests with `handler`.
+    pub fn new(handler: H) -> Self {
+        Self {
+            handler: Arc::new(handler),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        }
+    }
+
+    /// Sets the maximum size of requests and responses, in bytes.
+    ///
+    /// Larger requests are rejected without calling the handler. Defaults to
+    /// [`DEFAULT_MAX_MESSAGE_SIZE`].
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+}
+
+impl<H: RpcHandler> ProtocolHandler for RpcServer<H> {
+    async fn accept(&self, connection: Connection) -> Result<(), AcceptError> {
+        let mut streams = JoinSet::new();
+        loop {
+            tokio::select! {
+                res = connection.accept_bi() => {
+                    let (send, recv) = match res {
+                        Ok(streams) => streams,
+                        Err(err) => {
+                            debug!("connection closed: {err:#}");
+                            break;
+                        }
+                    };
+                    let span = debug_span!("rpc", stream = %recv.id());
+                    streams.spawn(
+                        serve_stream(
+                            self.handler.clone(),
+                            connection.clone(),
+                            send,
+                            recv,
+                            self.max_message_size,
+                        )
+                        .instrument(span),
+                    );
+                }
+                Some(_) = streams.join_next(), if !streams.is_empty() => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn serve_stream<H: RpcHandler>(
+    handler: Arc<H>,
+    connection: Connection,
+    mut send: SendStream,
+    mut recv: RecvStream,
+    max_message_size: usize,
+) {
+    let request = match read_frame(&mut recv, max_message_size).await {
+        Ok(request) => request,
+        Err(err) => {
+            debug!("failed to read request: {err:#}");
+            if matches!(err, RpcError::MessageTooLarge { .. }) {
+                recv.stop(MESSAGE_TOO_LARGE).ok();
+                send.reset(MESSAGE_TOO_LARGE).ok();
+            }
+            return;
+        }
+    };
+    let stopped = send.stopped();
+    let response = tokio::select! {
+        res = handler.handle(&connection, request) => res,
+        _ = stopped => {
+            debug!("call cancelled by client");
+            return;
+        }
+    };
+    let response = match response {
+        Ok(response) if response.len() > max_message_size => {
+            warn!(
+                size = response.len(),
+                "response exceeds the maximum message size"
+            );
+            send.reset(MESSAGE_TOO_LARGE).ok();
+            return;
+        }
+        Ok(response) => response,
+        Err(err) => {
+            debug!("handler failed: {err:#}");
+            send.reset(HANDLER_FAILED).ok();
+            return;
+        }
+    };
+    if let Err(err) = write_frame(&mut send, &response).await {
+        debug!("failed to write response: {err:#}");
+        return;
+    }
+    send.finish().ok();
+}
+
+/// A client for an [`RpcServer`].
+///
+/// Each [`Self::call`] opens a new stream on the connection, so calls can be made
+/// concurrently. The client is cheap to clone.
+#[derive(Debug, Clone)]
+pub struct RpcClient {
+    connection: Connection,
+    timeout: Option<Duration>,
+    max_message_size: usize,
+}
+
+impl RpcClient {
+    /// Creates a new client that makes calls on `connection`.
+    pub fn new(connection: Connection) -> Self {
+        Self {
+            connection,
+            timeout: None,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        }
+    }
+
+    /// Sets a timeout for each call, including opening the stream.
+    ///
+    /// By default calls do not time out.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum size of requests and responses, in bytes.
+    ///
+    /// Defaults to [`DEFAULT_MAX_MESSAGE_SIZE`].
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    /// Returns the connection the client makes calls on.
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Sends `request` and returns the response.
+    ///
+    /// Dropping the returned future cancels the call: the stream is reset, and the
+    /// server drops the future of its [`RpcHandler`].
+    pub async fn call(&self, request: impl Into<Bytes>) -> Result<Bytes, RpcError> {
+        let request = request.into();
+        if request.len() > self.max_message_size {
+            return Err(e!(RpcError::MessageTooLarge {
+                size: request.len(),
+                max: self.max_message_size,
+            }));
+        }
+        match self.timeout {
+            Some(timeout) => time::timeout(timeout, self.call_inner(request))
+                .await
+                .map_err(|_| e!(RpcError::Timeout))?,
+            None => self.call_inner(request).await,
+        }
+    }
+
+    async fn call_inner(&self, request: Bytes) -> Result<Bytes, RpcError> {
+        let (send, recv) = self
+            .connection
+            .open_bi()
+            .await
+            .map_err(|err| e!(RpcError::Open, err))?;
+        let mut call = CallGuard {
+            send,
+            recv,
+            done: false,
+        };
+        write_frame(&mut call.send, &request).await?;
+        call.send.finish().ok();
+        let response = read_frame(&mut call.recv, self.max_message_size).await;
+        if let Err(RpcError::MessageTooLarge { .. }) = response {
+            call.recv.stop(MESSAGE_TOO_LARGE).ok();
+        }
+        call.done = true;
+        response
+    }
+}
+
+/// Resets the streams of a call that did not complete.
+struct CallGuard {
+    send: SendStream,
+    recv: RecvStream,
+    done: bool,
+}
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        if !self.done {
+            self.send.reset(CANCELLED).ok();
+            self.recv.stop(CANCELLED).ok();
+        }
+    }
+}
+
+/// Error returned by [`RpcClient::call`].
+#[allow(missing_docs)]
+#[stack_error(derive, add_meta)]
+#[non_exhaustive]
+pub enum RpcError {
+    #[error("Failed to open stream")]
+    Open {
+        #[error(std_err)]
+        source: ConnectionError,
+    },
+    #[error("Message of {size} bytes exceeds the maximum message size of {max} bytes")]
+    MessageTooLarge { size: usize, max: usize },
+    #[error("Message exceeds the maximum message size of the remote")]
+    RemoteMessageTooLarge,
+    #[error("Remote failed to handle the request")]
+    RemoteFailed,
+    #[error("Call was cancelled")]
+    Cancelled,
+    #[error("Call timed out")]
+    Timeout,
+    #[error("Failed to write message")]
+    Write {
+        #[error(std_err)]
+        source: WriteError,
+    },
+    #[error("Failed to read message")]
+    Read {
+        #[error(std_err)]
+        source: ReadExactError,
+    },
+}
+
+impl RpcError {
+    /// Returns the error for a stream that was reset or stopped with `code` by the remote.
+    fn from_code(code: VarInt) -> Option<Self> {
+        match code {
+            MESSAGE_TOO_LARGE => Some(e!(RpcError::RemoteMessageTooLarge)),
+            HANDLER_FAILED => Some(e!(RpcError::RemoteFailed)),
+            CANCELLED => Some(e!(RpcError::Cancelled)),
+            _ => None,
+        }
+    }
+}
+
+async fn write_frame(send: &mut SendStream, message: &[u8]) -> Result<(), RpcError> {
+    let len = u32::try_from(message.len()).map_err(|_| {
+        e!(RpcError::MessageTooLarge {
+            size: message.len(),
+            max: u32::MAX as usize,
+        })
+    })?;
+    let write = async {
+        send.write_all(&len.to_be_bytes()).await?;
+        send.write_all(message).await
+    };
+    write.await.map_err(|err| match err {
+        WriteError::Stopped(code) => {
+            RpcError::from_code(code).unwrap_or_else(|| e!(RpcError::Write, err))
+        }
+        err => e!(RpcError::Write, err),
+    })
+}
+
+async fn read_frame(recv: &mut RecvStream, max_message_size: usize) -> Result<Bytes, RpcError> {
+    let map_err = |err: ReadExactError| match err {
+        ReadExactError::ReadError(ReadError::Reset(code)) => {
+            RpcError::from_code(code).unwrap_or_else(|| e!(RpcError::Read, err))
+        }
+        err => e!(RpcError::Read, err),
+    };
+    let mut len = [0u8; 4];
+    recv.read_exact(&mut len).await.map_err(map_err)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > max_message_size {
+        return Err(e!(RpcError::MessageTooLarge {
+            size: len,
+            max: max_message_size,
+        }));
+    }
+    let mut message = vec![0u8; len];
+    recv.read_exact(&mut message).await.map_err(map_err)?;
+    Ok(message.into())
+}
+
+#[cfg(all(test, with_crypto_provider))]
+mod tests {
+    use n0_error::{Result, StdResultExt, anyerr};
+    use n0_tracing_test::traced_test;
+    use tokio::sync::oneshot;
+
+    use super::*;
+    use crate::{
+        Endpoint,
+        endpoint::presets,
+        protocol::{Router, RouterBuilder},
+    };
+
+    const TEST_ALPN: &[u8] = b"/iroh/test/rpc/1";
+
+    /// Replies with the request in reverse, fails on `fail` and never replies to `hang`.
+    #[derive(Debug, Default)]
+    struct TestHandler {
+        dropped: std::sync::Mutex<Option<oneshot::Sender<()>>>,
+    }
+
+    impl RpcHandler for TestHandler {
+        async fn handle(&self, connection: &Connection, request: Bytes) -> Result<Bytes, AnyError> {
+            match &request[..] {
+                b"fail" => Err(anyerr!("failed")),
+                b"hang" => {
+                    // Signals when the handler future is dropped.
+                    let _guard = self.dropped.lock().expect("poisoned").take();
+                    std::future::pending().await
+                }
+                b"id" => Ok(connection.remote_id().as_bytes().to_vec().into()),
+                b"large" => Ok(vec![0u8; 1024].into()),
+                _ => Ok(request.iter().rev().copied().collect()),
+            }
+        }
+    }
+
+    async fn setup(server: RpcServer<TestHandler>) -> Result<(Router, Endpoint, Connection)> {
+        let router = RouterBuilder::new(Endpoint::bind(presets::Minimal).await?)
+            .accept(TEST_ALPN, server)
+            .spawn();
+        let client = Endpoint::bind(presets::Minimal).await?;
+        let conn = client.connect(router.endpoint().addr(), TEST_ALPN).await?;
+        Ok((router, client, conn))
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn rpc_call() -> Result {
+        let (router, client_ep, conn) = setup(RpcServer::new(TestHandler::default())).await?;
+        let client = RpcClient::new(conn);
+
+        let calls = n0_future::join_all((0..16).map(|i| {
+            let client = client.clone();
+            async move { client.call(format!("request {i}")).await }
+        }))
+        .await;
+        for (i, response) in calls.into_iter().enumerate() {
+            let expected: String = format!("request {i}").chars().rev().collect();
+            assert_eq!(response.anyerr()?, expected.as_bytes());
+        }
+
+        let response = client.call(&b"id"[..]).await.anyerr()?;
+        assert_eq!(&response[..], client_ep.id().as_bytes());
+
+        let err = client.call(&b"fail"[..]).await.unwrap_err();
+        assert!(matches!(err, RpcError::RemoteFailed { .. }), "{err:?}");
+
+        // The connection stays usable after a failed call.
+        let response = client.call(&b"ok"[..]).await.anyerr()?;
+        assert_eq!(&response[..], b"ko");
+
+        router.shutdown().await.anyerr()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn rpc_max_message_size() -> Result {
+        let server = RpcServer::new(TestHandler::default()).with_max_message_size(64);
+        let (router, _client_ep, conn) = setup(server).await?;
+
+        let client = RpcClient::new(conn.clone());
+        let err = client.call(vec![1u8; 100]).await.unwrap_err();
+        assert!(
+            matches!(err, RpcError::RemoteMessageTooLarge { .. }),
+            "{err:?}"
+        );
+
+        let client = RpcClient::new(conn).with_max_message_size(16);
+        let err = client.call(vec![1u8; 32]).await.unwrap_err();
+        assert!(
+            matches!(
+                err,
+                RpcError::MessageTooLarge {
+                    size: 32,
+                    max: 16,
+                    ..
+                }
+            ),
+            "{err:?}"
+        );
+        // The response of 1024 bytes exceeds the limits of both sides.
+        let err = client.call(&b"large"[..]).await.unwrap_err();
+        assert!(
+            matches!(err, RpcError::RemoteMessageTooLarge { .. }),
+            "{err:?}"
+        );
+        let response = client.call(&b"small"[..]).await.anyerr()?;
+        assert_eq!(&response[..], b"llams");
+
+        router.shutdown().await.anyerr()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn rpc_timeout_cancels_handler() -> Result {
+        let (dropped_tx, dropped_rx) = oneshot::channel();
+        let handler = TestHandler {
+            dropped: std::sync::Mutex::new(Some(dropped_tx)),
+        };
+        let (router, _client_ep, conn) = setup(RpcServer::new(handler)).await?;
+        let client = RpcClient::new(conn).with_timeout(Duration::from_millis(200));
+
+        let err = client.call(&b"hang"[..]).await.unwrap_err();
+        assert!(matches!(err, RpcError::Timeout { .. }), "{err:?}");
+
+        // The server drops the handler future once it sees the cancellation.
+        time::timeout(Duration::from_secs(5), dropped_rx)
+            .await
+            .anyerr()?
+            .unwrap_err();
+
+        let response = client.call(&b"ok"[..]).await.anyerr()?;
+        assert_eq!(&response[..], b"ko");
+
+        router.shutdown().await.anyerr()?;
+        Ok(())
+    }
+}
@@ -100,6 +100,12 @@ impl noq::Runtime for Runtime {
         wasm_bindgen_futures::spawn_local(future);
     }
 
+    /// Uses tokio's clock, so that QUIC honours a paused clock in tests.
+    #[cfg(not(wasm_browser))]
+    fn now(&self) -> std::time::Instant {
+        noq::TokioRuntime.now()
+    }
+
     // We're not actually using this function in iroh
     #[cfg(not(wasm_browser))]
     fn wrap_udp_socket(
@@ -25,7 +25,7 @@ use std::{
     },
 };
 
-use iroh_base::{EndpointAddr, EndpointId, RelayUrl, SecretKey, TransportAddr};
+use iroh_base::{EndpointAddr, EndpointId, RelayUrl, Signer, TransportAddr};
 use iroh_relay::{RelayConfig, RelayMap};
 use mapped_addrs::MultipathMappedAddr;
 use n0_error::{AnyError, anyerr, bail, e, stack_error};
@@ -48,7 +48,7 @@ use noq::{
 use rand::RngExt;
 use rustc_hash::FxHashSet;
 use tokio::sync::{
-    Mutex as AsyncMutex,
+    Mutex as AsyncMutex, broadcast,
     mpsc::{self},
     oneshot,
 };
@@ -95,7 +95,8 @@ pub(crate) mod remote_map;
 pub(crate) mod transports;
 
 use self::mapped_addrs::{EndpointIdMappedAddr, MappedAddr};
-pub use self::metrics::Metrics;
+use self::metrics::RemoteLabelSlots;
+pub use self::metrics::{Metrics, PathTransport, RemoteLabels};
 
 // TODO: Use this
 // /// How long we consider a QAD-derived endpoint valid for. UDP NAT mappings typically
@@ -144,6 +145,10 @@ pub(crate) const MAX_MULTIPATH_PATHS: u32 = 8;
 /// value.
 pub(crate) const MAX_QNT_ADDRESSES: u8 = 32;
 
+/// Number of connection announcements buffered for each subscriber of
+/// [`EndpointInner::connected`].
+const CONNECTED_QUEUE_DEPTH: usize = 64;
+
 /// Error returned when the endpoint state actor stopped while waiting for a reply.
 #[stack_error(add_meta, derive)]
 #[error("endpoint state actor stopped")]
@@ -163,8 +168,8 @@ pub(crate) struct Options {
     /// The configuration for the different transports.
     pub(crate) transports: Vec<TransportConfig>,
 
-    /// Secret key for this endpoint.
-    pub(crate) secret_key: SecretKey,
+    /// Signs for the key of this endpoint.
+    pub(crate) signer: Arc<dyn Signer>,
 
     /// Optional user-defined Address Lookup data.
     pub(crate) address_lookup_user_data: Option<UserData>,
@@ -186,6 +191,8 @@ pub(crate) struct Options {
     pub(crate) server_config: noq_proto::ServerConfig,
 
     pub(crate) metrics: EndpointMetrics,
+    /// Maximum number of remotes labelled with their endpoint id in the per-remote metrics.
+    pub(crate) per_remote_metrics: usize,
     pub(crate) hooks: EndpointHooksList,
     pub(crate) path_selector: Arc<dyn PathSelector>,
     pub(crate) portmapper_config: portmapper::PortmapperConfig,
@@ -215,6 +222,8 @@ pub(crate) struct EndpointInner {
     runtime: Arc<Runtime>,
     /// Static configuration for the endpoint.
     pub(crate) static_config: StaticConfig,
+    /// Announces the remote of every newly established connection.
+    pub(crate) connected: broadcast::Sender<EndpointId>,
 }
 
 impl Drop for EndpointInner {
@@ -878,7 +887,7 @@ impl EndpointInner {
         let span = tracing::Span::current();
 
         let Options {
-            secret_key,
+            signer,
             transports: transport_configs,
             address_lookup_user_data,
             #[cfg(not(wasm_browser))]
@@ -887,6 +896,7 @@ impl EndpointInner {
             server_config,
             tls_config,
             metrics,
+            per_remote_metrics,
             hooks,
             path_selector,
             portmapper_config,
@@ -896,7 +906,7 @@ impl EndpointInner {
         } = opts;
 
         let address_lookup = address_lookup::AddressLookupServices::default();
-        let port_mapper = portmapper::create_client(&portmapper_config);
+        let port_mapper = portmapper::create_client(&portmapper_config, metrics.portmapper.clone());
 
         let relay_transport_configs: Vec<_> = transport_configs
             .iter()
@@ -924,7 +934,7 @@ impl EndpointInner {
 
         let relay_actor_config = RelayActorConfig {
             my_relay: HomeRelayWatch::default(),
-            secret_key: secret_key.clone(),
+            signer: signer.clone(),
             #[cfg(not(wasm_browser))]
             dns_resolver: dns_resolver.clone(),
             proxy_url: proxy_url.clone(),
@@ -977,6 +987,7 @@ impl EndpointInner {
         let remote_map = {
             RemoteMap::new(
                 metrics.socket.clone(),
+                Arc::new(RemoteLabelSlots::new(per_remote_metrics)),
                 direct_addrs.addrs.watch(),
                 address_lookup.clone(),
                 shutdown_token.child_token(),
@@ -1022,7 +1033,7 @@ impl EndpointInner {
         let local_addrs_watch = transports.local_addrs_watch();
         let transports_network_change = transports.create_network_change_sender();
 
-        let runtime = Arc::new(Runtime::new(secret_key.public()));
+        let runtime = Arc::new(Runtime::new(signer.public_key()));
 
         let endpoint = noq::Endpoint::new_with_abstract_socket(
             endpoint_config,
@@ -1114,6 +1125,7 @@ impl EndpointInner {
             endpoint,
             runtime,
             static_config,
+            connected: broadcast::channel(CONNECTED_QUEUE_DEPTH).0,
         })
     }
 
@@ -1255,6 +1267,37 @@ impl EndpointInner {
         res
     }
 
+    /// Returns a copy of the current relays.
+    pub(crate) fn relay_map(&self) -> RelayMap {
+        self.relay_map.relays::<Vec<_>>().into_iter().collect()
+    }
+
+    /// Replaces the relays with the relays of `relay_map`.
+    ///
+    /// Returns `true` if the relays changed.
+    pub(crate) async fn set_relays(&self, relay_map: &RelayMap) -> bool {
+        let mut changed = false;
+        for url in self.relay_map.urls::<Vec<_>>() {
+            if !relay_map.contains(&url) {
+                self.relay_map.remove(&url);
+                changed = true;
+            }
+        }
+        for config in relay_map.relays::<Vec<_>>() {
+            if self.relay_map.get(&config.url).as_ref() != Some(&config) {
+                self.relay_map.insert(config.url.clone(), config);
+                changed = true;
+            }
+        }
+        if changed {
+            self.actor_sender
+                .send(ActorMessage::RelayMapChange)
+                .await
+                .ok();
+        }
+        changed
+    }
+
     /// Adds an external address to advertise to peers.
     pub(crate) async fn add_external_addr(&self, addr: SocketAddr) {
         self.sock
@@ -1293,6 +1336,16 @@ impl EndpointInner {
             .ok();
     }
 
+    /// Call after the endpoint key was rotated.
+    ///
+    /// Reconnects to relay servers with the new key and republishes our addressing information.
+    pub(crate) async fn identity_changed(&self) {
+        self.actor_sender
+            .send(ActorMessage::IdentityChanged)
+            .await
+            .ok();
+    }
+
     #[cfg(all(test, with_crypto_provider))]
     async fn force_network_change(&self, is_major: bool) {
         self.actor_sender
@@ -1392,6 +1445,8 @@ enum ActorMessage {
     ),
     /// Re-evaluate direct addresses, e.g. after configured external addresses changed.
     DirectAddrRefresh,
+    /// The endpoint key was rotated.
+    IdentityChanged,
     #[cfg(all(test, with_crypto_provider))]
     ForceNetworkChange(bool),
 }
@@ -1794,6 +1849,10 @@ impl Actor {
                     self.update_direct_addresses(report.as_ref());
                 }
             }
+            ActorMessage::IdentityChanged => {
+                self.transports_network_change.reconnect_relays();
+                self.sock.publish_my_addr();
+            }
             #[cfg(all(test, with_crypto_provider))]
             ActorMessage::ForceNetworkChange(is_major) => {
                 self.handle_network_change(is_major);
@@ -2168,7 +2227,7 @@ mod tests {
                 TransportConfig::default_ipv4(),
                 TransportConfig::default_ipv6(),
             ],
-            secret_key,
+            signer: Arc::new(secret_key),
             proxy_url: None,
             dns_resolver: DnsResolver::new(),
             server_config,
@@ -2179,6 +2238,7 @@ mod tests {
             address_lookup_user_data: None,
             metrics: Default::default(),
             hooks: Default::default(),
+            per_remote_metrics: 0,
             path_selector: Arc::new(BiasedRttPathSelector::default()),
             portmapper_config: Default::default(),
             net_report_config: Default::default(),
@@ -2585,7 +2645,7 @@ mod tests {
                 TransportConfig::default_ipv4(),
                 TransportConfig::default_ipv6(),
             ],
-            secret_key: secret_key.clone(),
+            signer: Arc::new(secret_key),
             address_lookup_user_data: None,
             dns_resolver,
             proxy_url: None,
@@ -2595,6 +2655,7 @@ mod tests {
                 .unwrap(),
             metrics: Default::default(),
             hooks: Default::default(),
+            per_remote_metrics: 0,
             path_selector: Arc::new(BiasedRttPathSelector::default()),
             portmapper_config: Default::default(),
             net_report_config: Default::default(),
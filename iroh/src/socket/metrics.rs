@@ -1,4 +1,7 @@
-use iroh_metrics::{Counter, MetricsGroup};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use iroh_base::EndpointId;
+use iroh_metrics::{Counter, EncodeLabelSet, EncodeLabelValue, Family, MetricsGroup};
 use serde::{Deserialize, Serialize};
 
 /// Metrics collected by the iroh socket.
@@ -33,6 +36,22 @@ pub struct Metrics {
     /// [`Self::recv_datagrams`] for the datagram count.
     pub recv_gro_datagrams: Counter,
 
+    /// Number of bytes sent to each remote endpoint.
+    ///
+    /// Sampled periodically from the QUIC path statistics of the connections to the
+    /// remote. The entries for a remote are removed once there have been no connections
+    /// to it for a while.
+    ///
+    /// Only remotes which got a label slot from
+    /// [`Builder::per_remote_metrics`](crate::endpoint::Builder::per_remote_metrics) are
+    /// labelled with their endpoint id, all other bytes are counted under
+    /// [`RemoteLabels::OTHER`].
+    pub remote_bytes_sent: Family<RemoteLabels, Counter>,
+    /// Number of bytes received from each remote endpoint.
+    ///
+    /// Sampled like [`Self::remote_bytes_sent`].
+    pub remote_bytes_recv: Family<RemoteLabels, Counter>,
+
     /// Number of times the home relay changed to a different relay.
     ///
     /// This includes the initial assignment from no home relay to a home relay.
@@ -103,3 +122,116 @@ pub struct Metrics {
     /// Number of times an input watcher or receiver closed in the socket actor loop.
     pub actor_tick_other: Counter,
 }
+
+/// Labels of the per-remote metrics in [`Metrics`].
+#[derive(
+    Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, EncodeLabelSet,
+)]
+#[non_exhaustive]
+pub struct RemoteLabels {
+    /// The endpoint id of the remote.
+    pub remote: String,
+    /// The kind of path the bytes were transferred on.
+    pub transport: PathTransport,
+}
+
+impl RemoteLabels {
+    /// The `remote` label of the bytes of remotes without a label of their own.
+    pub const OTHER: &str = "other";
+
+    /// Creates the labels for `remote` and `transport`.
+    pub fn new(remote: impl Into<String>, transport: PathTransport) -> Self {
+        Self {
+            remote: remote.into(),
+            transport,
+        }
+    }
+}
+
+/// Bounds the number of remotes labelled with their endpoint id in the per-remote metrics.
+#[derive(Debug, Default)]
+pub(crate) struct RemoteLabelSlots {
+    max: usize,
+    used: AtomicUsize,
+}
+
+impl RemoteLabelSlots {
+    /// Creates slots for labelling up to `max` remotes at the same time.
+    pub(crate) fn new(max: usize) -> Self {
+        Self {
+            max,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the `remote` label to use for `remote`.
+    ///
+    /// This is the endpoint id if a slot is free, which must then be given back with
+    /// [`Self::release`] once the labels are removed.  Otherwise it is
+    /// [`RemoteLabels::OTHER`].
+    pub(crate) fn label(&self, remote: EndpointId) -> String {
+        let acquired = self
+            .used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                (used < self.max).then_some(used + 1)
+            })
+            .is_ok();
+        match acquired {
+            true => remote.to_string(),
+            false => RemoteLabels::OTHER.to_string(),
+        }
+    }
+
+    /// Gives back a slot taken by [`Self::label`].
+    pub(crate) fn release(&self) {
+        self.used.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// The kind of path of a per-remote metric.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Hash,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    EncodeLabelValue,
+)]
+#[non_exhaustive]
+pub enum PathTransport {
+    /// A direct path over IP.
+    Direct,
+    /// A path via a relay server.
+    Relay,
+    /// A path over a custom transport.
+    Custom,
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh_base::SecretKey;
+    use rand::{RngExt, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn remote_label_slots() {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0);
+        let a = SecretKey::from_bytes(&rng.random()).public();
+        let b = SecretKey::from_bytes(&rng.random()).public();
+
+        let disabled = RemoteLabelSlots::default();
+        assert_eq!(disabled.label(a), RemoteLabels::OTHER);
+
+        let slots = RemoteLabelSlots::new(1);
+        assert_eq!(slots.label(a), a.to_string());
+        assert_eq!(slots.label(b), RemoteLabels::OTHER);
+        slots.release();
+        assert_eq!(slots.label(b), b.to_string());
+    }
+}
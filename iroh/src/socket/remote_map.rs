@@ -29,7 +29,7 @@ pub(crate) use self::remote_state::{
     PathSelection, PathSelectionContext, PathSelectionData, PathSelector,
 };
 use super::{
-    DirectAddr, Metrics as SocketMetrics,
+    DirectAddr, Metrics as SocketMetrics, RemoteLabelSlots,
     mapped_addrs::{
         AddrMap, CustomMappedAddr, EndpointIdMappedAddr, MultipathMappedAddr, RelayMappedAddr,
     },
@@ -130,6 +130,8 @@ struct Tasks {
     // State required for spawning new actors.
     //
     metrics: Arc<SocketMetrics>,
+    /// The slots for labelling the per-remote metrics, shared by all actors.
+    remote_label_slots: Arc<RemoteLabelSlots>,
     /// The "direct" addresses known for our local endpoint
     local_direct_addrs: n0_watcher::Direct<BTreeSet<DirectAddr>>,
     address_lookup: address_lookup::AddressLookupServices,
@@ -155,6 +157,7 @@ impl RemoteMap {
     /// Creates a new [`RemoteMap`].
     pub(super) fn new(
         metrics: Arc<SocketMetrics>,
+        remote_label_slots: Arc<RemoteLabelSlots>,
         local_direct_addrs: n0_watcher::Direct<BTreeSet<DirectAddr>>,
         address_lookup: address_lookup::AddressLookupServices,
         shutdown_token: CancellationToken,
@@ -166,6 +169,7 @@ impl RemoteMap {
             senders: Default::default(),
             tasks: Tasks {
                 metrics,
+                remote_label_slots,
                 local_direct_addrs,
                 address_lookup,
                 shutdown_token,
@@ -333,6 +337,7 @@ impl Tasks {
             mapped_addrs.relay_addrs.clone(),
             mapped_addrs.custom_addrs.clone(),
             self.metrics.clone(),
+            self.remote_label_slots.clone(),
             self.address_lookup.clone(),
             self.path_selector.clone(),
         )
@@ -397,6 +402,7 @@ mod tests {
         let shutdown_token = CancellationToken::new();
         let remote_map = RemoteMap::new(
             metrics,
+            Default::default(),
             local_direct_addrs,
             address_lookup::AddressLookupServices::default(),
             shutdown_token.clone(),
@@ -33,7 +33,8 @@ use crate::{
     address_lookup::{AddressLookupFailed, AddressLookupServices, Item as AddressLookupItem},
     endpoint::DirectAddr,
     socket::{
-        Metrics as SocketMetrics, RELAY_PATH_MAX_IDLE_TIMEOUT,
+        Metrics as SocketMetrics, PathTransport, RELAY_PATH_MAX_IDLE_TIMEOUT, RemoteLabelSlots,
+        RemoteLabels,
         mapped_addrs::{AddrMap, CustomMappedAddr, RelayMappedAddr},
         remote_map::remote_state::path_watcher::PathStateSender,
         transports::{self, OwnedTransmit, TransportsSender},
@@ -64,6 +65,10 @@ const GOOD_ENOUGH_LATENCY: Duration = Duration::from_millis(10);
 /// Even if we have some non-relay route that works.
 const UPGRADE_INTERVAL: Duration = Duration::from_secs(60);
 
+/// How often the per-remote byte counters in [`SocketMetrics`] are updated from the
+/// path statistics.
+const METRICS_INTERVAL: Duration = Duration::from_secs(5);
+
 /// The time after which an idle [`RemoteStateActor`] stops.
 ///
 /// The actor only enters the idle state if no connections are active and no inbox senders exist
@@ -113,6 +118,10 @@ struct State {
     //
     /// Metrics.
     metrics: Arc<SocketMetrics>,
+    /// The slots for labelling the per-remote metrics with the endpoint id.
+    remote_label_slots: Arc<RemoteLabelSlots>,
+    /// The `remote` label of our per-remote metrics, assigned on the first report.
+    remote_label: Option<String>,
     /// Our local addresses.
     ///
     /// These are our local addresses and any reflexive transport addresses.
@@ -178,6 +187,7 @@ impl RemoteStateActor {
         relay_mapped_addrs: AddrMap<(RelayUrl, EndpointId), RelayMappedAddr>,
         custom_mapped_addrs: AddrMap<CustomAddr, CustomMappedAddr>,
         metrics: Arc<SocketMetrics>,
+        remote_label_slots: Arc<RemoteLabelSlots>,
         address_lookup: AddressLookupServices,
         path_selector: Arc<dyn PathSelector>,
     ) -> Self {
@@ -186,6 +196,8 @@ impl RemoteStateActor {
             state: State {
                 endpoint_id,
                 metrics: metrics.clone(),
+                remote_label_slots,
+                remote_label: None,
                 local_direct_addrs,
                 relay_mapped_addrs,
                 custom_mapped_addrs,
@@ -252,6 +264,9 @@ impl RemoteStateActor {
         let check_connections = time::interval(UPGRADE_INTERVAL);
         n0_future::pin!(check_connections);
 
+        let report_metrics = time::interval(METRICS_INTERVAL);
+        n0_future::pin!(report_metrics);
+
         loop {
             let scheduled_path_open = match self.state.scheduled_open_path {
                 Some(when) => MaybeFuture::Some(time::sleep_until(when)),
@@ -320,6 +335,9 @@ impl RemoteStateActor {
                 _ = check_connections.tick() => {
                     self.check_connections();
                 }
+                _ = report_metrics.tick() => {
+                    self.report_path_stats();
+                }
                 _ = &mut idle_timeout => {
                     if self.is_idle(&inbox) {
                         trace!("idle timeout expired and still idle: terminate actor");
@@ -339,6 +357,7 @@ impl RemoteStateActor {
         inbox.recv_many(&mut leftover_msgs, inbox.len()).await;
 
         trace!("actor terminating");
+        self.state.remove_remote_metrics();
         (self.state.endpoint_id, leftover_msgs)
     }
 
@@ -414,6 +433,7 @@ impl RemoteStateActor {
                 handle: conn.weak_handle(),
                 path_state: path_state_sender,
                 paths: Default::default(),
+                reported_bytes: Default::default(),
                 has_been_direct: false,
             })
             .into_mut();
@@ -479,8 +499,16 @@ impl RemoteStateActor {
             reason=?closed.reason,
         );
 
-        if let Some(conn_state) = self.connections.remove(&conn_id) {
+        if let Some(mut conn_state) = self.connections.remove(&conn_id) {
             self.state.metrics.num_conns_closed.inc();
+            for (path_id, stats) in &closed.path_stats {
+                conn_state.report_path_stats(
+                    *path_id,
+                    stats,
+                    &self.state.remote_label(),
+                    &self.state.metrics,
+                );
+            }
             conn_state.path_state.close(closed);
         }
         if self.connections.is_empty() {
@@ -628,6 +656,13 @@ impl RemoteStateActor {
             }
             NoqPathEvent::Discarded { id, path_stats, .. } => {
                 trace!(%id, ?path_stats, "path discarded");
+                conn_state.report_path_stats(
+                    id,
+                    &path_stats,
+                    &self.state.remote_label(),
+                    &self.state.metrics,
+                );
+                conn_state.reported_bytes.remove(&id);
             }
             NoqPathEvent::RemoteStatus { .. } | NoqPathEvent::ObservedAddr { .. } => {
                 // Nothing to do for these events.
@@ -745,6 +780,26 @@ impl RemoteStateActor {
         }
     }
 
+    /// Updates the per-remote byte counters from the stats of all open paths.
+    fn report_path_stats(&mut self) {
+        for conn_state in self.connections.values_mut() {
+            let Some(conn) = conn_state.handle.upgrade() else {
+                continue;
+            };
+            let path_ids: Vec<_> = conn_state.reported_bytes.keys().copied().collect();
+            for path_id in path_ids {
+                if let Some(stats) = conn.path_stats(path_id) {
+                    conn_state.report_path_stats(
+                        path_id,
+                        &stats,
+                        &self.state.remote_label(),
+                        &self.state.metrics,
+                    );
+                }
+            }
+        }
+    }
+
     /// Handles regularly checking if any paths need hole punching currently
     ///
     /// Currently we need to have 1 IP path, with a good enough latency.
@@ -784,6 +839,35 @@ impl RemoteStateActor {
 }
 
 impl State {
+    /// Returns the `remote` label of our per-remote metrics, assigning it on first use.
+    fn remote_label(&mut self) -> String {
+        self.remote_label
+            .get_or_insert_with(|| self.remote_label_slots.label(self.endpoint_id))
+            .clone()
+    }
+
+    /// Removes the per-remote metrics of this remote endpoint.
+    ///
+    /// The metrics under [`RemoteLabels::OTHER`] are shared with other remotes and kept.
+    fn remove_remote_metrics(&mut self) {
+        let Some(remote) = self.remote_label.take() else {
+            return;
+        };
+        if remote == RemoteLabels::OTHER {
+            return;
+        }
+        self.remote_label_slots.release();
+        for transport in [
+            PathTransport::Direct,
+            PathTransport::Relay,
+            PathTransport::Custom,
+        ] {
+            let labels = RemoteLabels::new(remote.clone(), transport);
+            self.metrics.remote_bytes_sent.remove(&labels);
+            self.metrics.remote_bytes_recv.remove(&labels);
+        }
+    }
+
     /// Handles [`RemoteStateMessage::SendDatagram`].
     async fn handle_msg_send_datagram(
         &mut self,
@@ -1228,6 +1312,11 @@ struct ConnectionState {
     path_state: PathStateSender,
     /// The open paths that exist on this connection.
     paths: FxHashMap<PathId, transports::FourTuple>,
+    /// The bytes of each path already added to the per-remote metrics.
+    ///
+    /// Unlike [`Self::paths`], entries are kept until the path is discarded, so that the
+    /// bytes transferred between abandoning and discarding a path are counted.
+    reported_bytes: FxHashMap<PathId, ReportedBytes>,
     /// Whether this connection has ever had a direct path.
     ///
     /// Used for recording metrics.
@@ -1247,6 +1336,16 @@ impl ConnectionState {
             transports::FourTuple::Relay { .. } => metrics.paths_relay.inc(),
             transports::FourTuple::Custom { .. } => metrics.paths_custom.inc(),
         };
+        let transport = match network_path {
+            transports::FourTuple::Ip { .. } => PathTransport::Direct,
+            transports::FourTuple::Relay { .. } => PathTransport::Relay,
+            transports::FourTuple::Custom { .. } => PathTransport::Custom,
+        };
+        self.reported_bytes.entry(path_id).or_insert(ReportedBytes {
+            transport,
+            sent: 0,
+            recv: 0,
+        });
         if !self.has_been_direct && network_path.is_ip() {
             self.has_been_direct = true;
             metrics.num_conns_direct.inc();
@@ -1261,6 +1360,35 @@ impl ConnectionState {
         }
     }
 
+    /// Adds the bytes transferred on a path since the last report to the per-remote metrics.
+    fn report_path_stats(
+        &mut self,
+        path_id: PathId,
+        stats: &PathStats,
+        remote: &str,
+        metrics: &SocketMetrics,
+    ) {
+        let Some(reported) = self.reported_bytes.get_mut(&path_id) else {
+            return;
+        };
+        let sent = stats.udp_tx.bytes.saturating_sub(reported.sent);
+        let recv = stats.udp_rx.bytes.saturating_sub(reported.recv);
+        if sent == 0 && recv == 0 {
+            return;
+        }
+        let labels = RemoteLabels::new(remote, reported.transport);
+        metrics
+            .remote_bytes_sent
+            .get_or_create(&labels)
+            .inc_by(sent);
+        metrics
+            .remote_bytes_recv
+            .get_or_create(&labels)
+            .inc_by(recv);
+        reported.sent += sent;
+        reported.recv += recv;
+    }
+
     /// Removes a path from this connection.
     fn remove_path(
         &mut self,
@@ -1273,6 +1401,15 @@ impl ConnectionState {
     }
 }
 
+/// Bytes of a path that have been added to [`SocketMetrics::remote_bytes_sent`] and
+/// [`SocketMetrics::remote_bytes_recv`].
+#[derive(Debug)]
+struct ReportedBytes {
+    transport: PathTransport,
+    sent: u64,
+    recv: u64,
+}
+
 /// State of the endpoint relevant for path selection.
 ///
 /// Constructed by the endpoint and passed to [`PathSelector::select`].  Borrows from
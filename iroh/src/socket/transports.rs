@@ -522,6 +522,13 @@ impl NetworkChangeSender {
         }
     }
 
+    /// Reconnects relay connections after the endpoint identity changed.
+    pub(crate) fn reconnect_relays(&self) {
+        for relay in &self.relay {
+            relay.reconnect();
+        }
+    }
+
     /// Rebinds underlying connections, if necessary.
     pub(crate) fn rebind(&self) -> std::io::Result<()> {
         let mut res = Ok(());
@@ -1,11 +1,12 @@
 use std::{
     io,
     num::NonZeroU16,
+    sync::Arc,
     task::{Context, Poll},
 };
 
 use bytes::Bytes;
-use iroh_base::{EndpointId, RelayUrl};
+use iroh_base::{EndpointId, RelayUrl, Signer};
 use iroh_relay::protos::relay::Datagrams;
 use n0_future::{
     ready,
@@ -38,7 +39,8 @@ pub(crate) struct RelayTransport {
     actor_sender: mpsc::Sender<RelayActorMessage>,
     _actor_handle: AbortOnDropHandle<()>,
     my_relay: HomeRelayWatch,
-    my_endpoint_id: EndpointId,
+    /// Signs for our endpoint id, which can change when the endpoint key is rotated.
+    signer: Arc<dyn Signer>,
 }
 
 impl RelayTransport {
@@ -49,7 +51,7 @@ impl RelayTransport {
 
         let (actor_sender, actor_receiver) = mpsc::channel(256);
 
-        let my_endpoint_id = config.secret_key.public();
+        let signer = config.signer.clone();
         let my_relay = config.my_relay.clone();
 
         let relay_actor = RelayActor::new(config, relay_datagram_recv_tx, cancel_token);
@@ -70,7 +72,7 @@ impl RelayTransport {
             actor_sender,
             _actor_handle: actor_handle,
             my_relay,
-            my_endpoint_id,
+            signer,
         }
     }
 
@@ -172,10 +174,10 @@ impl RelayTransport {
     }
 
     pub(super) fn local_addr_watch(&self) -> RelayAddrWatcher {
-        let my_endpoint_id = self.my_endpoint_id;
+        let signer = self.signer.clone();
         self.my_relay
             .watch()
-            .map(move |status| status.map(|status| (status.url().clone(), my_endpoint_id)))
+            .map(move |status| status.map(|status| (status.url().clone(), signer.public_key())))
     }
 
     pub(super) fn my_relay_status(&self) -> n0_watcher::Direct<Option<RelayStatus>> {
@@ -228,6 +230,11 @@ impl RelayNetworkChangeSender {
         self.send_relay_actor(RelayActorMessage::CheckConnectionAfterNetworkChange);
     }
 
+    /// Reconnects to all relay servers, to authenticate with a new endpoint key.
+    pub(super) fn reconnect(&self) {
+        self.send_relay_actor(RelayActorMessage::Reconnect);
+    }
+
     pub(super) fn rebind(&self) -> io::Result<()> {
         self.send_relay_actor(RelayActorMessage::MaybeCloseRelaysOnRebind);
 
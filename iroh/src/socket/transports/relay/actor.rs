@@ -38,7 +38,7 @@ use std::{
 };
 
 use backon::{Backoff, BackoffBuilder, ExponentialBuilder};
-use iroh_base::{EndpointId, RelayUrl, SecretKey};
+use iroh_base::{EndpointId, RelayUrl, Signer};
 use iroh_relay::{
     self as relay, PingTracker, RelayMap,
     client::{Client, ConnectError, RecvError, SendError},
@@ -83,6 +83,11 @@ const SEND_DATAGRAM_BATCH_SIZE: usize = 20;
 /// handshake.
 const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// Maximum delay before reconnecting to a relay server.
+///
+/// Caps both the reconnect backoff and the delay a restarting relay server asks for.
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(16);
+
 /// Time after which the [`ActiveRelayActor`] will drop undeliverable datagrams.
 ///
 /// When the [`ActiveRelayActor`] is not connected it can not deliver datagrams.  However it
@@ -152,6 +157,10 @@ struct ActiveRelayActor {
     stop_token: CancellationToken,
     metrics: Arc<SocketMetrics>,
     my_relay: HomeRelayWatch,
+    /// Reports to the [`RelayActor`] that the relay server announced a restart.
+    restarting: mpsc::Sender<RelayRestarting>,
+    /// Delay before reconnecting, set when the relay server announced a restart.
+    reconnect_delay: Option<Duration>,
 }
 
 #[derive(Debug)]
@@ -167,6 +176,11 @@ enum ActiveRelayMessage {
     CheckConnection { local_ips: Vec<IpAddr> },
     /// Sets this relay as the home relay, or not.
     SetHomeRelay(bool),
+    /// Closes the current connection and connects again.
+    ///
+    /// Sent when the endpoint rotated its key, the relay server only knows the endpoint by
+    /// the key it authenticated the connection with.
+    Reconnect,
     #[cfg(test)]
     GetLocalAddr(oneshot::Sender<Option<SocketAddr>>),
     #[cfg(test)]
@@ -196,12 +210,23 @@ struct ActiveRelayActorOptions {
     stop_token: CancellationToken,
     metrics: Arc<SocketMetrics>,
     my_relay: HomeRelayWatch,
+    restarting: mpsc::Sender<RelayRestarting>,
+}
+
+/// A relay server announced that it is restarting.
+///
+/// Sent from the [`ActiveRelayActor`] to the [`RelayActor`], which avoids the relay as home
+/// relay until `until`.
+#[derive(Debug)]
+struct RelayRestarting {
+    url: RelayUrl,
+    until: Instant,
 }
 
 /// Configuration needed to create a connection to a relay server.
 #[derive(Debug, Clone)]
 struct RelayConnectionOptions {
-    secret_key: SecretKey,
+    signer: Arc<dyn Signer>,
     #[cfg(not(wasm_browser))]
     dns_resolver: DnsResolver,
     proxy_url: Option<Url>,
@@ -231,6 +256,10 @@ enum RunError {
     PingTimeout,
     #[error("Local IP no longer valid")]
     LocalIpInvalid,
+    #[error("Endpoint identity changed")]
+    IdentityChanged,
+    #[error("Relay server is restarting")]
+    ServerRestarting,
     #[error("No local address")]
     LocalAddrMissing,
     #[error("Stream closed by server.")]
@@ -268,6 +297,7 @@ impl ActiveRelayActor {
             stop_token,
             metrics,
             my_relay,
+            restarting,
         } = opts;
         let relay_client_builder = Self::create_relay_builder(url.clone(), connection_opts);
         ActiveRelayActor {
@@ -282,6 +312,8 @@ impl ActiveRelayActor {
             stop_token,
             metrics,
             my_relay,
+            restarting,
+            reconnect_delay: None,
         }
     }
 
@@ -290,7 +322,7 @@ impl ActiveRelayActor {
         opts: RelayConnectionOptions,
     ) -> relay::client::ClientBuilder {
         let RelayConnectionOptions {
-            secret_key,
+            signer,
             #[cfg(not(wasm_browser))]
             dns_resolver,
             proxy_url,
@@ -301,7 +333,7 @@ impl ActiveRelayActor {
 
         let mut builder = relay::client::ClientBuilder::new(
             url,
-            secret_key,
+            signer,
             #[cfg(not(wasm_browser))]
             dns_resolver,
         )
@@ -329,6 +361,13 @@ impl ActiveRelayActor {
             let last_error = Some(Arc::new(AnyError::from(err)));
             self.my_relay
                 .set_status(&self.url, RelayConnectionState::Disconnected { last_error });
+            if let Some(delay) = self.reconnect_delay.take() {
+                debug!("relay server is restarting, reconnecting in {delay:?}");
+                tokio::select! {
+                    _ = self.stop_token.cancelled() => break,
+                    _ = time::sleep(delay) => {}
+                }
+            }
             if !was_established {
                 // If dialing failed, or if the relay connection failed before we received a pong,
                 // we wait an exponentially increasing time until we attempt to reconnect again.
@@ -337,7 +376,10 @@ impl ActiveRelayActor {
                     break;
                 };
                 debug!("retry in {delay:?}");
-                time::sleep(delay).await;
+                tokio::select! {
+                    _ = self.stop_token.cancelled() => break,
+                    _ = time::sleep(delay) => {}
+                }
             } else {
                 // If the relay connection remained established long enough so that we received a pong
                 // from the relay server, we reset the backoff and attempt to reconnect immediately.
@@ -350,7 +392,7 @@ impl ActiveRelayActor {
     fn build_backoff() -> impl Backoff {
         ExponentialBuilder::new()
             .with_min_delay(Duration::from_millis(10))
-            .with_max_delay(Duration::from_secs(16))
+            .with_max_delay(MAX_RECONNECT_DELAY)
             .with_jitter()
             .without_max_times()
             .build()
@@ -450,6 +492,10 @@ impl ActiveRelayActor {
                             self.set_home_relay(is_home);
                         }
                         ActiveRelayMessage::CheckConnection { .. } => {}
+                        ActiveRelayMessage::Reconnect => {
+                            // The dial might already have authenticated with the old key.
+                            dialing_fut.set(self.dial_relay());
+                        }
                         #[cfg(test)]
                         ActiveRelayMessage::GetLocalAddr(sender) => {
                             sender.send(None).ok();
@@ -521,6 +567,7 @@ impl ActiveRelayActor {
             endpoints_present: BTreeSet::new(),
             last_packet_src: None,
             pong_pending: None,
+            restarting: None,
             established: false,
             #[cfg(test)]
             test_pong: None,
@@ -535,6 +582,10 @@ impl ActiveRelayActor {
         ping_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
         let res = loop {
+            if let Some((reconnect_in, try_for)) = state.restarting.take() {
+                self.on_server_restarting(reconnect_in, try_for);
+                break Err(e!(RunError::ServerRestarting));
+            }
             if let Some(data) = state.pong_pending.take() {
                 let fut = client_sink.send(ClientToRelayMsg::Pong(data));
                 self.run_sending(fut, &mut state, &mut client_stream)
@@ -594,6 +645,7 @@ impl ActiveRelayActor {
                                 None => break Err(e!(RunError::LocalAddrMissing)),
                             }
                         }
+                        ActiveRelayMessage::Reconnect => break Err(e!(RunError::IdentityChanged)),
                         #[cfg(test)]
                         ActiveRelayMessage::GetLocalAddr(sender) => {
                             let addr = client_stream.local_addr();
@@ -659,6 +711,24 @@ impl ActiveRelayActor {
         res.map_err(|err| state.map_err(err))
     }
 
+    /// Handles the announcement of the relay server that it is restarting.
+    ///
+    /// The connection is closed and only re-established after `reconnect_in`, capped at
+    /// [`MAX_RECONNECT_DELAY`]. Until the relay server is expected back, the [`RelayActor`]
+    /// moves the home relay elsewhere.
+    fn on_server_restarting(&mut self, reconnect_in: Duration, try_for: Duration) {
+        info!(?reconnect_in, ?try_for, "relay server is restarting");
+        let reconnect_in = reconnect_in.min(MAX_RECONNECT_DELAY);
+        self.reconnect_delay = Some(reconnect_in);
+        let msg = RelayRestarting {
+            url: self.url.clone(),
+            until: Instant::now() + reconnect_in + try_for,
+        };
+        if let Err(err) = self.restarting.try_send(msg) {
+            debug!("failed to report restarting relay: {err:#}");
+        }
+    }
+
     fn handle_relay_msg(&mut self, msg: RelayToClientMsg, state: &mut ConnectedRelayState) {
         match msg {
             RelayToClientMsg::Datagrams {
@@ -708,8 +778,11 @@ impl ActiveRelayActor {
                 Status::Healthy => info!("Relay server reports: {status}"),
                 _ => warn!("Relay server reports problem: {status}"),
             },
-            RelayToClientMsg::Restarting { .. } => {
-                trace!("Ignoring {msg:?}")
+            RelayToClientMsg::Restarting {
+                reconnect_in,
+                try_for,
+            } => {
+                state.restarting = Some((reconnect_in, try_for));
             }
             // Deprecated variants, kept for backwards compatibility with older relay protocol versions.
             RelayToClientMsg::Health { problem } => {
@@ -810,6 +883,8 @@ struct ConnectedRelayState {
     last_packet_src: Option<EndpointId>,
     /// A pong we need to send ASAP.
     pong_pending: Option<[u8; 8]>,
+    /// The `reconnect_in` and `try_for` of a restart announced by the relay server.
+    restarting: Option<(Duration, Duration)>,
     /// Whether the connection is to be considered established.
     ///
     /// This is set to `true` once a pong was received from the server.
@@ -838,6 +913,8 @@ pub(super) enum RelayActorMessage {
     /// Sent after a major network change to detect broken connections faster
     /// using RTT-based timeouts instead of the default 5s ping timeout.
     CheckConnectionAfterNetworkChange,
+    /// Reconnect all relay connections, because the endpoint identity changed.
+    Reconnect,
 }
 
 #[derive(Debug, Clone)]
@@ -861,13 +938,22 @@ pub(super) struct RelayActor {
     active_relays: BTreeMap<RelayUrl, ActiveRelayHandle>,
     /// The tasks for the [`ActiveRelayActor`]s in `active_relays` above.
     active_relay_tasks: JoinSet<()>,
+    /// Restarts announced by the [`ActiveRelayActor`]s.
+    restarting_rx: mpsc::Receiver<RelayRestarting>,
+    restarting_tx: mpsc::Sender<RelayRestarting>,
+    /// Relays which announced a restart, with the time they are expected back.
+    ///
+    /// These are not used as home relay until then.
+    restarting_relays: BTreeMap<RelayUrl, Instant>,
+    /// The last net_report report, to pick another home relay when the home relay restarts.
+    last_report: Option<Report>,
     cancel_token: CancellationToken,
 }
 
 #[derive(Debug, Clone)]
 pub(crate) struct Config {
     pub my_relay: HomeRelayWatch,
-    pub secret_key: SecretKey,
+    pub signer: Arc<dyn Signer>,
     #[cfg(not(wasm_browser))]
     pub dns_resolver: DnsResolver,
     /// Proxy
@@ -1001,11 +1087,16 @@ impl RelayActor {
         relay_datagram_recv_queue: mpsc::Sender<RelayRecvDatagram>,
         cancel_token: CancellationToken,
     ) -> Self {
+        let (restarting_tx, restarting_rx) = mpsc::channel(16);
         Self {
             config,
             relay_datagram_recv_queue,
             active_relays: Default::default(),
             active_relay_tasks: JoinSet::new(),
+            restarting_rx,
+            restarting_tx,
+            restarting_relays: Default::default(),
+            last_report: None,
             cancel_token,
         }
     }
@@ -1039,6 +1130,9 @@ impl RelayActor {
                     }
                     self.reap_active_relays();
                 }
+                Some(msg) = self.restarting_rx.recv() => {
+                    self.on_relay_restarting(msg).await;
+                }
                 msg = receiver.recv() => {
                     let Some(msg) = msg else {
                         debug!("Inbox dropped, shutting down.");
@@ -1087,6 +1181,9 @@ impl RelayActor {
             RelayActorMessage::CheckConnectionAfterNetworkChange => {
                 self.check_connection_after_network_change().await;
             }
+            RelayActorMessage::Reconnect => {
+                self.reconnect_all().await;
+            }
         }
     }
 
@@ -1122,14 +1219,16 @@ impl RelayActor {
     }
 
     async fn on_network_change(&mut self, report: Report) {
+        let preferred_relay = self.preferred_relay(&report);
+        self.last_report = Some(report);
         let prev = self.config.my_relay.get();
         let prev_url = prev.as_ref().map(RelayStatus::url);
-        if report.preferred_relay.as_ref() == prev_url {
+        if preferred_relay.as_ref() == prev_url {
             // No change.
             return;
         }
 
-        if let Some(relay_url) = report.preferred_relay {
+        if let Some(relay_url) = preferred_relay {
             self.config.metrics.relay_home_change.inc();
 
             // On change, notify all currently connected relay servers and
@@ -1148,6 +1247,35 @@ impl RelayActor {
         }
     }
 
+    /// Returns the relay to use as home relay according to `report`.
+    ///
+    /// If the preferred relay of the report announced a restart, the relay with the lowest
+    /// latency which did not is used instead, if any.
+    fn preferred_relay(&mut self, report: &Report) -> Option<RelayUrl> {
+        let now = Instant::now();
+        self.restarting_relays.retain(|_, until| *until > now);
+        let preferred = report.preferred_relay.clone()?;
+        if !self.restarting_relays.contains_key(&preferred) {
+            return Some(preferred);
+        }
+        let alternative = report
+            .relay_latency
+            .iter()
+            .filter(|(_, url, _)| !self.restarting_relays.contains_key(url))
+            .min_by_key(|(_, _, latency)| *latency)
+            .map(|(_, url, _)| url.clone());
+        Some(alternative.unwrap_or(preferred))
+    }
+
+    /// Moves the home relay elsewhere if it announced a restart.
+    async fn on_relay_restarting(&mut self, msg: RelayRestarting) {
+        let RelayRestarting { url, until } = msg;
+        self.restarting_relays.insert(url, until);
+        if let Some(report) = self.last_report.clone() {
+            self.on_network_change(report).await;
+        }
+    }
+
     async fn set_home_relay(&mut self, home_url: RelayUrl) {
         let home_url_ref = &home_url;
         n0_future::join_all(self.active_relays.iter().map(|(url, handle)| async move {
@@ -1239,7 +1367,7 @@ impl RelayActor {
             .get(&url)
             .and_then(|cfg| cfg.auth_token.clone());
         let connection_opts = RelayConnectionOptions {
-            secret_key: self.config.secret_key.clone(),
+            signer: self.config.signer.clone(),
             #[cfg(not(wasm_browser))]
             dns_resolver: self.config.dns_resolver.clone(),
             proxy_url: self.config.proxy_url.clone(),
@@ -1263,6 +1391,7 @@ impl RelayActor {
             stop_token: self.cancel_token.child_token(),
             metrics: self.config.metrics.clone(),
             my_relay: self.config.my_relay.clone(),
+            restarting: self.restarting_tx.clone(),
         };
         let actor = ActiveRelayActor::new(opts);
         self.active_relay_tasks.spawn(
@@ -1337,6 +1466,18 @@ impl RelayActor {
     }
 
     /// Stops all [`ActiveRelayActor`]s and awaits for them to finish.
+    /// Makes all [`ActiveRelayActor`]s connect again.
+    async fn reconnect_all(&self) {
+        let send_futs = self.active_relays.values().map(|handle| async move {
+            handle
+                .inbox_addr
+                .send(ActiveRelayMessage::Reconnect)
+                .await
+                .ok();
+        });
+        n0_future::join_all(send_futs).await;
+    }
+
     async fn close_all_active_relays(&mut self) {
         self.cancel_token.cancel();
         let tasks = std::mem::take(&mut self.active_relay_tasks);
@@ -1395,9 +1536,11 @@ mod tests {
     use iroh_relay::{
         PingTracker,
         protos::relay::Datagrams,
+        server::DrainConfig,
         tls::{CaTlsConfig, default_provider},
     };
     use n0_error::{AnyError as Error, Result, StackResultExt, StdResultExt};
+    use n0_future::time::Instant;
     use n0_tracing_test::traced_test;
     use tokio::sync::{mpsc, oneshot};
     use tokio_util::{sync::CancellationToken, task::AbortOnDropHandle};
@@ -1405,8 +1548,8 @@ mod tests {
 
     use super::{
         ActiveRelayActor, ActiveRelayActorOptions, ActiveRelayMessage, ActiveRelayPrioMessage,
-        RELAY_INACTIVE_CLEANUP_TIME, RelayConnectionOptions, RelayRecvDatagram, RelaySendItem,
-        UNDELIVERABLE_DATAGRAM_TIMEOUT,
+        MAX_RECONNECT_DELAY, RELAY_INACTIVE_CLEANUP_TIME, RelayConnectionOptions,
+        RelayRecvDatagram, RelayRestarting, RelaySendItem, UNDELIVERABLE_DATAGRAM_TIMEOUT,
     };
     use crate::{dns::DnsResolver, test_utils};
 
@@ -1420,6 +1563,7 @@ mod tests {
         inbox_rx: mpsc::Receiver<ActiveRelayMessage>,
         relay_datagrams_send: mpsc::Receiver<RelaySendItem>,
         relay_datagrams_recv: mpsc::Sender<RelayRecvDatagram>,
+        restarting: mpsc::Sender<RelayRestarting>,
         span: tracing::Span,
     ) -> AbortOnDropHandle<()> {
         let opts = ActiveRelayActorOptions {
@@ -1429,7 +1573,7 @@ mod tests {
             relay_datagrams_send,
             relay_datagrams_recv,
             connection_opts: RelayConnectionOptions {
-                secret_key,
+                signer: Arc::new(secret_key),
                 dns_resolver: DnsResolver::new(),
                 proxy_url: None,
                 prefer_ipv6: Arc::new(AtomicBool::new(true)),
@@ -1441,6 +1585,7 @@ mod tests {
             stop_token,
             metrics: Default::default(),
             my_relay: Default::default(),
+            restarting,
         };
         let task = tokio::spawn(ActiveRelayActor::new(opts).run().instrument(span));
         AbortOnDropHandle::new(task)
@@ -1466,6 +1611,7 @@ mod tests {
             inbox_rx,
             send_datagram_rx,
             recv_datagram_tx,
+            mpsc::channel(1).0,
             info_span!("echo-endpoint"),
         );
         let echo_task = tokio::spawn({
@@ -1562,6 +1708,7 @@ mod tests {
             inbox_rx,
             send_datagram_rx,
             datagram_recv_tx.clone(),
+            mpsc::channel(1).0,
             info_span!("actor-under-test"),
         );
 
@@ -1645,6 +1792,73 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    #[traced_test]
+    async fn test_active_relay_restarting() -> Result {
+        let (_relay_map, relay_url, server) = test_utils::run_relay_server().await?;
+
+        let (datagram_recv_tx, _datagram_recv_rx) = mpsc::channel(16);
+        let (_send_datagram_tx, send_datagram_rx) = mpsc::channel(16);
+        let (_prio_inbox_tx, prio_inbox_rx) = mpsc::channel(8);
+        let (inbox_tx, inbox_rx) = mpsc::channel(16);
+        let (restarting_tx, mut restarting_rx) = mpsc::channel(16);
+        let cancel_token = CancellationToken::new();
+        let task = start_active_relay_actor(
+            SecretKey::generate(),
+            cancel_token.clone(),
+            relay_url.clone(),
+            prio_inbox_rx,
+            inbox_rx,
+            send_datagram_rx,
+            datagram_recv_tx,
+            restarting_tx,
+            info_span!("actor-under-test"),
+        );
+
+        // Wait until connected, the actor drops the ping request while dialing.
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let (tx, rx) = oneshot::channel();
+                inbox_tx.send(ActiveRelayMessage::PingServer(tx)).await.ok();
+                if rx.await.is_ok() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .std_context("timeout waiting for connection")?;
+
+        let mut config = DrainConfig::default();
+        // The actor caps the delay, and stops without waiting for it.
+        config.reconnect_in = Duration::from_secs(60 * 60);
+        config.try_for = Duration::from_secs(1);
+        config.timeout = Duration::from_secs(10);
+        let drain = tokio::spawn(server.drain(config.clone()));
+
+        let restarting = tokio::time::timeout(Duration::from_secs(10), restarting_rx.recv())
+            .await
+            .std_context("timeout waiting for restart")?
+            .context("restarting channel closed")?;
+        assert_eq!(restarting.url, relay_url);
+        assert!(restarting.until > Instant::now());
+        assert!(restarting.until <= Instant::now() + MAX_RECONNECT_DELAY + config.try_for);
+
+        // The actor disconnected, so the drain completes before its timeout.
+        tokio::time::timeout(Duration::from_secs(5), drain)
+            .await
+            .std_context("timeout waiting for drain")?
+            .std_context("drain task")?
+            .anyerr()?;
+
+        cancel_token.cancel();
+        tokio::time::timeout(Duration::from_secs(1), task)
+            .await
+            .std_context("timeout waiting for task to finish")?
+            .std_context("wait for task to finish")?;
+        Ok(())
+    }
+
     #[tokio::test]
     #[traced_test]
     async fn test_active_relay_inactive() -> Result {
@@ -1664,6 +1878,7 @@ mod tests {
             inbox_rx,
             send_datagram_rx,
             datagram_recv_tx,
+            mpsc::channel(1).0,
             info_span!("actor-under-test"),
         );
 
@@ -31,6 +31,11 @@ pub struct CleanupDropGuard(pub(crate) oneshot::Sender<()>);
 ///
 /// The returned `Url` is the url of the relay server in the returned [`RelayMap`].
 /// When dropped, the returned [`Server`] does will stop running.
+///
+/// The server uses real sockets, so tests using it must not pause tokio's clock: the
+/// paused clock auto-advances while tasks wait for network I/O and fires timeouts early.
+/// Simulated time is only supported with the in-memory `TestNetwork` of the
+/// `test_transport` module.
 pub async fn run_relay_server() -> Result<(RelayMap, RelayUrl, Server), SpawnError> {
     run_relay_server_with(true).await
 }
@@ -101,6 +106,11 @@ mod dns_and_pkarr_servers {
     /// Handle and drop guard for test DNS and Pkarr servers.
     ///
     /// Once the struct is dropped the servers will shut down.
+    ///
+    /// Like [`run_relay_server`], these servers use real sockets and do not support a
+    /// paused tokio clock, so tests of pkarr republishing with them run in real time.
+    ///
+    /// [`run_relay_server`]: crate::test_utils::run_relay_server
     #[derive(Debug)]
     pub struct DnsPkarrServer {
         /// The endpoint origin domain.
@@ -2,16 +2,41 @@
 //!
 //! This module provides [`TestNetwork`] and [`TestTransport`] for testing
 //! using in-memory channels instead of real network transports.
+//!
+//! Links between transports deliver packets instantly by default.  To exercise congestion
+//! control, path switching or timeouts, each link can be shaped with [`LinkConditions`].
+//!
+//! Endpoints, including QUIC, follow tokio's clock.  Endpoints which only use these
+//! in-memory transports can thus be tested with a paused clock, e.g. using
+//! `#[tokio::test(start_paused = true)]`, and advance by hours within milliseconds to
+//! observe keep-alives, republishing or idle timeouts.  The timers of the relay actor,
+//! net_report and the pkarr publisher follow the paused clock as well.
+//!
+//! Simulated time is limited to these in-memory transports.  Tests using
+//! [`run_relay_server`] or [`DnsPkarrServer`] still run in real time, as these servers
+//! use real sockets: the paused clock advances whenever all tasks wait, including while
+//! waiting for network I/O, which fires timeouts early.  There is no in-memory relay or
+//! DNS server yet, so relay and pkarr behaviour cannot be tested with simulated time.
+//!
+//! [`run_relay_server`]: crate::test_utils::run_relay_server
+//! [`DnsPkarrServer`]: crate::test_utils::DnsPkarrServer
 
 use std::{
-    collections::BTreeMap,
+    cmp::{Ordering, Reverse},
+    collections::{BTreeMap, BinaryHeap},
     io,
     sync::{Arc, Mutex},
     task::Poll,
+    time::Duration,
 };
 
 use bytes::Bytes;
 use iroh_base::{CustomAddr, EndpointId, TransportAddr};
+use n0_future::{
+    task::{self, AbortOnDropHandle},
+    time::{self, Instant},
+};
+use rand::{RngExt, SeedableRng, rngs::StdRng};
 use tokio::sync::mpsc::{self, error::TrySendError};
 use tracing::info;
 
@@ -36,6 +61,121 @@ pub(crate) struct Packet {
     pub(crate) from: CustomAddr,
 }
 
+/// The conditions of a link between two transports of a [`TestNetwork`].
+///
+/// The default conditions describe an ideal link, which delivers every packet instantly.
+/// Conditions apply to one direction of a link, see [`TestNetwork::set_link`] and
+/// [`TestNetwork::set_directed_link`].
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct LinkConditions {
+    /// The one-way delay added to every packet.
+    pub latency: Duration,
+    /// The maximum random delay added on top of [`Self::latency`].
+    ///
+    /// The extra delay is chosen uniformly between zero and this value for every packet,
+    /// so jitter also reorders packets sent in quick succession.
+    pub jitter: Duration,
+    /// The bandwidth of the link in bytes per second, unlimited if `None`.
+    ///
+    /// Packets are queued while the link is busy sending earlier packets.
+    pub bandwidth: Option<u64>,
+    /// The maximum time a packet is queued for when the link is over its
+    /// [`Self::bandwidth`].
+    ///
+    /// Packets that would have to wait longer are dropped, like a router with a full
+    /// buffer would.
+    pub max_queue_delay: Duration,
+    /// The probability, between `0.0` and `1.0`, that a packet is lost.
+    pub loss: f64,
+    /// The probability, between `0.0` and `1.0`, that a packet is reordered.
+    ///
+    /// A reordered packet is held back for another [`Self::latency`], at least one
+    /// millisecond, so packets sent after it overtake it.
+    pub reorder: f64,
+    /// Whether the link is partitioned, in which case all packets are dropped.
+    pub partitioned: bool,
+}
+
+impl Default for LinkConditions {
+    fn default() -> Self {
+        Self {
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            bandwidth: None,
+            max_queue_delay: Self::DEFAULT_MAX_QUEUE_DELAY,
+            loss: 0.0,
+            reorder: 0.0,
+            partitioned: false,
+        }
+    }
+}
+
+impl LinkConditions {
+    /// The default for [`Self::max_queue_delay`].
+    pub const DEFAULT_MAX_QUEUE_DELAY: Duration = Duration::from_millis(200);
+
+    /// The minimum extra delay of a reordered packet.
+    const MIN_REORDER_DELAY: Duration = Duration::from_millis(1);
+
+    /// Creates the conditions of an ideal link.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the one-way [`Self::latency`] of the link.
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Sets the [`Self::jitter`] of the link.
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sets the [`Self::bandwidth`] of the link in bytes per second.
+    pub fn with_bandwidth(mut self, bytes_per_sec: u64) -> Self {
+        self.bandwidth = Some(bytes_per_sec);
+        self
+    }
+
+    /// Sets the [`Self::max_queue_delay`] of the link.
+    pub fn with_max_queue_delay(mut self, max_queue_delay: Duration) -> Self {
+        self.max_queue_delay = max_queue_delay;
+        self
+    }
+
+    /// Sets the [`Self::loss`] probability of the link.
+    pub fn with_loss(mut self, loss: f64) -> Self {
+        self.loss = loss;
+        self
+    }
+
+    /// Sets the [`Self::reorder`] probability of the link.
+    pub fn with_reorder(mut self, reorder: f64) -> Self {
+        self.reorder = reorder;
+        self
+    }
+
+    /// Sets whether the link is [`Self::partitioned`].
+    pub fn with_partitioned(mut self, partitioned: bool) -> Self {
+        self.partitioned = partitioned;
+        self
+    }
+
+    /// Returns true if packets are delivered instantly and never dropped.
+    fn is_ideal(&self) -> bool {
+        self.latency.is_zero()
+            && self.jitter.is_zero()
+            && self.bandwidth.is_none()
+            && self.loss <= 0.0
+            && self.reorder <= 0.0
+            && !self.partitioned
+    }
+}
+
 /// A test transport for use with [`TestNetwork`].
 ///
 /// Implements [`CustomTransport`] and [`CustomEndpoint`] for testing.
@@ -82,6 +222,30 @@ impl Preset for Arc<TestTransport> {
 /// let transport2 = network.create_transport(endpoint_id2)?;
 /// // transport1 and transport2 can now communicate via the network
 /// ```
+///
+/// # Link conditions
+///
+/// The links between transports can be shaped with [`LinkConditions`], to add latency,
+/// jitter, bandwidth limits, loss, reordering or partitions.  The conditions can be changed
+/// at any time and apply to all packets sent afterwards, packets already in flight are
+/// delivered as scheduled.
+///
+/// Random decisions are taken from a single generator per network.  Create the network
+/// with [`TestNetwork::with_seed`] to make them reproducible for the same sequence of sent
+/// packets.
+///
+/// ```ignore
+/// let network = TestNetwork::with_seed(42);
+/// network.set_link(
+///     endpoint_id1,
+///     endpoint_id2,
+///     LinkConditions::new()
+///         .with_latency(Duration::from_millis(50))
+///         .with_loss(0.01),
+/// );
+/// // later on
+/// network.partition(endpoint_id1, endpoint_id2);
+/// ```
 #[derive(Debug, Clone, Default)]
 pub struct TestNetwork {
     inner: Arc<Mutex<TestNetworkInner>>,
@@ -117,6 +281,71 @@ impl TestNetwork {
             id,
         }))
     }
+
+    /// Creates a new empty test network with a deterministic seed for its link conditions.
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(TestNetworkInner::with_seed(seed))),
+        }
+    }
+
+    /// Sets the conditions of all links that have no conditions of their own.
+    pub fn set_default_link(&self, conditions: LinkConditions) {
+        self.inner.lock().expect("poisoned").default_link = conditions;
+    }
+
+    /// Sets the conditions of the link between `a` and `b`, in both directions.
+    pub fn set_link(&self, a: EndpointId, b: EndpointId, conditions: LinkConditions) {
+        let mut guard = self.inner.lock().expect("poisoned");
+        guard.links.insert((a, b), conditions.clone());
+        guard.links.insert((b, a), conditions);
+    }
+
+    /// Sets the conditions of the link for packets sent from `from` to `to`.
+    pub fn set_directed_link(&self, from: EndpointId, to: EndpointId, conditions: LinkConditions) {
+        let mut guard = self.inner.lock().expect("poisoned");
+        guard.links.insert((from, to), conditions);
+    }
+
+    /// Resets the link between `a` and `b` to the default conditions, in both directions.
+    ///
+    /// See [`Self::set_default_link`].
+    pub fn reset_link(&self, a: EndpointId, b: EndpointId) {
+        let mut guard = self.inner.lock().expect("poisoned");
+        guard.links.remove(&(a, b));
+        guard.links.remove(&(b, a));
+    }
+
+    /// Returns the conditions of the link for packets sent from `from` to `to`.
+    pub fn link(&self, from: EndpointId, to: EndpointId) -> LinkConditions {
+        self.inner
+            .lock()
+            .expect("poisoned")
+            .conditions(from, to)
+            .clone()
+    }
+
+    /// Partitions `a` and `b`, dropping all packets between them until [`Self::heal`]ed.
+    ///
+    /// The other conditions of the link are kept.
+    pub fn partition(&self, a: EndpointId, b: EndpointId) {
+        self.set_partitioned(a, b, true);
+    }
+
+    /// Heals a partition between `a` and `b`, see [`Self::partition`].
+    pub fn heal(&self, a: EndpointId, b: EndpointId) {
+        self.set_partitioned(a, b, false);
+    }
+
+    fn set_partitioned(&self, a: EndpointId, b: EndpointId, partitioned: bool) {
+        let mut guard = self.inner.lock().expect("poisoned");
+        for key in [(a, b), (b, a)] {
+            let conditions = guard.conditions(key.0, key.1).clone();
+            guard
+                .links
+                .insert(key, conditions.with_partitioned(partitioned));
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -124,9 +353,217 @@ struct TestAddrLookup {
     network: TestNetwork,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 struct TestNetworkInner {
     channels: BTreeMap<EndpointId, (mpsc::Sender<Packet>, mpsc::Receiver<Packet>)>,
+    default_link: LinkConditions,
+    /// Conditions of directed links, keyed by `(from, to)`.
+    links: BTreeMap<(EndpointId, EndpointId), LinkConditions>,
+    /// Delivery state of directed links that delayed packets, keyed by `(from, to)`.
+    shapers: BTreeMap<(EndpointId, EndpointId), LinkShaper>,
+    rng: StdRng,
+}
+
+impl Default for TestNetworkInner {
+    fn default() -> Self {
+        Self::with_seed(rand::random())
+    }
+}
+
+impl TestNetworkInner {
+    fn with_seed(seed: u64) -> Self {
+        Self {
+            channels: Default::default(),
+            default_link: Default::default(),
+            links: Default::default(),
+            shapers: Default::default(),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    fn conditions(&self, from: EndpointId, to: EndpointId) -> &LinkConditions {
+        self.links.get(&(from, to)).unwrap_or(&self.default_link)
+    }
+
+    /// Sends a packet from `from` to `to`, applying the conditions of the link.
+    fn send(&mut self, from: EndpointId, to: EndpointId, packet: Packet) -> io::Result<()> {
+        let (s, _) = self
+            .channels
+            .get(&to)
+            .ok_or_else(|| io::Error::other("Unknown endpoint"))?;
+        let conditions = self.links.get(&(from, to)).unwrap_or(&self.default_link);
+        let len = packet.data.len();
+        if conditions.is_ideal() {
+            return deliver(s, from, to, packet);
+        }
+
+        if conditions.partitioned {
+            info!(
+                "send {} -> {}: partitioned, dropped {} bytes",
+                from.fmt_short(),
+                to.fmt_short(),
+                len
+            );
+            return Ok(());
+        }
+        if self.rng.random_bool(conditions.loss.clamp(0.0, 1.0)) {
+            info!(
+                "send {} -> {}: lost {} bytes",
+                from.fmt_short(),
+                to.fmt_short(),
+                len
+            );
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let shaper = self.shapers.entry((from, to)).or_insert_with(|| {
+            let (tx, rx) = mpsc::unbounded_channel();
+            let task = task::spawn(run_link(from, to, s.clone(), rx));
+            LinkShaper {
+                busy_until: now,
+                tx,
+                _task: AbortOnDropHandle::new(task),
+            }
+        });
+
+        // Packets leave the link once all packets queued before them have been sent.
+        let mut departure = now;
+        if let Some(bandwidth) = conditions.bandwidth {
+            let start = shaper.busy_until.max(now);
+            if start - now > conditions.max_queue_delay {
+                info!(
+                    "send {} -> {}: queue full, dropped {} bytes",
+                    from.fmt_short(),
+                    to.fmt_short(),
+                    len
+                );
+                return Ok(());
+            }
+            let transmission = Duration::from_secs_f64(len as f64 / bandwidth.max(1) as f64);
+            shaper.busy_until = start + transmission;
+            departure = shaper.busy_until;
+        }
+
+        let mut delay = conditions.latency;
+        if !conditions.jitter.is_zero() {
+            delay += self.rng.random_range(Duration::ZERO..=conditions.jitter);
+        }
+        if self.rng.random_bool(conditions.reorder.clamp(0.0, 1.0)) {
+            delay += conditions.latency.max(LinkConditions::MIN_REORDER_DELAY);
+        }
+        shaper
+            .tx
+            .send(Delayed {
+                deliver_at: departure + delay,
+                seq: 0,
+                packet,
+            })
+            .map_err(|_| io::Error::other("link closed"))
+    }
+}
+
+/// The delivery state of a directed link.
+#[derive(Debug)]
+struct LinkShaper {
+    /// Until when the link is busy sending the queued packets, if it has a bandwidth limit.
+    busy_until: Instant,
+    tx: mpsc::UnboundedSender<Delayed>,
+    _task: AbortOnDropHandle<()>,
+}
+
+/// A packet in flight on a shaped link.
+#[derive(Debug)]
+struct Delayed {
+    deliver_at: Instant,
+    /// Tie breaker to deliver packets with the same deadline in the order they were sent.
+    seq: u64,
+    packet: Packet,
+}
+
+impl PartialEq for Delayed {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Delayed {}
+
+impl PartialOrd for Delayed {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Delayed {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.deliver_at, self.seq).cmp(&(other.deliver_at, other.seq))
+    }
+}
+
+/// Delivers the packets of a shaped link once they are due.
+async fn run_link(
+    from: EndpointId,
+    to: EndpointId,
+    dst: mpsc::Sender<Packet>,
+    mut rx: mpsc::UnboundedReceiver<Delayed>,
+) {
+    let mut in_flight = BinaryHeap::new();
+    let mut seq = 0u64;
+    loop {
+        let next = in_flight
+            .peek()
+            .map(|Reverse(delayed): &Reverse<Delayed>| delayed.deliver_at);
+        tokio::select! {
+            delayed = rx.recv() => {
+                let Some(mut delayed) = delayed else {
+                    break;
+                };
+                delayed.seq = seq;
+                seq += 1;
+                in_flight.push(Reverse(delayed));
+            }
+            _ = time::sleep_until(next.unwrap_or_else(Instant::now)), if next.is_some() => {
+                let now = Instant::now();
+                while let Some(Reverse(delayed)) = in_flight.peek()
+                    && delayed.deliver_at <= now
+                {
+                    let Some(Reverse(delayed)) = in_flight.pop() else {
+                        break;
+                    };
+                    if deliver(&dst, from, to, delayed.packet).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Hands a packet to the receive channel of `to`, dropping it if the channel is full.
+fn deliver(
+    s: &mpsc::Sender<Packet>,
+    from: EndpointId,
+    to: EndpointId,
+    packet: Packet,
+) -> io::Result<()> {
+    let len = packet.data.len();
+    match s.try_send(packet) {
+        Ok(_) => info!(
+            "send {} -> {}: sent {} bytes",
+            from.fmt_short(),
+            to.fmt_short(),
+            len
+        ),
+        Err(TrySendError::Full(_)) => info!(
+            "send {} -> {}: dropped {} bytes",
+            from.fmt_short(),
+            to.fmt_short(),
+            len
+        ),
+        Err(TrySendError::Closed(_)) => return Err(io::Error::other("channel closed")),
+    }
+    Ok(())
 }
 
 impl AddressLookup for TestAddrLookup {
@@ -186,28 +623,9 @@ fn try_parse_custom_addr(addr: &CustomAddr) -> io::Result<EndpointId> {
 impl TestSender {
     fn send_sync(&self, dst: &CustomAddr, packets: Vec<Packet>) -> io::Result<()> {
         let to_id = try_parse_custom_addr(dst)?;
-        let guard = self.network.inner.lock().expect("poisoned");
-        let (s, _) = guard
-            .channels
-            .get(&to_id)
-            .ok_or_else(|| io::Error::other("Unknown endpoint"))?;
+        let mut guard = self.network.inner.lock().expect("poisoned");
         for packet in packets {
-            let len = packet.data.len();
-            match s.try_send(packet) {
-                Ok(_) => info!(
-                    "send {} -> {}: sent {} bytes",
-                    self.id.fmt_short(),
-                    to_id.fmt_short(),
-                    len
-                ),
-                Err(TrySendError::Full(_)) => info!(
-                    "send {} -> {}: dropped {} bytes",
-                    self.id.fmt_short(),
-                    to_id.fmt_short(),
-                    len
-                ),
-                Err(TrySendError::Closed(_)) => return Err(io::Error::other("channel closed")),
-            }
+            guard.send(self.id, to_id, packet)?;
         }
         Ok(())
     }
@@ -331,7 +749,10 @@ mod tests {
         Endpoint, EndpointAddr, RelayMode, SecretKey, TransportAddr,
         endpoint::{Builder, Connection, presets, transports::AddrKind},
         protocol::{AcceptError, ProtocolHandler, Router},
-        socket::biased_rtt_path_selector::{BiasedRttPathSelector, TransportBias},
+        socket::{
+            PATH_MAX_IDLE_TIMEOUT,
+            biased_rtt_path_selector::{BiasedRttPathSelector, TransportBias},
+        },
         test_utils::run_relay_server,
     };
 
@@ -466,6 +887,261 @@ mod tests {
         Ok(())
     }
 
+    /// Sends a single packet with `data` from `from` to `to`.
+    fn send_packet(network: &TestNetwork, from: EndpointId, to: EndpointId, data: &[u8]) {
+        let sender = TestSender {
+            id: from,
+            network: network.clone(),
+        };
+        let packet = Packet {
+            data: Bytes::copy_from_slice(data),
+            from: to_custom_addr(from),
+        };
+        sender
+            .send_sync(&to_custom_addr(to), vec![packet])
+            .expect("known endpoint");
+    }
+
+    /// Receives the next packet for `id`, or `None` if none arrives within `timeout`.
+    async fn recv_packet(
+        network: &TestNetwork,
+        id: EndpointId,
+        timeout: Duration,
+    ) -> Option<Bytes> {
+        let recv = std::future::poll_fn(|cx| {
+            let mut guard = network.inner.lock().expect("poisoned");
+            let (_, r) = guard.channels.get_mut(&id).expect("known endpoint");
+            r.poll_recv(cx)
+        });
+        tokio::time::timeout(timeout, recv)
+            .await
+            .ok()
+            .flatten()
+            .map(|packet| packet.data)
+    }
+
+    /// Creates a network with two transports, returning their endpoint ids.
+    fn two_endpoints(network: &TestNetwork) -> Result<(EndpointId, EndpointId)> {
+        let a = SecretKey::generate().public();
+        let b = SecretKey::generate().public();
+        network.create_transport(a)?;
+        network.create_transport(b)?;
+        Ok((a, b))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_link_latency() -> Result<()> {
+        let network = TestNetwork::with_seed(0);
+        let (a, b) = two_endpoints(&network)?;
+        let latency = Duration::from_millis(50);
+        network.set_directed_link(a, b, LinkConditions::new().with_latency(latency));
+
+        let start = tokio::time::Instant::now();
+        send_packet(&network, a, b, b"hello");
+        let data = recv_packet(&network, b, Duration::from_secs(1)).await;
+        assert_eq!(data.as_deref(), Some(&b"hello"[..]));
+        assert_eq!(start.elapsed(), latency);
+
+        // The other direction is not affected.
+        let start = tokio::time::Instant::now();
+        send_packet(&network, b, a, b"world");
+        let data = recv_packet(&network, a, Duration::from_secs(1)).await;
+        assert_eq!(data.as_deref(), Some(&b"world"[..]));
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_link_bandwidth() -> Result<()> {
+        let network = TestNetwork::with_seed(0);
+        let (a, b) = two_endpoints(&network)?;
+        // Every packet takes 100ms to send, and may wait for two packets ahead of it.
+        network.set_link(
+            a,
+            b,
+            LinkConditions::new()
+                .with_bandwidth(1000)
+                .with_max_queue_delay(Duration::from_millis(200)),
+        );
+
+        let start = tokio::time::Instant::now();
+        for i in 0..5u8 {
+            send_packet(&network, a, b, &[i; 100]);
+        }
+        let mut arrivals = Vec::new();
+        while let Some(data) = recv_packet(&network, b, Duration::from_secs(1)).await {
+            arrivals.push((data[0], start.elapsed()));
+        }
+        assert_eq!(
+            arrivals,
+            vec![
+                (0, Duration::from_millis(100)),
+                (1, Duration::from_millis(200)),
+                (2, Duration::from_millis(300)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_link_partition() -> Result<()> {
+        let network = TestNetwork::with_seed(0);
+        let (a, b) = two_endpoints(&network)?;
+        let latency = Duration::from_millis(10);
+        network.set_link(a, b, LinkConditions::new().with_latency(latency));
+
+        network.partition(a, b);
+        send_packet(&network, a, b, b"lost");
+        send_packet(&network, b, a, b"lost");
+        assert!(
+            recv_packet(&network, b, Duration::from_secs(1))
+                .await
+                .is_none()
+        );
+        assert!(
+            recv_packet(&network, a, Duration::from_secs(1))
+                .await
+                .is_none()
+        );
+
+        network.heal(a, b);
+        assert_eq!(
+            network.link(a, b),
+            LinkConditions::new().with_latency(latency)
+        );
+        send_packet(&network, a, b, b"healed");
+        let data = recv_packet(&network, b, Duration::from_secs(1)).await;
+        assert_eq!(data.as_deref(), Some(&b"healed"[..]));
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_link_loss_is_deterministic() -> Result<()> {
+        async fn received(seed: u64) -> Result<Vec<u8>> {
+            let network = TestNetwork::with_seed(seed);
+            let (a, b) = two_endpoints(&network)?;
+            network.set_default_link(
+                LinkConditions::new()
+                    .with_latency(Duration::from_millis(10))
+                    .with_jitter(Duration::from_millis(20))
+                    .with_loss(0.3),
+            );
+            for i in 0..100u8 {
+                send_packet(&network, a, b, &[i]);
+            }
+            let mut received = Vec::new();
+            while let Some(data) = recv_packet(&network, b, Duration::from_secs(1)).await {
+                received.push(data[0]);
+            }
+            Ok(received)
+        }
+
+        let first = received(7).await?;
+        assert!(!first.is_empty() && first.len() < 100);
+        // Jitter reorders packets.
+        assert!(!first.is_sorted());
+        assert_eq!(first, received(7).await?);
+        assert_ne!(first, received(8).await?);
+        Ok(())
+    }
+
+    /// Test that connections work over a shaped link, and observe its latency.
+    #[tokio::test]
+    #[traced_test]
+    async fn test_custom_transport_shaped_link() -> Result<()> {
+        let network = TestNetwork::with_seed(0);
+        let s1 = SecretKey::generate();
+        let s2 = SecretKey::generate();
+
+        let t1 = network.create_transport(s1.public())?;
+        let t2 = network.create_transport(s2.public())?;
+        network.set_link(
+            s1.public(),
+            s2.public(),
+            LinkConditions::new()
+                .with_latency(Duration::from_millis(20))
+                .with_jitter(Duration::from_millis(5))
+                .with_loss(0.05)
+                .with_reorder(0.05),
+        );
+
+        let ep1 = endpoint_builder(s1, t1, EndpointConfig::default())
+            .bind()
+            .await?;
+        let ep2 = endpoint_builder(s2.clone(), t2, EndpointConfig::default())
+            .bind()
+            .await?;
+        let router = Router::builder(ep2).accept(ECHO_ALPN, Echo).spawn();
+
+        let conn = ep1
+            .connect(custom_only_addr(s2.public()), ECHO_ALPN)
+            .await?;
+        verify_echo(&conn, b"shaped link").await?;
+
+        let paths = conn.paths();
+        let path = paths
+            .iter()
+            .find(|p| p.is_selected())
+            .expect("selected path");
+        assert!(
+            path.rtt() >= Duration::from_millis(40),
+            "rtt: {:?}",
+            path.rtt()
+        );
+
+        conn.close(0u32.into(), b"done");
+        router.shutdown().await.anyerr()?;
+        Ok(())
+    }
+
+    /// Test that endpoints on a [`TestNetwork`] run on tokio's paused clock.
+    #[tokio::test(start_paused = true)]
+    #[traced_test]
+    async fn test_custom_transport_virtual_time() -> Result<()> {
+        let network = TestNetwork::with_seed(0);
+        let s1 = SecretKey::generate();
+        let s2 = SecretKey::generate();
+
+        let t1 = network.create_transport(s1.public())?;
+        let t2 = network.create_transport(s2.public())?;
+        network.set_default_link(LinkConditions::new().with_latency(Duration::from_millis(10)));
+
+        let ep1 = endpoint_builder(s1.clone(), t1, EndpointConfig::default())
+            .bind()
+            .await?;
+        let ep2 = endpoint_builder(s2.clone(), t2, EndpointConfig::default())
+            .bind()
+            .await?;
+        let router = Router::builder(ep2).accept(ECHO_ALPN, Echo).spawn();
+
+        let conn = ep1
+            .connect(custom_only_addr(s2.public()), ECHO_ALPN)
+            .await?;
+        verify_echo(&conn, b"before").await?;
+
+        // Keep-alives keep the connection open for an hour.
+        tokio::time::sleep(Duration::from_secs(60 * 60)).await;
+        assert!(conn.close_reason().is_none());
+        let conn2 = ep1
+            .connect(custom_only_addr(s2.public()), ECHO_ALPN)
+            .await?;
+        verify_echo(&conn2, b"after").await?;
+
+        // Without connectivity, the connections time out.
+        network.partition(s1.public(), s2.public());
+        let start = tokio::time::Instant::now();
+        conn.closed().await;
+        let elapsed = start.elapsed();
+        assert!(elapsed >= PATH_MAX_IDLE_TIMEOUT, "closed after {elapsed:?}");
+        assert!(
+            elapsed < Duration::from_secs(60),
+            "closed after {elapsed:?}"
+        );
+
+        router.shutdown().await.anyerr()?;
+        Ok(())
+    }
+
     /// Test custom transport only - no IP, no relay, dial by custom address.
     #[tokio::test]
     #[traced_test]
@@ -7,15 +7,17 @@
 
 use std::sync::Arc;
 
-use iroh_base::SecretKey;
+use iroh_base::{EndpointId, KeySuccession, SecretKey, Signer};
 use noq::crypto::rustls::{QuicClientConfig, QuicServerConfig};
 use tracing::warn;
 
 use self::resolver::ResolveRawPublicKeyCert;
+use crate::endpoint::{Identity, RotateKeyError};
 
 pub(crate) mod misc;
 pub(crate) mod name;
 mod resolver;
+mod resumption;
 mod verifier;
 
 pub use iroh_relay::tls::CaTlsConfig;
@@ -41,11 +43,17 @@ pub(crate) const DEFAULT_MAX_TLS_TICKETS: usize = 8 * 32;
 /// This makes sure that's the case.
 #[derive(Debug)]
 pub(crate) struct TlsConfig {
-    pub(crate) secret_key: SecretKey,
+    /// The key of this endpoint, which can be rotated.
+    pub(crate) identity: Arc<Identity>,
+    /// The `identity` as a [`Signer`].
+    pub(crate) signer: Arc<dyn Signer>,
+    /// The secret key the endpoint was built with, unless it was built with a [`Signer`].
+    pub(crate) secret_key: Option<Arc<SecretKey>>,
     cert_resolver: Arc<ResolveRawPublicKeyCert>,
     server_verifier: Arc<verifier::ServerCertificateVerifier>,
     client_verifier: Arc<verifier::ClientCertificateVerifier>,
-    session_store: Arc<dyn rustls::client::ClientSessionStore>,
+    session_store: Arc<resumption::ClientSessions>,
+    server_session_store: Arc<resumption::ServerSessions>,
     crypto_provider: Arc<rustls::crypto::CryptoProvider>,
 }
 
@@ -55,18 +63,64 @@ impl TlsConfig {
         max_tls_tickets: usize,
         crypto_provider: Arc<rustls::crypto::CryptoProvider>,
     ) -> Self {
+        let secret_key = Arc::new(secret_key);
+        Self::from_parts(
+            secret_key.clone(),
+            Some(secret_key),
+            max_tls_tickets,
+            crypto_provider,
+        )
+    }
+
+    /// Creates a TLS config which signs with `signer`, without access to the secret key.
+    pub(crate) fn with_signer(
+        signer: Arc<dyn Signer>,
+        max_tls_tickets: usize,
+        crypto_provider: Arc<rustls::crypto::CryptoProvider>,
+    ) -> Self {
+        Self::from_parts(signer, None, max_tls_tickets, crypto_provider)
+    }
+
+    fn from_parts(
+        signer: Arc<dyn Signer>,
+        secret_key: Option<Arc<SecretKey>>,
+        max_tls_tickets: usize,
+        crypto_provider: Arc<rustls::crypto::CryptoProvider>,
+    ) -> Self {
+        let identity = Arc::new(Identity::new(signer, secret_key.clone()));
         Self {
-            cert_resolver: Arc::new(ResolveRawPublicKeyCert::new(&secret_key)),
+            cert_resolver: Arc::new(ResolveRawPublicKeyCert::new(identity.clone())),
             server_verifier: Arc::new(verifier::ServerCertificateVerifier),
             client_verifier: Arc::new(verifier::ClientCertificateVerifier),
-            session_store: Arc::new(rustls::client::ClientSessionMemoryCache::new(
-                max_tls_tickets,
-            )),
+            session_store: Arc::new(resumption::ClientSessions::new(max_tls_tickets)),
+            server_session_store: Arc::new(resumption::ServerSessions::new()),
             crypto_provider,
+            signer: identity.clone(),
+            identity,
             secret_key,
         }
     }
 
+    /// Returns the current endpoint id.
+    pub(crate) fn endpoint_id(&self) -> EndpointId {
+        self.identity.public_key()
+    }
+
+    /// Rotates the endpoint key to the key of `signer`.
+    ///
+    /// Stored TLS sessions are dropped, resuming them would skip authenticating with the new
+    /// key.
+    pub(crate) fn rotate(
+        &self,
+        signer: Arc<dyn Signer>,
+        secret_key: Option<Arc<SecretKey>>,
+    ) -> Result<KeySuccession, RotateKeyError> {
+        let succession = self.identity.rotate(signer, secret_key)?;
+        self.session_store.clear();
+        self.server_session_store.clear();
+        Ok(succession)
+    }
+
     /// Create a TLS client configuration.
     ///
     /// If *keylog* is `true` this will enable logging of the pre-master key to the file in the
@@ -108,6 +162,7 @@ impl TlsConfig {
             .with_protocol_versions(verifier::PROTOCOL_VERSIONS)?
             .with_client_cert_verifier(self.client_verifier.clone())
             .with_cert_resolver(self.cert_resolver.clone());
+        crypto.session_storage = self.server_session_store.clone();
         if keylog {
             warn!("enabling SSLKEYLOGFILE for TLS pre-master keys");
             crypto.key_log = Arc::new(rustls::KeyLogFile::new());
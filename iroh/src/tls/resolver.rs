@@ -1,35 +1,62 @@
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
-use iroh_base::SecretKey;
+use iroh_base::{PublicKey, Signer};
 use webpki_types::CertificateDer;
 
+use crate::endpoint::Identity;
+
 #[derive(Debug)]
 pub(super) struct ResolveRawPublicKeyCert {
-    key: Arc<rustls::sign::CertifiedKey>,
+    identity: Arc<Identity>,
+    /// The certified key for the current key of the `identity`.
+    key: RwLock<(PublicKey, Arc<rustls::sign::CertifiedKey>)>,
 }
 
 impl ResolveRawPublicKeyCert {
-    pub(super) fn new(secret_key: &SecretKey) -> Self {
-        let client_private_key = Arc::new(IrohSecretKey::from(secret_key.clone()));
-        let client_public_key = client_private_key.spki_public_key();
-        let client_public_key_as_cert = CertificateDer::from(client_public_key.to_vec());
-
-        let certified_key =
-            rustls::sign::CertifiedKey::new(vec![client_public_key_as_cert], client_private_key);
-
-        let key = Arc::new(certified_key);
+    pub(super) fn new(identity: Arc<Identity>) -> Self {
+        let (public_key, signer) = identity.current();
+        let key = RwLock::new((public_key, certified_key(signer, public_key)));
+        Self { identity, key }
+    }
 
-        Self { key }
+    /// Returns the certified key for the current key of the identity.
+    ///
+    /// The certified key is rebuilt when the endpoint key was rotated.
+    fn key(&self) -> Arc<rustls::sign::CertifiedKey> {
+        let (public_key, signer) = self.identity.current();
+        {
+            let key = self.key.read().expect("poisoned");
+            if key.0 == public_key {
+                return key.1.clone();
+            }
+        }
+        let certified_key = certified_key(signer, public_key);
+        *self.key.write().expect("poisoned") = (public_key, certified_key.clone());
+        certified_key
     }
 }
 
+fn certified_key(
+    signer: Arc<dyn Signer>,
+    public_key: PublicKey,
+) -> Arc<rustls::sign::CertifiedKey> {
+    let client_private_key = Arc::new(IrohSigningKey { signer, public_key });
+    let client_public_key = client_private_key.spki_public_key();
+    let client_public_key_as_cert = CertificateDer::from(client_public_key.to_vec());
+
+    Arc::new(rustls::sign::CertifiedKey::new(
+        vec![client_public_key_as_cert],
+        client_private_key,
+    ))
+}
+
 impl rustls::client::ResolvesClientCert for ResolveRawPublicKeyCert {
     fn resolve(
         &self,
         _root_hint_subjects: &[&[u8]],
         _sigschemes: &[rustls::SignatureScheme],
     ) -> Option<Arc<rustls::sign::CertifiedKey>> {
-        Some(Arc::clone(&self.key))
+        Some(self.key())
     }
 
     fn only_raw_public_keys(&self) -> bool {
@@ -46,7 +73,7 @@ impl rustls::server::ResolvesServerCert for ResolveRawPublicKeyCert {
         &self,
         _client_hello: rustls::server::ClientHello<'_>,
     ) -> Option<Arc<rustls::sign::CertifiedKey>> {
-        Some(Arc::clone(&self.key))
+        Some(self.key())
     }
 
     fn only_raw_public_keys(&self) -> bool {
@@ -54,21 +81,19 @@ impl rustls::server::ResolvesServerCert for ResolveRawPublicKeyCert {
     }
 }
 
-#[derive(Debug, Clone, derive_more::From)]
-struct IrohSecretKey {
-    #[from]
-    key: SecretKey,
+#[derive(Debug, Clone)]
+struct IrohSigningKey {
+    signer: Arc<dyn Signer>,
+    /// The public key of the `signer`, cached to not ask the signer on every handshake.
+    public_key: PublicKey,
 }
 
-impl IrohSecretKey {
+impl IrohSigningKey {
     fn spki_public_key(&self) -> webpki_types::SubjectPublicKeyInfoDer<'static> {
-        rustls::sign::public_key_to_spki(
-            &webpki_types::alg_id::ED25519,
-            self.key.public().as_bytes(),
-        )
+        rustls::sign::public_key_to_spki(&webpki_types::alg_id::ED25519, self.public_key.as_bytes())
     }
 }
-impl rustls::sign::SigningKey for IrohSecretKey {
+impl rustls::sign::SigningKey for IrohSigningKey {
     fn choose_scheme(
         &self,
         offered: &[rustls::SignatureScheme],
@@ -89,9 +114,13 @@ impl rustls::sign::SigningKey for IrohSecretKey {
     }
 }
 
-impl rustls::sign::Signer for IrohSecretKey {
+impl rustls::sign::Signer for IrohSigningKey {
     fn sign(&self, message: &[u8]) -> Result<Vec<u8>, rustls::Error> {
-        Ok(self.key.sign(message).to_bytes().to_vec())
+        let signature = self
+            .signer
+            .sign(message)
+            .map_err(|err| rustls::Error::General(format!("{err:#}")))?;
+        Ok(signature.to_bytes().to_vec())
     }
 
     fn scheme(&self) -> rustls::SignatureScheme {
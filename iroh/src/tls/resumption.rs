@@ -0,0 +1,124 @@
+//! Session stores for TLS session resumption which can be cleared.
+//!
+//! A resumed TLS session does not authenticate the peers again, it inherits the identities
+//! of the original session. When the endpoint rotates its key all stored sessions are
+//! dropped, so that no peer believes it is still talking to the old key.
+
+use std::sync::{Arc, RwLock};
+
+use rustls::{
+    NamedGroup,
+    client::{
+        ClientSessionMemoryCache, ClientSessionStore, Tls12ClientSessionValue,
+        Tls13ClientSessionValue,
+    },
+    pki_types::ServerName,
+    server::{ServerSessionMemoryCache, StoresServerSessions},
+};
+
+/// A [`ClientSessionMemoryCache`] which can be cleared.
+#[derive(Debug)]
+pub(super) struct ClientSessions {
+    max_tickets: usize,
+    cache: RwLock<Arc<ClientSessionMemoryCache>>,
+}
+
+impl ClientSessions {
+    pub(super) fn new(max_tickets: usize) -> Self {
+        Self {
+            max_tickets,
+            cache: RwLock::new(Arc::new(ClientSessionMemoryCache::new(max_tickets))),
+        }
+    }
+
+    /// Forgets all stored sessions.
+    pub(super) fn clear(&self) {
+        *self.cache.write().expect("poisoned") =
+            Arc::new(ClientSessionMemoryCache::new(self.max_tickets));
+    }
+
+    fn cache(&self) -> Arc<ClientSessionMemoryCache> {
+        self.cache.read().expect("poisoned").clone()
+    }
+}
+
+impl ClientSessionStore for ClientSessions {
+    fn set_kx_hint(&self, server_name: ServerName<'static>, group: NamedGroup) {
+        self.cache().set_kx_hint(server_name, group)
+    }
+
+    fn kx_hint(&self, server_name: &ServerName<'_>) -> Option<NamedGroup> {
+        self.cache().kx_hint(server_name)
+    }
+
+    fn set_tls12_session(&self, server_name: ServerName<'static>, value: Tls12ClientSessionValue) {
+        self.cache().set_tls12_session(server_name, value)
+    }
+
+    fn tls12_session(&self, server_name: &ServerName<'_>) -> Option<Tls12ClientSessionValue> {
+        self.cache().tls12_session(server_name)
+    }
+
+    fn remove_tls12_session(&self, server_name: &ServerName<'static>) {
+        self.cache().remove_tls12_session(server_name)
+    }
+
+    fn insert_tls13_ticket(
+        &self,
+        server_name: ServerName<'static>,
+        value: Tls13ClientSessionValue,
+    ) {
+        self.cache().insert_tls13_ticket(server_name, value)
+    }
+
+    fn take_tls13_ticket(
+        &self,
+        server_name: &ServerName<'static>,
+    ) -> Option<Tls13ClientSessionValue> {
+        self.cache().take_tls13_ticket(server_name)
+    }
+}
+
+/// Maximum number of sessions stored by the server, the rustls default.
+const MAX_SERVER_SESSIONS: usize = 256;
+
+/// A [`ServerSessionMemoryCache`] which can be cleared.
+#[derive(Debug)]
+pub(super) struct ServerSessions {
+    cache: RwLock<Arc<ServerSessionMemoryCache>>,
+}
+
+impl ServerSessions {
+    pub(super) fn new() -> Self {
+        Self {
+            cache: RwLock::new(ServerSessionMemoryCache::new(MAX_SERVER_SESSIONS)),
+        }
+    }
+
+    /// Forgets all stored sessions.
+    pub(super) fn clear(&self) {
+        *self.cache.write().expect("poisoned") = ServerSessionMemoryCache::new(MAX_SERVER_SESSIONS);
+    }
+
+    fn cache(&self) -> Arc<ServerSessionMemoryCache> {
+        self.cache.read().expect("poisoned").clone()
+    }
+}
+
+impl StoresServerSessions for ServerSessions {
+    fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool {
+        self.cache().put(key, value)
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.cache().get(key)
+    }
+
+    fn take(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.cache().take(key)
+    }
+
+    fn can_cache(&self) -> bool {
+        true
+    }
+}